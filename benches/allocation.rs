@@ -0,0 +1,132 @@
+//! Compares the cost of finding a free locker in a cloakroom with 100,000
+//! lockers against the linear probing that `Cloakroom` used to do.
+//!
+//! Run with `cargo bench`. Each run fills the cloakroom until 99% of its
+//! lockers are in use, then repeatedly vacates a random locker and finds a
+//! free one.
+
+extern crate cloakroom_model;
+
+use cloakroom_model::cloakroom::allocation::{
+    AllocationStrategy, BestFit, FirstFit, LeastRecentlyUsed, RoundRobin, SeededRandom,
+};
+use cloakroom_model::cloakroom::{Cloakroom, Locker, LockerNumber};
+use cloakroom_model::items::CloakroomItems;
+use std::collections::hash_map::HashMap;
+use std::time::{Duration, Instant};
+
+const NUM_LOCKERS: usize = 100_000;
+const NUM_IN_USE: usize = NUM_LOCKERS / 100 * 99;
+const NUM_CHURN_OPS: usize = 2_000;
+
+/// Small xorshift generator so that every run vacates the same lockers.
+struct XorShift(u64);
+
+impl XorShift {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+/// The bookkeeping `Cloakroom` used before it kept an index of free lockers.
+struct LinearProbe {
+    num_lockers: usize,
+    lockers_in_use: HashMap<LockerNumber, ()>,
+}
+
+impl LinearProbe {
+    fn find_free_locker(&mut self) -> Option<LockerNumber> {
+        if self.lockers_in_use.len() < self.num_lockers {
+            let mut locker_number = 1;
+            while self.lockers_in_use.contains_key(&locker_number) {
+                locker_number += 1;
+            }
+            self.lockers_in_use.insert(locker_number, ());
+            Some(locker_number)
+        } else {
+            None
+        }
+    }
+
+    fn vacate_locker(&mut self, locker_number: LockerNumber) {
+        self.lockers_in_use.remove(&locker_number);
+    }
+}
+
+fn per_op(elapsed: Duration, num_ops: usize) -> String {
+    format!("{:>10.0} ns/op", elapsed.as_nanos() as f64 / num_ops as f64)
+}
+
+fn bench_linear_probe() {
+    // Filling by probing would take quadratic time, so fill directly.
+    let mut cloakroom = LinearProbe {
+        num_lockers: NUM_LOCKERS,
+        lockers_in_use: (1..=NUM_IN_USE).map(|n| (n, ())).collect(),
+    };
+    let mut in_use: Vec<LockerNumber> = (1..=NUM_IN_USE).collect();
+    let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+
+    let start = Instant::now();
+    for _ in 0..NUM_CHURN_OPS {
+        let index = rng.below(in_use.len());
+        cloakroom.vacate_locker(in_use.swap_remove(index));
+        in_use.push(cloakroom.find_free_locker().expect("no free locker"));
+    }
+    println!(
+        "{:<22} fill: {:>16}  churn: {}",
+        "linear probe (old)",
+        "-",
+        per_op(start.elapsed(), NUM_CHURN_OPS)
+    );
+}
+
+fn bench_strategy<S: AllocationStrategy + Send + 'static>(name: &str, strategy: S) {
+    let mut cloakroom = Cloakroom::new(NUM_LOCKERS, 10).with_allocation_strategy(strategy);
+    let no_items = CloakroomItems::new();
+    let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+
+    let start = Instant::now();
+    let mut in_use: Vec<Locker> = (0..NUM_IN_USE)
+        .map(|_| {
+            cloakroom
+                .find_free_locker(&no_items)
+                .into_result()
+                .expect("no free locker")
+        })
+        .collect();
+    let fill = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..NUM_CHURN_OPS {
+        let index = rng.below(in_use.len());
+        cloakroom.vacate_locker(in_use.swap_remove(index)).unwrap();
+        in_use.push(
+            cloakroom
+                .find_free_locker(&no_items)
+                .into_result()
+                .expect("no free locker"),
+        );
+    }
+    println!(
+        "{:<22} fill: {}  churn: {}",
+        name,
+        per_op(fill, NUM_IN_USE),
+        per_op(start.elapsed(), NUM_CHURN_OPS)
+    );
+}
+
+fn main() {
+    println!(
+        "{} lockers, {} in use, {} vacate/find operations",
+        NUM_LOCKERS, NUM_IN_USE, NUM_CHURN_OPS
+    );
+    bench_linear_probe();
+    bench_strategy("first fit", FirstFit);
+    bench_strategy("best fit", BestFit);
+    bench_strategy("round robin", RoundRobin::new());
+    bench_strategy("least recently used", LeastRecentlyUsed);
+    bench_strategy("seeded random", SeededRandom::new(1));
+}
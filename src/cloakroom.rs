@@ -1,12 +1,334 @@
-use crate::items::CloakroomItems;
-use std::collections::hash_map::HashMap;
+pub mod allocation;
+pub mod audit;
+pub mod expiry;
+pub mod lost_key;
+pub mod maintenance;
+pub mod persistence;
+pub mod queue;
+pub mod replay;
+pub mod reservation;
+pub mod shared;
+mod slot;
+pub(crate) mod token;
+pub mod transfer;
+pub mod zone;
+
+use crate::clock::{Clock, SystemClock};
+use crate::items::{self, CatalogueError, CloakroomItems, ItemCatalogue};
+use crate::lost_property::LostPropertyStore;
+use crate::pricing::{Invoice, Stay, Tariff};
+use crate::statistics::{StatisticsCollector, StatisticsReport};
+use allocation::{AllocationStrategy, FirstFit, FreeLockers};
+use audit::{AuditAction, Journal};
+use lost_key::{AdminCredential, LostKeyRecord};
+use queue::CustomerQueue;
+use reservation::Reservations;
+use slot::{LockerAccess, LockerSlots, SlotRef};
+use std::error;
+use std::fmt;
+use std::mem;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+use token::{Token, TokenGenerator};
+use zone::Zone;
 
 pub type LockerNumber = usize;
 
+/// Optional label describing the physical size of a locker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SizeClass {
+    Small,
+    Medium,
+    Large,
+}
+
+impl fmt::Display for SizeClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SizeClass::Small => "small",
+            SizeClass::Medium => "medium",
+            SizeClass::Large => "large",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for SizeClass {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<SizeClass, ()> {
+        match s {
+            "small" => Ok(SizeClass::Small),
+            "medium" => Ok(SizeClass::Medium),
+            "large" => Ok(SizeClass::Large),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Room in a locker, or the room needed by some items. Space is measured in
+/// the units used by [`ItemCategory::get_space`](crate::items::ItemCategory::get_space).
+///
+/// Capacities are ordered by space and then by weight, which is the order in
+/// which lockers are considered by best fit allocation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Capacity {
+    pub space: u32,
+    pub weight_grams: u32,
+}
+
+impl Capacity {
+    /// Returns the room needed by `items`.
+    pub fn of_items(items: &CloakroomItems) -> Capacity {
+        Capacity {
+            space: items.get_total_space(),
+            weight_grams: items.get_total_weight_grams(),
+        }
+    }
+
+    /// Returns whether there is room for `required`.
+    pub fn can_hold(&self, required: Capacity) -> bool {
+        self.space >= required.space && self.weight_grams >= required.weight_grams
+    }
+}
+
+/// Specifies the capacity, and optionally the size class, of one locker in a
+/// cloakroom. A locker has a limit on the space taken up by its contents and
+/// optionally a limit on their weight.
+///
+/// # Examples
+///
+/// ```
+/// use cloakroom_model::cloakroom::{LockerSpec, SizeClass};
+///
+/// let spec = LockerSpec::new(20)
+///     .with_max_weight_grams(25_000)
+///     .with_size_class(SizeClass::Large);
+/// assert_eq!(spec.max_space, 20);
+/// assert_eq!(spec.max_weight_grams, Some(25_000));
+/// assert_eq!(spec.size_class, Some(SizeClass::Large));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockerSpec {
+    pub max_space: u32,
+    pub max_weight_grams: Option<u32>,
+    pub size_class: Option<SizeClass>,
+}
+
+impl LockerSpec {
+    pub fn new(max_space: u32) -> LockerSpec {
+        LockerSpec {
+            max_space,
+            max_weight_grams: None,
+            size_class: None,
+        }
+    }
+
+    pub fn with_max_weight_grams(self, max_weight_grams: u32) -> LockerSpec {
+        LockerSpec {
+            max_weight_grams: Some(max_weight_grams),
+            ..self
+        }
+    }
+
+    /// Returns the room in an empty locker. A locker without a weight limit
+    /// can hold any weight.
+    pub fn get_capacity(&self) -> Capacity {
+        Capacity {
+            space: self.max_space,
+            weight_grams: self.max_weight_grams.unwrap_or(u32::MAX),
+        }
+    }
+
+    pub fn with_size_class(self, size_class: SizeClass) -> LockerSpec {
+        LockerSpec {
+            size_class: Some(size_class),
+            ..self
+        }
+    }
+}
+
+/// Reasons why an operation on a `Cloakroom` or a `Locker` can fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CloakroomError {
+    /// The locker does not have space for the items.
+    InsufficientSpace { requested: u32, available: u32 },
+    /// The items would take the locker over its weight limit.
+    InsufficientWeight {
+        requested_grams: u32,
+        available_grams: u32,
+    },
+    /// The cloakroom holds no record for the locker, e.g. because it is
+    /// free.
+    UnknownLocker(LockerNumber),
+    /// The locker was expected to be closed but its contents are being
+    /// changed.
+    LockerNotClosed(LockerNumber),
+    /// The key does not carry the locker's current token. Either it is
+    /// forged, or the locker has been opened and closed again since the key
+    /// was issued.
+    InvalidKey(LockerNumber),
+    /// Every free locker is too small for the items, or there are no free
+    /// lockers at all.
+    NoFreeLockers,
+    /// The cloakroom's item catalogue has no category with this name.
+    UnknownItemCategory(String),
+    /// Only abandoned lockers can be reclaimed.
+    LockerNotAbandoned(LockerNumber),
+    /// The admin credential is wrong, or the cloakroom has none.
+    InvalidAdminCredential,
+    /// The description given in the lost-key procedure does not match the
+    /// contents of the locker.
+    ContentsDoNotMatch(LockerNumber),
+    /// Only overdue lockers can have their contents moved to lost property.
+    LockerNotOverdue(LockerNumber),
+    /// The lost-property store holds nothing for a key to this locker.
+    NoClaimForKey(LockerNumber),
+    /// Only free lockers can be taken out of service.
+    LockerNotFree(LockerNumber),
+    /// The locker has been taken out of service.
+    LockerOutOfService(LockerNumber),
+    /// The locker is held back for a reservation.
+    LockerReserved(LockerNumber),
+    /// Only lockers that are out of service can be returned to service.
+    LockerNotOutOfService(LockerNumber),
+    /// No locker in the cloakroom can hold the items, even when free.
+    NoLockerBigEnough,
+    /// The `Locker` given back was not handed out by this cloakroom, e.g.
+    /// it came from another cloakroom, or was handed out before this one
+    /// was saved and restored.
+    LockerNotHandedOut(LockerNumber),
+}
+
+impl fmt::Display for CloakroomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CloakroomError::InsufficientSpace {
+                requested,
+                available,
+            } => write!(
+                f,
+                "not enough space in locker ({} units of space requested, {} available)",
+                requested, available
+            ),
+
+            CloakroomError::InsufficientWeight {
+                requested_grams,
+                available_grams,
+            } => write!(
+                f,
+                "locker weight limit would be exceeded ({} g requested, {} g available)",
+                requested_grams, available_grams
+            ),
+
+            CloakroomError::UnknownLocker(locker_number) => {
+                write!(f, "no record found for locker number {}", locker_number)
+            }
+
+            CloakroomError::LockerNotClosed(locker_number) => {
+                write!(f, "locker number {} is not closed", locker_number)
+            }
+
+            CloakroomError::InvalidKey(locker_number) => write!(
+                f,
+                "key for locker number {} is not valid or is out of date",
+                locker_number
+            ),
+
+            CloakroomError::NoFreeLockers => write!(f, "there are no free lockers"),
+
+            CloakroomError::UnknownItemCategory(category) => {
+                write!(f, "unknown item category '{}'", category)
+            }
+
+            CloakroomError::LockerNotAbandoned(locker_number) => {
+                write!(f, "locker number {} has not been abandoned", locker_number)
+            }
+
+            CloakroomError::InvalidAdminCredential => write!(f, "admin credential is not valid"),
+
+            CloakroomError::ContentsDoNotMatch(locker_number) => write!(
+                f,
+                "description does not match the contents of locker number {}",
+                locker_number
+            ),
+
+            CloakroomError::LockerNotOverdue(locker_number) => {
+                write!(f, "locker number {} is not overdue", locker_number)
+            }
+
+            CloakroomError::NoClaimForKey(locker_number) => write!(
+                f,
+                "no lost property is held for the key to locker number {}",
+                locker_number
+            ),
+
+            CloakroomError::LockerNotFree(locker_number) => {
+                write!(f, "locker number {} is not free", locker_number)
+            }
+
+            CloakroomError::LockerOutOfService(locker_number) => {
+                write!(f, "locker number {} is out of service", locker_number)
+            }
+
+            CloakroomError::LockerNotOutOfService(locker_number) => {
+                write!(f, "locker number {} is not out of service", locker_number)
+            }
+
+            CloakroomError::LockerReserved(locker_number) => {
+                write!(f, "locker number {} is reserved", locker_number)
+            }
+
+            CloakroomError::NoLockerBigEnough => {
+                write!(f, "no locker in the cloakroom is big enough for the items")
+            }
+
+            CloakroomError::LockerNotHandedOut(locker_number) => write!(
+                f,
+                "locker number {} was not handed out by this cloakroom",
+                locker_number
+            ),
+        }
+    }
+}
+
+impl error::Error for CloakroomError {}
+
+/// Converts the error given when using a category that is not in a
+/// catalogue. Other catalogue errors only arise when creating a catalogue.
+fn unknown_item_category(err: CatalogueError) -> CloakroomError {
+    match err {
+        CatalogueError::UnknownCategory(category) => CloakroomError::UnknownItemCategory(category),
+        other => unreachable!("unexpected item catalogue error: {}", other),
+    }
+}
+
+/// Returns the time from `start` to `time` in minutes, or 0 if `time` is
+/// earlier.
+fn minutes_since(start: SystemTime, time: SystemTime) -> f64 {
+    time.duration_since(start).unwrap_or_default().as_secs_f64() / 60.0
+}
+
+/// Lockers that were dropped without being closed or vacated, with their
+/// contents at the time, waiting to be recorded by the cloakroom.
+type AbandonedLockers = Arc<Mutex<Vec<(LockerNumber, CloakroomItems)>>>;
+
+/// A locker whose contents are being changed. It must be given back to the
+/// cloakroom, by closing or vacating it, once the changes are done.
+///
+/// A `Locker` that is dropped instead is reported to the cloakroom as
+/// abandoned, together with its contents. Abandoned lockers cannot be
+/// allocated or opened until they are recovered with
+/// [`Cloakroom::reclaim_locker`]. A `Locker` passed to [`mem::forget`] is
+/// never reported, and its locker stays in use for good.
 pub struct Locker {
     number: LockerNumber,
-    max_items: u16,
+    spec: LockerSpec,
     items: CloakroomItems,
+
+    // None once the locker has been given back to the cloakroom.
+    abandoned_lockers: Option<AbandonedLockers>,
 }
 
 impl Locker {
@@ -18,53 +340,140 @@ impl Locker {
         self.items.get_total_num_items()
     }
 
-    pub fn get_max_items(&self) -> u16 {
-        self.max_items
+    /// Returns the space taken up by the contents of the locker.
+    pub fn get_used_space(&self) -> u32 {
+        self.items.get_total_space()
+    }
+
+    pub fn get_max_space(&self) -> u32 {
+        self.spec.max_space
+    }
+
+    pub fn get_total_weight_grams(&self) -> u32 {
+        self.items.get_total_weight_grams()
+    }
+
+    /// Returns the weight limit of the locker, or `None` if it has none.
+    pub fn get_max_weight_grams(&self) -> Option<u32> {
+        self.spec.max_weight_grams
     }
 
     pub fn get_items(&self) -> &CloakroomItems {
         &self.items
     }
 
-    pub fn set_num_coats(&mut self, num_items: u8) -> Result<(), ()> {
-        self.items.num_coats = self.check_change_items(self.items.num_coats, num_items)?;
-        Ok(())
+    /// Sets the number of items of one category in the locker, where
+    /// `category` is the name of a category in the cloakroom's item
+    /// catalogue. The contents are left unchanged if the locker does not
+    /// have room for the new items alongside the items of other categories.
+    pub fn set_num_items(&mut self, category: &str, num_items: u8) -> Result<(), CloakroomError> {
+        let mut others = self.items.clone();
+        others
+            .set_num_items(category, 0)
+            .map_err(unknown_item_category)?;
+        let mut added = CloakroomItems::from_catalogue(self.items.get_catalogue().clone());
+        added
+            .set_num_items(category, num_items)
+            .map_err(unknown_item_category)?;
+        self.check_room(Capacity::of_items(&others), Capacity::of_items(&added))?;
+
+        self.items
+            .set_num_items(category, num_items)
+            .map_err(unknown_item_category)
     }
 
-    pub fn set_num_backpacks(&mut self, num_items: u8) -> Result<(), ()> {
-        self.items.num_backpacks = self.check_change_items(self.items.num_backpacks, num_items)?;
+    /// Replaces the contents of the locker. The contents are left unchanged
+    /// if the locker does not have room for `items`, or `items` includes
+    /// categories that the cloakroom's item catalogue does not have.
+    pub fn set_items(&mut self, items: CloakroomItems) -> Result<(), CloakroomError> {
+        let items = items
+            .convert_to_catalogue(self.items.get_catalogue())
+            .map_err(unknown_item_category)?;
+        self.check_room(Capacity::default(), Capacity::of_items(&items))?;
+        self.items = items;
         Ok(())
     }
 
-    pub fn set_num_umbrellas(&mut self, num_items: u8) -> Result<(), ()> {
-        self.items.num_umbrellas = self.check_change_items(self.items.num_umbrellas, num_items)?;
-        Ok(())
+    /// Takes the contents out of a locker that is being given back to the
+    /// cloakroom, so that it is not reported as abandoned when dropped.
+    fn give_back(mut self) -> CloakroomItems {
+        self.abandoned_lockers = None;
+        mem::take(&mut self.items)
     }
 
-    pub fn set_num_other_items(&mut self, num_items: u8) -> Result<(), ()> {
-        self.items.num_other_items =
-            self.check_change_items(self.items.num_other_items, num_items)?;
+    /// Checks that there is room for `requested` in the locker when `used`
+    /// is already taken up.
+    fn check_room(&self, used: Capacity, requested: Capacity) -> Result<(), CloakroomError> {
+        let available = self.spec.max_space.saturating_sub(used.space);
+        if requested.space > available {
+            return Err(CloakroomError::InsufficientSpace {
+                requested: requested.space,
+                available,
+            });
+        }
+
+        if let Some(max_weight_grams) = self.spec.max_weight_grams {
+            let available_grams = max_weight_grams.saturating_sub(used.weight_grams);
+            if requested.weight_grams > available_grams {
+                return Err(CloakroomError::InsufficientWeight {
+                    requested_grams: requested.weight_grams,
+                    available_grams,
+                });
+            }
+        }
+
         Ok(())
     }
+}
 
-    fn check_change_items(&self, curr_num_items: u8, new_num_items: u8) -> Result<u8, ()> {
-        if self.items.get_total_num_items() - curr_num_items as u16 + new_num_items as u16
-            > self.max_items
-        {
-            Err(())
-        } else {
-            Ok(new_num_items)
+impl Drop for Locker {
+    fn drop(&mut self) {
+        if let Some(abandoned_lockers) = self.abandoned_lockers.take() {
+            // A poisoned lock still holds a usable list.
+            let mut abandoned_lockers = abandoned_lockers
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            abandoned_lockers.push((self.number, mem::take(&mut self.items)));
         }
     }
 }
 
+/// Key for a closed locker. As well as the locker number, a key carries a
+/// secret token that is generated each time the locker is closed, so a key
+/// cannot be forged by guessing and stops working once its locker has been
+/// opened. Tokens are read from the operating system's random number
+/// generator, `/dev/urandom`; closing a locker panics if it is not there.
+///
+/// A key can be written out as a ticket, for example to print on a slip
+/// given to the customer, and read back again.
+///
+/// # Examples
+///
+/// ```
+/// use cloakroom_model::cloakroom::{Cloakroom, Key};
+/// use cloakroom_model::items::CloakroomItems;
+///
+/// let mut cloakroom = Cloakroom::new(10, 7);
+/// let locker = cloakroom.find_free_locker(&CloakroomItems::new()).into_result().unwrap();
+/// let key = cloakroom.close_locker(locker).unwrap();
+///
+/// let ticket = key.to_string();
+/// assert!(ticket.starts_with("1-"));
+///
+/// let key: Key = ticket.parse().unwrap();
+/// assert!(cloakroom.open_locker(key).is_ok());
+/// ```
 pub struct Key {
     locker_number: LockerNumber,
+    token: Token,
 }
 
 impl Key {
-    fn new(locker_number: LockerNumber) -> Key {
-        Key { locker_number }
+    fn new(locker_number: LockerNumber, token: Token) -> Key {
+        Key {
+            locker_number,
+            token,
+        }
     }
 
     pub fn get_locker_number(&self) -> LockerNumber {
@@ -72,28 +481,106 @@ impl Key {
     }
 }
 
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.locker_number, self.token)
+    }
+}
+
+impl FromStr for Key {
+    type Err = ();
+
+    /// Reads a ticket in the form written by `Display`.
+    fn from_str(s: &str) -> Result<Key, ()> {
+        let pos = s.find('-').ok_or(())?;
+        let locker_number = s[..pos].parse().map_err(|_| ())?;
+        let token = s[pos + 1..].parse()?;
+        Ok(Key::new(locker_number, token))
+    }
+}
+
 pub enum FreeLockerResult {
     FreeLockerFound(Locker),
+    /// No free locker is big enough for the items.
     NoFreeLockers,
 }
 
+impl FreeLockerResult {
+    /// Converts the result into a `Result`, so that it can be used with the
+    /// `?` operator.
+    pub fn into_result(self) -> Result<Locker, CloakroomError> {
+        match self {
+            FreeLockerResult::FreeLockerFound(locker) => Ok(locker),
+            FreeLockerResult::NoFreeLockers => Err(CloakroomError::NoFreeLockers),
+        }
+    }
+}
+
 pub enum LockerState<'a> {
     Free,
-    Closed(&'a CloakroomItems),
-    ContentsBeingChanged,
+    Closed(&'a CloakroomItems, LockerTimes),
+    ContentsBeingChanged(LockerTimes),
+    /// The `Locker` handed out for the locker was dropped without being
+    /// closed or vacated. See [`Cloakroom::reclaim_locker`].
+    Abandoned(LockerTimes),
+    /// The locker has been taken out of service, and is not handed out. See
+    /// [`Cloakroom::take_out_of_service`].
+    OutOfService {
+        reason: &'a str,
+    },
     NonExistent,
 }
 
+/// When a locker in use was deposited in and last used, as read from the
+/// cloakroom's clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockerTimes {
+    deposited_at: Option<SystemTime>,
+    last_accessed_at: SystemTime,
+}
+
+impl LockerTimes {
+    /// Returns when the locker was first closed after being allocated, or
+    /// `None` if it has not been closed yet.
+    pub fn get_deposited_at(&self) -> Option<SystemTime> {
+        self.deposited_at
+    }
+
+    /// Returns when the locker was last allocated, opened, closed or
+    /// otherwise changed.
+    pub fn get_last_accessed_at(&self) -> SystemTime {
+        self.last_accessed_at
+    }
+}
+
 enum LockerInUseState {
-    Closed(CloakroomItems),
-    ContentsBeingChanged,
+    Closed {
+        items: CloakroomItems,
+        token: Token,
+    },
+    /// `items` are the contents when the `Locker` was handed out, kept so
+    /// that they are not lost if the cloakroom is saved and restored before
+    /// the locker is given back.
+    ContentsBeingChanged {
+        items: CloakroomItems,
+    },
+    Abandoned {
+        items: CloakroomItems,
+    },
+    OutOfService {
+        reason: String,
+    },
 }
 
-/// Representation of a cloakroom containing 1 or more lockers.
+/// Representation of a cloakroom containing 1 or more lockers, each of
+/// which may have a different capacity. Capacity is measured in units of
+/// space, and each category of item takes up a given amount of space, 1
+/// unit unless changed. Lockers may also have a weight limit.
 /// A customer can:
-///    - find a free locker
-///    - place items (coats, backpacks, umbrellas and other items) in the
-///      locker
+///    - find a free locker big enough for their items
+///    - place items in the locker, of the categories in the cloakroom's
+///      item catalogue (coats, backpacks, umbrellas and other items unless
+///      changed)
 ///    - close and lock the locker, and take away the key
 ///    - open the locker with the key
 ///    - remove the items from an open locker
@@ -102,11 +589,12 @@ enum LockerInUseState {
 ///
 /// ```
 /// use cloakroom_model::cloakroom::{Cloakroom, FreeLockerResult};
+/// use cloakroom_model::items::CloakroomItems;
 ///
 /// // Create cloakroom containing 10 lockers each holding up to 7 items.
 /// let mut cloakroom = Cloakroom::new(10, 7);
 ///
-/// let mut locker = match cloakroom.find_free_locker() {
+/// let mut locker = match cloakroom.find_free_locker(&CloakroomItems::new()) {
 ///     FreeLockerResult::FreeLockerFound(locker) => locker,
 ///
 ///     FreeLockerResult::NoFreeLockers => {
@@ -115,25 +603,25 @@ enum LockerInUseState {
 ///     }
 /// };
 ///
-/// assert!(locker.set_num_backpacks(2).is_ok());
-/// assert!(locker.set_num_other_items(5).is_ok());
+/// assert!(locker.set_num_items("backpacks", 2).is_ok());
+/// assert!(locker.set_num_items("other_items", 5).is_ok());
 ///
 /// // Cannot add any more items because locker is full.
-/// assert!(locker.set_num_umbrellas(1).is_err());
+/// assert!(locker.set_num_items("umbrellas", 1).is_err());
 ///
-/// let key = cloakroom.close_locker(locker);
+/// let key = cloakroom.close_locker(locker).unwrap();
 ///
 /// let locker = match cloakroom.open_locker(key) {
 ///     Ok(locker) => locker,
 ///
-///     Err(err_str) => {
-///         assert!(false, err_str);
+///     Err(err) => {
+///         assert!(false, "{}", err);
 ///         return;
 ///     }
 ///
 /// };
 ///
-/// let items = cloakroom.vacate_locker(locker);
+/// let items = cloakroom.vacate_locker(locker).unwrap();
 /// assert_eq!(items.get_total_num_items(), 7);
 /// let expected_str =
 ///     "num coats: 0, num backpacks: 2, num umbrellas: 0, num other items: 5"
@@ -141,117 +629,551 @@ enum LockerInUseState {
 /// assert_eq!(items.to_string(), expected_str);
 /// ```
 pub struct Cloakroom {
-    num_lockers: usize,
-    max_items_per_locker: u16,
+    // Locker number n is described by lockers[n - 1].
+    lockers: Vec<LockerSpec>,
+
+    slots: LockerSlots,
 
-    lockers_in_use: HashMap<LockerNumber, LockerInUseState>,
+    free_lockers: FreeLockers,
+    allocation_strategy: Box<dyn AllocationStrategy + Send>,
+
+    token_generator: Arc<TokenGenerator>,
+
+    item_catalogue: Arc<ItemCatalogue>,
+
+    abandoned_lockers: AbandonedLockers,
+
+    admin_credential: Option<AdminCredential>,
+    lost_key_log: Vec<LostKeyRecord>,
+
+    // The audit sink and statistics, which are shared with the desks of a
+    // shared cloakroom.
+    journal: Arc<Mutex<Journal>>,
+    attendant_id: Option<String>,
+
+    tariff: Tariff,
+
+    clock: Arc<dyn Clock + Send + Sync>,
+
+    max_storage_duration: Option<Duration>,
+    lost_property: LostPropertyStore,
+
+    queue: CustomerQueue,
+    reservations: Reservations,
+
+    // Empty unless the cloakroom was made with with_zones.
+    zones: Vec<Zone>,
 }
 
 impl Cloakroom {
-    pub fn new(num_lockers: usize, max_items_per_locker: u16) -> Cloakroom {
+    /// Creates a cloakroom in which every locker has the same amount of
+    /// space and no weight limit.
+    pub fn new(num_lockers: usize, max_space_per_locker: u32) -> Cloakroom {
+        Cloakroom::with_lockers(vec![LockerSpec::new(max_space_per_locker); num_lockers])
+    }
+
+    /// Creates a cloakroom with one locker for each spec. The locker
+    /// described by `lockers[0]` is locker number 1, and so on.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cloakroom_model::cloakroom::{Cloakroom, LockerSpec, LockerState, SizeClass};
+    /// use cloakroom_model::items::CloakroomItems;
+    ///
+    /// let mut cloakroom = Cloakroom::with_lockers(vec![
+    ///     LockerSpec::new(5).with_size_class(SizeClass::Small),
+    ///     LockerSpec::new(20).with_size_class(SizeClass::Large),
+    /// ]);
+    ///
+    /// // Only the large locker can hold 8 coats.
+    /// let mut items = CloakroomItems::new();
+    /// items.set_num_items("coats", 8).unwrap();
+    /// let locker = cloakroom.find_free_locker(&items).into_result().unwrap();
+    /// assert_eq!(locker.get_locker_number(), 2);
+    /// assert_eq!(locker.get_max_space(), 20);
+    ///
+    /// // The small locker is still free, but cannot hold them either.
+    /// assert!(matches!(cloakroom.get_locker_state(1), LockerState::Free));
+    /// assert!(cloakroom.find_free_locker(&items).into_result().is_err());
+    /// ```
+    pub fn with_lockers(lockers: Vec<LockerSpec>) -> Cloakroom {
+        let free_lockers = FreeLockers::new(&lockers);
+        let slots = LockerSlots::new(lockers.len());
         Cloakroom {
-            num_lockers,
-            max_items_per_locker,
-            lockers_in_use: HashMap::new(),
+            lockers,
+            slots,
+            free_lockers,
+            allocation_strategy: Box::new(FirstFit),
+            token_generator: Arc::new(TokenGenerator::new()),
+            item_catalogue: items::default_catalogue(),
+            abandoned_lockers: Arc::new(Mutex::new(Vec::new())),
+            admin_credential: None,
+            lost_key_log: Vec::new(),
+            journal: Arc::new(Mutex::new(Journal::new())),
+            attendant_id: None,
+            tariff: Tariff::default(),
+            clock: Arc::new(SystemClock),
+            max_storage_duration: None,
+            lost_property: LostPropertyStore::new(),
+            queue: CustomerQueue::new(),
+            reservations: Reservations::new(),
+            zones: Vec::new(),
         }
     }
 
-    pub fn get_num_lockers(&self) -> usize {
-        self.num_lockers
+    /// Replaces the strategy used to choose free lockers, which is
+    /// [`FirstFit`] unless changed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cloakroom_model::cloakroom::allocation::RoundRobin;
+    /// use cloakroom_model::cloakroom::Cloakroom;
+    /// use cloakroom_model::items::CloakroomItems;
+    ///
+    /// let mut cloakroom = Cloakroom::new(3, 5).with_allocation_strategy(RoundRobin::new());
+    /// let no_items = CloakroomItems::new();
+    ///
+    /// let locker = cloakroom.find_free_locker(&no_items).into_result().unwrap();
+    /// assert_eq!(locker.get_locker_number(), 1);
+    /// cloakroom.vacate_locker(locker).unwrap();
+    ///
+    /// // Locker 1 is free again, but the next locker is used instead.
+    /// let locker = cloakroom.find_free_locker(&no_items).into_result().unwrap();
+    /// assert_eq!(locker.get_locker_number(), 2);
+    /// ```
+    pub fn with_allocation_strategy<S>(self, allocation_strategy: S) -> Cloakroom
+    where
+        S: AllocationStrategy + Send + 'static,
+    {
+        Cloakroom {
+            allocation_strategy: Box::new(allocation_strategy),
+            ..self
+        }
     }
 
-    pub fn find_free_locker(&mut self) -> FreeLockerResult {
-        if self.lockers_in_use.len() < self.get_num_lockers() {
-            let mut locker_number = 1;
-            while self.lockers_in_use.contains_key(&locker_number) {
-                locker_number += 1;
-            }
-
-            self.lockers_in_use
-                .insert(locker_number, LockerInUseState::ContentsBeingChanged);
-
-            let locker = Locker {
-                number: locker_number,
-                max_items: self.max_items_per_locker,
-                items: CloakroomItems::new(),
-            };
-            FreeLockerResult::FreeLockerFound(locker)
-        } else {
-            FreeLockerResult::NoFreeLockers
+    /// Replaces the categories of item that the cloakroom accepts, which are
+    /// those of [`ItemCatalogue::default`] unless changed. This should be
+    /// done before any lockers are used, as the contents of lockers already
+    /// in use are still described using the old catalogue.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cloakroom_model::cloakroom::Cloakroom;
+    /// use cloakroom_model::items::{CloakroomItems, ItemCatalogue};
+    /// use std::sync::Arc;
+    ///
+    /// let catalogue = ItemCatalogue::new(vec!["helmets", "prams"]).unwrap();
+    /// let mut cloakroom = Cloakroom::new(3, 5).with_item_catalogue(Arc::new(catalogue));
+    ///
+    /// let mut locker = cloakroom.find_free_locker(&CloakroomItems::new()).into_result().unwrap();
+    /// assert!(locker.set_num_items("helmets", 2).is_ok());
+    /// assert!(locker.set_num_items("coats", 1).is_err());
+    /// assert_eq!(locker.get_items().to_string(), "num helmets: 2, num prams: 0");
+    /// ```
+    pub fn with_item_catalogue(self, item_catalogue: Arc<ItemCatalogue>) -> Cloakroom {
+        Cloakroom {
+            item_catalogue,
+            ..self
         }
     }
 
-    pub fn close_locker(&mut self, locker: Locker) -> Key {
-        let locker_number = locker.get_locker_number();
-        self.lockers_in_use
-            .insert(locker_number, LockerInUseState::Closed(locker.items));
+    /// Sets the charges made when customers collect their items. Nothing is
+    /// charged unless this is called.
+    pub fn with_tariff(self, tariff: Tariff) -> Cloakroom {
+        Cloakroom { tariff, ..self }
+    }
 
-        Key::new(locker_number)
+    pub fn get_tariff(&self) -> &Tariff {
+        &self.tariff
     }
 
-    pub fn open_locker(&mut self, key: Key) -> Result<Locker, String> {
-        let locker_number = key.get_locker_number();
-        let items = match self.lockers_in_use.remove(&locker_number) {
-            Some(items) => items,
+    /// Replaces the clock from which the cloakroom reads the time, which is
+    /// the [`SystemClock`] unless changed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cloakroom_model::clock::MockClock;
+    /// use cloakroom_model::cloakroom::{Cloakroom, LockerState};
+    /// use cloakroom_model::items::CloakroomItems;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// let clock = MockClock::new(UNIX_EPOCH);
+    /// let mut cloakroom = Cloakroom::new(2, 5).with_clock(clock.clone());
+    /// let locker = cloakroom.find_free_locker(&CloakroomItems::new()).into_result().unwrap();
+    /// clock.advance(Duration::from_secs(60));
+    /// let _key = cloakroom.close_locker(locker).unwrap();
+    ///
+    /// match cloakroom.get_locker_state(1) {
+    ///     LockerState::Closed(_, times) => {
+    ///         assert_eq!(times.get_deposited_at(), Some(UNIX_EPOCH + Duration::from_secs(60)))
+    ///     }
+    ///     _ => panic!("locker is not closed"),
+    /// }
+    /// ```
+    pub fn with_clock<C: Clock + Send + Sync + 'static>(self, clock: C) -> Cloakroom {
+        Cloakroom {
+            clock: Arc::new(clock),
+            ..self
+        }
+    }
 
-            None => {
-                let err_str = format!(
-                    "unexpectedly failed to find record for locker number {}",
-                    key.get_locker_number()
-                );
-                return Err(err_str);
+    /// Collects [statistics](crate::statistics) on how the cloakroom is
+    /// used from now on, as read from its clock, which should be set first.
+    /// Lockers already in use are counted as occupied from now on, but not
+    /// as allocated. The statistics are not saved with the cloakroom.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cloakroom_model::clock::MockClock;
+    /// use cloakroom_model::cloakroom::Cloakroom;
+    /// use cloakroom_model::items::CloakroomItems;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// let clock = MockClock::new(UNIX_EPOCH);
+    /// let mut cloakroom = Cloakroom::new(1, 5).with_clock(clock.clone()).with_statistics();
+    /// let mut locker = cloakroom.find_free_locker(&CloakroomItems::new()).into_result().unwrap();
+    /// locker.set_num_items("coats", 2).unwrap();
+    /// let key = cloakroom.close_locker(locker).unwrap();
+    /// assert!(cloakroom.find_free_locker(&CloakroomItems::new()).into_result().is_err());
+    ///
+    /// clock.advance(Duration::from_secs(30 * 60));
+    /// let locker = cloakroom.open_locker(key).unwrap();
+    /// cloakroom.vacate_locker(locker).unwrap();
+    /// clock.advance(Duration::from_secs(30 * 60));
+    ///
+    /// let report = cloakroom.get_statistics_report().unwrap();
+    /// assert_eq!(report.duration_minutes, 60.0);
+    /// assert_eq!((report.allocations, report.rejections), (1, 1));
+    /// assert_eq!(report.items_stored[0], ("coats".to_string(), 2));
+    /// assert_eq!(report.locker_utilisation, vec![0.5]);
+    /// ```
+    pub fn with_statistics(self) -> Cloakroom {
+        let now = self.now();
+        let mut collector =
+            StatisticsCollector::new(&self.item_catalogue, self.get_num_lockers(), 0.0);
+        for locker_number in 1..=self.get_num_lockers() {
+            if let Some(LockerInUseState::Closed { .. })
+            | Some(LockerInUseState::ContentsBeingChanged { .. })
+            | Some(LockerInUseState::Abandoned { .. }) = self.slot(locker_number).state
+            {
+                collector.record_occupied(0.0, locker_number);
             }
-        };
+        }
+        lock(&self.journal).statistics = Some((now, collector));
+        self
+    }
+
+    /// Summarises the statistics collected up to now, or returns `None` if
+    /// the cloakroom was not made with [`Cloakroom::with_statistics`].
+    pub fn get_statistics_report(&self) -> Option<StatisticsReport> {
+        let journal = lock(&self.journal);
+        let (started_at, collector) = journal.statistics.as_ref()?;
+        Some(collector.report(minutes_since(*started_at, self.now())))
+    }
 
-        match items {
-            LockerInUseState::Closed(items) => {
-                self.lockers_in_use
-                    .insert(locker_number, LockerInUseState::ContentsBeingChanged);
-
-                let locker = Locker {
-                    number: locker_number,
-                    max_items: self.max_items_per_locker,
-                    items,
-                };
-                Ok(locker)
+    pub fn get_item_catalogue(&self) -> &Arc<ItemCatalogue> {
+        &self.item_catalogue
+    }
+
+    pub fn get_num_lockers(&self) -> usize {
+        self.lockers.len()
+    }
+
+    /// Returns the spec of a locker, or `None` if there is no such locker.
+    pub fn get_locker_spec(&self, locker_number: LockerNumber) -> Option<&LockerSpec> {
+        if locker_number < 1 {
+            return None;
+        }
+        self.lockers.get(locker_number - 1)
+    }
+
+    /// Finds a free locker with the space, and weight limit, to hold
+    /// `items`, using the cloakroom's allocation strategy to choose between
+    /// suitable lockers. The locker is returned empty, ready for the items
+    /// to be placed in it. `FreeLockerResult::NoFreeLockers` is returned if
+    /// no free locker is big enough. Lockers held back for
+    /// [reservations](reservation) are passed over.
+    pub fn find_free_locker(&mut self, items: &CloakroomItems) -> FreeLockerResult {
+        self.record_abandoned_lockers();
+        self.expire_served_customers();
+        self.update_reservations();
+        let required_capacity = Capacity::of_items(items);
+        let found = self
+            .allocation_strategy
+            .choose_locker(&self.free_lockers, required_capacity);
+        debug_assert!(found.iter().all(|&locker_number| {
+            self.free_lockers.is_free(locker_number)
+                && self.lockers[locker_number - 1]
+                    .get_capacity()
+                    .can_hold(required_capacity)
+        }));
+
+        match found {
+            Some(locker_number) => {
+                self.emit(locker_number, AuditAction::Allocated);
+                FreeLockerResult::FreeLockerFound(self.hand_out(locker_number))
             }
 
-            LockerInUseState::ContentsBeingChanged => {
-                let err_str = format!(
-                    "record for locker number {} unexpectedly contains no data",
-                    locker_number
-                );
-                Err(err_str)
+            None => {
+                let now = self.now();
+                if let Some((started_at, collector)) = &mut lock(&self.journal).statistics {
+                    collector.record_rejected(minutes_since(*started_at, now));
+                }
+                FreeLockerResult::NoFreeLockers
             }
         }
     }
 
+    /// Closes a locker and returns its key. A new token is generated each
+    /// time a locker is closed, so keys issued before it was last opened no
+    /// longer work. `CloakroomError::LockerNotHandedOut` is returned if the
+    /// `Locker` came from somewhere else.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the operating system's random number generator,
+    /// `/dev/urandom`, cannot be read to generate the token.
+    pub fn close_locker(&mut self, locker: Locker) -> Result<Key, CloakroomError> {
+        match self.locker_access(locker.get_locker_number()) {
+            Some(access) => access.close(locker),
+            None => Err(CloakroomError::LockerNotHandedOut(
+                locker.get_locker_number(),
+            )),
+        }
+    }
+
+    /// Opens a closed locker. `CloakroomError::InvalidKey` is returned, and
+    /// the locker stays closed, if the key's token is not the one issued
+    /// when the locker was last closed.
+    pub fn open_locker(&mut self, key: Key) -> Result<Locker, CloakroomError> {
+        self.record_abandoned_lockers();
+        match self.locker_access(key.get_locker_number()) {
+            Some(access) => access.open(key),
+            None => Err(CloakroomError::UnknownLocker(key.get_locker_number())),
+        }
+    }
+
     /// Remove all items from a locker and leave it in a free state so
     /// that it can be used by another customer.
-    pub fn vacate_locker(&mut self, locker: Locker) -> CloakroomItems {
-        // Remove record for locker from records of lockers in use so that
-        // it's state is free.
-        let _ = self.lockers_in_use.remove(&locker.get_locker_number());
-
-        locker.items
+    pub fn vacate_locker(&mut self, locker: Locker) -> Result<CloakroomItems, CloakroomError> {
+        let (items, _invoice) = self.checkout_locker(locker)?;
+        Ok(items)
     }
 
-    pub fn get_locker_state(&self, locker_number: LockerNumber) -> LockerState {
-        if (locker_number < 1) || (locker_number > self.num_lockers) {
-            return LockerState::NonExistent;
+    /// Vacates a locker, as [`Cloakroom::vacate_locker`] does, and returns
+    /// an itemised invoice for its use under the cloakroom's tariff. Storage
+    /// is charged from when the locker was first closed after being
+    /// allocated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cloakroom_model::cloakroom::Cloakroom;
+    /// use cloakroom_model::items::CloakroomItems;
+    /// use cloakroom_model::pricing::Tariff;
+    ///
+    /// let tariff = Tariff::new().with_deposit_fee("coats", 150);
+    /// let mut cloakroom = Cloakroom::new(2, 5).with_tariff(tariff);
+    /// let mut locker = cloakroom.find_free_locker(&CloakroomItems::new()).into_result().unwrap();
+    /// locker.set_num_items("coats", 2).unwrap();
+    /// let key = cloakroom.close_locker(locker).unwrap();
+    ///
+    /// let locker = cloakroom.open_locker(key).unwrap();
+    /// let (items, invoice) = cloakroom.checkout_locker(locker).unwrap();
+    /// assert_eq!(items.get_num_items("coats"), Some(2));
+    /// assert_eq!(invoice.get_total_pence(), 300);
+    /// ```
+    pub fn checkout_locker(
+        &mut self,
+        locker: Locker,
+    ) -> Result<(CloakroomItems, Invoice), CloakroomError> {
+        // Vacating the locker clears its slot, so that its state is free.
+        let (locker_number, items) = self.take_back(locker)?;
+        self.emit_contents_change(locker_number, &items);
+
+        let now = self.now();
+        let invoice = match &self.slot(locker_number).stay {
+            Some(stay) => self.tariff.invoice(locker_number, stay, now),
+            None => return Err(CloakroomError::LockerNotHandedOut(locker_number)),
         };
+        self.emit_at(
+            now,
+            locker_number,
+            AuditAction::Vacated {
+                items: items.clone(),
+            },
+        );
+        self.serve_queue();
 
-        let items = match self.lockers_in_use.get(&locker_number) {
-            Some(items) => items,
-            None => {
-                return LockerState::Free;
-            }
+        Ok((items, invoice))
+    }
+
+    /// Recovers an abandoned locker, handing out a `Locker` holding the
+    /// contents it had when it was abandoned. It can then be closed, to
+    /// issue a new key, or vacated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cloakroom_model::cloakroom::{Cloakroom, LockerState};
+    /// use cloakroom_model::items::CloakroomItems;
+    ///
+    /// let mut cloakroom = Cloakroom::new(1, 5);
+    /// let mut locker = cloakroom.find_free_locker(&CloakroomItems::new()).into_result().unwrap();
+    /// locker.set_num_items("coats", 2).unwrap();
+    ///
+    /// // The locker is dropped without being closed or vacated.
+    /// drop(locker);
+    /// assert!(matches!(cloakroom.get_locker_state(1), LockerState::Abandoned(_)));
+    /// assert!(cloakroom.find_free_locker(&CloakroomItems::new()).into_result().is_err());
+    ///
+    /// let locker = cloakroom.reclaim_locker(1).unwrap();
+    /// let items = cloakroom.vacate_locker(locker).unwrap();
+    /// assert_eq!(items.get_num_items("coats"), Some(2));
+    /// assert!(matches!(cloakroom.get_locker_state(1), LockerState::Free));
+    /// ```
+    pub fn reclaim_locker(
+        &mut self,
+        locker_number: LockerNumber,
+    ) -> Result<Locker, CloakroomError> {
+        self.record_abandoned_lockers();
+        self.with_state(locker_number, |state| match state {
+            Some(LockerInUseState::Abandoned { .. }) => Ok(()),
+            Some(_) => Err(CloakroomError::LockerNotAbandoned(locker_number)),
+            None => Err(CloakroomError::UnknownLocker(locker_number)),
+        })?;
+
+        self.emit(locker_number, AuditAction::Reclaimed);
+        Ok(self.hand_out(locker_number))
+    }
+
+    /// Returns the numbers of the abandoned lockers, in order.
+    pub fn get_abandoned_lockers(&self) -> Vec<LockerNumber> {
+        (1..=self.get_num_lockers())
+            .filter(|&locker_number| {
+                matches!(
+                    self.get_locker_state(locker_number),
+                    LockerState::Abandoned(_)
+                )
+            })
+            .collect()
+    }
+
+    pub fn get_locker_state(&self, locker_number: LockerNumber) -> LockerState<'_> {
+        match self.slots.borrow(locker_number) {
+            Some(slot) => slot.describe(locker_number, &self.abandoned_lockers),
+            None => LockerState::NonExistent,
+        }
+    }
+
+    /// Hands out a `Locker` holding the recorded contents of a locker whose
+    /// contents are being changed.
+    fn hand_out(&self, locker_number: LockerNumber) -> Locker {
+        self.slot(locker_number).hand_out(
+            locker_number,
+            self.lockers[locker_number - 1],
+            &self.item_catalogue,
+            &self.abandoned_lockers,
+        )
+    }
+
+    /// Takes the contents out of a `Locker` being given back, after checking
+    /// that it was handed out by this cloakroom and is still being changed.
+    /// A `Locker` that fails the check is dropped, and so reported as
+    /// abandoned to the cloakroom that handed it out.
+    fn take_back(&self, locker: Locker) -> Result<(LockerNumber, CloakroomItems), CloakroomError> {
+        let locker_number = locker.get_locker_number();
+        let handed_out = match self.slots.get(locker_number) {
+            Some(slot) => slot.has_handed_out(&locker, &self.abandoned_lockers),
+            None => false,
         };
+        if !handed_out {
+            return Err(CloakroomError::LockerNotHandedOut(locker_number));
+        }
+
+        Ok((locker_number, locker.give_back()))
+    }
 
-        match items {
-            LockerInUseState::Closed(ref items) => LockerState::Closed(items),
+    /// Borrows, or locks, the slot of a locker that exists.
+    fn slot(&self, locker_number: LockerNumber) -> SlotRef<'_> {
+        self.slots
+            .get(locker_number)
+            .expect("locker number is in range")
+    }
 
-            LockerInUseState::ContentsBeingChanged => LockerState::ContentsBeingChanged,
+    /// Calls `f` with the state of a locker, which is `None` if the locker
+    /// is free or there is no such locker.
+    fn with_state<R, F>(&self, locker_number: LockerNumber, f: F) -> R
+    where
+        F: FnOnce(Option<&LockerInUseState>) -> R,
+    {
+        match self.slots.get(locker_number) {
+            Some(slot) => f(slot.state.as_ref()),
+            None => f(None),
         }
     }
+
+    /// Gives access to a locker to close or open it, or returns `None` if
+    /// there is no such locker.
+    fn locker_access(&mut self, locker_number: LockerNumber) -> Option<LockerAccess<'_>> {
+        let spec = *self.get_locker_spec(locker_number)?;
+        Some(LockerAccess {
+            locker_number,
+            spec,
+            slot: self.slots.get_mut(locker_number)?,
+            item_catalogue: &self.item_catalogue,
+            clock: &*self.clock,
+            token_generator: &self.token_generator,
+            journal: &self.journal,
+            abandoned_lockers: &self.abandoned_lockers,
+            attendant_id: self.attendant_id.as_deref(),
+        })
+    }
+
+    /// Reads the time from the cloakroom's clock.
+    fn now(&self) -> SystemTime {
+        self.clock.now()
+    }
+
+    /// Marks the lockers whose `Locker` has been dropped as abandoned.
+    fn record_abandoned_lockers(&mut self) {
+        let reported = mem::take(&mut *lock(&self.abandoned_lockers));
+        for (locker_number, items) in reported {
+            let being_changed = self.with_state(locker_number, |state| {
+                matches!(state, Some(LockerInUseState::ContentsBeingChanged { .. }))
+            });
+            if being_changed {
+                self.emit_contents_change(locker_number, &items);
+                self.emit(locker_number, AuditAction::Abandoned { items });
+            }
+        }
+    }
+
+    /// Records that a free locker is now in use.
+    fn mark_in_use(
+        &mut self,
+        locker_number: LockerNumber,
+        state: LockerInUseState,
+        stay: Option<Stay>,
+    ) {
+        self.free_lockers.remove(locker_number);
+        let mut slot = self
+            .slots
+            .get_mut(locker_number)
+            .expect("locker number is in range");
+        slot.state = Some(state);
+        slot.stay = stay;
+    }
+}
+
+/// Locks a mutex, carrying on if another thread panicked while holding it.
+fn lock<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
 }
@@ -0,0 +1,620 @@
+//! Strategies for choosing which free locker a customer is given.
+//!
+//! A [`Cloakroom`](super::Cloakroom) asks its strategy for a locker each time
+//! `find_free_locker` is called. The strategy is given the cloakroom's
+//! [`FreeLockers`] index, which answers the queries the built-in strategies
+//! need efficiently, and returns the number of the locker to use.
+
+use super::{Capacity, LockerNumber, LockerSpec, SizeClass};
+use crate::rng::Rng;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::RangeInclusive;
+
+/// Chooses a free locker with room for some items.
+pub trait AllocationStrategy {
+    /// Returns the number of a free locker that can hold
+    /// `required_capacity`, or `None` if there is no such locker.
+    fn choose_locker(
+        &mut self,
+        free_lockers: &FreeLockers,
+        required_capacity: Capacity,
+    ) -> Option<LockerNumber>;
+}
+
+/// Index of the free lockers in a cloakroom, kept up to date as lockers are
+/// allocated and vacated.
+///
+/// Free lockers are grouped by capacity. Within each group they are kept
+/// both in locker number order and in the order in which they were vacated.
+/// Free lockers with a size class are also grouped by it, in locker number
+/// order. A Fenwick tree over all lockers, ordered by capacity and then locker
+/// number, counts the free lockers before any point in that order.
+///
+/// With `k` distinct locker capacities every query takes `O(k log n)` time
+/// or better, and allocating or vacating a locker takes `O(log n)` time.
+/// Cloakrooms rarely have more than a handful of distinct capacities.
+pub struct FreeLockers {
+    capacities: Vec<Capacity>,
+    size_classes: Vec<Option<SizeClass>>,
+
+    by_number: BTreeMap<Capacity, BTreeSet<LockerNumber>>,
+    by_last_vacated: BTreeMap<Capacity, BTreeSet<(u64, LockerNumber)>>,
+    by_size_class: BTreeMap<SizeClass, BTreeSet<LockerNumber>>,
+
+    // Value of num_vacated when each locker was last vacated, or 0 if it has
+    // never been used.
+    num_vacated: u64,
+    last_vacated: Vec<u64>,
+
+    // Locker numbers in capacity order, the position of each locker in that
+    // order, and a Fenwick tree (indexed from 1) holding 1 for each position
+    // whose locker is free.
+    capacity_order: Vec<LockerNumber>,
+    positions: Vec<usize>,
+    fenwick: Vec<usize>,
+    num_free: usize,
+}
+
+impl FreeLockers {
+    /// Creates an index in which every locker is free.
+    pub(crate) fn new(lockers: &[LockerSpec]) -> FreeLockers {
+        let num_lockers = lockers.len();
+        let capacities: Vec<Capacity> = lockers.iter().map(LockerSpec::get_capacity).collect();
+
+        let mut capacity_order: Vec<LockerNumber> = (1..=num_lockers).collect();
+        capacity_order.sort_by_key(|&locker_number| capacities[locker_number - 1]);
+        let mut positions = vec![0; num_lockers];
+        for (index, &locker_number) in capacity_order.iter().enumerate() {
+            positions[locker_number - 1] = index + 1;
+        }
+
+        let mut free_lockers = FreeLockers {
+            capacities,
+            size_classes: lockers.iter().map(|spec| spec.size_class).collect(),
+            by_number: BTreeMap::new(),
+            by_last_vacated: BTreeMap::new(),
+            by_size_class: BTreeMap::new(),
+            num_vacated: 0,
+            last_vacated: vec![0; num_lockers],
+            capacity_order,
+            positions,
+            fenwick: vec![0; num_lockers + 1],
+            num_free: 0,
+        };
+        for locker_number in 1..=num_lockers {
+            free_lockers.insert(locker_number);
+        }
+        free_lockers
+    }
+
+    pub fn get_num_lockers(&self) -> usize {
+        self.capacities.len()
+    }
+
+    /// Returns the number of free lockers, whatever their capacity.
+    pub fn len(&self) -> usize {
+        self.num_free
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num_free == 0
+    }
+
+    pub fn is_free(&self, locker_number: LockerNumber) -> bool {
+        locker_number >= 1
+            && locker_number <= self.get_num_lockers()
+            && self
+                .by_number
+                .get(&self.capacities[locker_number - 1])
+                .is_some_and(|locker_numbers| locker_numbers.contains(&locker_number))
+    }
+
+    /// Returns the lowest numbered free locker with enough capacity.
+    pub fn first_fit(&self, required_capacity: Capacity) -> Option<LockerNumber> {
+        self.groups_by_number(required_capacity)
+            .filter_map(|(_, locker_numbers)| locker_numbers.iter().next())
+            .min()
+            .copied()
+    }
+
+    /// Returns the free locker with the smallest capacity that is enough,
+    /// comparing space first and then the weight limit. Ties are broken by
+    /// choosing the lowest numbered locker.
+    pub fn best_fit(&self, required_capacity: Capacity) -> Option<LockerNumber> {
+        // Empty groups are removed, so the first suitable group has a free
+        // locker.
+        let (_, locker_numbers) = self.groups_by_number(required_capacity).next()?;
+        locker_numbers.iter().next().copied()
+    }
+
+    /// Returns the free locker numbered within `locker_numbers` with the
+    /// smallest capacity that is enough, as [`FreeLockers::best_fit`] does.
+    pub fn best_fit_in_range(
+        &self,
+        locker_numbers: &RangeInclusive<LockerNumber>,
+        required_capacity: Capacity,
+    ) -> Option<LockerNumber> {
+        self.groups_by_number(required_capacity)
+            .find_map(|(_, free)| free.range(locker_numbers.clone()).next())
+            .copied()
+    }
+
+    /// Returns the first free locker with enough capacity whose number is
+    /// `start` or more, wrapping round to locker number 1 if there is none.
+    pub fn next_fit(
+        &self,
+        start: LockerNumber,
+        required_capacity: Capacity,
+    ) -> Option<LockerNumber> {
+        self.groups_by_number(required_capacity)
+            .filter_map(|(_, locker_numbers)| locker_numbers.range(start..).next())
+            .min()
+            .copied()
+            .or_else(|| self.first_fit(required_capacity))
+    }
+
+    /// Returns the free locker with enough capacity that was vacated the
+    /// longest time ago. Lockers that have never been used come first, and
+    /// ties are broken by choosing the lowest numbered locker.
+    pub fn least_recently_used(&self, required_capacity: Capacity) -> Option<LockerNumber> {
+        self.by_last_vacated
+            .range(min_capacity_in_range(required_capacity)..)
+            .filter(|(capacity, _)| capacity.can_hold(required_capacity))
+            .filter_map(|(_, lockers)| lockers.iter().next())
+            .min()
+            .map(|&(_, locker_number)| locker_number)
+    }
+
+    /// Returns the lowest numbered free locker of a size class.
+    pub fn first_of_size_class(&self, size_class: SizeClass) -> Option<LockerNumber> {
+        self.by_size_class
+            .get(&size_class)
+            .and_then(|locker_numbers| locker_numbers.iter().next())
+            .copied()
+    }
+
+    /// Returns the number of free lockers with enough capacity.
+    pub fn count_fit(&self, required_capacity: Capacity) -> usize {
+        self.groups_by_number(required_capacity)
+            .map(|(_, locker_numbers)| locker_numbers.len())
+            .sum()
+    }
+
+    /// Returns the free locker at position `index` (starting from 0) among
+    /// those with enough capacity, ordered from the smallest capacity to the
+    /// largest and then by locker number.
+    pub fn nth_fit(&self, required_capacity: Capacity, index: usize) -> Option<LockerNumber> {
+        // Find the group holding the locker. Each group occupies a run of
+        // positions in capacity order.
+        let mut index = index;
+        let (capacity, _) =
+            self.groups_by_number(required_capacity)
+                .find(|(_, locker_numbers)| {
+                    if index < locker_numbers.len() {
+                        true
+                    } else {
+                        index -= locker_numbers.len();
+                        false
+                    }
+                })?;
+        let rank = self.count_free_before(self.first_position(*capacity)) + index + 1;
+
+        // Find the lowest position at which rank free lockers have been seen.
+        let mut position = 0;
+        let mut remaining = rank;
+        let mut step = self.fenwick.len().next_power_of_two();
+        while step > 0 {
+            let next = position + step;
+            if next < self.fenwick.len() && self.fenwick[next] < remaining {
+                position = next;
+                remaining -= self.fenwick[next];
+            }
+            step /= 2;
+        }
+        Some(self.capacity_order[position])
+    }
+
+    /// Removes a free locker from the index when it is allocated.
+    pub(crate) fn remove(&mut self, locker_number: LockerNumber) {
+        let capacity = self.capacities[locker_number - 1];
+        let last_vacated = self.last_vacated[locker_number - 1];
+
+        let locker_numbers = match self.by_number.get_mut(&capacity) {
+            Some(locker_numbers) => locker_numbers,
+            None => return,
+        };
+        if !locker_numbers.remove(&locker_number) {
+            return;
+        }
+        if locker_numbers.is_empty() {
+            self.by_number.remove(&capacity);
+        }
+
+        if let Some(lockers) = self.by_last_vacated.get_mut(&capacity) {
+            lockers.remove(&(last_vacated, locker_number));
+            if lockers.is_empty() {
+                self.by_last_vacated.remove(&capacity);
+            }
+        }
+        if let Some(size_class) = self.size_classes[locker_number - 1] {
+            if let Some(locker_numbers) = self.by_size_class.get_mut(&size_class) {
+                locker_numbers.remove(&locker_number);
+                if locker_numbers.is_empty() {
+                    self.by_size_class.remove(&size_class);
+                }
+            }
+        }
+
+        self.num_free -= 1;
+        let position = self.positions[locker_number - 1];
+        self.fenwick_update(position, false);
+    }
+
+    /// Returns a locker to the index when it is vacated.
+    pub(crate) fn release(&mut self, locker_number: LockerNumber) {
+        if self.is_free(locker_number) {
+            return;
+        }
+        self.num_vacated += 1;
+        self.last_vacated[locker_number - 1] = self.num_vacated;
+        self.insert(locker_number);
+    }
+
+    /// Returns a locker that was held back to the index, keeping its place
+    /// in the order in which lockers were vacated.
+    pub(crate) fn reinstate(&mut self, locker_number: LockerNumber) {
+        if !self.is_free(locker_number) {
+            self.insert(locker_number);
+        }
+    }
+
+    fn insert(&mut self, locker_number: LockerNumber) {
+        let capacity = self.capacities[locker_number - 1];
+        self.by_number
+            .entry(capacity)
+            .or_default()
+            .insert(locker_number);
+        self.by_last_vacated
+            .entry(capacity)
+            .or_default()
+            .insert((self.last_vacated[locker_number - 1], locker_number));
+        if let Some(size_class) = self.size_classes[locker_number - 1] {
+            self.by_size_class
+                .entry(size_class)
+                .or_default()
+                .insert(locker_number);
+        }
+
+        self.num_free += 1;
+        let position = self.positions[locker_number - 1];
+        self.fenwick_update(position, true);
+    }
+
+    /// Returns the groups of free lockers with enough capacity, in capacity
+    /// order.
+    fn groups_by_number(
+        &self,
+        required_capacity: Capacity,
+    ) -> impl Iterator<Item = (&Capacity, &BTreeSet<LockerNumber>)> {
+        self.by_number
+            .range(min_capacity_in_range(required_capacity)..)
+            .filter(move |(capacity, _)| capacity.can_hold(required_capacity))
+    }
+
+    /// Returns the first position (starting from 1) in capacity order whose
+    /// locker has the given capacity or a larger one.
+    fn first_position(&self, capacity: Capacity) -> usize {
+        let capacities = &self.capacities;
+        self.capacity_order
+            .partition_point(|&locker_number| capacities[locker_number - 1] < capacity)
+            + 1
+    }
+
+    /// Returns the number of free lockers at positions before `position`.
+    fn count_free_before(&self, position: usize) -> usize {
+        let mut count = 0;
+        let mut index = position - 1;
+        while index > 0 {
+            count += self.fenwick[index];
+            index &= index - 1;
+        }
+        count
+    }
+
+    fn fenwick_update(&mut self, position: usize, free: bool) {
+        let mut index = position;
+        while index < self.fenwick.len() {
+            if free {
+                self.fenwick[index] += 1;
+            } else {
+                self.fenwick[index] -= 1;
+            }
+            index += index & index.wrapping_neg();
+        }
+    }
+}
+
+/// Returns the smallest capacity, in capacity order, that might be able to
+/// hold `required_capacity`. Capacities after it with too low a weight limit
+/// still have to be skipped.
+fn min_capacity_in_range(required_capacity: Capacity) -> Capacity {
+    Capacity {
+        space: required_capacity.space,
+        weight_grams: 0,
+    }
+}
+
+/// Always chooses the lowest numbered suitable locker. This is the default
+/// strategy.
+#[derive(Debug, Default)]
+pub struct FirstFit;
+
+impl AllocationStrategy for FirstFit {
+    fn choose_locker(
+        &mut self,
+        free_lockers: &FreeLockers,
+        required_capacity: Capacity,
+    ) -> Option<LockerNumber> {
+        free_lockers.first_fit(required_capacity)
+    }
+}
+
+/// Chooses the smallest suitable locker, keeping large lockers free for
+/// customers who need them.
+#[derive(Debug, Default)]
+pub struct BestFit;
+
+impl AllocationStrategy for BestFit {
+    fn choose_locker(
+        &mut self,
+        free_lockers: &FreeLockers,
+        required_capacity: Capacity,
+    ) -> Option<LockerNumber> {
+        free_lockers.best_fit(required_capacity)
+    }
+}
+
+/// Works through the lockers in turn, continuing from just after the last
+/// locker it chose, so that wear is spread across all of them.
+#[derive(Debug)]
+pub struct RoundRobin {
+    next: LockerNumber,
+}
+
+impl RoundRobin {
+    pub fn new() -> RoundRobin {
+        RoundRobin { next: 1 }
+    }
+}
+
+impl Default for RoundRobin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AllocationStrategy for RoundRobin {
+    fn choose_locker(
+        &mut self,
+        free_lockers: &FreeLockers,
+        required_capacity: Capacity,
+    ) -> Option<LockerNumber> {
+        let locker_number = free_lockers.next_fit(self.next, required_capacity)?;
+        self.next = locker_number + 1;
+        Some(locker_number)
+    }
+}
+
+/// Chooses the suitable locker that has been free for longest.
+#[derive(Debug, Default)]
+pub struct LeastRecentlyUsed;
+
+impl AllocationStrategy for LeastRecentlyUsed {
+    fn choose_locker(
+        &mut self,
+        free_lockers: &FreeLockers,
+        required_capacity: Capacity,
+    ) -> Option<LockerNumber> {
+        free_lockers.least_recently_used(required_capacity)
+    }
+}
+
+/// Chooses uniformly at random between the suitable lockers. The choices
+/// depend only on the seed and the state of the cloakroom, so runs can be
+/// reproduced.
+#[derive(Debug)]
+pub struct SeededRandom {
+    rng: Rng,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> SeededRandom {
+        SeededRandom {
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl AllocationStrategy for SeededRandom {
+    fn choose_locker(
+        &mut self,
+        free_lockers: &FreeLockers,
+        required_capacity: Capacity,
+    ) -> Option<LockerNumber> {
+        let count = free_lockers.count_fit(required_capacity);
+        if count == 0 {
+            return None;
+        }
+        let index = self.rng.below(count as u64) as usize;
+        free_lockers.nth_fit(required_capacity, index)
+    }
+}
+
+/// Chooses the smallest suitable locker in a preferred range of lockers,
+/// e.g. the [zone](super::zone) nearest the entrance, and only if there is
+/// none uses another strategy to choose between the rest.
+///
+/// # Examples
+///
+/// ```
+/// use cloakroom_model::cloakroom::allocation::{FirstFit, PreferZone};
+/// use cloakroom_model::cloakroom::zone::ZoneSpec;
+/// use cloakroom_model::cloakroom::{Cloakroom, LockerSpec};
+/// use cloakroom_model::items::CloakroomItems;
+///
+/// let cloakroom = Cloakroom::with_zones(vec![
+///     ZoneSpec::new("upstairs", "First floor", vec![LockerSpec::new(5); 2]),
+///     ZoneSpec::new("entrance", "By the front door", vec![LockerSpec::new(5)]),
+/// ])
+/// .unwrap();
+/// let entrance = cloakroom.get_zone("entrance").unwrap().get_locker_numbers();
+/// let mut cloakroom = cloakroom.with_allocation_strategy(PreferZone::new(entrance, FirstFit));
+///
+/// let no_items = CloakroomItems::new();
+/// let locker = cloakroom.find_free_locker(&no_items).into_result().unwrap();
+/// assert_eq!(locker.get_locker_number(), 3);
+/// let locker = cloakroom.find_free_locker(&no_items).into_result().unwrap();
+/// assert_eq!(locker.get_locker_number(), 1);
+/// ```
+#[derive(Debug)]
+pub struct PreferZone<S> {
+    locker_numbers: RangeInclusive<LockerNumber>,
+    fallback: S,
+}
+
+impl<S: AllocationStrategy> PreferZone<S> {
+    pub fn new(locker_numbers: RangeInclusive<LockerNumber>, fallback: S) -> PreferZone<S> {
+        PreferZone {
+            locker_numbers,
+            fallback,
+        }
+    }
+}
+
+impl<S: AllocationStrategy> AllocationStrategy for PreferZone<S> {
+    fn choose_locker(
+        &mut self,
+        free_lockers: &FreeLockers,
+        required_capacity: Capacity,
+    ) -> Option<LockerNumber> {
+        free_lockers
+            .best_fit_in_range(&self.locker_numbers, required_capacity)
+            .or_else(|| self.fallback.choose_locker(free_lockers, required_capacity))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cloakroom::allocation::FreeLockers;
+    use crate::cloakroom::{Capacity, LockerNumber, LockerSpec, SizeClass};
+    use crate::rng::Rng;
+
+    // Compares every query against a straightforward scan of the lockers.
+    #[test]
+    fn test1() {
+        let specs: Vec<LockerSpec> = [
+            (5, None),
+            (10, Some(4000)),
+            (5, None),
+            (20, None),
+            (10, None),
+            (5, Some(2000)),
+            (8, None),
+            (20, Some(4000)),
+            (5, None),
+            (10, Some(4000)),
+        ]
+        .iter()
+        .map(|&(max_space, max_weight_grams)| match max_weight_grams {
+            Some(max_weight_grams) => {
+                LockerSpec::new(max_space).with_max_weight_grams(max_weight_grams)
+            }
+            None => LockerSpec::new(max_space),
+        })
+        .enumerate()
+        .map(|(index, spec)| match index % 3 {
+            0 => spec.with_size_class(SizeClass::Small),
+            1 => spec.with_size_class(SizeClass::Large),
+            _ => spec,
+        })
+        .collect();
+        let mut free_lockers = FreeLockers::new(&specs);
+        let mut is_free = vec![true; specs.len()];
+        let mut last_vacated = vec![0; specs.len()];
+        let mut num_vacated = 0;
+        let mut rng = Rng::new(3);
+
+        let mut requirements = Vec::new();
+        for &space in &[0, 5, 6, 10, 15, 20, 21] {
+            for &weight_grams in &[0, 2000, 3000, 5000] {
+                requirements.push(Capacity {
+                    space,
+                    weight_grams,
+                });
+            }
+        }
+
+        for _ in 0..500 {
+            let locker_number = rng.below(specs.len() as u64) as LockerNumber + 1;
+            if is_free[locker_number - 1] {
+                free_lockers.remove(locker_number);
+            } else {
+                free_lockers.release(locker_number);
+                num_vacated += 1;
+                last_vacated[locker_number - 1] = num_vacated;
+            }
+            is_free[locker_number - 1] = !is_free[locker_number - 1];
+
+            for &required in &requirements {
+                let mut suitable: Vec<LockerNumber> = (1..=specs.len())
+                    .filter(|&n| is_free[n - 1] && specs[n - 1].get_capacity().can_hold(required))
+                    .collect();
+
+                assert_eq!(free_lockers.first_fit(required), suitable.first().copied());
+                assert_eq!(
+                    free_lockers.least_recently_used(required),
+                    suitable
+                        .iter()
+                        .copied()
+                        .min_by_key(|&n| last_vacated[n - 1])
+                );
+                let start = rng.below(specs.len() as u64 + 1) as LockerNumber + 1;
+                assert_eq!(
+                    free_lockers.next_fit(start, required),
+                    suitable
+                        .iter()
+                        .copied()
+                        .find(|&n| n >= start)
+                        .or_else(|| suitable.first().copied())
+                );
+
+                suitable.sort_by_key(|&n| specs[n - 1].get_capacity());
+                assert_eq!(free_lockers.best_fit(required), suitable.first().copied());
+                let zone = start..=start + 3;
+                assert_eq!(
+                    free_lockers.best_fit_in_range(&zone, required),
+                    suitable.iter().copied().find(|n| zone.contains(n))
+                );
+                assert_eq!(free_lockers.count_fit(required), suitable.len());
+                for index in 0..=suitable.len() {
+                    assert_eq!(
+                        free_lockers.nth_fit(required, index),
+                        suitable.get(index).copied()
+                    );
+                }
+            }
+
+            for &size_class in &[SizeClass::Small, SizeClass::Medium, SizeClass::Large] {
+                assert_eq!(
+                    free_lockers.first_of_size_class(size_class),
+                    (1..=specs.len())
+                        .find(|&n| is_free[n - 1] && specs[n - 1].size_class == Some(size_class))
+                );
+            }
+            assert_eq!(
+                free_lockers.len(),
+                is_free.iter().filter(|&&free| free).count()
+            );
+        }
+    }
+}
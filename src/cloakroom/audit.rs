@@ -0,0 +1,888 @@
+//! Append-only record of everything that happens to each locker.
+//!
+//! Every change to the state of a cloakroom's lockers is made by applying
+//! an [`AuditEvent`]: a locker is allocated, opened, closed, vacated,
+//! abandoned or reclaimed, its contents are changed, they are moved to lost
+//! property and later claimed or disposed of, they are transferred to
+//! another locker, or it is taken out of service and returned to service.
+//! A cloakroom given an [`AuditSink`] with [`Cloakroom::with_audit_sink`]
+//! sends it each event as it is applied. Each event carries the time at
+//! which it happened and the ID of the attendant on duty, if one has been
+//! set with [`Cloakroom::set_attendant_id`]. The sink is not saved with the
+//! cloakroom.
+//!
+//! Since the events are the only way in which the lockers change, the state
+//! of a cloakroom can be rebuilt from them with [`Cloakroom::replay`], up to
+//! any point in time. Bookings that are kept alongside the lockers, namely
+//! [reservations](super::reservation) and the [queue](super::queue), are
+//! not events and are not rebuilt.
+
+use super::{Cloakroom, LockerNumber};
+use crate::items::{CloakroomItems, ItemCatalogue};
+use crate::json::{self, Value};
+use crate::lost_property::{ClaimReference, IntakeReason};
+use crate::statistics::StatisticsCollector;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// What happened to a locker.
+///
+/// Events that take a locker out of having its contents changed (`Closed`,
+/// `Vacated` and `Abandoned`) are preceded by a `ContentsChanged` event
+/// whenever its contents differ from when it was handed out, so their items
+/// always match the contents recorded by the events before them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditAction {
+    /// The locker was found free and handed out, empty.
+    Allocated,
+    /// The locker was opened with its key.
+    Opened,
+    /// The locker was opened with the lost-key procedure.
+    OpenedWithoutKey { fee_pence: Option<u32> },
+    /// The contents of the locker were changed while it was open.
+    ContentsChanged {
+        before: CloakroomItems,
+        after: CloakroomItems,
+    },
+    /// The locker was closed holding `items`.
+    Closed { items: CloakroomItems },
+    /// `items` were taken out of the locker, leaving it free.
+    Vacated { items: CloakroomItems },
+    /// The locker was found to have been abandoned while holding `items`.
+    Abandoned { items: CloakroomItems },
+    /// The abandoned locker was reclaimed.
+    Reclaimed,
+    /// `items` were moved to the lost-property store under `description`,
+    /// leaving the locker free, because it was overdue or forced open.
+    MovedToLostProperty {
+        items: CloakroomItems,
+        claim_reference: ClaimReference,
+        reason: IntakeReason,
+        description: String,
+    },
+    /// The items moved out of the locker under `claim_reference` were
+    /// handed back to the customer.
+    ClaimRedeemed { claim_reference: ClaimReference },
+    /// The items moved out of the locker under `claim_reference` were
+    /// disposed of, having been held for longer than the retention period.
+    LostPropertyDisposed { claim_reference: ClaimReference },
+    /// `items` were transferred into the free locker from locker number
+    /// `from`, and it was closed with a new token.
+    TransferredIn {
+        from: LockerNumber,
+        items: CloakroomItems,
+    },
+    /// `items` were transferred out of the locker into locker number `to`,
+    /// leaving it free.
+    TransferredOut {
+        to: LockerNumber,
+        items: CloakroomItems,
+    },
+    /// The free locker was taken out of service for `reason`.
+    TakenOutOfService { reason: String },
+    /// The locker was returned to service, leaving it free.
+    ReturnedToService,
+}
+
+impl AuditAction {
+    /// Returns the name used for the action in JSON.
+    pub(super) fn name(&self) -> &'static str {
+        match self {
+            AuditAction::Allocated => "allocated",
+            AuditAction::Opened => "opened",
+            AuditAction::OpenedWithoutKey { .. } => "opened_without_key",
+            AuditAction::ContentsChanged { .. } => "contents_changed",
+            AuditAction::Closed { .. } => "closed",
+            AuditAction::Vacated { .. } => "vacated",
+            AuditAction::Abandoned { .. } => "abandoned",
+            AuditAction::Reclaimed => "reclaimed",
+            AuditAction::MovedToLostProperty { .. } => "moved_to_lost_property",
+            AuditAction::ClaimRedeemed { .. } => "claim_redeemed",
+            AuditAction::LostPropertyDisposed { .. } => "lost_property_disposed",
+            AuditAction::TransferredIn { .. } => "transferred_in",
+            AuditAction::TransferredOut { .. } => "transferred_out",
+            AuditAction::TakenOutOfService { .. } => "taken_out_of_service",
+            AuditAction::ReturnedToService => "returned_to_service",
+        }
+    }
+}
+
+impl fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditAction::Allocated => write!(f, "allocated"),
+            AuditAction::Opened => write!(f, "opened"),
+            AuditAction::OpenedWithoutKey { fee_pence: None } => {
+                write!(f, "opened without key")
+            }
+            AuditAction::OpenedWithoutKey {
+                fee_pence: Some(fee_pence),
+            } => write!(f, "opened without key (fee {}p)", fee_pence),
+            AuditAction::ContentsChanged { before, after } => {
+                write!(f, "contents changed from ({}) to ({})", before, after)
+            }
+            AuditAction::Closed { items } => write!(f, "closed holding ({})", items),
+            AuditAction::Vacated { items } => write!(f, "vacated, removing ({})", items),
+            AuditAction::Abandoned { items } => write!(f, "abandoned holding ({})", items),
+            AuditAction::Reclaimed => write!(f, "reclaimed"),
+            AuditAction::MovedToLostProperty {
+                items,
+                claim_reference,
+                reason,
+                description,
+            } => {
+                let reason = match reason {
+                    IntakeReason::Overdue => "overdue",
+                    IntakeReason::ForcedOpen => "forced open",
+                    IntakeReason::Found => "found",
+                };
+                write!(
+                    f,
+                    "{}, moved to lost property as {} holding ({})",
+                    reason, claim_reference, items
+                )?;
+                if !description.is_empty() {
+                    write!(f, ": {}", description)?;
+                }
+                Ok(())
+            }
+            AuditAction::ClaimRedeemed { claim_reference } => {
+                write!(f, "lost property {} claimed", claim_reference)
+            }
+            AuditAction::LostPropertyDisposed { claim_reference } => {
+                write!(f, "lost property {} disposed of", claim_reference)
+            }
+            AuditAction::TransferredIn { from, items } => write!(
+                f,
+                "transferred in from locker number {} holding ({})",
+                from, items
+            ),
+            AuditAction::TransferredOut { to, items } => write!(
+                f,
+                "transferred out to locker number {} holding ({})",
+                to, items
+            ),
+            AuditAction::TakenOutOfService { reason } => {
+                write!(f, "taken out of service: {}", reason)
+            }
+            AuditAction::ReturnedToService => write!(f, "returned to service"),
+        }
+    }
+}
+
+/// Something that happened to a locker, and when.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEvent {
+    timestamp: SystemTime,
+    locker_number: LockerNumber,
+    attendant_id: Option<String>,
+    action: AuditAction,
+}
+
+impl AuditEvent {
+    pub fn new(
+        timestamp: SystemTime,
+        locker_number: LockerNumber,
+        attendant_id: Option<String>,
+        action: AuditAction,
+    ) -> AuditEvent {
+        AuditEvent {
+            timestamp,
+            locker_number,
+            attendant_id,
+            action,
+        }
+    }
+
+    pub fn get_timestamp(&self) -> SystemTime {
+        self.timestamp
+    }
+
+    pub fn get_locker_number(&self) -> LockerNumber {
+        self.locker_number
+    }
+
+    pub fn get_attendant_id(&self) -> Option<&str> {
+        self.attendant_id.as_deref()
+    }
+
+    pub fn get_action(&self) -> &AuditAction {
+        &self.action
+    }
+
+    /// Returns the event as a single line of JSON, without a line break.
+    /// Timestamps are written as milliseconds since the Unix epoch.
+    ///
+    /// ```text
+    /// {"timestamp_ms":1700000000000,"locker_number":3,"attendant_id":"ann","action":"closed","items":{"coats":1,...}}
+    /// ```
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            "{{\"timestamp_ms\":{},\"locker_number\":{},\"attendant_id\":{},\"action\":{}",
+            timestamp_ms(self.timestamp),
+            self.locker_number,
+            match &self.attendant_id {
+                Some(attendant_id) => json::quote(attendant_id),
+                None => "null".to_string(),
+            },
+            json::quote(self.action.name())
+        );
+        match &self.action {
+            AuditAction::OpenedWithoutKey { fee_pence } => {
+                let fee_pence = match fee_pence {
+                    Some(fee_pence) => fee_pence.to_string(),
+                    None => "null".to_string(),
+                };
+                json.push_str(&format!(",\"fee_pence\":{}", fee_pence));
+            }
+            AuditAction::ContentsChanged { before, after } => {
+                json.push_str(&format!(
+                    ",\"before\":{},\"after\":{}",
+                    items_to_json(before),
+                    items_to_json(after)
+                ));
+            }
+            AuditAction::Closed { items }
+            | AuditAction::Vacated { items }
+            | AuditAction::Abandoned { items } => {
+                json.push_str(&format!(",\"items\":{}", items_to_json(items)));
+            }
+            AuditAction::MovedToLostProperty {
+                items,
+                claim_reference,
+                reason,
+                description,
+            } => {
+                json.push_str(&format!(
+                    ",\"items\":{},\"claim_reference\":{},\"reason\":{},\"description\":{}",
+                    items_to_json(items),
+                    json::quote(&claim_reference.to_string()),
+                    json::quote(&reason.to_string()),
+                    json::quote(description)
+                ));
+            }
+            AuditAction::ClaimRedeemed { claim_reference }
+            | AuditAction::LostPropertyDisposed { claim_reference } => {
+                json.push_str(&format!(
+                    ",\"claim_reference\":{}",
+                    json::quote(&claim_reference.to_string())
+                ));
+            }
+            AuditAction::TransferredIn { from, items } => {
+                json.push_str(&format!(
+                    ",\"from\":{},\"items\":{}",
+                    from,
+                    items_to_json(items)
+                ));
+            }
+            AuditAction::TransferredOut { to, items } => {
+                json.push_str(&format!(
+                    ",\"to\":{},\"items\":{}",
+                    to,
+                    items_to_json(items)
+                ));
+            }
+            AuditAction::TakenOutOfService { reason } => {
+                json.push_str(&format!(",\"reason\":{}", json::quote(reason)));
+            }
+            AuditAction::Allocated
+            | AuditAction::Opened
+            | AuditAction::Reclaimed
+            | AuditAction::ReturnedToService => (),
+        }
+        json.push('}');
+        json
+    }
+
+    /// Reads an event written by [`AuditEvent::to_json`]. The items are
+    /// given categories from `catalogue`.
+    pub fn from_json(line: &str, catalogue: &Arc<ItemCatalogue>) -> Result<AuditEvent, String> {
+        let value = json::parse(line)?;
+        let timestamp_ms = get_u64(&value, "timestamp_ms")?;
+        let locker_number = get_u64(&value, "locker_number")? as LockerNumber;
+        let attendant_id = match value.get("attendant_id") {
+            Some(Value::String(attendant_id)) => Some(attendant_id.clone()),
+            Some(Value::Null) | None => None,
+            Some(_) => return Err("'attendant_id' is not a string".to_string()),
+        };
+        let get_items = |name| match value.get(name) {
+            Some(items) => items_from_json(items, catalogue),
+            None => Err(format!("missing '{}'", name)),
+        };
+        let get_claim_reference = || {
+            value
+                .get("claim_reference")
+                .and_then(Value::as_str)
+                .and_then(|claim_reference| claim_reference.parse().ok())
+                .ok_or("invalid 'claim_reference'")
+        };
+
+        let action = match value.get("action").and_then(Value::as_str) {
+            Some("allocated") => AuditAction::Allocated,
+            Some("opened") => AuditAction::Opened,
+            Some("opened_without_key") => AuditAction::OpenedWithoutKey {
+                fee_pence: match value.get("fee_pence") {
+                    Some(Value::Null) | None => None,
+                    Some(fee_pence) => Some(
+                        fee_pence
+                            .as_u64()
+                            .and_then(|fee_pence| u32::try_from(fee_pence).ok())
+                            .ok_or("invalid 'fee_pence'")?,
+                    ),
+                },
+            },
+            Some("contents_changed") => AuditAction::ContentsChanged {
+                before: get_items("before")?,
+                after: get_items("after")?,
+            },
+            Some("closed") => AuditAction::Closed {
+                items: get_items("items")?,
+            },
+            Some("vacated") => AuditAction::Vacated {
+                items: get_items("items")?,
+            },
+            Some("abandoned") => AuditAction::Abandoned {
+                items: get_items("items")?,
+            },
+            Some("reclaimed") => AuditAction::Reclaimed,
+            Some("moved_to_lost_property") => AuditAction::MovedToLostProperty {
+                items: get_items("items")?,
+                claim_reference: get_claim_reference()?,
+                reason: value
+                    .get("reason")
+                    .and_then(Value::as_str)
+                    .and_then(|reason| reason.parse().ok())
+                    .ok_or("invalid 'reason'")?,
+                description: value
+                    .get("description")
+                    .and_then(Value::as_str)
+                    .ok_or("invalid 'description'")?
+                    .to_string(),
+            },
+            Some("claim_redeemed") => AuditAction::ClaimRedeemed {
+                claim_reference: get_claim_reference()?,
+            },
+            Some("lost_property_disposed") => AuditAction::LostPropertyDisposed {
+                claim_reference: get_claim_reference()?,
+            },
+            Some("transferred_in") => AuditAction::TransferredIn {
+                from: get_u64(&value, "from")? as LockerNumber,
+                items: get_items("items")?,
+            },
+            Some("transferred_out") => AuditAction::TransferredOut {
+                to: get_u64(&value, "to")? as LockerNumber,
+                items: get_items("items")?,
+            },
+            Some("taken_out_of_service") => AuditAction::TakenOutOfService {
+                reason: value
+                    .get("reason")
+                    .and_then(Value::as_str)
+                    .ok_or("invalid 'reason'")?
+                    .to_string(),
+            },
+            Some("returned_to_service") => AuditAction::ReturnedToService,
+            Some(other) => return Err(format!("unknown action '{}'", other)),
+            None => return Err("missing 'action'".to_string()),
+        };
+
+        Ok(AuditEvent {
+            timestamp: UNIX_EPOCH + Duration::from_millis(timestamp_ms),
+            locker_number,
+            attendant_id,
+            action,
+        })
+    }
+}
+
+impl fmt::Display for AuditEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} locker number {} {}",
+            format_timestamp(self.timestamp),
+            self.locker_number,
+            self.action
+        )?;
+        if let Some(attendant_id) = &self.attendant_id {
+            write!(f, " [attendant {}]", attendant_id)?;
+        }
+        Ok(())
+    }
+}
+
+fn timestamp_ms(timestamp: SystemTime) -> u64 {
+    timestamp
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+/// Formats a timestamp as a UTC date and time, e.g. `2024-03-01 09:30:00`.
+fn format_timestamp(timestamp: SystemTime) -> String {
+    let secs = timestamp_ms(timestamp) / 1000;
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+
+    // Converts days since 1970-01-01 to a civil date, counting in 400-year
+    // eras that start on 1 March so that leap days fall at the end.
+    let days = days as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+fn items_to_json(items: &CloakroomItems) -> String {
+    let counts: Vec<String> = items
+        .iter()
+        .map(|(category, num_items)| format!("{}:{}", json::quote(category.get_name()), num_items))
+        .collect();
+    format!("{{{}}}", counts.join(","))
+}
+
+fn items_from_json(
+    value: &Value,
+    catalogue: &Arc<ItemCatalogue>,
+) -> Result<CloakroomItems, String> {
+    let members = match value {
+        Value::Object(members) => members,
+        _ => return Err("items are not an object".to_string()),
+    };
+    let mut items = CloakroomItems::from_catalogue(catalogue.clone());
+    for (name, num_items) in members {
+        let num_items = num_items
+            .as_u64()
+            .and_then(|num_items| u8::try_from(num_items).ok())
+            .ok_or_else(|| format!("invalid number of {}", name))?;
+        items
+            .set_num_items(name, num_items)
+            .map_err(|err| err.to_string())?;
+    }
+    Ok(items)
+}
+
+fn get_u64(value: &Value, name: &str) -> Result<u64, String> {
+    value
+        .get(name)
+        .and_then(Value::as_u64)
+        .ok_or_else(|| format!("missing or invalid '{}'", name))
+}
+
+/// Destination for audit events.
+pub trait AuditSink {
+    /// Appends an event to the log.
+    fn record(&mut self, event: &AuditEvent) -> io::Result<()>;
+
+    /// Returns every event logged, oldest first. `catalogue` is the
+    /// cloakroom's item catalogue, for sinks that need to read items back
+    /// from storage.
+    fn events(&self, catalogue: &Arc<ItemCatalogue>) -> io::Result<Vec<AuditEvent>>;
+
+    /// Returns the events logged for a locker, oldest first.
+    fn history(
+        &self,
+        locker_number: LockerNumber,
+        catalogue: &Arc<ItemCatalogue>,
+    ) -> io::Result<Vec<AuditEvent>> {
+        let mut events = self.events(catalogue)?;
+        events.retain(|event| event.locker_number == locker_number);
+        Ok(events)
+    }
+}
+
+/// Reads events written one per line by [`AuditEvent::to_json`], e.g. from
+/// the file of a [`JsonLinesAuditSink`]. Blank lines are skipped.
+pub fn read_events<R: BufRead>(
+    reader: R,
+    catalogue: &Arc<ItemCatalogue>,
+) -> io::Result<Vec<AuditEvent>> {
+    let mut events = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event = AuditEvent::from_json(&line, catalogue).map_err(|message| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", index + 1, message),
+            )
+        })?;
+        events.push(event);
+    }
+    Ok(events)
+}
+
+/// Keeps events in memory, for tests and short-lived cloakrooms.
+#[derive(Debug, Default)]
+pub struct InMemoryAuditSink {
+    events: Vec<AuditEvent>,
+}
+
+impl InMemoryAuditSink {
+    pub fn new() -> InMemoryAuditSink {
+        InMemoryAuditSink::default()
+    }
+}
+
+impl AuditSink for InMemoryAuditSink {
+    fn record(&mut self, event: &AuditEvent) -> io::Result<()> {
+        self.events.push(event.clone());
+        Ok(())
+    }
+
+    fn events(&self, _catalogue: &Arc<ItemCatalogue>) -> io::Result<Vec<AuditEvent>> {
+        Ok(self.events.clone())
+    }
+
+    fn history(
+        &self,
+        locker_number: LockerNumber,
+        _catalogue: &Arc<ItemCatalogue>,
+    ) -> io::Result<Vec<AuditEvent>> {
+        Ok(self
+            .events
+            .iter()
+            .filter(|event| event.locker_number == locker_number)
+            .cloned()
+            .collect())
+    }
+}
+
+/// Appends events to a file, one line of JSON per event, as written by
+/// [`AuditEvent::to_json`]. Each event is written with a single write, so an
+/// interrupted program loses at most the event it was writing.
+#[derive(Debug)]
+pub struct JsonLinesAuditSink {
+    path: PathBuf,
+    file: File,
+}
+
+impl JsonLinesAuditSink {
+    /// Opens the log in `path` for appending, creating it if there is none.
+    pub fn open(path: &Path) -> io::Result<JsonLinesAuditSink> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(JsonLinesAuditSink {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+}
+
+impl AuditSink for JsonLinesAuditSink {
+    fn record(&mut self, event: &AuditEvent) -> io::Result<()> {
+        let mut line = event.to_json();
+        line.push('\n');
+        self.file.write_all(line.as_bytes())
+    }
+
+    fn events(&self, catalogue: &Arc<ItemCatalogue>) -> io::Result<Vec<AuditEvent>> {
+        let reader = BufReader::new(File::open(&self.path)?);
+        read_events(reader, catalogue)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}, {}", self.path.display(), err)))
+    }
+}
+
+/// Where a cloakroom records the events applied to its lockers: its audit
+/// sink and its statistics. It is kept apart from the rest of the
+/// cloakroom so that the desks of a [shared](super::shared) cloakroom can
+/// record the lockers they close and open without locking the rest.
+pub(super) struct Journal {
+    pub(super) audit_sink: Option<Box<dyn AuditSink + Send>>,
+
+    // The first error met while sending events to the audit sink since it
+    // was last taken.
+    pub(super) audit_error: Option<io::Error>,
+
+    pub(super) statistics: Option<(SystemTime, StatisticsCollector)>,
+}
+
+impl Journal {
+    pub(super) fn new() -> Journal {
+        Journal {
+            audit_sink: None,
+            audit_error: None,
+            statistics: None,
+        }
+    }
+
+    /// Returns every event logged, oldest first, or no events if there is
+    /// no audit sink.
+    pub(super) fn events(
+        &self,
+        item_catalogue: &Arc<ItemCatalogue>,
+    ) -> io::Result<Vec<AuditEvent>> {
+        match &self.audit_sink {
+            Some(audit_sink) => audit_sink.events(item_catalogue),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Returns the events logged for a locker, oldest first, or no events
+    /// if there is no audit sink.
+    pub(super) fn history(
+        &self,
+        locker_number: LockerNumber,
+        item_catalogue: &Arc<ItemCatalogue>,
+    ) -> io::Result<Vec<AuditEvent>> {
+        match &self.audit_sink {
+            Some(audit_sink) => audit_sink.history(locker_number, item_catalogue),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Records an event that has been applied to a locker.
+    pub(super) fn record(
+        &mut self,
+        timestamp: SystemTime,
+        locker_number: LockerNumber,
+        attendant_id: Option<&str>,
+        action: AuditAction,
+        item_catalogue: &Arc<ItemCatalogue>,
+    ) {
+        if let Some((started_at, collector)) = &mut self.statistics {
+            let minutes = super::minutes_since(*started_at, timestamp);
+            match &action {
+                AuditAction::Allocated => {
+                    let no_items = CloakroomItems::from_catalogue(item_catalogue.clone());
+                    collector.record_allocated(minutes, locker_number, &no_items);
+                }
+                AuditAction::ContentsChanged { before, after } => {
+                    collector.record_contents_changed(before, after)
+                }
+                AuditAction::TransferredIn { from, .. } => {
+                    collector.record_transferred(minutes, *from, locker_number)
+                }
+                AuditAction::Vacated { .. } | AuditAction::MovedToLostProperty { .. } => {
+                    collector.record_vacated(minutes, locker_number)
+                }
+                _ => (),
+            }
+        }
+
+        if let Some(audit_sink) = &mut self.audit_sink {
+            let event = AuditEvent::new(
+                timestamp,
+                locker_number,
+                attendant_id.map(str::to_string),
+                action,
+            );
+            if let Err(err) = audit_sink.record(&event) {
+                self.audit_error.get_or_insert(err);
+            }
+        }
+    }
+}
+
+impl Cloakroom {
+    /// Sends an audit event to `audit_sink` for everything that happens to
+    /// the cloakroom's lockers from now on.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cloakroom_model::cloakroom::audit::{AuditAction, InMemoryAuditSink};
+    /// use cloakroom_model::cloakroom::Cloakroom;
+    /// use cloakroom_model::items::CloakroomItems;
+    ///
+    /// let mut cloakroom = Cloakroom::new(2, 5).with_audit_sink(InMemoryAuditSink::new());
+    /// cloakroom.set_attendant_id(Some("ann"));
+    ///
+    /// let mut locker = cloakroom.find_free_locker(&CloakroomItems::new()).into_result().unwrap();
+    /// locker.set_num_items("coats", 1).unwrap();
+    /// let _key = cloakroom.close_locker(locker).unwrap();
+    ///
+    /// let history = cloakroom.get_locker_history(1).unwrap();
+    /// assert_eq!(history.len(), 3);
+    /// assert_eq!(history[0].get_action(), &AuditAction::Allocated);
+    /// assert!(matches!(history[1].get_action(), AuditAction::ContentsChanged { .. }));
+    /// assert_eq!(history[2].get_attendant_id(), Some("ann"));
+    /// ```
+    pub fn with_audit_sink<S>(self, audit_sink: S) -> Cloakroom
+    where
+        S: AuditSink + Send + 'static,
+    {
+        super::lock(&self.journal).audit_sink = Some(Box::new(audit_sink));
+        self
+    }
+
+    /// Sets the ID of the attendant on duty, which is recorded with every
+    /// audit event until it is changed.
+    pub fn set_attendant_id(&mut self, attendant_id: Option<&str>) {
+        self.attendant_id = attendant_id.map(str::to_string);
+    }
+
+    pub fn get_attendant_id(&self) -> Option<&str> {
+        self.attendant_id.as_deref()
+    }
+
+    /// Returns every event logged, oldest first, or no events if the
+    /// cloakroom has no audit sink.
+    pub fn get_audit_events(&self) -> io::Result<Vec<AuditEvent>> {
+        super::lock(&self.journal).events(&self.item_catalogue)
+    }
+
+    /// Returns the events logged for a locker, oldest first, or no events
+    /// if the cloakroom has no audit sink.
+    pub fn get_locker_history(&self, locker_number: LockerNumber) -> io::Result<Vec<AuditEvent>> {
+        super::lock(&self.journal).history(locker_number, &self.item_catalogue)
+    }
+
+    /// Returns, and clears, the first error met while sending events to the
+    /// audit sink since the last call. The cloakroom carries on when an
+    /// event cannot be logged, since the change to the locker has already
+    /// been made.
+    pub fn take_audit_error(&mut self) -> Option<io::Error> {
+        super::lock(&self.journal).audit_error.take()
+    }
+
+    /// Applies an event to a locker whose state has already been checked,
+    /// sending it to the audit sink first.
+    pub(super) fn emit(&mut self, locker_number: LockerNumber, action: AuditAction) {
+        self.emit_at(self.now(), locker_number, action);
+    }
+
+    /// Emits an event that happened at `timestamp`. The event is applied
+    /// before it is sent to the audit sink, so an event that the locker is
+    /// not in a state for is neither applied nor logged. Callers check the
+    /// locker's state first, so this only happens if the cloakroom has a
+    /// bug.
+    pub(super) fn emit_at(
+        &mut self,
+        timestamp: SystemTime,
+        locker_number: LockerNumber,
+        action: AuditAction,
+    ) {
+        let applied = self.apply(timestamp, locker_number, &action);
+        debug_assert!(applied.is_ok(), "invalid event: {:?}", applied);
+        if applied.is_err() {
+            return;
+        }
+
+        super::lock(&self.journal).record(
+            timestamp,
+            locker_number,
+            self.attendant_id.as_deref(),
+            action,
+            &self.item_catalogue,
+        );
+    }
+
+    /// Emits a `ContentsChanged` event if `items` differ from the contents
+    /// recorded for a locker that is being changed.
+    pub(super) fn emit_contents_change(
+        &mut self,
+        locker_number: LockerNumber,
+        items: &CloakroomItems,
+    ) {
+        if let Some(mut access) = self.locker_access(locker_number) {
+            access.emit_contents_change(items);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cloakroom::audit::{format_timestamp, AuditAction, AuditEvent};
+    use crate::items::{self, CloakroomItems};
+    use crate::lost_property::IntakeReason;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test1() {
+        let catalogue = items::default_catalogue();
+        let mut before = CloakroomItems::from_catalogue(catalogue.clone());
+        before.set_num_items("coats", 1).unwrap();
+        let mut after = before.clone();
+        after.set_num_items("umbrellas", 2).unwrap();
+
+        let timestamp = UNIX_EPOCH + Duration::from_millis(1_709_285_400_123);
+        for (attendant_id, action) in [
+            (Some("ann".to_string()), AuditAction::Allocated),
+            (None, AuditAction::OpenedWithoutKey { fee_pence: None }),
+            (None, AuditAction::OpenedWithoutKey { fee_pence: Some(5) }),
+            (
+                None,
+                AuditAction::MovedToLostProperty {
+                    items: before.clone(),
+                    claim_reference: "C000012".parse().unwrap(),
+                    reason: IntakeReason::ForcedOpen,
+                    description: "blue \"Acme\" coat".to_string(),
+                },
+            ),
+            (None, AuditAction::Reclaimed),
+            (
+                None,
+                AuditAction::TakenOutOfService {
+                    reason: "door\nsticks".to_string(),
+                },
+            ),
+            (None, AuditAction::ReturnedToService),
+            (
+                None,
+                AuditAction::TransferredOut {
+                    to: 3,
+                    items: before.clone(),
+                },
+            ),
+            (
+                None,
+                AuditAction::TransferredIn {
+                    from: 1,
+                    items: after.clone(),
+                },
+            ),
+            (
+                Some("bo \"b\"".to_string()),
+                AuditAction::ContentsChanged { before, after },
+            ),
+            (
+                None,
+                AuditAction::LostPropertyDisposed {
+                    claim_reference: "C000012".parse().unwrap(),
+                },
+            ),
+        ] {
+            let event = AuditEvent::new(timestamp, 7, attendant_id, action);
+            let json = event.to_json();
+            assert!(!json.contains('\n'));
+            assert_eq!(AuditEvent::from_json(&json, &catalogue), Ok(event));
+        }
+
+        assert_eq!(format_timestamp(timestamp), "2024-03-01 09:30:00");
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01 00:00:00");
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "2000-02-29 00:00:00"
+        );
+
+        assert!(AuditEvent::from_json("{\"timestamp_ms\":0}", &catalogue).is_err());
+        assert!(AuditEvent::from_json(
+            "{\"timestamp_ms\":0,\"locker_number\":1,\"action\":\"closed\",\"items\":{\"hats\":1}}",
+            &catalogue
+        )
+        .is_err());
+    }
+}
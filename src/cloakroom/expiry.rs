@@ -0,0 +1,296 @@
+//! Expiry of lockers that have been closed for too long, and moving the
+//! contents of lockers to lost property.
+//!
+//! A cloakroom can be given a maximum storage duration. A closed locker is
+//! overdue once that long has passed since it was deposited in, and an
+//! attendant can then move its contents to the cloakroom's
+//! [lost-property store](crate::lost_property), freeing the locker for other
+//! customers. An attendant holding the admin credential can also force open
+//! any closed locker and move its contents to the store in the same way.
+//! The contents are kept under a claim reference until the customer redeems
+//! the claim with their old key, which no longer opens the locker.
+//!
+//! Bundles taken out of lockers are claimed and disposed of through the
+//! cloakroom, so that their audit trail is complete. Items found in the
+//! cloakroom are handed in to the store without an audit event, as they do
+//! not belong to a locker.
+
+use super::audit::AuditAction;
+use super::lost_key::AdminCredential;
+use super::{
+    unknown_item_category, Cloakroom, CloakroomError, Key, LockerInUseState, LockerNumber,
+};
+use crate::items::CloakroomItems;
+use crate::lost_property::{
+    Bundle, ClaimReference, IntakeReason, LostPropertyError, LostPropertyStore,
+};
+use crate::pricing::Invoice;
+use std::time::Duration;
+
+impl Cloakroom {
+    /// Sets how long a locker can stay closed, from when it was deposited
+    /// in, before it is overdue. Lockers are never overdue unless this is
+    /// called. The duration is not saved with the cloakroom.
+    pub fn with_max_storage_duration(self, max_storage_duration: Duration) -> Cloakroom {
+        Cloakroom {
+            max_storage_duration: Some(max_storage_duration),
+            ..self
+        }
+    }
+
+    pub fn get_max_storage_duration(&self) -> Option<Duration> {
+        self.max_storage_duration
+    }
+
+    /// Returns the numbers of the closed lockers that are overdue, in
+    /// order.
+    pub fn get_overdue_lockers(&self) -> Vec<LockerNumber> {
+        (1..=self.get_num_lockers())
+            .filter(|&locker_number| {
+                self.with_state(locker_number, |state| {
+                    matches!(state, Some(LockerInUseState::Closed { .. }))
+                }) && self.is_overdue(locker_number)
+            })
+            .collect()
+    }
+
+    /// Moves the contents of an overdue locker to the lost-property store,
+    /// as described in the [module documentation](self), and frees the
+    /// locker. Returns the claim reference under which they are kept.
+    /// Nothing is given as their description.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cloakroom_model::clock::MockClock;
+    /// use cloakroom_model::cloakroom::Cloakroom;
+    /// use cloakroom_model::items::CloakroomItems;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// let clock = MockClock::new(UNIX_EPOCH);
+    /// let mut cloakroom = Cloakroom::new(2, 5)
+    ///     .with_clock(clock.clone())
+    ///     .with_max_storage_duration(Duration::from_secs(12 * 60 * 60));
+    /// let mut locker = cloakroom.find_free_locker(&CloakroomItems::new()).into_result().unwrap();
+    /// locker.set_num_items("coats", 1).unwrap();
+    /// let key = cloakroom.close_locker(locker).unwrap();
+    ///
+    /// clock.advance(Duration::from_secs(13 * 60 * 60));
+    /// assert_eq!(cloakroom.get_overdue_lockers(), vec![1]);
+    /// let claim_reference = cloakroom.move_to_lost_property(1).unwrap();
+    /// assert_eq!(claim_reference.to_string(), "C000001");
+    ///
+    /// // The key no longer opens the locker, but redeems the claim.
+    /// assert_eq!(
+    ///     cloakroom.find_claim(&key).map(|claim| claim.get_claim_reference()),
+    ///     Some(claim_reference)
+    /// );
+    /// let (items, _invoice) = cloakroom.redeem_claim(key).unwrap();
+    /// assert_eq!(items.get_num_items("coats"), Some(1));
+    /// ```
+    pub fn move_to_lost_property(
+        &mut self,
+        locker_number: LockerNumber,
+    ) -> Result<ClaimReference, CloakroomError> {
+        self.check_closed(locker_number)?;
+        if !self.is_overdue(locker_number) {
+            return Err(CloakroomError::LockerNotOverdue(locker_number));
+        }
+        Ok(self.take_to_lost_property(locker_number, IntakeReason::Overdue, ""))
+    }
+
+    /// Forces open a closed locker, whether or not it is overdue, and moves
+    /// its contents to the lost-property store under `description`, freeing
+    /// the locker. Returns the claim reference under which they are kept.
+    pub fn force_open_to_lost_property(
+        &mut self,
+        locker_number: LockerNumber,
+        admin_credential: &AdminCredential,
+        description: &str,
+    ) -> Result<ClaimReference, CloakroomError> {
+        self.check_admin_credential(admin_credential)?;
+        self.check_closed(locker_number)?;
+        Ok(self.take_to_lost_property(locker_number, IntakeReason::ForcedOpen, description))
+    }
+
+    /// Sets how many days bundles are kept in the lost-property store before
+    /// they are due for disposal. Nothing is ever due for disposal unless
+    /// this is called. The retention period is not saved with the
+    /// cloakroom.
+    pub fn with_lost_property_retention_days(self, retention_days: u32) -> Cloakroom {
+        Cloakroom {
+            lost_property: self.lost_property.with_retention_days(retention_days),
+            ..self
+        }
+    }
+
+    pub fn get_lost_property(&self) -> &LostPropertyStore {
+        &self.lost_property
+    }
+
+    /// Puts items found in the cloakroom, perhaps in or next to
+    /// `locker_number`, in the lost-property store, and returns the claim
+    /// reference under which they are kept.
+    pub fn hand_in_found_items(
+        &mut self,
+        items: &CloakroomItems,
+        description: &str,
+        locker_number: Option<LockerNumber>,
+    ) -> Result<ClaimReference, CloakroomError> {
+        let items = items
+            .convert_to_catalogue(&self.item_catalogue)
+            .map_err(unknown_item_category)?;
+        if let Some(locker_number) = locker_number {
+            if self.get_locker_spec(locker_number).is_none() {
+                return Err(CloakroomError::UnknownLocker(locker_number));
+            }
+        }
+        let now = self.now();
+        Ok(self
+            .lost_property
+            .hand_in(items, description, locker_number, now))
+    }
+
+    /// Hands a bundle back to whoever the attendant is satisfied owns it,
+    /// removing it from the lost-property store. Bundles taken out of
+    /// lockers are usually redeemed with their key instead, with
+    /// [`Cloakroom::redeem_claim`], which also charges for the locker.
+    pub fn claim_lost_property(
+        &mut self,
+        claim_reference: ClaimReference,
+    ) -> Result<Bundle, LostPropertyError> {
+        self.release_lost_property(
+            claim_reference,
+            AuditAction::ClaimRedeemed { claim_reference },
+        )
+    }
+
+    /// Returns the bundles in the lost-property store that have been held
+    /// for longer than its retention period, oldest first.
+    pub fn get_lost_property_due_for_disposal(&self) -> Vec<&Bundle> {
+        self.lost_property.get_due_for_disposal(self.now())
+    }
+
+    /// Removes a bundle that is due for disposal from the lost-property
+    /// store.
+    pub fn dispose_of_lost_property(
+        &mut self,
+        claim_reference: ClaimReference,
+    ) -> Result<Bundle, LostPropertyError> {
+        self.lost_property
+            .check_due_for_disposal(claim_reference, self.now())?;
+        self.release_lost_property(
+            claim_reference,
+            AuditAction::LostPropertyDisposed { claim_reference },
+        )
+    }
+
+    /// Returns the bundle in the lost-property store that `key` redeems, if
+    /// any.
+    pub fn find_claim(&self, key: &Key) -> Option<&Bundle> {
+        self.lost_property
+            .find_redeemable(key.locker_number, key.token)
+    }
+
+    /// Hands back the bundle that `key` redeems, removing it from the
+    /// lost-property store, with an invoice for the use of its locker up to
+    /// when its contents were taken out. `CloakroomError::NoClaimForKey` is
+    /// returned if the store holds nothing for the key.
+    pub fn redeem_claim(&mut self, key: Key) -> Result<(CloakroomItems, Invoice), CloakroomError> {
+        let bundle = match self.find_claim(&key) {
+            Some(bundle) => bundle,
+            None => return Err(CloakroomError::NoClaimForKey(key.locker_number)),
+        };
+        let invoice = match &bundle.redemption {
+            Some(redemption) => self.tariff.invoice(
+                key.locker_number,
+                &redemption.stay,
+                bundle.get_taken_in_at(),
+            ),
+            None => unreachable!("bundle found by key cannot be redeemed with it"),
+        };
+        let claim_reference = bundle.get_claim_reference();
+
+        let bundle = self
+            .claim_lost_property(claim_reference)
+            .expect("bundle was found by key");
+        Ok((bundle.get_items().clone(), invoice))
+    }
+
+    /// Removes a bundle from the lost-property store. Bundles taken out of
+    /// lockers are removed by emitting `action` for their locker.
+    fn release_lost_property(
+        &mut self,
+        claim_reference: ClaimReference,
+        action: AuditAction,
+    ) -> Result<Bundle, LostPropertyError> {
+        let bundle = match self.lost_property.get_bundle(claim_reference) {
+            Some(bundle) => bundle,
+            None => return Err(LostPropertyError::UnknownClaim(claim_reference)),
+        };
+        match bundle.get_locker_number() {
+            Some(locker_number) if bundle.is_redeemable_with_key() => {
+                let bundle = bundle.clone();
+                self.emit(locker_number, action);
+                Ok(bundle)
+            }
+
+            _ => self.lost_property.claim(claim_reference),
+        }
+    }
+
+    /// Moves the contents of a closed locker to the lost-property store,
+    /// freeing the locker, and returns the claim reference under which they
+    /// are kept.
+    fn take_to_lost_property(
+        &mut self,
+        locker_number: LockerNumber,
+        reason: IntakeReason,
+        description: &str,
+    ) -> ClaimReference {
+        let items = self.with_state(locker_number, |state| match state {
+            Some(LockerInUseState::Closed { items, .. }) => items.clone(),
+            _ => unreachable!("locker number {} is not closed", locker_number),
+        });
+        let claim_reference = self.lost_property.get_next_claim_reference();
+        self.emit(
+            locker_number,
+            AuditAction::MovedToLostProperty {
+                items,
+                claim_reference,
+                reason,
+                description: description.to_string(),
+            },
+        );
+        self.serve_queue();
+        claim_reference
+    }
+
+    fn check_closed(&self, locker_number: LockerNumber) -> Result<(), CloakroomError> {
+        self.with_state(locker_number, |state| match state {
+            Some(LockerInUseState::Closed { .. }) => Ok(()),
+            Some(_) => Err(CloakroomError::LockerNotClosed(locker_number)),
+            None => Err(CloakroomError::UnknownLocker(locker_number)),
+        })
+    }
+
+    /// Returns whether a locker has been deposited in for longer than the
+    /// maximum storage duration, timed from when it was first closed.
+    fn is_overdue(&self, locker_number: LockerNumber) -> bool {
+        let max_storage_duration = match self.max_storage_duration {
+            Some(max_storage_duration) => max_storage_duration,
+            None => return false,
+        };
+        let deposited_at = match self
+            .slots
+            .get(locker_number)
+            .and_then(|slot| slot.stay.as_ref()?.first_closed)
+        {
+            Some(first_closed) => first_closed,
+            None => return false,
+        };
+        self.now()
+            .duration_since(deposited_at)
+            .is_ok_and(|stored_for| stored_for > max_storage_duration)
+    }
+}
@@ -0,0 +1,177 @@
+//! Opening a closed locker whose key has been lost.
+//!
+//! An attendant holding the cloakroom's admin credential can open the locker
+//! without its key, once the customer has described its contents
+//! accurately. The locker's token is discarded, so the lost key no longer
+//! opens it even if it turns up again, and the opening is recorded in the
+//! cloakroom's lost-key log.
+
+use super::audit::AuditAction;
+use super::{
+    unknown_item_category, Cloakroom, CloakroomError, Locker, LockerInUseState, LockerNumber,
+};
+use crate::items::CloakroomItems;
+use std::fmt;
+
+/// Secret passphrase that authorises the lost-key procedure.
+#[derive(Clone)]
+pub struct AdminCredential {
+    passphrase: String,
+}
+
+impl AdminCredential {
+    pub fn new(passphrase: &str) -> AdminCredential {
+        AdminCredential {
+            passphrase: passphrase.to_string(),
+        }
+    }
+
+    /// Compares the passphrases in time that depends only on their lengths,
+    /// so that the comparison does not give away how much of a guess is
+    /// right.
+    fn matches(&self, other: &AdminCredential) -> bool {
+        let a = self.passphrase.as_bytes();
+        let b = other.passphrase.as_bytes();
+        a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+    }
+}
+
+impl fmt::Debug for AdminCredential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AdminCredential(..)")
+    }
+}
+
+/// Record of a locker opened with the lost-key procedure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LostKeyRecord {
+    locker_number: LockerNumber,
+    items: CloakroomItems,
+    fee_pence: Option<u32>,
+}
+
+impl LostKeyRecord {
+    pub(crate) fn new(
+        locker_number: LockerNumber,
+        items: CloakroomItems,
+        fee_pence: Option<u32>,
+    ) -> LostKeyRecord {
+        LostKeyRecord {
+            locker_number,
+            items,
+            fee_pence,
+        }
+    }
+
+    pub fn get_locker_number(&self) -> LockerNumber {
+        self.locker_number
+    }
+
+    /// Returns the contents of the locker when it was opened.
+    pub fn get_items(&self) -> &CloakroomItems {
+        &self.items
+    }
+
+    /// Returns the fee charged for the lost key, if any.
+    pub fn get_fee_pence(&self) -> Option<u32> {
+        self.fee_pence
+    }
+}
+
+impl Cloakroom {
+    /// Sets the credential that attendants must give to use the lost-key
+    /// procedure. Without one, the procedure cannot be used. The credential
+    /// is not saved with the cloakroom.
+    pub fn with_admin_credential(mut self, admin_credential: AdminCredential) -> Cloakroom {
+        self.admin_credential = Some(admin_credential);
+        self
+    }
+
+    /// Opens a closed locker without its key, as described in the
+    /// [module documentation](self). `description` is the customer's account
+    /// of the locker's contents, and must match them exactly. The opening is
+    /// added to the lost-key log, together with `fee_pence`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cloakroom_model::cloakroom::lost_key::AdminCredential;
+    /// use cloakroom_model::cloakroom::{Cloakroom, CloakroomError};
+    /// use cloakroom_model::items::CloakroomItems;
+    ///
+    /// let credential = AdminCredential::new("open sesame");
+    /// let mut cloakroom = Cloakroom::new(2, 5).with_admin_credential(credential.clone());
+    ///
+    /// let mut locker = cloakroom.find_free_locker(&CloakroomItems::new()).into_result().unwrap();
+    /// locker.set_num_items("umbrellas", 1).unwrap();
+    /// let lost_key = cloakroom.close_locker(locker).unwrap();
+    ///
+    /// let mut description = CloakroomItems::new();
+    /// description.set_num_items("coats", 1).unwrap();
+    /// assert_eq!(
+    ///     cloakroom.open_locker_without_key(1, &credential, &description, Some(500)).err(),
+    ///     Some(CloakroomError::ContentsDoNotMatch(1))
+    /// );
+    ///
+    /// let mut description = CloakroomItems::new();
+    /// description.set_num_items("umbrellas", 1).unwrap();
+    /// let locker = cloakroom
+    ///     .open_locker_without_key(1, &credential, &description, Some(500))
+    ///     .unwrap();
+    /// let key = cloakroom.close_locker(locker).unwrap();
+    ///
+    /// // The lost key no longer opens the locker.
+    /// assert_eq!(cloakroom.open_locker(lost_key).err(), Some(CloakroomError::InvalidKey(1)));
+    /// assert!(cloakroom.open_locker(key).is_ok());
+    /// assert_eq!(cloakroom.get_lost_key_log()[0].get_fee_pence(), Some(500));
+    /// ```
+    pub fn open_locker_without_key(
+        &mut self,
+        locker_number: LockerNumber,
+        admin_credential: &AdminCredential,
+        description: &CloakroomItems,
+        fee_pence: Option<u32>,
+    ) -> Result<Locker, CloakroomError> {
+        self.check_admin_credential(admin_credential)?;
+
+        self.record_abandoned_lockers();
+        let description = description
+            .convert_to_catalogue(&self.item_catalogue)
+            .map_err(unknown_item_category)?;
+        self.with_state(locker_number, |state| match state {
+            Some(LockerInUseState::Closed { items, .. }) if *items == description => Ok(()),
+
+            Some(LockerInUseState::Closed { .. }) => {
+                Err(CloakroomError::ContentsDoNotMatch(locker_number))
+            }
+
+            Some(_) => Err(CloakroomError::LockerNotClosed(locker_number)),
+
+            None => Err(CloakroomError::UnknownLocker(locker_number)),
+        })?;
+
+        // Opening the locker drops its token, which invalidates the lost
+        // key, and adds the opening to the lost-key log.
+        self.emit(locker_number, AuditAction::OpenedWithoutKey { fee_pence });
+
+        Ok(self.hand_out(locker_number))
+    }
+
+    /// Returns the lockers opened with the lost-key procedure, oldest
+    /// first.
+    pub fn get_lost_key_log(&self) -> &[LostKeyRecord] {
+        &self.lost_key_log
+    }
+
+    /// Fails with `CloakroomError::InvalidAdminCredential` unless
+    /// `admin_credential` matches the cloakroom's admin credential.
+    pub(super) fn check_admin_credential(
+        &self,
+        admin_credential: &AdminCredential,
+    ) -> Result<(), CloakroomError> {
+        match &self.admin_credential {
+            Some(credential) if credential.matches(admin_credential) => Ok(()),
+            _ => Err(CloakroomError::InvalidAdminCredential),
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Taking lockers out of service for maintenance.
+//!
+//! An attendant holding the cloakroom's admin credential can take a free
+//! locker out of service, e.g. because its lock is broken, giving the
+//! reason. It is not handed out again until it is returned to service. A
+//! locker in use must first be vacated, or its contents moved to lost
+//! property with [`Cloakroom::force_open_to_lost_property`]. A locker held
+//! back for a reservation can be taken out of service, and another is held
+//! back instead if one is free.
+
+use super::audit::AuditAction;
+use super::lost_key::AdminCredential;
+use super::{Cloakroom, CloakroomError, LockerInUseState, LockerNumber};
+
+impl Cloakroom {
+    /// Takes a free locker out of service for `reason`, as described in the
+    /// [module documentation](self).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cloakroom_model::cloakroom::lost_key::AdminCredential;
+    /// use cloakroom_model::cloakroom::{Cloakroom, LockerState};
+    /// use cloakroom_model::items::CloakroomItems;
+    ///
+    /// let credential = AdminCredential::new("open sesame");
+    /// let mut cloakroom = Cloakroom::new(2, 5).with_admin_credential(credential.clone());
+    /// cloakroom.take_out_of_service(1, &credential, "broken lock").unwrap();
+    /// assert!(matches!(
+    ///     cloakroom.get_locker_state(1),
+    ///     LockerState::OutOfService { reason: "broken lock" }
+    /// ));
+    ///
+    /// // Locker 1 is skipped until it is returned to service.
+    /// let locker = cloakroom.find_free_locker(&CloakroomItems::new()).into_result().unwrap();
+    /// assert_eq!(locker.get_locker_number(), 2);
+    /// cloakroom.return_to_service(1, &credential).unwrap();
+    /// assert!(matches!(cloakroom.get_locker_state(1), LockerState::Free));
+    /// ```
+    pub fn take_out_of_service(
+        &mut self,
+        locker_number: LockerNumber,
+        admin_credential: &AdminCredential,
+        reason: &str,
+    ) -> Result<(), CloakroomError> {
+        self.check_admin_credential(admin_credential)?;
+        self.record_abandoned_lockers();
+        if self.get_locker_spec(locker_number).is_none() {
+            return Err(CloakroomError::UnknownLocker(locker_number));
+        }
+        self.with_state(locker_number, |state| match state {
+            None => Ok(()),
+            Some(LockerInUseState::OutOfService { .. }) => {
+                Err(CloakroomError::LockerOutOfService(locker_number))
+            }
+            Some(_) => Err(CloakroomError::LockerNotFree(locker_number)),
+        })?;
+
+        self.release_reserved_locker(locker_number);
+        self.emit(
+            locker_number,
+            AuditAction::TakenOutOfService {
+                reason: reason.to_string(),
+            },
+        );
+        self.hold_reserved_lockers();
+        Ok(())
+    }
+
+    /// Returns a locker that is out of service to service, leaving it free.
+    pub fn return_to_service(
+        &mut self,
+        locker_number: LockerNumber,
+        admin_credential: &AdminCredential,
+    ) -> Result<(), CloakroomError> {
+        self.check_admin_credential(admin_credential)?;
+        self.with_state(locker_number, |state| match state {
+            Some(LockerInUseState::OutOfService { .. }) => Ok(()),
+            _ if self.get_locker_spec(locker_number).is_none() => {
+                Err(CloakroomError::UnknownLocker(locker_number))
+            }
+            _ => Err(CloakroomError::LockerNotOutOfService(locker_number)),
+        })?;
+
+        self.emit(locker_number, AuditAction::ReturnedToService);
+        self.serve_queue();
+        Ok(())
+    }
+
+    /// Returns the numbers of the lockers that are out of service, in order.
+    pub fn get_out_of_service_lockers(&self) -> Vec<LockerNumber> {
+        (1..=self.get_num_lockers())
+            .filter(|&locker_number| {
+                self.with_state(locker_number, |state| {
+                    matches!(state, Some(LockerInUseState::OutOfService { .. }))
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,1199 @@
+//! Saving and restoring the full state of a cloakroom.
+//!
+//! The state is written as UTF-8 text, one record per line. Each record
+//! starts with its type and is followed by `name=value` attributes separated
+//! by whitespace. Blank lines and lines starting with `#` are ignored.
+//!
+//! The first record must be the header, which gives the format version:
+//!
+//! ```text
+//! cloakroom-state version=1
+//! ```
+//!
+//! It is followed by these records:
+//!
+//! - `cloakroom num_lockers=<n> [next_claim_number=<n>]
+//!   [next_ticket_number=<n>] [next_reservation_number=<n>]` (exactly once),
+//!   where `num_lockers` is at most 1,000,000 and `next_claim_number`,
+//!   `next_ticket_number` and `next_reservation_number` give the numbers of
+//!   the next claim reference, queue ticket and reservation reference to be
+//!   issued, if they are not 1
+//! - `category name=<name> space=<n> weight_grams=<n>` for each category in
+//!   the cloakroom's item catalogue, in order (at least once), giving the
+//!   space taken up by, and the weight of, each item of the category
+//! - `lockers first=<n> last=<n> max_space=<n> [max_weight_grams=<n>]
+//!   [size_class=<class>]` giving the capacity, and optionally the weight
+//!   limit and the size class (`small`, `medium` or `large`), of lockers
+//!   `first` to `last` inclusive. Every locker must be covered by exactly
+//!   one `lockers` record.
+//! - `zone name=<text> first=<n> last=<n> [location=<text>]` for each zone
+//!   of a cloakroom divided into zones, in order, giving its name, its
+//!   lockers, `first` to `last` inclusive, and where it is. `name` and
+//!   `location` are written in the same way as the `description` of a
+//!   `lost_property` record. If there are any `zone` records, every locker
+//!   must be covered by exactly one of them.
+//! - `locker number=<n> state=closed items=<category>:<n>,... token=<token>
+//!   <stay>` for each closed locker, where `items` gives the number of items
+//!   of each category, omitted categories having none, and `token` is the
+//!   secret token, as 32 hexadecimal digits, that its key must carry
+//! - `locker number=<n> state=being_changed items=<category>:<n>,... <stay>`
+//!   for each locker whose contents are being changed, where `items` gives
+//!   its contents when they started being changed
+//! - `locker number=<n> state=abandoned items=<category>:<n>,... <stay>` for
+//!   each abandoned locker, where `items` gives its contents when it was
+//!   abandoned
+//! - `locker number=<n> state=out_of_service [reason=<text>]` for each
+//!   locker that has been taken out of service, where `reason` is written
+//!   in the same way as the `description` of a `lost_property` record
+//! - `key locker_number=<n> token=<token>` for each key that has been handed
+//!   out and not yet used to open its locker or redeem its claim
+//! - `lost_key locker_number=<n> items=<category>:<n>,... [fee_pence=<n>]`
+//!   for each locker opened with the lost-key procedure, oldest first,
+//!   giving its contents when it was opened and the fee charged
+//! - `lost_property claim_reference=<reference> reason=<reason>
+//!   items=<category>:<n>,... taken_in_at_ms=<n> [locker_number=<n>]
+//!   [description=<text>] [token=<token> <stay>]` for each bundle held in
+//!   the lost-property store, oldest first, where `reason` is `overdue`,
+//!   `forced_open` or `found`. `token` gives the token of the key that
+//!   redeems a bundle taken out of a locker, and is followed by the stay of
+//!   the locker up to when it was taken out. In `description`, whitespace
+//!   and `%` are written as `%` followed by two hexadecimal digits for each
+//!   byte of their UTF-8 encoding.
+//! - `queue ticket=<ticket> priority=<class> items=<category>:<n>,...
+//!   joined_at_ms=<n> [locker_number=<n> served_at_ms=<n>]` for each
+//!   customer in the queue, those who have been served first, in the order
+//!   in which they were served, and then those waiting, in the order in
+//!   which they joined. `priority` is `standard` or `priority`, and `items`
+//!   gives what the customer wants to deposit. `locker_number` gives the
+//!   locker allocated to a customer who has been served, which must have a
+//!   `being_changed` record.
+//! - `reservation reference=<reference> holder=<text>
+//!   (locker_number=<n> | size_class=<class>) starts_at_ms=<n> ends_at_ms=<n>
+//!   [held_locker_number=<n>]` for each reservation that has not been
+//!   claimed, in the order in which they were made, where `holder` is
+//!   written in the same way as the `description` of a `lost_property`
+//!   record. `held_locker_number` gives the free locker held back for the
+//!   reservation.
+//!
+//! In `locker` and `lost_property` records, `<stay>` is
+//! `deposited=<category>:<n>,... accessed_at_ms=<n> [closed_at_ms=<n>]
+//! [lost_key_fees=<fee>,...]`, giving the number of items of each category
+//! put in the locker since it was allocated, when it was last used and when
+//! it was first closed, in milliseconds since the Unix epoch, and the fee
+//! set, or `-` if none was, each time it was opened with the lost-key
+//! procedure. Closed lockers must give `closed_at_ms`.
+//!
+//! Lockers without a `locker` record are free. Readers reject files with a
+//! version they do not know about rather than guessing at their meaning.
+//!
+//! The `Locker` values handed out for lockers whose contents are being
+//! changed do not survive a restore, so those lockers are restored as
+//! abandoned, holding the contents in their record.
+//!
+//! Since the file holds the tokens needed to open every closed locker, it
+//! should be kept as safe as the keys themselves.
+
+use super::lost_key::LostKeyRecord;
+use super::queue::{QueueEntry, QueueTicket, ServedCustomer};
+use super::reservation::{Reservation, ReservationReference, ReservationTarget};
+use super::zone::Zone;
+use super::{Capacity, Cloakroom, Key, LockerInUseState, LockerNumber, LockerSpec};
+use crate::items::{CatalogueError, CloakroomItems, ItemCatalogue, ItemCategory};
+use crate::lost_property::{Bundle, ClaimReference, Redemption};
+use crate::pricing::Stay;
+use std::collections::hash_map::HashMap;
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const STATE_FILE_VERSION: u32 = 1;
+
+const HEADER_RECORD: &str = "cloakroom-state";
+
+/// Most lockers a state file may describe, so that a corrupt file cannot
+/// make loading it use up all the memory.
+const MAX_NUM_LOCKERS: usize = 1_000_000;
+
+/// Reasons why a cloakroom state file cannot be read.
+#[derive(Debug)]
+pub enum StateFileError {
+    Io(io::Error),
+    UnsupportedVersion(u32),
+    /// The file is not in the expected format. `line_number` starts at 1.
+    Parse {
+        line_number: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for StateFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateFileError::Io(err) => write!(f, "failed to access state file: {}", err),
+
+            StateFileError::UnsupportedVersion(version) => {
+                write!(f, "unsupported state file version {}", version)
+            }
+
+            StateFileError::Parse {
+                line_number,
+                message,
+            } => write!(f, "state file line {}: {}", line_number, message),
+        }
+    }
+}
+
+impl error::Error for StateFileError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            StateFileError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for StateFileError {
+    fn from(err: io::Error) -> StateFileError {
+        StateFileError::Io(err)
+    }
+}
+
+/// A record split into its type and its attributes.
+struct Record<'a> {
+    line_number: usize,
+    record_type: &'a str,
+    attributes: HashMap<&'a str, &'a str>,
+}
+
+impl<'a> Record<'a> {
+    fn parse(line_number: usize, line: &'a str) -> Result<Record<'a>, StateFileError> {
+        let mut fields = line.split_whitespace();
+        let record_type = fields.next().unwrap_or_default();
+
+        let mut attributes = HashMap::new();
+        for field in fields {
+            let (name, value) = match field.find('=') {
+                Some(pos) => (&field[..pos], &field[pos + 1..]),
+                None => {
+                    return Err(parse_error(
+                        line_number,
+                        format!("expected name=value but found '{}'", field),
+                    ));
+                }
+            };
+
+            if attributes.insert(name, value).is_some() {
+                return Err(parse_error(
+                    line_number,
+                    format!("attribute '{}' is repeated", name),
+                ));
+            }
+        }
+
+        Ok(Record {
+            line_number,
+            record_type,
+            attributes,
+        })
+    }
+
+    fn get_str(&self, name: &str) -> Result<&'a str, StateFileError> {
+        match self.attributes.get(name) {
+            Some(value) => Ok(value),
+            None => Err(self.error(format!("missing attribute '{}'", name))),
+        }
+    }
+
+    fn get<T: FromStr>(&self, name: &str) -> Result<T, StateFileError> {
+        let value = self.get_str(name)?;
+        value
+            .parse()
+            .map_err(|_| self.error(format!("invalid value '{}' for '{}'", value, name)))
+    }
+
+    fn error(&self, message: String) -> StateFileError {
+        parse_error(self.line_number, message)
+    }
+}
+
+fn parse_error(line_number: usize, message: String) -> StateFileError {
+    StateFileError::Parse {
+        line_number,
+        message,
+    }
+}
+
+impl Cloakroom {
+    /// Writes the state of the cloakroom, together with the keys that have
+    /// been handed out for its closed lockers, in the format described in
+    /// the [module documentation](self).
+    pub fn save<'a, W, I>(&self, mut writer: W, keys: I) -> io::Result<()>
+    where
+        W: Write,
+        I: IntoIterator<Item = &'a Key>,
+    {
+        writeln!(writer, "{} version={}", HEADER_RECORD, STATE_FILE_VERSION)?;
+        write!(writer, "cloakroom num_lockers={}", self.get_num_lockers())?;
+        let next_claim_number = self.lost_property.get_next_claim_reference().get_number();
+        if next_claim_number != 1 {
+            write!(writer, " next_claim_number={}", next_claim_number)?;
+        }
+        if self.queue.next_ticket_number != 1 {
+            write!(
+                writer,
+                " next_ticket_number={}",
+                self.queue.next_ticket_number
+            )?;
+        }
+        if self.reservations.next_reference_number != 1 {
+            write!(
+                writer,
+                " next_reservation_number={}",
+                self.reservations.next_reference_number
+            )?;
+        }
+        writeln!(writer)?;
+        for category in self.item_catalogue.get_categories() {
+            writeln!(
+                writer,
+                "category name={} space={} weight_grams={}",
+                category.get_name(),
+                category.get_space(),
+                category.get_weight_grams()
+            )?;
+        }
+
+        // Write runs of consecutive lockers with the same spec as one record.
+        let mut first = 1;
+        while first <= self.get_num_lockers() {
+            let spec = self.lockers[first - 1];
+            let mut last = first;
+            while last < self.get_num_lockers() && self.lockers[last] == spec {
+                last += 1;
+            }
+
+            write!(
+                writer,
+                "lockers first={} last={} max_space={}",
+                first, last, spec.max_space
+            )?;
+            if let Some(max_weight_grams) = spec.max_weight_grams {
+                write!(writer, " max_weight_grams={}", max_weight_grams)?;
+            }
+            if let Some(size_class) = spec.size_class {
+                write!(writer, " size_class={}", size_class)?;
+            }
+            writeln!(writer)?;
+
+            first = last + 1;
+        }
+
+        for zone in self.get_zones() {
+            let locker_numbers = zone.get_locker_numbers();
+            write!(
+                writer,
+                "zone name={} first={} last={}",
+                encode_text(zone.get_name()),
+                locker_numbers.start(),
+                locker_numbers.end()
+            )?;
+            if !zone.get_location().is_empty() {
+                write!(writer, " location={}", encode_text(zone.get_location()))?;
+            }
+            writeln!(writer)?;
+        }
+
+        for locker_number in 1..=self.get_num_lockers() {
+            let slot = self.slot(locker_number);
+            let state = match &slot.state {
+                Some(state) => state,
+                None => continue,
+            };
+            match state {
+                LockerInUseState::Closed { items, token } => write!(
+                    writer,
+                    "locker number={} state=closed items={} token={}",
+                    locker_number,
+                    format_items(items),
+                    token
+                )?,
+
+                LockerInUseState::ContentsBeingChanged { items } => write!(
+                    writer,
+                    "locker number={} state=being_changed items={}",
+                    locker_number,
+                    format_items(items)
+                )?,
+
+                LockerInUseState::Abandoned { items } => write!(
+                    writer,
+                    "locker number={} state=abandoned items={}",
+                    locker_number,
+                    format_items(items)
+                )?,
+
+                LockerInUseState::OutOfService { reason } => {
+                    write!(
+                        writer,
+                        "locker number={} state=out_of_service",
+                        locker_number
+                    )?;
+                    if !reason.is_empty() {
+                        write!(writer, " reason={}", encode_text(reason))?;
+                    }
+                    writeln!(writer)?;
+                    continue;
+                }
+            }
+            let stay = slot.stay.as_ref().expect("locker in use has a stay");
+            write_stay(&mut writer, stay)?;
+        }
+
+        for key in keys {
+            writeln!(
+                writer,
+                "key locker_number={} token={}",
+                key.get_locker_number(),
+                key.token
+            )?;
+        }
+
+        for record in &self.lost_key_log {
+            write!(
+                writer,
+                "lost_key locker_number={} items={}",
+                record.get_locker_number(),
+                format_items(record.get_items())
+            )?;
+            if let Some(fee_pence) = record.get_fee_pence() {
+                write!(writer, " fee_pence={}", fee_pence)?;
+            }
+            writeln!(writer)?;
+        }
+
+        for bundle in self.lost_property.get_bundles() {
+            write!(
+                writer,
+                "lost_property claim_reference={} reason={} items={} taken_in_at_ms={}",
+                bundle.get_claim_reference(),
+                bundle.get_reason(),
+                format_items(bundle.get_items()),
+                to_ms(bundle.get_taken_in_at())
+            )?;
+            if let Some(locker_number) = bundle.get_locker_number() {
+                write!(writer, " locker_number={}", locker_number)?;
+            }
+            if !bundle.get_description().is_empty() {
+                write!(
+                    writer,
+                    " description={}",
+                    encode_text(bundle.get_description())
+                )?;
+            }
+            match &bundle.redemption {
+                Some(redemption) => {
+                    write!(writer, " token={}", redemption.token)?;
+                    write_stay(&mut writer, &redemption.stay)?;
+                }
+                None => writeln!(writer)?,
+            }
+        }
+
+        let served = self
+            .queue
+            .served
+            .iter()
+            .map(|served| (&served.entry, Some(served)));
+        let waiting = self.queue.waiting.iter().map(|entry| (entry, None));
+        for (entry, served) in served.chain(waiting) {
+            write!(
+                writer,
+                "queue ticket={} priority={} items={} joined_at_ms={}",
+                entry.get_ticket(),
+                entry.get_priority(),
+                format_items(entry.get_items()),
+                to_ms(entry.get_joined_at())
+            )?;
+            if let Some(served) = served {
+                write!(
+                    writer,
+                    " locker_number={} served_at_ms={}",
+                    served.locker.get_locker_number(),
+                    to_ms(served.served_at)
+                )?;
+            }
+            writeln!(writer)?;
+        }
+
+        for reservation in &self.reservations.pending {
+            write!(
+                writer,
+                "reservation reference={} holder={}",
+                reservation.get_reference(),
+                encode_text(reservation.get_holder())
+            )?;
+            match reservation.get_target() {
+                ReservationTarget::Locker(locker_number) => {
+                    write!(writer, " locker_number={}", locker_number)?
+                }
+                ReservationTarget::SizeClass(size_class) => {
+                    write!(writer, " size_class={}", size_class)?
+                }
+            }
+            write!(
+                writer,
+                " starts_at_ms={} ends_at_ms={}",
+                to_ms(reservation.get_starts_at()),
+                to_ms(reservation.get_ends_at())
+            )?;
+            if let Some(locker_number) = reservation.get_held_locker_number() {
+                write!(writer, " held_locker_number={}", locker_number)?;
+            }
+            writeln!(writer)?;
+        }
+
+        writer.flush()
+    }
+
+    /// Reads a cloakroom, and the keys handed out for its closed lockers,
+    /// from state written by [`Cloakroom::save`]. The allocation strategy is
+    /// not saved, so the restored cloakroom uses the default one until
+    /// [`Cloakroom::with_allocation_strategy`] is called.
+    pub fn load<R: BufRead>(reader: R) -> Result<(Cloakroom, Vec<Key>), StateFileError> {
+        let mut lines = Vec::new();
+        for line in reader.lines() {
+            lines.push(line?);
+        }
+
+        let mut records = Vec::new();
+        for (index, line) in lines.iter().enumerate() {
+            let line = line.trim();
+            if !line.is_empty() && !line.starts_with('#') {
+                records.push(Record::parse(index + 1, line)?);
+            }
+        }
+        let mut records = records.into_iter();
+
+        match records.next() {
+            Some(ref header) if header.record_type == HEADER_RECORD => {
+                let version = header.get("version")?;
+                if version != STATE_FILE_VERSION {
+                    return Err(StateFileError::UnsupportedVersion(version));
+                }
+            }
+
+            _ => {
+                return Err(parse_error(
+                    1,
+                    format!("file does not start with a '{}' record", HEADER_RECORD),
+                ));
+            }
+        }
+
+        let mut cloakroom_record = None;
+        let mut category_records = Vec::new();
+        let mut lockers_records = Vec::new();
+        let mut zone_records = Vec::new();
+        let mut locker_records = Vec::new();
+        let mut key_records = Vec::new();
+        let mut lost_key_records = Vec::new();
+        let mut lost_property_records = Vec::new();
+        let mut queue_records = Vec::new();
+        let mut reservation_records = Vec::new();
+        for record in records {
+            match record.record_type {
+                "cloakroom" => {
+                    if cloakroom_record.is_some() {
+                        return Err(record.error("repeated 'cloakroom' record".to_string()));
+                    }
+                    cloakroom_record = Some(record);
+                }
+
+                "category" => category_records.push(record),
+                "lockers" => lockers_records.push(record),
+                "zone" => zone_records.push(record),
+                "locker" => locker_records.push(record),
+                "key" => key_records.push(record),
+                "lost_key" => lost_key_records.push(record),
+                "lost_property" => lost_property_records.push(record),
+                "queue" => queue_records.push(record),
+                "reservation" => reservation_records.push(record),
+
+                other => {
+                    return Err(record.error(format!("unknown record type '{}'", other)));
+                }
+            }
+        }
+
+        let cloakroom_record = match cloakroom_record {
+            Some(record) => record,
+            None => {
+                return Err(parse_error(
+                    lines.len(),
+                    "missing 'cloakroom' record".to_string(),
+                ));
+            }
+        };
+        let mut cloakroom =
+            Cloakroom::with_lockers(load_locker_specs(&cloakroom_record, &lockers_records)?);
+        load_zones(&mut cloakroom, &zone_records)?;
+        let catalogue = load_item_catalogue(&cloakroom_record, &category_records)?;
+        cloakroom = cloakroom.with_item_catalogue(Arc::new(catalogue));
+
+        for record in &locker_records {
+            load_locker(&mut cloakroom, record)?;
+        }
+
+        if cloakroom_record
+            .attributes
+            .contains_key("next_claim_number")
+        {
+            cloakroom
+                .lost_property
+                .set_next_claim_number(cloakroom_record.get("next_claim_number")?);
+        }
+        for record in &lost_property_records {
+            load_bundle(&mut cloakroom, record)?;
+        }
+
+        if cloakroom_record
+            .attributes
+            .contains_key("next_ticket_number")
+        {
+            cloakroom.queue.next_ticket_number = cloakroom_record.get("next_ticket_number")?;
+        }
+        for record in &queue_records {
+            load_queue_entry(&mut cloakroom, record)?;
+        }
+
+        if cloakroom_record
+            .attributes
+            .contains_key("next_reservation_number")
+        {
+            cloakroom.reservations.next_reference_number =
+                cloakroom_record.get("next_reservation_number")?;
+        }
+        for record in &reservation_records {
+            load_reservation(&mut cloakroom, record)?;
+        }
+
+        let mut keys: Vec<Key> = Vec::new();
+        for record in &key_records {
+            let line_number = record.line_number;
+            let locker_number = record.get("locker_number")?;
+            let token = record.get("token")?;
+            let locker_token = cloakroom.with_state(locker_number, |state| match state {
+                Some(LockerInUseState::Closed { token, .. }) => Some(*token),
+                _ => None,
+            });
+            let not_closed_error = || {
+                parse_error(
+                    line_number,
+                    format!(
+                        "key for locker number {} which is not closed",
+                        locker_number
+                    ),
+                )
+            };
+
+            // Keys whose locker's contents have been moved to lost property
+            // redeem the claim instead.
+            let redeems_claim = cloakroom
+                .lost_property
+                .find_redeemable(locker_number, token)
+                .is_some();
+            match locker_token {
+                _ if redeems_claim => (),
+                Some(locker_token) if locker_token == token => (),
+                Some(_) => {
+                    return Err(record.error(format!(
+                        "key token does not match locker number {}",
+                        locker_number
+                    )));
+                }
+                None => return Err(not_closed_error()),
+            }
+
+            if keys
+                .iter()
+                .any(|key| key.get_locker_number() == locker_number && key.token == token)
+            {
+                return Err(parse_error(
+                    line_number,
+                    format!("repeated key for locker number {}", locker_number),
+                ));
+            }
+            keys.push(Key::new(locker_number, token));
+        }
+
+        for record in &lost_key_records {
+            let locker_number = record.get("locker_number")?;
+            if cloakroom.get_locker_spec(locker_number).is_none() {
+                return Err(record.error(format!("locker number {} does not exist", locker_number)));
+            }
+            let items = load_items(&cloakroom.item_catalogue, record)?;
+            let fee_pence = if record.attributes.contains_key("fee_pence") {
+                Some(record.get("fee_pence")?)
+            } else {
+                None
+            };
+            cloakroom
+                .lost_key_log
+                .push(LostKeyRecord::new(locker_number, items, fee_pence));
+        }
+
+        Ok((cloakroom, keys))
+    }
+}
+
+/// Builds the specs of the lockers from the `cloakroom` record and the
+/// `lockers` records, checking that every locker is described exactly once.
+fn load_locker_specs(
+    cloakroom_record: &Record,
+    lockers_records: &[Record],
+) -> Result<Vec<LockerSpec>, StateFileError> {
+    let num_lockers: usize = cloakroom_record.get("num_lockers")?;
+    if num_lockers > MAX_NUM_LOCKERS {
+        return Err(cloakroom_record.error(format!(
+            "{} lockers is more than the limit of {}",
+            num_lockers, MAX_NUM_LOCKERS
+        )));
+    }
+    let mut specs: Vec<Option<LockerSpec>> = vec![None; num_lockers];
+    for record in lockers_records {
+        let first: LockerNumber = record.get("first")?;
+        let last: LockerNumber = record.get("last")?;
+        if first < 1 || first > last || last > num_lockers {
+            return Err(record.error(format!(
+                "invalid range of locker numbers {} to {}",
+                first, last
+            )));
+        }
+
+        let mut spec = LockerSpec::new(record.get("max_space")?);
+        if record.attributes.contains_key("max_weight_grams") {
+            spec = spec.with_max_weight_grams(record.get("max_weight_grams")?);
+        }
+        if record.attributes.contains_key("size_class") {
+            spec = spec.with_size_class(record.get("size_class")?);
+        }
+
+        for slot in &mut specs[first - 1..last] {
+            if slot.is_some() {
+                return Err(record.error("locker numbers overlap earlier record".to_string()));
+            }
+            *slot = Some(spec);
+        }
+    }
+
+    specs
+        .iter()
+        .enumerate()
+        .map(|(index, spec)| {
+            spec.ok_or_else(|| {
+                cloakroom_record.error(format!("no capacity given for locker number {}", index + 1))
+            })
+        })
+        .collect()
+}
+
+fn load_item_catalogue(
+    cloakroom_record: &Record,
+    category_records: &[Record],
+) -> Result<ItemCatalogue, StateFileError> {
+    let mut categories: Vec<ItemCategory> = Vec::new();
+    for record in category_records {
+        let name = record.get_str("name")?;
+        if categories
+            .iter()
+            .any(|category| category.get_name() == name)
+        {
+            return Err(record.error(format!("repeated item category '{}'", name)));
+        }
+
+        categories.push(
+            ItemCategory::new(name)
+                .with_space(record.get("space")?)
+                .with_weight_grams(record.get("weight_grams")?),
+        );
+    }
+
+    ItemCatalogue::from_categories(categories).map_err(|err| match category_records.first() {
+        Some(record) => record.error(err.to_string()),
+        None => cloakroom_record.error(err.to_string()),
+    })
+}
+
+/// Formats the contents of a locker for the `items` attribute of its record.
+fn format_items(items: &CloakroomItems) -> String {
+    let counts: Vec<String> = items
+        .iter()
+        .map(|(category, num_items)| format!("{}:{}", category.get_name(), num_items))
+        .collect();
+    counts.join(",")
+}
+
+/// Writes the attributes giving the stay of a locker, ending its record.
+fn write_stay<W: Write>(writer: &mut W, stay: &Stay) -> io::Result<()> {
+    let deposited: Vec<String> = stay
+        .get_catalogue()
+        .get_categories()
+        .iter()
+        .zip(&stay.deposited)
+        .map(|(category, num_items)| format!("{}:{}", category.get_name(), num_items))
+        .collect();
+    write!(writer, " deposited={}", deposited.join(","))?;
+    write!(writer, " accessed_at_ms={}", to_ms(stay.last_accessed))?;
+    if let Some(first_closed) = stay.first_closed {
+        write!(writer, " closed_at_ms={}", to_ms(first_closed))?;
+    }
+    if !stay.lost_key_fees.is_empty() {
+        let fees: Vec<String> = stay
+            .lost_key_fees
+            .iter()
+            .map(|fee| fee.map_or("-".to_string(), |fee| fee.to_string()))
+            .collect();
+        write!(writer, " lost_key_fees={}", fees.join(","))?;
+    }
+    writeln!(writer)
+}
+
+/// Reads the stay of a locker from its record.
+fn load_stay(catalogue: &Arc<ItemCatalogue>, record: &Record) -> Result<Stay, StateFileError> {
+    let mut stay = Stay::new(catalogue.clone(), from_ms(record.get("accessed_at_ms")?));
+
+    for (name, num_items) in parse_counts(record, "deposited")? {
+        match catalogue
+            .get_categories()
+            .iter()
+            .position(|category| category.get_name() == name)
+        {
+            Some(index) => stay.deposited[index] = num_items,
+            None => {
+                return Err(
+                    record.error(CatalogueError::UnknownCategory(name.to_string()).to_string())
+                );
+            }
+        }
+    }
+    if record.attributes.contains_key("closed_at_ms") {
+        stay.record_closed(from_ms(record.get("closed_at_ms")?));
+    }
+    if let Some(fees) = record.attributes.get("lost_key_fees") {
+        for fee in fees.split(',') {
+            if fee == "-" {
+                stay.record_lost_key(None);
+            } else {
+                let fee = fee
+                    .parse()
+                    .map_err(|_| record.error(format!("invalid lost-key fee '{}'", fee)))?;
+                stay.record_lost_key(Some(fee));
+            }
+        }
+    }
+
+    Ok(stay)
+}
+
+fn to_ms(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis())
+}
+
+fn from_ms(ms: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(ms)
+}
+
+/// Splits an attribute of the form `<category>:<n>,...` into its
+/// categories and numbers of items, checking that no category is repeated.
+fn parse_counts<'a>(
+    record: &Record<'a>,
+    attribute: &str,
+) -> Result<Vec<(&'a str, u32)>, StateFileError> {
+    let counts = record.get_str(attribute)?;
+    let mut parsed: Vec<(&str, u32)> = Vec::new();
+    for count in counts.split(',').filter(|count| !count.is_empty()) {
+        let (name, num_items) = match count.find(':') {
+            Some(pos) => (&count[..pos], &count[pos + 1..]),
+            None => {
+                return Err(record.error(format!("expected category:n but found '{}'", count)));
+            }
+        };
+        let num_items = num_items.parse().map_err(|_| {
+            record.error(format!(
+                "invalid number of items '{}' for '{}'",
+                num_items, name
+            ))
+        })?;
+        if parsed.iter().any(|(seen, _)| *seen == name) {
+            return Err(record.error(format!("item category '{}' is repeated", name)));
+        }
+        parsed.push((name, num_items));
+    }
+
+    Ok(parsed)
+}
+
+/// Reads the contents of a locker from the `items` attribute of its
+/// record.
+fn load_items(
+    catalogue: &Arc<ItemCatalogue>,
+    record: &Record,
+) -> Result<CloakroomItems, StateFileError> {
+    let mut items = CloakroomItems::from_catalogue(catalogue.clone());
+    for (name, num_items) in parse_counts(record, "items")? {
+        let num_items = u8::try_from(num_items).map_err(|_| {
+            record.error(format!(
+                "invalid number of items '{}' for '{}'",
+                num_items, name
+            ))
+        })?;
+        items
+            .set_num_items(name, num_items)
+            .map_err(|err| record.error(err.to_string()))?;
+    }
+
+    Ok(items)
+}
+
+/// Reads a bundle held in the lost-property store from its `lost_property`
+/// record.
+fn load_bundle(cloakroom: &mut Cloakroom, record: &Record) -> Result<(), StateFileError> {
+    let claim_reference: ClaimReference = record.get("claim_reference")?;
+    if cloakroom
+        .lost_property
+        .get_bundle(claim_reference)
+        .is_some()
+    {
+        return Err(record.error(format!("repeated claim reference {}", claim_reference)));
+    }
+    if claim_reference >= cloakroom.lost_property.get_next_claim_reference() {
+        return Err(record.error(format!(
+            "claim reference {} has not been issued",
+            claim_reference
+        )));
+    }
+
+    let reason = record.get("reason")?;
+    let taken_in_at = from_ms(record.get("taken_in_at_ms")?);
+    let locker_number = if record.attributes.contains_key("locker_number") {
+        let locker_number = record.get("locker_number")?;
+        if cloakroom.get_locker_spec(locker_number).is_none() {
+            return Err(record.error(format!("locker number {} does not exist", locker_number)));
+        }
+        Some(locker_number)
+    } else {
+        None
+    };
+    let description = match record.attributes.get("description") {
+        Some(description) => decode_text(description)
+            .ok_or_else(|| record.error(format!("invalid description '{}'", description)))?,
+        None => String::new(),
+    };
+    let items = load_items(&cloakroom.item_catalogue, record)?;
+
+    let redemption = if record.attributes.contains_key("token") {
+        if locker_number.is_none() {
+            return Err(record
+                .error("bundle with a token does not say which locker it came from".to_string()));
+        }
+        Some(Redemption {
+            token: record.get("token")?,
+            stay: load_stay(&cloakroom.item_catalogue, record)?,
+        })
+    } else {
+        None
+    };
+
+    cloakroom
+        .lost_property
+        .insert(Bundle::new(
+            claim_reference,
+            items,
+            taken_in_at,
+            locker_number,
+            reason,
+            &description,
+            redemption,
+        ))
+        .map_err(|message| record.error(message))
+}
+
+/// Reads a customer in the queue from their `queue` record. A customer who
+/// has been served is given back the locker allocated to them, which was
+/// restored as abandoned.
+fn load_queue_entry(cloakroom: &mut Cloakroom, record: &Record) -> Result<(), StateFileError> {
+    let ticket: QueueTicket = record.get("ticket")?;
+    if cloakroom.queue.contains(ticket) {
+        return Err(record.error(format!("repeated queue ticket {}", ticket)));
+    }
+    if ticket.get_number() >= cloakroom.queue.next_ticket_number {
+        return Err(record.error(format!("queue ticket {} has not been issued", ticket)));
+    }
+    let entry = QueueEntry::new(
+        ticket,
+        record.get("priority")?,
+        load_items(&cloakroom.item_catalogue, record)?,
+        from_ms(record.get("joined_at_ms")?),
+    );
+
+    if !record.attributes.contains_key("locker_number") {
+        cloakroom.queue.waiting.push(entry);
+        return Ok(());
+    }
+    if !cloakroom.queue.waiting.is_empty() {
+        return Err(record.error(format!(
+            "served customer {} follows customers still waiting",
+            ticket
+        )));
+    }
+
+    let locker_number = record.get("locker_number")?;
+    let served_at = from_ms(record.get("served_at_ms")?);
+    let reopened = match cloakroom.slots.get_mut(locker_number) {
+        Some(mut slot) => match slot.state.take() {
+            Some(LockerInUseState::Abandoned { items }) => {
+                slot.state = Some(LockerInUseState::ContentsBeingChanged { items });
+                true
+            }
+            state => {
+                slot.state = state;
+                false
+            }
+        },
+        None => false,
+    };
+    if !reopened {
+        return Err(record.error(format!(
+            "served customer {} has locker number {} which is not being changed",
+            ticket, locker_number
+        )));
+    }
+    cloakroom.queue.served.push(ServedCustomer {
+        entry,
+        locker: cloakroom.hand_out(locker_number),
+        served_at,
+    });
+    Ok(())
+}
+
+/// Reads the zones of the cloakroom from its `zone` records, which must
+/// cover every locker, in order.
+fn load_zones(cloakroom: &mut Cloakroom, zone_records: &[Record]) -> Result<(), StateFileError> {
+    let mut zones: Vec<Zone> = Vec::new();
+    for record in zone_records {
+        let name = load_text(record, "name")?;
+        let location = if record.attributes.contains_key("location") {
+            load_text(record, "location")?
+        } else {
+            String::new()
+        };
+        if name.is_empty() || zones.iter().any(|zone| zone.get_name() == name) {
+            return Err(record.error(format!("invalid or repeated zone name '{}'", name)));
+        }
+
+        let expected_first = zones
+            .last()
+            .map_or(1, |zone| zone.get_locker_numbers().end() + 1);
+        let first: LockerNumber = record.get("first")?;
+        let last: LockerNumber = record.get("last")?;
+        if first != expected_first {
+            return Err(record.error(format!(
+                "zone '{}' does not start at locker number {}",
+                name, expected_first
+            )));
+        }
+        if last < first || last > cloakroom.get_num_lockers() {
+            return Err(record.error(format!(
+                "zone '{}' has invalid last locker number {}",
+                name, last
+            )));
+        }
+        zones.push(Zone::new(&name, &location, first, last));
+    }
+
+    if let (Some(zone), Some(record)) = (zones.last(), zone_records.last()) {
+        if *zone.get_locker_numbers().end() != cloakroom.get_num_lockers() {
+            return Err(record.error("zones do not cover every locker".to_string()));
+        }
+    }
+    cloakroom.zones = zones;
+    Ok(())
+}
+
+/// Reads an attribute written by [`encode_text`].
+fn load_text(record: &Record, name: &str) -> Result<String, StateFileError> {
+    let encoded = record.get_str(name)?;
+    decode_text(encoded)
+        .ok_or_else(|| record.error(format!("invalid value '{}' for '{}'", encoded, name)))
+}
+
+/// Reads a reservation that has not been claimed from its `reservation`
+/// record, holding back the locker held for it.
+fn load_reservation(cloakroom: &mut Cloakroom, record: &Record) -> Result<(), StateFileError> {
+    let reference: ReservationReference = record.get("reference")?;
+    if cloakroom
+        .reservations
+        .pending
+        .iter()
+        .any(|reservation| reservation.get_reference() == reference)
+    {
+        return Err(record.error(format!("repeated reservation {}", reference)));
+    }
+    if reference.get_number() >= cloakroom.reservations.next_reference_number {
+        return Err(record.error(format!("reservation {} has not been made", reference)));
+    }
+    let holder = record.get_str("holder")?;
+    let holder =
+        decode_text(holder).ok_or_else(|| record.error(format!("invalid holder '{}'", holder)))?;
+
+    let target = if record.attributes.contains_key("locker_number") {
+        let locker_number = record.get("locker_number")?;
+        if cloakroom.get_locker_spec(locker_number).is_none() {
+            return Err(record.error(format!("locker number {} does not exist", locker_number)));
+        }
+        ReservationTarget::Locker(locker_number)
+    } else {
+        ReservationTarget::SizeClass(record.get("size_class")?)
+    };
+    let starts_at = from_ms(record.get("starts_at_ms")?);
+    let ends_at = from_ms(record.get("ends_at_ms")?);
+    if ends_at <= starts_at {
+        return Err(record.error(format!("reservation {} ends before it starts", reference)));
+    }
+
+    let held_locker = if record.attributes.contains_key("held_locker_number") {
+        let locker_number: LockerNumber = record.get("held_locker_number")?;
+        let suits = match target {
+            ReservationTarget::Locker(reserved) => reserved == locker_number,
+            ReservationTarget::SizeClass(size_class) => cloakroom
+                .get_locker_spec(locker_number)
+                .is_some_and(|spec| spec.size_class == Some(size_class)),
+        };
+        if !suits || !cloakroom.free_lockers.is_free(locker_number) {
+            return Err(record.error(format!(
+                "reservation {} holds back locker number {} which is not free or does not suit it",
+                reference, locker_number
+            )));
+        }
+        cloakroom.free_lockers.remove(locker_number);
+        Some(locker_number)
+    } else {
+        None
+    };
+
+    cloakroom.reservations.pending.push(Reservation::new(
+        reference,
+        &holder,
+        target,
+        starts_at,
+        ends_at,
+        held_locker,
+    ));
+    Ok(())
+}
+
+/// Encodes free text as a single attribute value, writing whitespace and
+/// `%` as `%` followed by two hexadecimal digits for each byte of their
+/// UTF-8 encoding.
+fn encode_text(text: &str) -> String {
+    let mut encoded = String::new();
+    for c in text.chars() {
+        if c.is_whitespace() || c == '%' {
+            let mut bytes = [0; 4];
+            for byte in c.encode_utf8(&mut bytes).bytes() {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        } else {
+            encoded.push(c);
+        }
+    }
+    encoded
+}
+
+/// Decodes text written by [`encode_text`], returning `None` if it is not
+/// validly encoded.
+fn decode_text(encoded: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut rest = encoded.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail
+                .get(..2)
+                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))?;
+            bytes.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn load_locker(cloakroom: &mut Cloakroom, record: &Record) -> Result<(), StateFileError> {
+    let locker_number: LockerNumber = record.get("number")?;
+    let capacity = match cloakroom.get_locker_spec(locker_number) {
+        Some(spec) => spec.get_capacity(),
+        None => {
+            return Err(record.error(format!("locker number {} does not exist", locker_number)));
+        }
+    };
+    if cloakroom.with_state(locker_number, |state| state.is_some()) {
+        return Err(record.error(format!("repeated locker number {}", locker_number)));
+    }
+
+    let load_contents = |cloakroom: &Cloakroom| {
+        let items = load_items(&cloakroom.item_catalogue, record)?;
+        if !capacity.can_hold(Capacity::of_items(&items)) {
+            return Err(record.error(format!(
+                "locker number {} does not have room for its contents",
+                locker_number
+            )));
+        }
+        Ok(items)
+    };
+
+    let state = match record.get_str("state")? {
+        "closed" => LockerInUseState::Closed {
+            items: load_contents(cloakroom)?,
+            token: record.get("token")?,
+        },
+
+        "being_changed" | "abandoned" => LockerInUseState::Abandoned {
+            items: load_contents(cloakroom)?,
+        },
+
+        "out_of_service" => {
+            let reason = match record.attributes.get("reason") {
+                Some(reason) => decode_text(reason)
+                    .ok_or_else(|| record.error(format!("invalid reason '{}'", reason)))?,
+                None => String::new(),
+            };
+            cloakroom.mark_in_use(
+                locker_number,
+                LockerInUseState::OutOfService { reason },
+                None,
+            );
+            return Ok(());
+        }
+
+        other => {
+            return Err(record.error(format!("unknown locker state '{}'", other)));
+        }
+    };
+    let stay = load_stay(&cloakroom.item_catalogue, record)?;
+    if matches!(state, LockerInUseState::Closed { .. }) && stay.first_closed.is_none() {
+        return Err(record.error(format!(
+            "closed locker number {} has no 'closed_at_ms'",
+            locker_number
+        )));
+    }
+    cloakroom.mark_in_use(locker_number, state, Some(stay));
+
+    Ok(())
+}
@@ -0,0 +1,470 @@
+//! Queueing customers when there are no free lockers.
+//!
+//! A customer who cannot be given a locker can join the cloakroom's queue,
+//! saying what they want to deposit, and is given a [`QueueTicket`]. Each
+//! time a locker is freed, e.g. by [`Cloakroom::vacate_locker`], the
+//! customers waiting are served in order of [`PriorityClass`], and in the
+//! order in which they joined within each class. A customer is passed over
+//! while no free locker can hold their items, so that one who needs a large
+//! locker does not hold up those behind them who need only a small one.
+//!
+//! Serving a customer allocates a locker to them, which the cloakroom keeps
+//! until they take it with [`Cloakroom::take_served_locker`] and fill it.
+//! The time each customer waited, from joining the queue until they were
+//! served, is kept until then.
+//!
+//! A cloakroom can be given a pickup timeout with
+//! [`Cloakroom::with_queue_pickup_timeout`]. A customer who has not taken
+//! their locker that long after being served loses it, and it is passed to
+//! the next customer in the queue who can use it. Their ticket is then no
+//! longer valid. As with reservations, this happens when the cloakroom is
+//! next used after the time comes.
+
+use super::audit::AuditAction;
+use super::{unknown_item_category, Capacity, Cloakroom, CloakroomError, Locker, LockerNumber};
+use crate::items::CloakroomItems;
+use std::cmp::Reverse;
+use std::error;
+use std::fmt;
+use std::mem;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+/// Ticket given to a customer who joins the queue. It is written as `Q`
+/// followed by six or more digits, e.g. `Q000042`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct QueueTicket(u32);
+
+impl QueueTicket {
+    pub(crate) fn get_number(&self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for QueueTicket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Q{:06}", self.0)
+    }
+}
+
+impl FromStr for QueueTicket {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<QueueTicket, ()> {
+        match s.strip_prefix('Q') {
+            Some(digits) if digits.bytes().all(|b| b.is_ascii_digit()) => {
+                digits.parse().map(QueueTicket).map_err(|_| ())
+            }
+            _ => Err(()),
+        }
+    }
+}
+
+/// How soon a customer in the queue is served. Customers in a higher class
+/// are served before everyone in a lower one who can use the same locker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PriorityClass {
+    Standard,
+    /// E.g. customers with access needs, or performers.
+    Priority,
+}
+
+impl fmt::Display for PriorityClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PriorityClass::Standard => "standard",
+            PriorityClass::Priority => "priority",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for PriorityClass {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<PriorityClass, ()> {
+        match s {
+            "standard" => Ok(PriorityClass::Standard),
+            "priority" => Ok(PriorityClass::Priority),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A customer waiting in the queue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueEntry {
+    ticket: QueueTicket,
+    priority: PriorityClass,
+    items: CloakroomItems,
+    joined_at: SystemTime,
+}
+
+impl QueueEntry {
+    pub(super) fn new(
+        ticket: QueueTicket,
+        priority: PriorityClass,
+        items: CloakroomItems,
+        joined_at: SystemTime,
+    ) -> QueueEntry {
+        QueueEntry {
+            ticket,
+            priority,
+            items,
+            joined_at,
+        }
+    }
+
+    pub fn get_ticket(&self) -> QueueTicket {
+        self.ticket
+    }
+
+    pub fn get_priority(&self) -> PriorityClass {
+        self.priority
+    }
+
+    /// Returns the items the customer wants to deposit, which decide the
+    /// lockers they can be given.
+    pub fn get_items(&self) -> &CloakroomItems {
+        &self.items
+    }
+
+    pub fn get_joined_at(&self) -> SystemTime {
+        self.joined_at
+    }
+}
+
+/// A customer who has been served, and the locker allocated to them.
+pub(super) struct ServedCustomer {
+    pub(super) entry: QueueEntry,
+    pub(super) locker: Locker,
+    pub(super) served_at: SystemTime,
+}
+
+/// The customers in a cloakroom's queue.
+pub(super) struct CustomerQueue {
+    // In the order in which they joined.
+    pub(super) waiting: Vec<QueueEntry>,
+    // In the order in which they were served.
+    pub(super) served: Vec<ServedCustomer>,
+    pub(super) next_ticket_number: u32,
+    pub(super) pickup_timeout: Option<Duration>,
+}
+
+impl CustomerQueue {
+    pub(super) fn new() -> CustomerQueue {
+        CustomerQueue {
+            waiting: Vec::new(),
+            served: Vec::new(),
+            next_ticket_number: 1,
+            pickup_timeout: None,
+        }
+    }
+
+    pub(super) fn contains(&self, ticket: QueueTicket) -> bool {
+        self.waiting.iter().any(|entry| entry.ticket == ticket)
+            || self
+                .served
+                .iter()
+                .any(|served| served.entry.ticket == ticket)
+    }
+}
+
+/// Reasons why a queue ticket cannot be used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueueError {
+    /// The ticket was never issued, or its customer has left the queue or
+    /// taken their locker.
+    UnknownTicket(QueueTicket),
+    /// The customer is still waiting for a locker.
+    NotServed(QueueTicket),
+}
+
+impl fmt::Display for QueueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueueError::UnknownTicket(ticket) => {
+                write!(f, "no customer in the queue has ticket {}", ticket)
+            }
+
+            QueueError::NotServed(ticket) => {
+                write!(f, "customer with ticket {} is still waiting", ticket)
+            }
+        }
+    }
+}
+
+impl error::Error for QueueError {}
+
+impl Cloakroom {
+    /// Sets how long a customer who has been served has to take their
+    /// locker before it is passed to the next customer, as described in the
+    /// [module documentation](self). Customers keep their lockers for as
+    /// long as they like unless this is called. The timeout is not saved
+    /// with the cloakroom.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cloakroom_model::cloakroom::queue::{PriorityClass, QueueError};
+    /// use cloakroom_model::cloakroom::Cloakroom;
+    /// use cloakroom_model::clock::MockClock;
+    /// use cloakroom_model::items::CloakroomItems;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// let clock = MockClock::new(UNIX_EPOCH);
+    /// let mut cloakroom = Cloakroom::new(1, 5)
+    ///     .with_clock(clock.clone())
+    ///     .with_queue_pickup_timeout(Duration::from_secs(5 * 60));
+    /// let no_items = CloakroomItems::new();
+    /// let first = cloakroom.join_queue(&no_items, PriorityClass::Standard).unwrap();
+    /// let second = cloakroom.join_queue(&no_items, PriorityClass::Standard).unwrap();
+    /// assert_eq!(cloakroom.get_served_tickets(), vec![first]);
+    ///
+    /// clock.advance(Duration::from_secs(5 * 60));
+    /// assert_eq!(
+    ///     cloakroom.take_served_locker(first).err(),
+    ///     Some(QueueError::UnknownTicket(first))
+    /// );
+    /// assert_eq!(cloakroom.get_served_tickets(), vec![second]);
+    /// ```
+    pub fn with_queue_pickup_timeout(mut self, pickup_timeout: Duration) -> Cloakroom {
+        self.queue.pickup_timeout = Some(pickup_timeout);
+        self
+    }
+
+    pub fn get_queue_pickup_timeout(&self) -> Option<Duration> {
+        self.queue.pickup_timeout
+    }
+
+    /// Adds a customer wanting to deposit `items` to the queue, as described
+    /// in the [module documentation](self), and returns their ticket. They
+    /// are served at once if a free locker can hold the items.
+    /// `CloakroomError::NoLockerBigEnough` is returned if no locker in the
+    /// cloakroom could ever hold them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cloakroom_model::cloakroom::queue::PriorityClass;
+    /// use cloakroom_model::cloakroom::Cloakroom;
+    /// use cloakroom_model::items::CloakroomItems;
+    ///
+    /// let mut cloakroom = Cloakroom::new(1, 5);
+    /// let no_items = CloakroomItems::new();
+    /// let locker = cloakroom.find_free_locker(&no_items).into_result().unwrap();
+    ///
+    /// let first = cloakroom.join_queue(&no_items, PriorityClass::Standard).unwrap();
+    /// let second = cloakroom.join_queue(&no_items, PriorityClass::Priority).unwrap();
+    /// assert!(cloakroom.take_served_locker(second).is_err());
+    ///
+    /// // The customer with priority is served first.
+    /// cloakroom.vacate_locker(locker).unwrap();
+    /// assert_eq!(cloakroom.get_served_tickets(), vec![second]);
+    /// let locker = cloakroom.take_served_locker(second).unwrap();
+    /// assert_eq!(locker.get_locker_number(), 1);
+    /// assert_eq!(cloakroom.get_queue()[0].get_ticket(), first);
+    /// ```
+    pub fn join_queue(
+        &mut self,
+        items: &CloakroomItems,
+        priority: PriorityClass,
+    ) -> Result<QueueTicket, CloakroomError> {
+        self.record_abandoned_lockers();
+        self.expire_served_customers();
+        self.update_reservations();
+        let items = items
+            .convert_to_catalogue(&self.item_catalogue)
+            .map_err(unknown_item_category)?;
+        let required_capacity = Capacity::of_items(&items);
+        if !self
+            .lockers
+            .iter()
+            .any(|spec| spec.get_capacity().can_hold(required_capacity))
+        {
+            return Err(CloakroomError::NoLockerBigEnough);
+        }
+
+        let ticket = QueueTicket(self.queue.next_ticket_number);
+        self.queue.next_ticket_number += 1;
+        let joined_at = self.now();
+        self.queue
+            .waiting
+            .push(QueueEntry::new(ticket, priority, items, joined_at));
+        self.serve_queue();
+        Ok(ticket)
+    }
+
+    /// Removes a customer from the queue. If they have already been served,
+    /// the locker allocated to them is vacated, and may be given to the
+    /// next customer.
+    pub fn leave_queue(&mut self, ticket: QueueTicket) -> Result<(), QueueError> {
+        self.expire_served_customers();
+        if let Some(index) = self
+            .queue
+            .waiting
+            .iter()
+            .position(|entry| entry.ticket == ticket)
+        {
+            self.queue.waiting.remove(index);
+            return Ok(());
+        }
+
+        // The locker was handed out by this cloakroom, so giving it back
+        // cannot fail.
+        let served = self.take_served(ticket)?;
+        let _ = self.vacate_locker(served.locker);
+        Ok(())
+    }
+
+    /// Hands over the locker allocated to a customer who has been served,
+    /// removing them from the queue. The locker is empty, ready for their
+    /// items to be placed in it.
+    pub fn take_served_locker(&mut self, ticket: QueueTicket) -> Result<Locker, QueueError> {
+        self.expire_served_customers();
+        self.take_served(ticket).map(|served| served.locker)
+    }
+
+    /// Returns the customers waiting to be served, in the order in which
+    /// they would be served if every locker suited them all.
+    pub fn get_queue(&self) -> Vec<&QueueEntry> {
+        let mut entries: Vec<&QueueEntry> = self.queue.waiting.iter().collect();
+        entries.sort_by_key(|entry| Reverse(entry.priority));
+        entries
+    }
+
+    /// Returns the tickets of the customers who have been served but have
+    /// not yet taken their locker, in the order in which they were served.
+    pub fn get_served_tickets(&self) -> Vec<QueueTicket> {
+        self.queue
+            .served
+            .iter()
+            .map(|served| served.entry.ticket)
+            .collect()
+    }
+
+    /// Returns the number of the locker allocated to a customer who has
+    /// been served.
+    pub fn get_served_locker_number(
+        &self,
+        ticket: QueueTicket,
+    ) -> Result<LockerNumber, QueueError> {
+        match self.find_served(ticket) {
+            Some(served) => Ok(served.locker.get_locker_number()),
+            None => Err(self.not_served_error(ticket)),
+        }
+    }
+
+    /// Returns how long a customer has been waiting, or, if they have been
+    /// served, how long they waited.
+    pub fn get_waiting_time(&self, ticket: QueueTicket) -> Result<Duration, QueueError> {
+        let (joined_at, until) = match self.find_served(ticket) {
+            Some(served) => (served.entry.joined_at, served.served_at),
+            None => match self
+                .queue
+                .waiting
+                .iter()
+                .find(|entry| entry.ticket == ticket)
+            {
+                Some(entry) => (entry.joined_at, self.now()),
+                None => return Err(QueueError::UnknownTicket(ticket)),
+            },
+        };
+        Ok(until.duration_since(joined_at).unwrap_or_default())
+    }
+
+    /// Allocates free lockers to the customers waiting, in turn, as
+    /// described in the [module documentation](self).
+    pub(super) fn serve_queue(&mut self) {
+        // Lockers are held back for reservations before anyone in the queue
+        // is given them.
+        self.hold_reserved_lockers();
+        let mut order: Vec<usize> = (0..self.queue.waiting.len()).collect();
+        order.sort_by_key(|&index| Reverse(self.queue.waiting[index].priority));
+
+        let mut served = Vec::new();
+        for index in order {
+            if self.free_lockers.is_empty() {
+                break;
+            }
+            let required_capacity = Capacity::of_items(&self.queue.waiting[index].items);
+            if let Some(locker_number) = self
+                .allocation_strategy
+                .choose_locker(&self.free_lockers, required_capacity)
+            {
+                self.emit(locker_number, AuditAction::Allocated);
+                served.push((index, self.hand_out(locker_number)));
+            }
+        }
+        if served.is_empty() {
+            return;
+        }
+
+        let served_at = self.now();
+        let mut is_served = vec![false; self.queue.waiting.len()];
+        for (index, locker) in served {
+            is_served[index] = true;
+            let entry = self.queue.waiting[index].clone();
+            self.queue.served.push(ServedCustomer {
+                entry,
+                locker,
+                served_at,
+            });
+        }
+        let mut is_served = is_served.into_iter();
+        self.queue
+            .waiting
+            .retain(|_| !is_served.next().unwrap_or(false));
+    }
+
+    /// Removes the customers who were served at least the pickup timeout
+    /// ago from the queue, passing their lockers to the next customers.
+    pub(super) fn expire_served_customers(&mut self) {
+        let pickup_timeout = match self.queue.pickup_timeout {
+            Some(pickup_timeout) => pickup_timeout,
+            None => return,
+        };
+        let now = self.now();
+        let (expired, served): (Vec<ServedCustomer>, Vec<ServedCustomer>) =
+            mem::take(&mut self.queue.served)
+                .into_iter()
+                .partition(|served| {
+                    now.duration_since(served.served_at)
+                        .is_ok_and(|waited| waited >= pickup_timeout)
+                });
+        self.queue.served = served;
+
+        // The lockers were handed out by this cloakroom, so giving them back
+        // cannot fail. Each one vacated is offered to the queue.
+        for served in expired {
+            let _ = self.vacate_locker(served.locker);
+        }
+    }
+
+    fn find_served(&self, ticket: QueueTicket) -> Option<&ServedCustomer> {
+        self.queue
+            .served
+            .iter()
+            .find(|served| served.entry.ticket == ticket)
+    }
+
+    fn take_served(&mut self, ticket: QueueTicket) -> Result<ServedCustomer, QueueError> {
+        match self
+            .queue
+            .served
+            .iter()
+            .position(|served| served.entry.ticket == ticket)
+        {
+            Some(index) => Ok(self.queue.served.remove(index)),
+            None => Err(self.not_served_error(ticket)),
+        }
+    }
+
+    fn not_served_error(&self, ticket: QueueTicket) -> QueueError {
+        if self.queue.contains(ticket) {
+            QueueError::NotServed(ticket)
+        } else {
+            QueueError::UnknownTicket(ticket)
+        }
+    }
+}
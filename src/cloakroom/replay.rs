@@ -0,0 +1,272 @@
+//! Rebuilding the state of a cloakroom from its audit events.
+//!
+//! Every change to a cloakroom's lockers is made by applying an
+//! [`AuditEvent`], so replaying the events logged by a cloakroom onto a new
+//! cloakroom with the same lockers and item catalogue gives the same locker
+//! states. Replaying only the events up to a given time shows what the
+//! cloakroom looked like then.
+//!
+//! Tokens are secret, so they are not logged. Closed lockers in a replayed
+//! cloakroom are given new tokens, and keys issued by the original
+//! cloakroom do not open them. Nor is the state of the allocation strategy
+//! logged, so a replayed cloakroom may choose different lockers from then
+//! on if its strategy depends on earlier choices, as `RoundRobin` and
+//! `SeededRandom` do.
+
+use super::audit::{AuditAction, AuditEvent};
+use super::lost_key::LostKeyRecord;
+use super::slot::{self, LockerSlot};
+use super::{Cloakroom, LockerInUseState, LockerNumber};
+use crate::items::CloakroomItems;
+use crate::lost_property::{Bundle, Redemption};
+use crate::pricing::Stay;
+use std::error;
+use std::fmt;
+use std::time::SystemTime;
+
+/// An event could not be applied to the cloakroom, e.g. because it closes a
+/// locker that is already closed. This means that events are missing from
+/// the log, or that it belongs to a different cloakroom.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayError {
+    /// Index of the event in the events being replayed, starting from 0.
+    pub event_index: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cannot replay event {}: {}",
+            self.event_index, self.message
+        )
+    }
+}
+
+impl error::Error for ReplayError {}
+
+impl Cloakroom {
+    /// Applies `events`, in order, to the cloakroom, which would normally
+    /// have just been created with the same lockers and item catalogue as
+    /// the one that logged them. The events are not sent to the cloakroom's
+    /// audit sink.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a replayed locker is closed and the operating system's
+    /// random number generator cannot be read to give it a token, as
+    /// [`Cloakroom::close_locker`] does.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cloakroom_model::cloakroom::audit::InMemoryAuditSink;
+    /// use cloakroom_model::cloakroom::{Cloakroom, LockerState};
+    /// use cloakroom_model::items::CloakroomItems;
+    ///
+    /// let mut cloakroom = Cloakroom::new(2, 5).with_audit_sink(InMemoryAuditSink::new());
+    /// let mut locker = cloakroom.find_free_locker(&CloakroomItems::new()).into_result().unwrap();
+    /// locker.set_num_items("coats", 3).unwrap();
+    /// let _key = cloakroom.close_locker(locker).unwrap();
+    ///
+    /// let events = cloakroom.get_audit_events().unwrap();
+    /// let replayed = Cloakroom::new(2, 5).replay(&events).unwrap();
+    /// match replayed.get_locker_state(1) {
+    ///     LockerState::Closed(items, _) => assert_eq!(items.get_num_items("coats"), Some(3)),
+    ///     _ => panic!("locker is not closed"),
+    /// }
+    /// ```
+    pub fn replay<'a, I>(self, events: I) -> Result<Cloakroom, ReplayError>
+    where
+        I: IntoIterator<Item = &'a AuditEvent>,
+    {
+        self.replay_events(events, None)
+    }
+
+    /// Applies the events that happened at or before `until`, giving the
+    /// state of the cloakroom at that time. See [`Cloakroom::replay`].
+    pub fn replay_until<'a, I>(self, events: I, until: SystemTime) -> Result<Cloakroom, ReplayError>
+    where
+        I: IntoIterator<Item = &'a AuditEvent>,
+    {
+        self.replay_events(events, Some(until))
+    }
+
+    fn replay_events<'a, I>(
+        mut self,
+        events: I,
+        until: Option<SystemTime>,
+    ) -> Result<Cloakroom, ReplayError>
+    where
+        I: IntoIterator<Item = &'a AuditEvent>,
+    {
+        for (event_index, event) in events.into_iter().enumerate() {
+            if until.is_some_and(|until| event.get_timestamp() > until) {
+                break;
+            }
+            self.apply(
+                event.get_timestamp(),
+                event.get_locker_number(),
+                event.get_action(),
+            )
+            .map_err(|message| ReplayError {
+                event_index,
+                message,
+            })?;
+        }
+
+        Ok(self)
+    }
+
+    /// Changes the state of a locker as described by `action`, which
+    /// happened at `timestamp`, or explains, leaving the locker as it was,
+    /// why the action cannot happen to the locker in its current state.
+    /// The locker's own state is changed by its [slot](super::slot), and
+    /// the rest of the cloakroom here.
+    pub(super) fn apply(
+        &mut self,
+        timestamp: SystemTime,
+        locker_number: LockerNumber,
+        action: &AuditAction,
+    ) -> Result<(), String> {
+        let capacity = match self.get_locker_spec(locker_number) {
+            Some(spec) => spec.get_capacity(),
+            None => return Err(format!("locker number {} does not exist", locker_number)),
+        };
+
+        // Bundles are claimed and disposed of from the lost-property store,
+        // leaving the locker the items came from, which may be in use again,
+        // as it is.
+        if let AuditAction::ClaimRedeemed { claim_reference }
+        | AuditAction::LostPropertyDisposed { claim_reference } = *action
+        {
+            match self.lost_property.get_bundle(claim_reference) {
+                Some(bundle) if bundle.get_locker_number() == Some(locker_number) => {
+                    self.lost_property.take(claim_reference);
+                    return Ok(());
+                }
+                _ => {
+                    return Err(format!(
+                        "no lost property {} from locker number {}",
+                        claim_reference, locker_number
+                    ));
+                }
+            }
+        }
+
+        self.slot(locker_number)
+            .check(locker_number, action, capacity, &self.item_catalogue)?;
+
+        let transferred_stay = match action {
+            AuditAction::TransferredIn { from, items } => {
+                Some(self.transferred_stay(locker_number, *from, items)?)
+            }
+
+            AuditAction::MovedToLostProperty {
+                claim_reference, ..
+            } if self.lost_property.get_bundle(*claim_reference).is_some() => {
+                return Err(format!("claim reference {} is in use", claim_reference));
+            }
+
+            _ => None,
+        };
+
+        let mut slot = self
+            .slots
+            .get_mut(locker_number)
+            .expect("locker number is in range");
+        let freed = slot.change(
+            timestamp,
+            action,
+            &self.item_catalogue,
+            &self.token_generator,
+        );
+        match action {
+            AuditAction::Allocated | AuditAction::TakenOutOfService { .. } => {
+                self.free_lockers.remove(locker_number);
+            }
+
+            AuditAction::OpenedWithoutKey { fee_pence } => {
+                if let Some(LockerInUseState::ContentsBeingChanged { items }) = &slot.state {
+                    self.lost_key_log.push(LostKeyRecord::new(
+                        locker_number,
+                        items.clone(),
+                        *fee_pence,
+                    ));
+                }
+            }
+
+            AuditAction::TransferredIn { .. } => {
+                self.free_lockers.remove(locker_number);
+                let mut stay = transferred_stay.expect("stay was found");
+                stay.record_access(timestamp);
+                slot.stay = Some(stay);
+            }
+
+            AuditAction::TransferredOut { .. }
+            | AuditAction::ReturnedToService
+            | AuditAction::Vacated { .. } => {
+                self.free_lockers.release(locker_number);
+            }
+
+            AuditAction::MovedToLostProperty {
+                claim_reference,
+                reason,
+                description,
+                ..
+            } => {
+                self.free_lockers.release(locker_number);
+                let (items, token, stay) = match freed {
+                    Some(LockerSlot {
+                        state: Some(LockerInUseState::Closed { items, token }),
+                        stay: Some(stay),
+                    }) => (items, token, stay),
+                    _ => unreachable!("locker number {} was not closed", locker_number),
+                };
+                self.lost_property
+                    .insert(Bundle::new(
+                        *claim_reference,
+                        items,
+                        timestamp,
+                        Some(locker_number),
+                        *reason,
+                        description,
+                        Some(Redemption { token, stay }),
+                    ))
+                    .expect("claim reference was checked");
+            }
+
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Returns the stay of the locker that a transfer into locker number
+    /// `locker_number` takes `items` from, after checking that the locker
+    /// is closed and holds them.
+    fn transferred_stay(
+        &self,
+        locker_number: LockerNumber,
+        from: LockerNumber,
+        items: &CloakroomItems,
+    ) -> Result<Stay, String> {
+        if let Some(from_slot) = self.slots.get(from) {
+            if let (
+                Some(LockerInUseState::Closed {
+                    items: recorded, ..
+                }),
+                Some(stay),
+            ) = (&from_slot.state, &from_slot.stay)
+            {
+                slot::check_items(&self.item_catalogue, items, recorded)?;
+                return Ok(stay.clone());
+            }
+        }
+        Err(format!(
+            "transfer into locker number {} from locker number {} which is not closed",
+            locker_number, from
+        ))
+    }
+}
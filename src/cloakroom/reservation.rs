@@ -0,0 +1,514 @@
+//! Reserving lockers in advance.
+//!
+//! Event organisers can book a locker for a named holder, e.g. a VIP guest,
+//! over a window of time. A reservation is either for a particular locker
+//! or for any locker of a [`SizeClass`]. From the start of the window the
+//! cloakroom holds a free locker back for the reservation, so that
+//! [`Cloakroom::find_free_locker`], transfers and the queue pass it over. If
+//! no suitable locker is free when the window starts, the first one to be
+//! freed is held back.
+//!
+//! A locker held back is still free, as nothing is in it, until the holder
+//! claims the reservation with [`Cloakroom::claim_reservation`] and is
+//! handed the locker. A reservation not claimed by the end of its window
+//! expires, and the locker held back for it can be used by anyone again.
+//! Reservations start and expire when the cloakroom is next used after the
+//! time comes.
+//!
+//! Reservations are saved with the cloakroom, but they are not
+//! [audit](super::audit) events: making, cancelling and expiring them, and
+//! holding lockers back, are not logged and are not rebuilt by
+//! [`Cloakroom::replay`]. Only the locker being handed out when a
+//! reservation is claimed is logged, as an `Allocated` event.
+
+use super::audit::AuditAction;
+use super::{Cloakroom, Locker, LockerNumber, SizeClass};
+use std::error;
+use std::fmt;
+use std::mem;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// Reference given for a reservation. It is written as `R` followed by six
+/// or more digits, e.g. `R000042`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ReservationReference(u32);
+
+impl ReservationReference {
+    pub(crate) fn get_number(&self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for ReservationReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "R{:06}", self.0)
+    }
+}
+
+impl FromStr for ReservationReference {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<ReservationReference, ()> {
+        match s.strip_prefix('R') {
+            Some(digits) if digits.bytes().all(|b| b.is_ascii_digit()) => {
+                digits.parse().map(ReservationReference).map_err(|_| ())
+            }
+            _ => Err(()),
+        }
+    }
+}
+
+/// What a reservation is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReservationTarget {
+    /// A particular locker.
+    Locker(LockerNumber),
+    /// Any locker of a size class.
+    SizeClass(SizeClass),
+}
+
+/// A reservation that has not yet been claimed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reservation {
+    reference: ReservationReference,
+    holder: String,
+    target: ReservationTarget,
+    starts_at: SystemTime,
+    ends_at: SystemTime,
+    held_locker: Option<LockerNumber>,
+}
+
+impl Reservation {
+    pub(super) fn new(
+        reference: ReservationReference,
+        holder: &str,
+        target: ReservationTarget,
+        starts_at: SystemTime,
+        ends_at: SystemTime,
+        held_locker: Option<LockerNumber>,
+    ) -> Reservation {
+        Reservation {
+            reference,
+            holder: holder.to_string(),
+            target,
+            starts_at,
+            ends_at,
+            held_locker,
+        }
+    }
+
+    pub fn get_reference(&self) -> ReservationReference {
+        self.reference
+    }
+
+    pub fn get_holder(&self) -> &str {
+        &self.holder
+    }
+
+    pub fn get_target(&self) -> ReservationTarget {
+        self.target
+    }
+
+    pub fn get_starts_at(&self) -> SystemTime {
+        self.starts_at
+    }
+
+    pub fn get_ends_at(&self) -> SystemTime {
+        self.ends_at
+    }
+
+    /// Returns the number of the locker held back for the reservation, or
+    /// `None` if its window has not started or no suitable locker has been
+    /// free since it did.
+    pub fn get_held_locker_number(&self) -> Option<LockerNumber> {
+        self.held_locker
+    }
+
+    fn overlaps(&self, starts_at: SystemTime, ends_at: SystemTime) -> bool {
+        self.starts_at < ends_at && starts_at < self.ends_at
+    }
+}
+
+/// The reservations of a cloakroom that have not been claimed.
+pub(super) struct Reservations {
+    // In the order in which they were made.
+    pub(super) pending: Vec<Reservation>,
+    pub(super) next_reference_number: u32,
+}
+
+impl Reservations {
+    pub(super) fn new() -> Reservations {
+        Reservations {
+            pending: Vec::new(),
+            next_reference_number: 1,
+        }
+    }
+
+    /// Returns whether `locker_number` is held back for a reservation.
+    pub(super) fn holds(&self, locker_number: LockerNumber) -> bool {
+        self.pending
+            .iter()
+            .any(|reservation| reservation.held_locker == Some(locker_number))
+    }
+}
+
+/// Reasons why a locker cannot be reserved, or a reservation claimed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReservationError {
+    /// The reservation does not name who it is for.
+    MissingHolder,
+    /// The window ends before it starts, or has already ended.
+    InvalidWindow,
+    /// The locker to be reserved does not exist.
+    UnknownLocker(LockerNumber),
+    /// No locker in the cloakroom is of the size class to be reserved.
+    NoLockersOfSizeClass(SizeClass),
+    /// The locker, or every locker of its size class, is already reserved
+    /// for part of the window.
+    AlreadyReserved,
+    /// The reference was never given, or its reservation has been claimed,
+    /// cancelled or has expired.
+    UnknownReservation(ReservationReference),
+    /// The reservation is for someone else.
+    WrongHolder(ReservationReference),
+    /// The window of the reservation has not started.
+    NotStarted(ReservationReference),
+    /// No suitable locker has been free since the window started.
+    NoLockerHeld(ReservationReference),
+}
+
+impl fmt::Display for ReservationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReservationError::MissingHolder => write!(f, "reservation has no holder"),
+
+            ReservationError::InvalidWindow => {
+                write!(f, "reservation must end after it starts, and in the future")
+            }
+
+            ReservationError::UnknownLocker(locker_number) => {
+                write!(f, "no record found for locker number {}", locker_number)
+            }
+
+            ReservationError::NoLockersOfSizeClass(size_class) => {
+                write!(f, "no lockers are {}", size_class)
+            }
+
+            ReservationError::AlreadyReserved => {
+                write!(f, "no locker is left to reserve for that time")
+            }
+
+            ReservationError::UnknownReservation(reference) => {
+                write!(f, "no reservation has reference {}", reference)
+            }
+
+            ReservationError::WrongHolder(reference) => {
+                write!(f, "reservation {} is for someone else", reference)
+            }
+
+            ReservationError::NotStarted(reference) => {
+                write!(f, "reservation {} has not started", reference)
+            }
+
+            ReservationError::NoLockerHeld(reference) => {
+                write!(f, "no locker is free yet for reservation {}", reference)
+            }
+        }
+    }
+}
+
+impl error::Error for ReservationError {}
+
+/// Returns the largest number of `reservations` whose windows overlap at
+/// any one time from `starts_at` until `ends_at`.
+fn peak_overlap(
+    reservations: &[&Reservation],
+    starts_at: SystemTime,
+    ends_at: SystemTime,
+) -> usize {
+    // The number only goes up when a window starts, so the peak is at the
+    // start of the window given or of one of the reservations.
+    reservations
+        .iter()
+        .map(|reservation| reservation.starts_at)
+        .filter(|&time| starts_at < time && time < ends_at)
+        .chain(Some(starts_at))
+        .map(|time| {
+            reservations
+                .iter()
+                .filter(|reservation| reservation.starts_at <= time && time < reservation.ends_at)
+                .count()
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+impl Cloakroom {
+    /// Reserves a locker for `holder` from `starts_at` until `ends_at`, as
+    /// described in the [module documentation](self), and returns the
+    /// reservation's reference. A particular locker can only be reserved
+    /// once at a time, and at no time can more lockers of a size class be
+    /// reserved than there are.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cloakroom_model::cloakroom::reservation::ReservationTarget;
+    /// use cloakroom_model::cloakroom::{Cloakroom, LockerSpec, SizeClass};
+    /// use cloakroom_model::clock::MockClock;
+    /// use cloakroom_model::items::CloakroomItems;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// let clock = MockClock::new(UNIX_EPOCH);
+    /// let mut cloakroom = Cloakroom::with_lockers(vec![
+    ///     LockerSpec::new(5),
+    ///     LockerSpec::new(20).with_size_class(SizeClass::Large),
+    /// ])
+    /// .with_clock(clock.clone());
+    /// let hour = Duration::from_secs(3600);
+    /// let reference = cloakroom
+    ///     .reserve_locker(
+    ///         "Ada",
+    ///         ReservationTarget::SizeClass(SizeClass::Large),
+    ///         UNIX_EPOCH,
+    ///         UNIX_EPOCH + hour,
+    ///     )
+    ///     .unwrap();
+    ///
+    /// // The large locker is held back for the reservation.
+    /// let no_items = CloakroomItems::new();
+    /// let locker = cloakroom.find_free_locker(&no_items).into_result().unwrap();
+    /// assert_eq!(locker.get_locker_number(), 1);
+    /// assert!(cloakroom.find_free_locker(&no_items).into_result().is_err());
+    ///
+    /// let locker = cloakroom.claim_reservation(reference, "Ada").unwrap();
+    /// assert_eq!(locker.get_locker_number(), 2);
+    /// ```
+    pub fn reserve_locker(
+        &mut self,
+        holder: &str,
+        target: ReservationTarget,
+        starts_at: SystemTime,
+        ends_at: SystemTime,
+    ) -> Result<ReservationReference, ReservationError> {
+        self.update_reservations();
+        if holder.is_empty() {
+            return Err(ReservationError::MissingHolder);
+        }
+        if ends_at <= starts_at || ends_at <= self.now() {
+            return Err(ReservationError::InvalidWindow);
+        }
+        let size_class = match target {
+            ReservationTarget::Locker(locker_number) => match self.get_locker_spec(locker_number) {
+                Some(spec) => spec.size_class,
+                None => return Err(ReservationError::UnknownLocker(locker_number)),
+            },
+            ReservationTarget::SizeClass(size_class) => {
+                if self.count_lockers_of_size_class(size_class) == 0 {
+                    return Err(ReservationError::NoLockersOfSizeClass(size_class));
+                }
+                Some(size_class)
+            }
+        };
+
+        let overlapping: Vec<&Reservation> = self
+            .reservations
+            .pending
+            .iter()
+            .filter(|reservation| reservation.overlaps(starts_at, ends_at))
+            .collect();
+        if let ReservationTarget::Locker(_) = target {
+            if overlapping
+                .iter()
+                .any(|reservation| reservation.target == target)
+            {
+                return Err(ReservationError::AlreadyReserved);
+            }
+        }
+        if let Some(size_class) = size_class {
+            let same_size_class: Vec<&Reservation> = overlapping
+                .into_iter()
+                .filter(|reservation| {
+                    self.get_reserved_size_class(reservation.target) == Some(size_class)
+                })
+                .collect();
+            if peak_overlap(&same_size_class, starts_at, ends_at)
+                >= self.count_lockers_of_size_class(size_class)
+            {
+                return Err(ReservationError::AlreadyReserved);
+            }
+        }
+
+        let reference = ReservationReference(self.reservations.next_reference_number);
+        self.reservations.next_reference_number += 1;
+        self.reservations.pending.push(Reservation::new(
+            reference, holder, target, starts_at, ends_at, None,
+        ));
+        self.hold_reserved_lockers();
+        Ok(reference)
+    }
+
+    /// Hands `holder` the locker held back for their reservation, which is
+    /// then claimed. The locker is empty, ready for their items to be
+    /// placed in it.
+    pub fn claim_reservation(
+        &mut self,
+        reference: ReservationReference,
+        holder: &str,
+    ) -> Result<Locker, ReservationError> {
+        self.record_abandoned_lockers();
+        self.expire_served_customers();
+        self.update_reservations();
+        let index = self.find_reservation(reference)?;
+        let reservation = &self.reservations.pending[index];
+        if reservation.holder != holder {
+            return Err(ReservationError::WrongHolder(reference));
+        }
+        if self.now() < reservation.starts_at {
+            return Err(ReservationError::NotStarted(reference));
+        }
+        let locker_number = match reservation.held_locker {
+            Some(locker_number) => locker_number,
+            None => return Err(ReservationError::NoLockerHeld(reference)),
+        };
+
+        self.reservations.pending.remove(index);
+        self.emit(locker_number, AuditAction::Allocated);
+        Ok(self.hand_out(locker_number))
+    }
+
+    /// Cancels a reservation, so that any locker held back for it can be
+    /// used by anyone.
+    pub fn cancel_reservation(
+        &mut self,
+        reference: ReservationReference,
+    ) -> Result<(), ReservationError> {
+        let index = self.find_reservation(reference)?;
+        let reservation = self.reservations.pending.remove(index);
+        if let Some(locker_number) = reservation.held_locker {
+            self.free_lockers.reinstate(locker_number);
+            self.serve_queue();
+        }
+        Ok(())
+    }
+
+    /// Returns the reservations that have not been claimed, in the order in
+    /// which their windows start.
+    pub fn get_reservations(&self) -> Vec<&Reservation> {
+        let mut reservations: Vec<&Reservation> = self.reservations.pending.iter().collect();
+        reservations.sort_by_key(|reservation| reservation.starts_at);
+        reservations
+    }
+
+    /// Expires the reservations whose windows have ended, so that the
+    /// lockers held back for them can be used, and holds lockers back for
+    /// those whose windows have started.
+    pub(super) fn update_reservations(&mut self) {
+        let now = self.now();
+        let (expired, pending): (Vec<Reservation>, Vec<Reservation>) =
+            mem::take(&mut self.reservations.pending)
+                .into_iter()
+                .partition(|reservation| reservation.ends_at <= now);
+        self.reservations.pending = pending;
+
+        let mut released = false;
+        for locker_number in expired
+            .iter()
+            .filter_map(|reservation| reservation.held_locker)
+        {
+            self.free_lockers.reinstate(locker_number);
+            released = true;
+        }
+        if released {
+            // Serving the queue holds lockers back first.
+            self.serve_queue();
+        } else {
+            self.hold_reserved_lockers();
+        }
+    }
+
+    /// Holds free lockers back for the reservations whose windows have
+    /// started and which do not yet have one. A reservation for a
+    /// particular locker takes it from a reservation for a size class
+    /// holding it, which is given another locker if one is free.
+    pub(super) fn hold_reserved_lockers(&mut self) {
+        let now = self.now();
+        let is_waiting = |reservation: &Reservation| {
+            reservation.held_locker.is_none() && reservation.starts_at <= now
+        };
+
+        for index in 0..self.reservations.pending.len() {
+            let reservation = &self.reservations.pending[index];
+            let locker_number = match reservation.target {
+                ReservationTarget::Locker(locker_number) if is_waiting(reservation) => {
+                    locker_number
+                }
+                _ => continue,
+            };
+            if let Some(other) = self
+                .reservations
+                .pending
+                .iter_mut()
+                .find(|reservation| reservation.held_locker == Some(locker_number))
+            {
+                other.held_locker = None;
+            } else if !self.free_lockers.is_free(locker_number) {
+                continue;
+            }
+            self.free_lockers.remove(locker_number);
+            self.reservations.pending[index].held_locker = Some(locker_number);
+        }
+
+        let mut order: Vec<usize> = (0..self.reservations.pending.len())
+            .filter(|&index| is_waiting(&self.reservations.pending[index]))
+            .collect();
+        order.sort_by_key(|&index| self.reservations.pending[index].starts_at);
+        for index in order {
+            let size_class = match self.reservations.pending[index].target {
+                ReservationTarget::SizeClass(size_class) => size_class,
+                ReservationTarget::Locker(_) => continue,
+            };
+            if let Some(locker_number) = self.free_lockers.first_of_size_class(size_class) {
+                self.free_lockers.remove(locker_number);
+                self.reservations.pending[index].held_locker = Some(locker_number);
+            }
+        }
+    }
+
+    /// Stops holding a locker back for the reservation it is held for,
+    /// e.g. because it is being taken out of service.
+    pub(super) fn release_reserved_locker(&mut self, locker_number: LockerNumber) {
+        for reservation in &mut self.reservations.pending {
+            if reservation.held_locker == Some(locker_number) {
+                reservation.held_locker = None;
+            }
+        }
+    }
+
+    fn find_reservation(&self, reference: ReservationReference) -> Result<usize, ReservationError> {
+        self.reservations
+            .pending
+            .iter()
+            .position(|reservation| reservation.reference == reference)
+            .ok_or(ReservationError::UnknownReservation(reference))
+    }
+
+    /// Returns the size class that a reservation for `target` uses up a
+    /// locker of, if any.
+    fn get_reserved_size_class(&self, target: ReservationTarget) -> Option<SizeClass> {
+        match target {
+            ReservationTarget::Locker(locker_number) => self
+                .get_locker_spec(locker_number)
+                .and_then(|spec| spec.size_class),
+            ReservationTarget::SizeClass(size_class) => Some(size_class),
+        }
+    }
+
+    fn count_lockers_of_size_class(&self, size_class: SizeClass) -> usize {
+        self.lockers
+            .iter()
+            .filter(|spec| spec.size_class == Some(size_class))
+            .count()
+    }
+}
@@ -0,0 +1,402 @@
+//! A cloakroom that several attendant desks can serve at once.
+//!
+//! Every change to a [`Cloakroom`] needs `&mut self`, so only one desk can
+//! use it at a time. A [`SharedCloakroom`] is a handle to a `Cloakroom`
+//! that can be cloned and sent to other threads, one for each desk. Each
+//! locker has a lock of its own, and the cloakroom's bookkeeping, namely
+//! its index of free lockers, its queue and its reservations, has another.
+//! Opening, closing and inspecting a locker take only that locker's lock,
+//! so desks using different lockers do not wait for each other. Finding a
+//! free locker, vacating a locker and reclaiming an abandoned one also lock
+//! the bookkeeping, but only while they run, never while a [`Locker`] is
+//! handed out, and they lock each locker they look at only while they look
+//! at it.
+//!
+//! Every operation is carried out by the `Cloakroom`'s own code, so a
+//! shared cloakroom chooses lockers with its allocation strategy, passes
+//! over lockers that are out of service or held back for reservations,
+//! serves its queue, and sends its audit events to its audit sink.
+//! Anything else, such as taking a locker out of service, reserving one or
+//! saving the state of the cloakroom, is done by locking the whole
+//! cloakroom, every locker included, with [`SharedCloakroom::lock`].
+//!
+//! With the `async` feature, the `asynchronous` module adds versions of
+//! these operations that can be awaited, including waiting for a free
+//! locker.
+
+#[cfg(feature = "async")]
+pub mod asynchronous;
+
+use super::audit::{AuditEvent, Journal};
+use super::slot::{LockerAccess, LockerSlot, LockerSlots, SlotMut};
+use super::token::TokenGenerator;
+use super::{
+    lock, AbandonedLockers, Cloakroom, CloakroomError, FreeLockerResult, Key, Locker, LockerNumber,
+    LockerSpec, LockerState,
+};
+use crate::clock::Clock;
+use crate::items::{CloakroomItems, ItemCatalogue};
+use crate::pricing::Invoice;
+use std::io;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// What the handles to a shared cloakroom share.
+struct Shared {
+    // The cloakroom's bookkeeping, and everything else but the state of its
+    // lockers, which it only holds while it is locked with
+    // `SharedCloakroom::lock`.
+    cloakroom: Mutex<Cloakroom>,
+
+    // The state of each locker, behind a lock of its own. Locker number n
+    // has slot n - 1.
+    slots: Arc<[Mutex<LockerSlot>]>,
+
+    // What opening and closing a locker need from the cloakroom, none of
+    // which changes once it is shared.
+    lockers: Vec<LockerSpec>,
+    item_catalogue: Arc<ItemCatalogue>,
+    clock: Arc<dyn Clock + Send + Sync>,
+    token_generator: Arc<TokenGenerator>,
+    journal: Arc<Mutex<Journal>>,
+    abandoned_lockers: AbandonedLockers,
+
+    #[cfg(feature = "async")]
+    waiters: Mutex<asynchronous::Waiters>,
+}
+
+impl Shared {
+    /// Gives access to a locker whose slot has been locked, to close or
+    /// open it.
+    fn locker_access<'a>(
+        &'a self,
+        locker_number: LockerNumber,
+        slot: MutexGuard<'a, LockerSlot>,
+        attendant_id: Option<&'a str>,
+    ) -> LockerAccess<'a> {
+        LockerAccess {
+            locker_number,
+            spec: self.lockers[locker_number - 1],
+            slot: SlotMut::Locked(slot),
+            item_catalogue: &self.item_catalogue,
+            clock: &*self.clock,
+            token_generator: &self.token_generator,
+            journal: &self.journal,
+            abandoned_lockers: &self.abandoned_lockers,
+            attendant_id,
+        }
+    }
+
+    /// Returns the lock of a locker's slot, or `None` if there is no such
+    /// locker.
+    fn slot(&self, locker_number: LockerNumber) -> Option<&Mutex<LockerSlot>> {
+        self.slots.get(locker_number.checked_sub(1)?)
+    }
+}
+
+/// Handle to a cloakroom whose lockers can be used from several threads at
+/// once, as described in the [module documentation](self). Clones are
+/// handles to the same cloakroom, but each has its own attendant ID.
+///
+/// # Examples
+///
+/// ```
+/// use cloakroom_model::cloakroom::shared::SharedCloakroom;
+/// use cloakroom_model::cloakroom::{Cloakroom, LockerState};
+/// use cloakroom_model::items::CloakroomItems;
+/// use std::thread;
+///
+/// let cloakroom = SharedCloakroom::new(Cloakroom::new(10, 5));
+/// let desks: Vec<_> = (0..4)
+///     .map(|_| {
+///         let cloakroom = cloakroom.clone();
+///         thread::spawn(move || {
+///             let found = cloakroom.find_free_locker(&CloakroomItems::new());
+///             let mut locker = found.into_result().unwrap();
+///             locker.set_num_items("coats", 1).unwrap();
+///             cloakroom.close_locker(locker).unwrap()
+///         })
+///     })
+///     .collect();
+/// let mut locker_numbers: Vec<_> = desks
+///     .into_iter()
+///     .map(|desk| desk.join().unwrap().get_locker_number())
+///     .collect();
+///
+/// // Each desk was given a different locker.
+/// locker_numbers.sort_unstable();
+/// assert_eq!(locker_numbers, vec![1, 2, 3, 4]);
+/// assert!(cloakroom.inspect_locker(4, |state| matches!(state, LockerState::Closed(..))));
+/// ```
+#[derive(Clone)]
+pub struct SharedCloakroom {
+    shared: Arc<Shared>,
+    attendant_id: Option<String>,
+}
+
+impl SharedCloakroom {
+    /// Shares a cloakroom, which should be set up first, between desks.
+    /// The handle returned has the cloakroom's attendant ID.
+    pub fn new(mut cloakroom: Cloakroom) -> SharedCloakroom {
+        let slots: Arc<[Mutex<LockerSlot>]> =
+            match mem::replace(&mut cloakroom.slots, LockerSlots::Owned(Vec::new())) {
+                LockerSlots::Owned(slots) => slots.into_iter().map(Mutex::new).collect(),
+                LockerSlots::Shared(slots) => slots,
+            };
+        cloakroom.slots = LockerSlots::Shared(slots.clone());
+
+        let attendant_id = cloakroom.attendant_id.clone();
+        SharedCloakroom {
+            shared: Arc::new(Shared {
+                slots,
+                lockers: cloakroom.lockers.clone(),
+                item_catalogue: cloakroom.item_catalogue.clone(),
+                clock: cloakroom.clock.clone(),
+                token_generator: cloakroom.token_generator.clone(),
+                journal: cloakroom.journal.clone(),
+                abandoned_lockers: cloakroom.abandoned_lockers.clone(),
+                cloakroom: Mutex::new(cloakroom),
+                #[cfg(feature = "async")]
+                waiters: Mutex::default(),
+            }),
+            attendant_id,
+        }
+    }
+
+    /// Sets the ID of the attendant using this handle, which is recorded
+    /// with every audit event sent through it until it is changed.
+    pub fn set_attendant_id(&mut self, attendant_id: Option<&str>) {
+        self.attendant_id = attendant_id.map(str::to_string);
+    }
+
+    pub fn get_attendant_id(&self) -> Option<&str> {
+        self.attendant_id.as_deref()
+    }
+
+    /// Locks the whole cloakroom, every locker included, so that any of its
+    /// operations can be used. The events they cause are recorded with
+    /// this handle's attendant ID. Other desks wait until the guard
+    /// returned is dropped, so it should not be kept for long.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cloakroom_model::cloakroom::lost_key::AdminCredential;
+    /// use cloakroom_model::cloakroom::shared::SharedCloakroom;
+    /// use cloakroom_model::cloakroom::Cloakroom;
+    /// use cloakroom_model::items::CloakroomItems;
+    ///
+    /// let credential = AdminCredential::new("open sesame");
+    /// let cloakroom = SharedCloakroom::new(
+    ///     Cloakroom::new(2, 5).with_admin_credential(credential.clone()),
+    /// );
+    /// cloakroom
+    ///     .lock()
+    ///     .take_out_of_service(1, &credential, "broken lock")
+    ///     .unwrap();
+    ///
+    /// let locker = cloakroom.find_free_locker(&CloakroomItems::new()).into_result().unwrap();
+    /// assert_eq!(locker.get_locker_number(), 2);
+    /// ```
+    pub fn lock(&self) -> CloakroomGuard<'_> {
+        // The bookkeeping is always locked before any locker, and lockers
+        // in order of number, so that desks cannot wait for each other in
+        // a circle.
+        let cloakroom = lock(&self.shared.cloakroom);
+        let slots = self.shared.slots.iter().map(|slot| lock(slot)).collect();
+        self.guard(cloakroom, Some(slots))
+    }
+
+    /// Locks the cloakroom's bookkeeping. The operations of the cloakroom
+    /// returned lock each locker they use while they use it, so only those
+    /// that choose, free or recover lockers can be used.
+    fn lock_bookkeeping(&self) -> CloakroomGuard<'_> {
+        self.guard(lock(&self.shared.cloakroom), None)
+    }
+
+    /// Makes a guard for the cloakroom, giving it the state of its lockers
+    /// if they have been locked as well.
+    fn guard<'a>(
+        &'a self,
+        mut cloakroom: MutexGuard<'a, Cloakroom>,
+        mut slots: Option<Vec<MutexGuard<'a, LockerSlot>>>,
+    ) -> CloakroomGuard<'a> {
+        if let Some(slots) = &mut slots {
+            let owned = slots.iter_mut().map(|slot| mem::take(&mut **slot));
+            cloakroom.slots = LockerSlots::Owned(owned.collect());
+        }
+        cloakroom.set_attendant_id(self.attendant_id.as_deref());
+        CloakroomGuard {
+            cloakroom: Some(cloakroom),
+            slots,
+            shared: &self.shared,
+        }
+    }
+
+    /// Calls `f` with access to a locker, holding only that locker's lock,
+    /// or returns `None` if there is no such locker.
+    fn with_locker<F, R>(&self, locker_number: LockerNumber, f: F) -> Option<R>
+    where
+        F: FnOnce(LockerAccess<'_>) -> R,
+    {
+        let slot = lock(self.shared.slot(locker_number)?);
+        let access = self
+            .shared
+            .locker_access(locker_number, slot, self.attendant_id.as_deref());
+        let result = f(access);
+        #[cfg(feature = "async")]
+        self.shared.wake_lock_waiters();
+        Some(result)
+    }
+
+    pub fn get_item_catalogue(&self) -> Arc<ItemCatalogue> {
+        self.shared.item_catalogue.clone()
+    }
+
+    pub fn get_num_lockers(&self) -> usize {
+        self.shared.lockers.len()
+    }
+
+    /// Finds a free locker for `items`, as
+    /// [`Cloakroom::find_free_locker`] does.
+    pub fn find_free_locker(&self, items: &CloakroomItems) -> FreeLockerResult {
+        self.lock_bookkeeping().find_free_locker(items)
+    }
+
+    /// Closes a locker and returns its key, as [`Cloakroom::close_locker`]
+    /// does, holding only the locker's lock.
+    pub fn close_locker(&self, locker: Locker) -> Result<Key, CloakroomError> {
+        let locker_number = locker.get_locker_number();
+        self.with_locker(locker_number, |access| access.close(locker))
+            .unwrap_or(Err(CloakroomError::LockerNotHandedOut(locker_number)))
+    }
+
+    /// Opens a closed locker, as [`Cloakroom::open_locker`] does, holding
+    /// only the locker's lock.
+    pub fn open_locker(&self, key: Key) -> Result<Locker, CloakroomError> {
+        let locker_number = key.get_locker_number();
+        self.with_locker(locker_number, |access| access.open(key))
+            .unwrap_or(Err(CloakroomError::UnknownLocker(locker_number)))
+    }
+
+    /// Vacates a locker, as [`Cloakroom::vacate_locker`] does.
+    pub fn vacate_locker(&self, locker: Locker) -> Result<CloakroomItems, CloakroomError> {
+        self.checkout_locker(locker).map(|(items, _invoice)| items)
+    }
+
+    /// Vacates a locker and returns an itemised invoice for its use, as
+    /// [`Cloakroom::checkout_locker`] does.
+    pub fn checkout_locker(
+        &self,
+        locker: Locker,
+    ) -> Result<(CloakroomItems, Invoice), CloakroomError> {
+        self.lock_bookkeeping().checkout_locker(locker)
+    }
+
+    /// Recovers an abandoned locker, as [`Cloakroom::reclaim_locker`] does.
+    pub fn reclaim_locker(&self, locker_number: LockerNumber) -> Result<Locker, CloakroomError> {
+        self.lock_bookkeeping().reclaim_locker(locker_number)
+    }
+
+    /// Calls `f` with the state of a locker, holding the locker's lock so
+    /// that its state cannot change until `f` returns, and returns what `f`
+    /// returns. `f` may open, close or inspect other lockers, but must not
+    /// use this one or lock the cloakroom.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cloakroom_model::cloakroom::shared::SharedCloakroom;
+    /// use cloakroom_model::cloakroom::{Cloakroom, LockerState};
+    ///
+    /// let cloakroom = SharedCloakroom::new(Cloakroom::new(2, 5));
+    /// let both_free = cloakroom.inspect_locker(1, |first| {
+    ///     let second = cloakroom.inspect_locker(2, |second| matches!(second, LockerState::Free));
+    ///     matches!(first, LockerState::Free) && second
+    /// });
+    /// assert!(both_free);
+    /// ```
+    pub fn inspect_locker<F, R>(&self, locker_number: LockerNumber, f: F) -> R
+    where
+        F: FnOnce(LockerState<'_>) -> R,
+    {
+        let slot = match self.shared.slot(locker_number) {
+            Some(slot) => lock(slot),
+            None => return f(LockerState::NonExistent),
+        };
+        let result = f(slot.describe(locker_number, &self.shared.abandoned_lockers));
+        drop(slot);
+        #[cfg(feature = "async")]
+        self.shared.wake_lock_waiters();
+        result
+    }
+
+    /// Returns every event logged, oldest first, or no events if the
+    /// cloakroom has no audit sink.
+    pub fn get_audit_events(&self) -> io::Result<Vec<AuditEvent>> {
+        lock(&self.shared.journal).events(&self.shared.item_catalogue)
+    }
+
+    /// Returns the events logged for a locker, oldest first, or no events
+    /// if the cloakroom has no audit sink.
+    pub fn get_locker_history(&self, locker_number: LockerNumber) -> io::Result<Vec<AuditEvent>> {
+        lock(&self.shared.journal).history(locker_number, &self.shared.item_catalogue)
+    }
+
+    /// Returns, and clears, the first error met by any handle while sending
+    /// events to the audit sink since the last call.
+    pub fn take_audit_error(&self) -> Option<io::Error> {
+        lock(&self.shared.journal).audit_error.take()
+    }
+}
+
+/// A shared cloakroom locked with [`SharedCloakroom::lock`]. The cloakroom
+/// is unlocked when this is dropped.
+pub struct CloakroomGuard<'a> {
+    // Only taken while the guard is being dropped.
+    cloakroom: Option<MutexGuard<'a, Cloakroom>>,
+
+    // The locks of the lockers, whose state the cloakroom holds until the
+    // guard is dropped, or `None` if only the bookkeeping is locked.
+    slots: Option<Vec<MutexGuard<'a, LockerSlot>>>,
+
+    shared: &'a Shared,
+}
+
+impl Deref for CloakroomGuard<'_> {
+    type Target = Cloakroom;
+
+    fn deref(&self) -> &Cloakroom {
+        self.cloakroom.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for CloakroomGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Cloakroom {
+        self.cloakroom.as_mut().unwrap()
+    }
+}
+
+impl Drop for CloakroomGuard<'_> {
+    fn drop(&mut self) {
+        let mut cloakroom = match self.cloakroom.take() {
+            Some(cloakroom) => cloakroom,
+            None => return,
+        };
+
+        // The state of the lockers is put back behind their locks before
+        // they are unlocked.
+        if let Some(mut slots) = self.slots.take() {
+            let shared_slots = LockerSlots::Shared(self.shared.slots.clone());
+            if let LockerSlots::Owned(owned) = mem::replace(&mut cloakroom.slots, shared_slots) {
+                for (slot, owned) in slots.iter_mut().zip(owned) {
+                    **slot = owned;
+                }
+            }
+        }
+
+        #[cfg(feature = "async")]
+        self.shared.unlock(cloakroom);
+    }
+}
@@ -0,0 +1,357 @@
+//! Versions of the [`SharedCloakroom`] operations that can be awaited,
+//! enabled by the `async` feature.
+//!
+//! The operations here do not block the thread they run on waiting for
+//! another desk. If the cloakroom, or the locker they need, is locked, the
+//! task waits to be woken when it is unlocked instead. The only waits that
+//! can block the thread are for the cloakroom's audit sink, which every
+//! operation holds while it records an event, and for a locker that an
+//! operation finding or vacating lockers looks at while another desk is
+//! opening or closing it. Both are only held for a moment. The futures are
+//! not tied to any executor.
+//!
+//! When every locker that could hold some items is in use,
+//! [`SharedCloakroom::wait_for_free_locker`] joins the cloakroom's
+//! [queue](crate::cloakroom::queue) and waits to be served, where
+//! [`SharedCloakroom::find_free_locker`] would give up. Waiting customers
+//! are served in the same order as everyone else in the queue, and lockers
+//! held back for reservations are passed over.
+
+use super::{lock, CloakroomGuard, Shared, SharedCloakroom};
+use crate::cloakroom::queue::{PriorityClass, QueueError, QueueTicket};
+use crate::cloakroom::slot::{LockerAccess, LockerSlot};
+use crate::cloakroom::{Cloakroom, CloakroomError, FreeLockerResult, Key, Locker, LockerNumber};
+use crate::items::CloakroomItems;
+use std::collections::HashSet;
+use std::future::{self, Future};
+use std::mem;
+use std::pin::Pin;
+use std::sync::{Mutex, MutexGuard, TryLockError};
+use std::task::{Context, Poll, Waker};
+
+/// The tasks waiting for something to happen to a shared cloakroom.
+#[derive(Default)]
+pub(super) struct Waiters {
+    // Waiting for the cloakroom, or one of its lockers, to be unlocked.
+    lock: Vec<Waker>,
+    // Waiting in the queue with these tickets.
+    queue: Vec<(QueueTicket, Waker)>,
+}
+
+impl Shared {
+    /// Unlocks the cloakroom, then wakes the tasks waiting for it to be
+    /// unlocked and those in the queue that have been served, or have left
+    /// it.
+    pub(super) fn unlock(&self, cloakroom: MutexGuard<'_, Cloakroom>) {
+        let mut waiters = lock(&self.waiters);
+        let mut woken = Vec::new();
+        if !waiters.queue.is_empty() {
+            let waiting: HashSet<QueueTicket> = cloakroom
+                .get_queue()
+                .iter()
+                .map(|entry| entry.get_ticket())
+                .collect();
+            let (still_waiting, done) = mem::take(&mut waiters.queue)
+                .into_iter()
+                .partition(|(ticket, _)| waiting.contains(ticket));
+            waiters.queue = still_waiting;
+            woken.extend(done.into_iter().map(|(_, waker)| waker));
+        }
+
+        // The cloakroom is unlocked before the tasks waiting for it are
+        // taken, so a task that adds itself after this will find it unlocked
+        // when it tries again.
+        drop(cloakroom);
+        woken.append(&mut waiters.lock);
+        drop(waiters);
+        for waker in woken {
+            waker.wake();
+        }
+    }
+
+    /// Wakes the tasks waiting for the cloakroom, or one of its lockers, to
+    /// be unlocked, after a locker has been unlocked.
+    pub(super) fn wake_lock_waiters(&self) {
+        let woken = mem::take(&mut lock(&self.waiters).lock);
+        for waker in woken {
+            waker.wake();
+        }
+    }
+
+    /// Adds a task to those waiting for the cloakroom, or one of its
+    /// lockers, to be unlocked.
+    fn add_lock_waiter(&self, waker: &Waker) {
+        let mut waiters = lock(&self.waiters);
+        if !waiters.lock.iter().any(|waiter| waiter.will_wake(waker)) {
+            waiters.lock.push(waker.clone());
+        }
+    }
+}
+
+impl SharedCloakroom {
+    /// Returns a future that locks the cloakroom, as
+    /// [`SharedCloakroom::lock`] does, without blocking the thread.
+    pub fn lock_async(&self) -> LockCloakroom<'_> {
+        LockCloakroom { cloakroom: self }
+    }
+
+    /// Finds a free locker for `items`, as
+    /// [`SharedCloakroom::find_free_locker`] does.
+    pub async fn find_free_locker_async(&self, items: &CloakroomItems) -> FreeLockerResult {
+        self.lock_bookkeeping_async().await.find_free_locker(items)
+    }
+
+    /// Opens a closed locker, as [`SharedCloakroom::open_locker`] does.
+    pub async fn open_locker_async(&self, key: Key) -> Result<Locker, CloakroomError> {
+        let locker_number = key.get_locker_number();
+        self.with_locker_async(locker_number, |access| access.open(key))
+            .await
+            .unwrap_or(Err(CloakroomError::UnknownLocker(locker_number)))
+    }
+
+    /// Closes a locker and returns its key, as
+    /// [`SharedCloakroom::close_locker`] does.
+    pub async fn close_locker_async(&self, locker: Locker) -> Result<Key, CloakroomError> {
+        let locker_number = locker.get_locker_number();
+        self.with_locker_async(locker_number, |access| access.close(locker))
+            .await
+            .unwrap_or(Err(CloakroomError::LockerNotHandedOut(locker_number)))
+    }
+
+    /// Vacates a locker, as [`SharedCloakroom::vacate_locker`] does. The
+    /// locker may be given to a task waiting in the queue.
+    pub async fn vacate_locker_async(
+        &self,
+        locker: Locker,
+    ) -> Result<CloakroomItems, CloakroomError> {
+        self.lock_bookkeeping_async().await.vacate_locker(locker)
+    }
+
+    /// Returns a future that joins the queue with `priority`, as
+    /// [`Cloakroom::join_queue`] does, and resolves to the locker allocated
+    /// when the customer is served. It resolves to
+    /// `FreeLockerResult::NoFreeLockers` if no locker in the cloakroom is big
+    /// enough for the items, or if the customer loses their place, e.g.
+    /// because the locker was passed to the next customer after the
+    /// [pickup timeout](Cloakroom::with_queue_pickup_timeout). Dropping the
+    /// future before it resolves leaves the queue, which locks the
+    /// cloakroom's bookkeeping, blocking the thread if need be.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cloakroom_model::cloakroom::queue::PriorityClass;
+    /// use cloakroom_model::cloakroom::shared::SharedCloakroom;
+    /// use cloakroom_model::cloakroom::{Cloakroom, FreeLockerResult};
+    /// use cloakroom_model::items::CloakroomItems;
+    /// use std::future::Future;
+    /// use std::sync::Arc;
+    /// use std::task::{Context, Poll, Wake, Waker};
+    ///
+    /// struct NoopWaker;
+    ///
+    /// impl Wake for NoopWaker {
+    ///     fn wake(self: Arc<Self>) {}
+    /// }
+    ///
+    /// let cloakroom = SharedCloakroom::new(Cloakroom::new(1, 5));
+    /// let no_items = CloakroomItems::new();
+    /// let locker = cloakroom.find_free_locker(&no_items).into_result().unwrap();
+    ///
+    /// let waker = Waker::from(Arc::new(NoopWaker));
+    /// let mut context = Context::from_waker(&waker);
+    /// let mut waiting = Box::pin(cloakroom.wait_for_free_locker(&no_items, PriorityClass::Standard));
+    /// assert!(waiting.as_mut().poll(&mut context).is_pending());
+    /// assert_eq!(cloakroom.lock().get_queue().len(), 1);
+    ///
+    /// cloakroom.vacate_locker(locker).unwrap();
+    /// match waiting.as_mut().poll(&mut context) {
+    ///     Poll::Ready(FreeLockerResult::FreeLockerFound(locker)) => {
+    ///         assert_eq!(locker.get_locker_number(), 1)
+    ///     }
+    ///     _ => panic!("no locker was found"),
+    /// }
+    /// ```
+    pub fn wait_for_free_locker(
+        &self,
+        items: &CloakroomItems,
+        priority: PriorityClass,
+    ) -> WaitForFreeLocker {
+        WaitForFreeLocker {
+            cloakroom: self.clone(),
+            items: items.clone(),
+            priority,
+            ticket: None,
+        }
+    }
+
+    /// Locks the cloakroom's bookkeeping, as
+    /// [`SharedCloakroom::lock_bookkeeping`] does, without blocking the
+    /// thread.
+    async fn lock_bookkeeping_async(&self) -> CloakroomGuard<'_> {
+        future::poll_fn(|context| self.poll_lock_bookkeeping(context)).await
+    }
+
+    /// Calls `f` with access to a locker, as
+    /// [`SharedCloakroom::with_locker`] does, without blocking the thread
+    /// while the locker is locked.
+    async fn with_locker_async<F, R>(&self, locker_number: LockerNumber, f: F) -> Option<R>
+    where
+        F: FnOnce(LockerAccess<'_>) -> R,
+    {
+        let slot = self.shared.slot(locker_number)?;
+        let slot = future::poll_fn(|context| self.poll_lock_slot(slot, context)).await;
+        let access = self
+            .shared
+            .locker_access(locker_number, slot, self.attendant_id.as_deref());
+        let result = f(access);
+        self.shared.wake_lock_waiters();
+        Some(result)
+    }
+
+    /// Locks the whole cloakroom if no one else has locked it or any of its
+    /// lockers, or arranges for the task to be woken when something is
+    /// unlocked.
+    fn poll_lock(&self, context: &mut Context<'_>) -> Poll<CloakroomGuard<'_>> {
+        self.poll_locking(context, SharedCloakroom::try_lock)
+    }
+
+    fn poll_lock_bookkeeping(&self, context: &mut Context<'_>) -> Poll<CloakroomGuard<'_>> {
+        self.poll_locking(context, |cloakroom| {
+            try_lock(&cloakroom.shared.cloakroom).map(|locked| cloakroom.guard(locked, None))
+        })
+    }
+
+    fn poll_lock_slot<'a>(
+        &self,
+        slot: &'a Mutex<LockerSlot>,
+        context: &mut Context<'_>,
+    ) -> Poll<MutexGuard<'a, LockerSlot>> {
+        self.poll_locking(context, |_| try_lock(slot))
+    }
+
+    /// Locks something with `try_lock` if no one else has, or arranges for
+    /// the task to be woken when something is unlocked.
+    fn poll_locking<'a, T, F>(&'a self, context: &mut Context<'_>, try_lock: F) -> Poll<T>
+    where
+        F: Fn(&'a SharedCloakroom) -> Option<T>,
+    {
+        if let Some(locked) = try_lock(self) {
+            return Poll::Ready(locked);
+        }
+
+        // It may have been unlocked after the last try but before the waker
+        // was added, in which case no wake-up is coming for it.
+        self.shared.add_lock_waiter(context.waker());
+        match try_lock(self) {
+            Some(locked) => Poll::Ready(locked),
+            None => Poll::Pending,
+        }
+    }
+
+    fn try_lock(&self) -> Option<CloakroomGuard<'_>> {
+        let cloakroom = try_lock(&self.shared.cloakroom)?;
+        let mut slots = Vec::with_capacity(self.shared.slots.len());
+        for slot in self.shared.slots.iter() {
+            match try_lock(slot) {
+                Some(slot) => slots.push(slot),
+
+                // Another task may have tried to lock the cloakroom while it
+                // was held here, and would not be woken by whoever holds the
+                // locker.
+                None => {
+                    drop(slots);
+                    self.shared.unlock(cloakroom);
+                    return None;
+                }
+            }
+        }
+        Some(self.guard(cloakroom, Some(slots)))
+    }
+}
+
+/// Locks a mutex if no one else has, ignoring poisoning as
+/// [`lock`](super::lock) does.
+fn try_lock<T: ?Sized>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+    match mutex.try_lock() {
+        Ok(locked) => Some(locked),
+        Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+/// Future returned by [`SharedCloakroom::lock_async`].
+pub struct LockCloakroom<'a> {
+    cloakroom: &'a SharedCloakroom,
+}
+
+impl<'a> Future for LockCloakroom<'a> {
+    type Output = CloakroomGuard<'a>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<CloakroomGuard<'a>> {
+        self.cloakroom.poll_lock(context)
+    }
+}
+
+/// Future returned by [`SharedCloakroom::wait_for_free_locker`].
+pub struct WaitForFreeLocker {
+    cloakroom: SharedCloakroom,
+    items: CloakroomItems,
+    priority: PriorityClass,
+    // Given once the customer has joined the queue, and taken back when
+    // they leave it.
+    ticket: Option<QueueTicket>,
+}
+
+impl Future for WaitForFreeLocker {
+    type Output = FreeLockerResult;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<FreeLockerResult> {
+        let this = self.get_mut();
+        let mut cloakroom = match this.cloakroom.poll_lock_bookkeeping(context) {
+            Poll::Ready(cloakroom) => cloakroom,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        let ticket = match this.ticket {
+            Some(ticket) => ticket,
+            None => match cloakroom.join_queue(&this.items, this.priority) {
+                Ok(ticket) => ticket,
+                Err(_) => return Poll::Ready(FreeLockerResult::NoFreeLockers),
+            },
+        };
+        match cloakroom.take_served_locker(ticket) {
+            Ok(locker) => {
+                this.ticket = None;
+                Poll::Ready(FreeLockerResult::FreeLockerFound(locker))
+            }
+
+            // The waker is added while the cloakroom is locked, so the
+            // customer cannot be served before it is there to be woken.
+            Err(QueueError::NotServed(_)) => {
+                this.ticket = Some(ticket);
+                let mut waiters = lock(&this.cloakroom.shared.waiters);
+                waiters.queue.retain(|(waiting, _)| *waiting != ticket);
+                waiters.queue.push((ticket, context.waker().clone()));
+                Poll::Pending
+            }
+
+            Err(QueueError::UnknownTicket(_)) => {
+                this.ticket = None;
+                Poll::Ready(FreeLockerResult::NoFreeLockers)
+            }
+        }
+    }
+}
+
+impl Drop for WaitForFreeLocker {
+    fn drop(&mut self) {
+        if let Some(ticket) = self.ticket.take() {
+            let mut cloakroom = self.cloakroom.lock_bookkeeping();
+            let _ = cloakroom.leave_queue(ticket);
+            lock(&self.cloakroom.shared.waiters)
+                .queue
+                .retain(|(waiting, _)| *waiting != ticket);
+        }
+    }
+}
@@ -0,0 +1,560 @@
+//! The state of each of a cloakroom's lockers.
+//!
+//! Each locker has a [`LockerSlot`] holding its state. A cloakroom keeps
+//! the slots itself until it is [shared](super::shared), when each slot is
+//! given a lock of its own so that desks using different lockers do not
+//! wait for each other.
+//!
+//! Every event is checked against, and applied to, the locker's slot by
+//! [`LockerSlot::check`] and [`LockerSlot::change`], whether the cloakroom
+//! is shared or not. The cloakroom does whatever else an event calls for,
+//! such as updating its index of free lockers. Closing and opening a locker
+//! call for nothing else, so they are done through a [`LockerAccess`],
+//! which holds the locker's slot and what the cloakroom never changes once
+//! it has been set up, and so can be used without the rest of the
+//! cloakroom.
+
+use super::audit::{AuditAction, Journal};
+use super::token::TokenGenerator;
+use super::{
+    AbandonedLockers, Capacity, CloakroomError, Key, Locker, LockerInUseState, LockerNumber,
+    LockerSpec, LockerState, LockerTimes,
+};
+use crate::clock::Clock;
+use crate::items::{CloakroomItems, ItemCatalogue};
+use crate::pricing::Stay;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+
+/// The state of one locker.
+#[derive(Default)]
+pub(super) struct LockerSlot {
+    // None while the locker is free.
+    pub(super) state: Option<LockerInUseState>,
+
+    // What affects the invoice of the locker, and when it was used. Every
+    // locker in use has one, unless it is out of service.
+    pub(super) stay: Option<Stay>,
+}
+
+impl LockerSlot {
+    /// Describes the state of locker number `locker_number`, whose slot
+    /// this is.
+    pub(super) fn describe(
+        &self,
+        locker_number: LockerNumber,
+        abandoned_lockers: &AbandonedLockers,
+    ) -> LockerState<'_> {
+        let state = match &self.state {
+            Some(state) => state,
+            None => return LockerState::Free,
+        };
+
+        let times = self.stay.as_ref().map(|stay| LockerTimes {
+            deposited_at: stay.first_closed,
+            last_accessed_at: stay.last_accessed,
+        });
+
+        match (state, times) {
+            (LockerInUseState::OutOfService { reason }, _) => LockerState::OutOfService { reason },
+
+            (LockerInUseState::Closed { items, .. }, Some(times)) => {
+                LockerState::Closed(items, times)
+            }
+
+            (LockerInUseState::ContentsBeingChanged { .. }, Some(times)) => {
+                if is_reported_abandoned(abandoned_lockers, locker_number) {
+                    LockerState::Abandoned(times)
+                } else {
+                    LockerState::ContentsBeingChanged(times)
+                }
+            }
+
+            (LockerInUseState::Abandoned { .. }, Some(times)) => LockerState::Abandoned(times),
+
+            (_, None) => LockerState::NonExistent,
+        }
+    }
+
+    /// Hands out a `Locker` holding the recorded contents of the locker,
+    /// whose contents are being changed.
+    pub(super) fn hand_out(
+        &self,
+        locker_number: LockerNumber,
+        spec: LockerSpec,
+        item_catalogue: &Arc<ItemCatalogue>,
+        abandoned_lockers: &AbandonedLockers,
+    ) -> Locker {
+        let items = match &self.state {
+            Some(LockerInUseState::ContentsBeingChanged { items }) => items.clone(),
+            _ => CloakroomItems::from_catalogue(item_catalogue.clone()),
+        };
+        debug_assert!(matches!(
+            self.state,
+            Some(LockerInUseState::ContentsBeingChanged { .. })
+        ));
+        Locker {
+            number: locker_number,
+            spec,
+            items,
+            abandoned_lockers: Some(abandoned_lockers.clone()),
+        }
+    }
+
+    /// Returns whether `locker`, for the locker whose slot this is, was
+    /// handed out by the cloakroom that reports abandoned lockers to
+    /// `abandoned_lockers`, and is still being changed.
+    pub(super) fn has_handed_out(
+        &self,
+        locker: &Locker,
+        abandoned_lockers: &AbandonedLockers,
+    ) -> bool {
+        let handed_out_here = locker
+            .abandoned_lockers
+            .as_ref()
+            .is_some_and(|handed_out_by| Arc::ptr_eq(handed_out_by, abandoned_lockers));
+        handed_out_here
+            && matches!(
+                self.state,
+                Some(LockerInUseState::ContentsBeingChanged { .. })
+            )
+            && self.stay.is_some()
+    }
+
+    /// Explains why `action` cannot happen to locker number
+    /// `locker_number`, whose slot this is and whose capacity is
+    /// `capacity`, in its current state. What the action needs from the
+    /// rest of the cloakroom is checked by the cloakroom.
+    pub(super) fn check(
+        &self,
+        locker_number: LockerNumber,
+        action: &AuditAction,
+        capacity: Capacity,
+        item_catalogue: &Arc<ItemCatalogue>,
+    ) -> Result<(), String> {
+        match (action, &self.state) {
+            (AuditAction::Allocated, None)
+            | (AuditAction::Opened, Some(LockerInUseState::Closed { .. }))
+            | (AuditAction::OpenedWithoutKey { .. }, Some(LockerInUseState::Closed { .. }))
+            | (AuditAction::Reclaimed, Some(LockerInUseState::Abandoned { .. }))
+            | (AuditAction::TakenOutOfService { .. }, None)
+            | (AuditAction::ReturnedToService, Some(LockerInUseState::OutOfService { .. })) => {
+                Ok(())
+            }
+
+            (
+                AuditAction::ContentsChanged { before, after },
+                Some(LockerInUseState::ContentsBeingChanged { items }),
+            ) => {
+                check_items(item_catalogue, before, items)?;
+                let after = own_items(item_catalogue, after.clone())?;
+                check_room(locker_number, capacity, &after)
+            }
+
+            (
+                AuditAction::Closed { items: given }
+                | AuditAction::Vacated { items: given }
+                | AuditAction::Abandoned { items: given },
+                Some(LockerInUseState::ContentsBeingChanged { items }),
+            ) => check_items(item_catalogue, given, items),
+
+            (AuditAction::TransferredIn { items, .. }, None) => {
+                check_room(locker_number, capacity, items)
+            }
+
+            (
+                AuditAction::TransferredOut { items: given, .. }
+                | AuditAction::MovedToLostProperty { items: given, .. },
+                Some(LockerInUseState::Closed { items, .. }),
+            ) => check_items(item_catalogue, given, items),
+
+            (action, state) => {
+                let state_name = match state {
+                    None => "free",
+                    Some(LockerInUseState::Closed { .. }) => "closed",
+                    Some(LockerInUseState::ContentsBeingChanged { .. }) => "being changed",
+                    Some(LockerInUseState::Abandoned { .. }) => "abandoned",
+                    Some(LockerInUseState::OutOfService { .. }) => "out of service",
+                };
+                Err(format!(
+                    "'{}' event for locker number {} when it is {}",
+                    action.name(),
+                    locker_number,
+                    state_name
+                ))
+            }
+        }
+    }
+
+    /// Changes the state of the locker as described by `action`, which
+    /// happened at `timestamp` and has been checked against its state.
+    /// Returns what the locker held if the action frees it. A locker
+    /// transferred into is left for the cloakroom to give the stay of the
+    /// locker the items came from.
+    pub(super) fn change(
+        &mut self,
+        timestamp: SystemTime,
+        action: &AuditAction,
+        item_catalogue: &Arc<ItemCatalogue>,
+        token_generator: &TokenGenerator,
+    ) -> Option<LockerSlot> {
+        if let Some(stay) = &mut self.stay {
+            stay.record_access(timestamp);
+        }
+        let new_state = match (action, self.state.take()) {
+            (AuditAction::Allocated, None) => {
+                self.stay = Some(Stay::new(item_catalogue.clone(), timestamp));
+                LockerInUseState::ContentsBeingChanged {
+                    items: CloakroomItems::from_catalogue(item_catalogue.clone()),
+                }
+            }
+
+            (
+                AuditAction::OpenedWithoutKey { fee_pence },
+                Some(LockerInUseState::Closed { items, .. }),
+            ) => {
+                self.stay_mut().record_lost_key(*fee_pence);
+                LockerInUseState::ContentsBeingChanged { items }
+            }
+
+            (AuditAction::Opened, Some(LockerInUseState::Closed { items, .. }))
+            | (AuditAction::Reclaimed, Some(LockerInUseState::Abandoned { items })) => {
+                LockerInUseState::ContentsBeingChanged { items }
+            }
+
+            (
+                AuditAction::ContentsChanged { after, .. },
+                Some(LockerInUseState::ContentsBeingChanged { items }),
+            ) => {
+                let after = own_items(item_catalogue, after.clone()).expect("items were checked");
+                self.stay_mut().record_contents_change(&items, &after);
+                LockerInUseState::ContentsBeingChanged { items: after }
+            }
+
+            (
+                AuditAction::Closed { .. },
+                Some(LockerInUseState::ContentsBeingChanged { items }),
+            ) => {
+                self.stay_mut().record_closed(timestamp);
+                LockerInUseState::Closed {
+                    items,
+                    token: token_generator.generate(),
+                }
+            }
+
+            (
+                AuditAction::Abandoned { .. },
+                Some(LockerInUseState::ContentsBeingChanged { items }),
+            ) => LockerInUseState::Abandoned { items },
+
+            (AuditAction::TransferredIn { items, .. }, None) => LockerInUseState::Closed {
+                items: own_items(item_catalogue, items.clone()).expect("items were checked"),
+                token: token_generator.generate(),
+            },
+
+            (AuditAction::TakenOutOfService { reason }, None) => LockerInUseState::OutOfService {
+                reason: reason.clone(),
+            },
+
+            (
+                AuditAction::TransferredOut { .. }
+                | AuditAction::ReturnedToService
+                | AuditAction::Vacated { .. }
+                | AuditAction::MovedToLostProperty { .. },
+                state @ Some(_),
+            ) => {
+                return Some(LockerSlot {
+                    state,
+                    stay: self.stay.take(),
+                });
+            }
+
+            _ => unreachable!("event was checked against the locker's state"),
+        };
+        self.state = Some(new_state);
+        None
+    }
+
+    fn stay_mut(&mut self) -> &mut Stay {
+        match &mut self.stay {
+            Some(stay) => stay,
+            None => unreachable!("locker in use has no stay"),
+        }
+    }
+}
+
+/// Returns whether the `Locker` for a locker has been dropped but this has
+/// not yet been recorded.
+pub(super) fn is_reported_abandoned(
+    abandoned_lockers: &AbandonedLockers,
+    locker_number: LockerNumber,
+) -> bool {
+    super::lock(abandoned_lockers)
+        .iter()
+        .any(|(number, _)| *number == locker_number)
+}
+
+/// Checks that the items given in an event are the recorded contents of a
+/// locker.
+pub(super) fn check_items(
+    item_catalogue: &Arc<ItemCatalogue>,
+    given: &CloakroomItems,
+    recorded: &CloakroomItems,
+) -> Result<(), String> {
+    let given = own_items(item_catalogue, given.clone())?;
+    if given != *recorded {
+        return Err(format!(
+            "event gives contents ({}) but locker holds ({})",
+            given, recorded
+        ));
+    }
+    Ok(())
+}
+
+/// Gives items from an event the cloakroom's item catalogue.
+pub(super) fn own_items(
+    item_catalogue: &Arc<ItemCatalogue>,
+    items: CloakroomItems,
+) -> Result<CloakroomItems, String> {
+    if Arc::ptr_eq(items.get_catalogue(), item_catalogue) {
+        return Ok(items);
+    }
+    items
+        .convert_to_catalogue(item_catalogue)
+        .map_err(|err| err.to_string())
+}
+
+fn check_room(
+    locker_number: LockerNumber,
+    capacity: Capacity,
+    items: &CloakroomItems,
+) -> Result<(), String> {
+    if !capacity.can_hold(Capacity::of_items(items)) {
+        return Err(format!(
+            "locker number {} does not have room for ({})",
+            locker_number, items
+        ));
+    }
+    Ok(())
+}
+
+/// The slots of a cloakroom's lockers. Locker number n has slot n - 1.
+pub(super) enum LockerSlots {
+    Owned(Vec<LockerSlot>),
+
+    // The slots of a shared cloakroom, each behind its own lock. They are
+    // moved into `Owned` while the whole cloakroom is locked.
+    Shared(Arc<[Mutex<LockerSlot>]>),
+}
+
+impl LockerSlots {
+    pub(super) fn new(num_lockers: usize) -> LockerSlots {
+        LockerSlots::Owned((0..num_lockers).map(|_| LockerSlot::default()).collect())
+    }
+
+    /// Borrows, or locks, the slot of a locker, or returns `None` if there
+    /// is no such locker.
+    pub(super) fn get(&self, locker_number: LockerNumber) -> Option<SlotRef<'_>> {
+        let index = locker_number.checked_sub(1)?;
+        match self {
+            LockerSlots::Owned(slots) => slots.get(index).map(SlotRef::Borrowed),
+            LockerSlots::Shared(slots) => slots
+                .get(index)
+                .map(|slot| SlotRef::Locked(super::lock(slot))),
+        }
+    }
+
+    /// Borrows, or locks, the slot of a locker to change it, or returns
+    /// `None` if there is no such locker.
+    pub(super) fn get_mut(&mut self, locker_number: LockerNumber) -> Option<SlotMut<'_>> {
+        let index = locker_number.checked_sub(1)?;
+        match self {
+            LockerSlots::Owned(slots) => slots.get_mut(index).map(SlotMut::Borrowed),
+            LockerSlots::Shared(slots) => slots
+                .get(index)
+                .map(|slot| SlotMut::Locked(super::lock(slot))),
+        }
+    }
+
+    /// Borrows the slot of a locker, or returns `None` if there is no such
+    /// locker. The slots of a shared cloakroom can only be borrowed while
+    /// the whole cloakroom is locked.
+    pub(super) fn borrow(&self, locker_number: LockerNumber) -> Option<&LockerSlot> {
+        match self {
+            LockerSlots::Owned(slots) => slots.get(locker_number.checked_sub(1)?),
+            LockerSlots::Shared(_) => {
+                unreachable!("the lockers of a shared cloakroom are borrowed while it is unlocked")
+            }
+        }
+    }
+}
+
+/// The slot of a locker, borrowed from the cloakroom or locked.
+pub(super) enum SlotRef<'a> {
+    Borrowed(&'a LockerSlot),
+    Locked(MutexGuard<'a, LockerSlot>),
+}
+
+impl Deref for SlotRef<'_> {
+    type Target = LockerSlot;
+
+    fn deref(&self) -> &LockerSlot {
+        match self {
+            SlotRef::Borrowed(slot) => slot,
+            SlotRef::Locked(slot) => slot,
+        }
+    }
+}
+
+/// The slot of a locker, borrowed from the cloakroom to be changed or
+/// locked.
+pub(super) enum SlotMut<'a> {
+    Borrowed(&'a mut LockerSlot),
+    Locked(MutexGuard<'a, LockerSlot>),
+}
+
+impl Deref for SlotMut<'_> {
+    type Target = LockerSlot;
+
+    fn deref(&self) -> &LockerSlot {
+        match self {
+            SlotMut::Borrowed(slot) => slot,
+            SlotMut::Locked(slot) => slot,
+        }
+    }
+}
+
+impl DerefMut for SlotMut<'_> {
+    fn deref_mut(&mut self) -> &mut LockerSlot {
+        match self {
+            SlotMut::Borrowed(slot) => slot,
+            SlotMut::Locked(slot) => slot,
+        }
+    }
+}
+
+/// A locker of a cloakroom, with what is needed to close or open it.
+pub(super) struct LockerAccess<'a> {
+    pub(super) locker_number: LockerNumber,
+    pub(super) spec: LockerSpec,
+    pub(super) slot: SlotMut<'a>,
+    pub(super) item_catalogue: &'a Arc<ItemCatalogue>,
+    pub(super) clock: &'a (dyn Clock + Send + Sync),
+    pub(super) token_generator: &'a TokenGenerator,
+    pub(super) journal: &'a Mutex<Journal>,
+    pub(super) abandoned_lockers: &'a AbandonedLockers,
+    pub(super) attendant_id: Option<&'a str>,
+}
+
+impl LockerAccess<'_> {
+    /// Closes the locker, which was handed out as `locker`, and returns its
+    /// key.
+    pub(super) fn close(mut self, locker: Locker) -> Result<Key, CloakroomError> {
+        let items = self.take_back(locker)?;
+        self.emit_contents_change(&items);
+        self.emit(AuditAction::Closed { items });
+
+        match &self.slot.state {
+            Some(LockerInUseState::Closed { token, .. }) => {
+                Ok(Key::new(self.locker_number, *token))
+            }
+            _ => Err(CloakroomError::LockerNotHandedOut(self.locker_number)),
+        }
+    }
+
+    /// Opens the locker with `key`.
+    pub(super) fn open(mut self, key: Key) -> Result<Locker, CloakroomError> {
+        match &self.slot.state {
+            Some(LockerInUseState::Closed { token, .. }) if *token == key.token => (),
+
+            Some(LockerInUseState::Closed { .. }) => {
+                return Err(CloakroomError::InvalidKey(self.locker_number))
+            }
+
+            Some(LockerInUseState::ContentsBeingChanged { .. })
+            | Some(LockerInUseState::Abandoned { .. }) => {
+                return Err(CloakroomError::LockerNotClosed(self.locker_number))
+            }
+
+            Some(LockerInUseState::OutOfService { .. }) => {
+                return Err(CloakroomError::LockerOutOfService(self.locker_number))
+            }
+
+            None => return Err(CloakroomError::UnknownLocker(self.locker_number)),
+        }
+
+        self.emit(AuditAction::Opened);
+        Ok(self.slot.hand_out(
+            self.locker_number,
+            self.spec,
+            self.item_catalogue,
+            self.abandoned_lockers,
+        ))
+    }
+
+    /// Takes the contents out of `locker`, which is being given back, after
+    /// checking that it was handed out for this locker. A `Locker` that
+    /// fails the check is dropped, and so reported as abandoned to the
+    /// cloakroom that handed it out.
+    pub(super) fn take_back(&self, locker: Locker) -> Result<CloakroomItems, CloakroomError> {
+        if locker.get_locker_number() != self.locker_number
+            || !self.slot.has_handed_out(&locker, self.abandoned_lockers)
+        {
+            return Err(CloakroomError::LockerNotHandedOut(
+                locker.get_locker_number(),
+            ));
+        }
+        Ok(locker.give_back())
+    }
+
+    /// Emits a `ContentsChanged` event if the locker, whose contents are
+    /// being changed, is being given back holding something other than
+    /// `items`.
+    pub(super) fn emit_contents_change(&mut self, items: &CloakroomItems) {
+        if let Some(LockerInUseState::ContentsBeingChanged { items: before }) = &self.slot.state {
+            if before != items {
+                let before = before.clone();
+                self.emit(AuditAction::ContentsChanged {
+                    before,
+                    after: items.clone(),
+                });
+            }
+        }
+    }
+
+    /// Applies an event that changes nothing but the locker, then records
+    /// it, as [`Cloakroom::emit`](super::Cloakroom::emit) does.
+    pub(super) fn emit(&mut self, action: AuditAction) {
+        let timestamp = self.clock.now();
+        let applied = self.slot.check(
+            self.locker_number,
+            &action,
+            self.spec.get_capacity(),
+            self.item_catalogue,
+        );
+        debug_assert!(applied.is_ok(), "invalid event: {:?}", applied);
+        if applied.is_err() {
+            return;
+        }
+        let freed = self.slot.change(
+            timestamp,
+            &action,
+            self.item_catalogue,
+            self.token_generator,
+        );
+        debug_assert!(
+            freed.is_none(),
+            "'{}' event frees the locker",
+            action.name()
+        );
+
+        super::lock(self.journal).record(
+            timestamp,
+            self.locker_number,
+            self.attendant_id,
+            action,
+            self.item_catalogue,
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::str::FromStr;
+
+/// Secret value that a key must carry to open its locker.
+#[derive(Debug, Clone, Copy, Eq)]
+pub(crate) struct Token(u128);
+
+impl PartialEq for Token {
+    /// Compares the tokens in constant time, so that the comparison does not
+    /// give away how much of a forged token is right.
+    fn eq(&self, other: &Token) -> bool {
+        let a = self.0.to_le_bytes();
+        let b = other.0.to_le_bytes();
+        a.iter().zip(&b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+impl FromStr for Token {
+    type Err = ();
+
+    /// Reads a token written by `Display`, which is 32 hex digits and
+    /// nothing else.
+    fn from_str(s: &str) -> Result<Token, ()> {
+        if s.len() != 32 || !s.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(());
+        }
+        u128::from_str_radix(s, 16).map(Token).map_err(|_| ())
+    }
+}
+
+/// Generates unpredictable tokens.
+///
+/// Each token is read from the operating system's random number generator,
+/// `/dev/urandom`, which is cryptographically secure. There is no weaker
+/// fallback: generating a token panics if there is no such device or it
+/// cannot be read, rather than handing out keys that could be forged.
+pub(crate) struct TokenGenerator {
+    // None if the operating system's random number generator could not be
+    // opened.
+    os_random: Option<File>,
+}
+
+impl TokenGenerator {
+    pub(crate) fn new() -> TokenGenerator {
+        TokenGenerator {
+            os_random: File::open("/dev/urandom").ok(),
+        }
+    }
+
+    pub(crate) fn generate(&self) -> Token {
+        let mut os_random = self
+            .os_random
+            .as_ref()
+            .expect("no operating system random number generator to generate tokens from");
+        let mut bytes = [0; 16];
+        os_random
+            .read_exact(&mut bytes)
+            .expect("could not read the operating system random number generator");
+        Token(u128::from_le_bytes(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cloakroom::token::{Token, TokenGenerator};
+    use std::fs::File;
+
+    #[test]
+    fn test1() {
+        let generator = TokenGenerator::new();
+        let tokens: Vec<Token> = (0..100).map(|_| generator.generate()).collect();
+        for (index, token) in tokens.iter().enumerate() {
+            assert!(!tokens[index + 1..].contains(token));
+            assert_eq!(token.to_string().parse(), Ok(*token));
+        }
+
+        assert!("1234".parse::<Token>().is_err());
+        assert!("0123456789abcdef0123456789abcdeg".parse::<Token>().is_err());
+        assert!("+123456789abcdef0123456789abcdef".parse::<Token>().is_err());
+        assert!(" 123456789abcdef0123456789abcdef".parse::<Token>().is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "no operating system random number generator")]
+    fn test2() {
+        // Tokens are never made without the operating system's random number
+        // generator.
+        let mut generator = TokenGenerator::new();
+        generator.os_random = None;
+        generator.generate();
+    }
+
+    #[test]
+    #[should_panic(expected = "could not read the operating system random number generator")]
+    fn test3() {
+        let mut generator = TokenGenerator::new();
+        generator.os_random = Some(File::open("/dev/null").unwrap());
+        generator.generate();
+    }
+}
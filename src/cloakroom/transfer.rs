@@ -0,0 +1,132 @@
+//! Moving the contents of a closed locker into another locker.
+//!
+//! If a locker breaks, or a customer needs a bigger one, their items can be
+//! moved into a free locker with room for them in a single step, so the
+//! items are never in neither locker. The customer's stay carries over to
+//! the new locker, so they are charged as if they had used it all along.
+//! The old key no longer opens anything, and a new key is issued for the
+//! new locker.
+
+use super::audit::AuditAction;
+use super::{Capacity, Cloakroom, CloakroomError, Key, LockerInUseState, LockerNumber};
+
+impl Cloakroom {
+    /// Moves the contents of the closed locker that `key` opens into
+    /// `destination`, or, if it is `None`, into a free locker chosen by the
+    /// allocation strategy, as described in the [module
+    /// documentation](self). Returns the key to the new locker. Nothing
+    /// changes if an error is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cloakroom_model::cloakroom::{Cloakroom, CloakroomError, LockerSpec, LockerState};
+    /// use cloakroom_model::items::CloakroomItems;
+    ///
+    /// let mut cloakroom = Cloakroom::with_lockers(vec![LockerSpec::new(2), LockerSpec::new(5)]);
+    /// let mut locker = cloakroom.find_free_locker(&CloakroomItems::new()).into_result().unwrap();
+    /// locker.set_num_items("coats", 2).unwrap();
+    /// let old_key = cloakroom.close_locker(locker).unwrap();
+    ///
+    /// let new_key = cloakroom.transfer(&old_key, Some(2)).unwrap();
+    /// assert_eq!(new_key.get_locker_number(), 2);
+    /// assert!(matches!(cloakroom.get_locker_state(1), LockerState::Free));
+    /// assert!(cloakroom.open_locker(old_key).is_err());
+    /// let locker = cloakroom.open_locker(new_key).unwrap();
+    /// assert_eq!(locker.get_items().get_num_items("coats"), Some(2));
+    /// ```
+    pub fn transfer(
+        &mut self,
+        key: &Key,
+        destination: Option<LockerNumber>,
+    ) -> Result<Key, CloakroomError> {
+        self.record_abandoned_lockers();
+        self.expire_served_customers();
+        self.update_reservations();
+        let source = key.locker_number;
+        let items = self.with_state(source, |state| match state {
+            Some(LockerInUseState::Closed { items, token }) if *token == key.token => {
+                Ok(items.clone())
+            }
+            Some(LockerInUseState::Closed { .. }) => Err(CloakroomError::InvalidKey(source)),
+            Some(LockerInUseState::OutOfService { .. }) => {
+                Err(CloakroomError::LockerOutOfService(source))
+            }
+            Some(_) => Err(CloakroomError::LockerNotClosed(source)),
+            None => Err(CloakroomError::UnknownLocker(source)),
+        })?;
+        let required = Capacity::of_items(&items);
+
+        let destination = match destination {
+            Some(destination) => {
+                self.check_transfer_destination(destination, required)?;
+                destination
+            }
+            None => self
+                .allocation_strategy
+                .choose_locker(&self.free_lockers, required)
+                .ok_or(CloakroomError::NoFreeLockers)?,
+        };
+
+        // The items are put in the new locker before they are taken out of
+        // the old one, so a replay stopped between the two events does not
+        // lose them.
+        self.emit(
+            destination,
+            AuditAction::TransferredIn {
+                from: source,
+                items: items.clone(),
+            },
+        );
+        self.emit(
+            source,
+            AuditAction::TransferredOut {
+                to: destination,
+                items,
+            },
+        );
+
+        let key = self.with_state(destination, |state| match state {
+            Some(LockerInUseState::Closed { token, .. }) => Key::new(destination, *token),
+            _ => unreachable!("locker number {} was not closed", destination),
+        });
+        self.serve_queue();
+        Ok(key)
+    }
+
+    /// Checks that `destination` is a free locker with room for `required`.
+    fn check_transfer_destination(
+        &self,
+        destination: LockerNumber,
+        required: Capacity,
+    ) -> Result<(), CloakroomError> {
+        let capacity = match self.get_locker_spec(destination) {
+            Some(spec) => spec.get_capacity(),
+            None => return Err(CloakroomError::UnknownLocker(destination)),
+        };
+        self.with_state(destination, |state| match state {
+            None => Ok(()),
+            Some(LockerInUseState::OutOfService { .. }) => {
+                Err(CloakroomError::LockerOutOfService(destination))
+            }
+            Some(_) => Err(CloakroomError::LockerNotFree(destination)),
+        })?;
+        if self.reservations.holds(destination) {
+            return Err(CloakroomError::LockerReserved(destination));
+        }
+
+        if required.space > capacity.space {
+            return Err(CloakroomError::InsufficientSpace {
+                requested: required.space,
+                available: capacity.space,
+            });
+        }
+        if required.weight_grams > capacity.weight_grams {
+            return Err(CloakroomError::InsufficientWeight {
+                requested_grams: required.weight_grams,
+                available_grams: capacity.weight_grams,
+            });
+        }
+        Ok(())
+    }
+}
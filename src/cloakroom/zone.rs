@@ -0,0 +1,310 @@
+//! Dividing a cloakroom into zones.
+//!
+//! A venue may have banks of lockers in several places, e.g. one on each
+//! floor. A cloakroom made with [`Cloakroom::with_zones`] is divided into
+//! named zones, each with its own lockers and a label saying where it is.
+//! The lockers are numbered through the zones in order, so each zone has a
+//! consecutive range of locker numbers, and those of the first zone start
+//! at 1.
+//!
+//! Allocation can prefer one zone, e.g. the one nearest the entrance, using
+//! the [`PreferZone`](super::allocation::PreferZone) strategy, and
+//! [`Cloakroom::get_zone_report`] sums up the state of the lockers in a
+//! zone.
+
+use super::{Cloakroom, LockerInUseState, LockerNumber, LockerSpec, LockerState};
+use std::error;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// Specifies the name, location and lockers of a zone of a new cloakroom.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZoneSpec {
+    name: String,
+    location: String,
+    lockers: Vec<LockerSpec>,
+}
+
+impl ZoneSpec {
+    pub fn new(name: &str, location: &str, lockers: Vec<LockerSpec>) -> ZoneSpec {
+        ZoneSpec {
+            name: name.to_string(),
+            location: location.to_string(),
+            lockers,
+        }
+    }
+}
+
+/// Reasons why a cloakroom cannot be divided into the zones asked for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ZoneError {
+    MissingName,
+    /// The zone with this name has no lockers.
+    NoLockers(String),
+    RepeatedName(String),
+}
+
+impl fmt::Display for ZoneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ZoneError::MissingName => write!(f, "zone has no name"),
+
+            ZoneError::NoLockers(name) => write!(f, "zone '{}' has no lockers", name),
+
+            ZoneError::RepeatedName(name) => write!(f, "zone '{}' is repeated", name),
+        }
+    }
+}
+
+impl error::Error for ZoneError {}
+
+/// A named zone of a cloakroom, and the lockers in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Zone {
+    name: String,
+    location: String,
+    first: LockerNumber,
+    last: LockerNumber,
+}
+
+impl Zone {
+    pub(super) fn new(name: &str, location: &str, first: LockerNumber, last: LockerNumber) -> Zone {
+        Zone {
+            name: name.to_string(),
+            location: location.to_string(),
+            first,
+            last,
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the label saying where the zone is, e.g. "Ground floor, by
+    /// the entrance", which may be empty.
+    pub fn get_location(&self) -> &str {
+        &self.location
+    }
+
+    pub fn get_locker_numbers(&self) -> RangeInclusive<LockerNumber> {
+        self.first..=self.last
+    }
+
+    pub fn get_num_lockers(&self) -> usize {
+        self.last - self.first + 1
+    }
+
+    pub fn contains(&self, locker_number: LockerNumber) -> bool {
+        self.get_locker_numbers().contains(&locker_number)
+    }
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if !self.location.is_empty() {
+            write!(f, " ({})", self.location)?;
+        }
+        write!(f, ", lockers {} to {}", self.first, self.last)
+    }
+}
+
+/// The number of lockers in each state in a zone, and how much of their
+/// space is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZoneReport<'a> {
+    zone: &'a Zone,
+    num_free: usize,
+    num_closed: usize,
+    num_being_changed: usize,
+    num_abandoned: usize,
+    num_out_of_service: usize,
+    used_space: u64,
+    max_space: u64,
+}
+
+impl<'a> ZoneReport<'a> {
+    pub fn get_zone(&self) -> &'a Zone {
+        self.zone
+    }
+
+    /// Returns the number of free lockers, including any held back for
+    /// reservations.
+    pub fn get_num_free(&self) -> usize {
+        self.num_free
+    }
+
+    pub fn get_num_closed(&self) -> usize {
+        self.num_closed
+    }
+
+    pub fn get_num_being_changed(&self) -> usize {
+        self.num_being_changed
+    }
+
+    pub fn get_num_abandoned(&self) -> usize {
+        self.num_abandoned
+    }
+
+    pub fn get_num_out_of_service(&self) -> usize {
+        self.num_out_of_service
+    }
+
+    /// Returns the space taken up by the contents of the lockers in use.
+    pub fn get_used_space(&self) -> u64 {
+        self.used_space
+    }
+
+    /// Returns the space in all of the zone's lockers.
+    pub fn get_max_space(&self) -> u64 {
+        self.max_space
+    }
+}
+
+impl fmt::Display for ZoneReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} free, {} closed, {} being changed, {} abandoned, {} out of service, \
+             space used: {} of {}",
+            self.zone,
+            self.num_free,
+            self.num_closed,
+            self.num_being_changed,
+            self.num_abandoned,
+            self.num_out_of_service,
+            self.used_space,
+            self.max_space
+        )
+    }
+}
+
+impl Cloakroom {
+    /// Creates a cloakroom divided into zones, as described in the [module
+    /// documentation](self). Every zone must have a name and at least one
+    /// locker, and no two zones may have the same name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cloakroom_model::cloakroom::zone::ZoneSpec;
+    /// use cloakroom_model::cloakroom::{Cloakroom, LockerSpec};
+    ///
+    /// let cloakroom = Cloakroom::with_zones(vec![
+    ///     ZoneSpec::new("ground", "Ground floor", vec![LockerSpec::new(5); 10]),
+    ///     ZoneSpec::new("first", "First floor", vec![LockerSpec::new(20); 4]),
+    /// ])
+    /// .unwrap();
+    /// assert_eq!(cloakroom.get_num_lockers(), 14);
+    /// let zone = cloakroom.get_zone_of(12).unwrap();
+    /// assert_eq!(zone.get_name(), "first");
+    /// assert_eq!(zone.get_locker_numbers(), 11..=14);
+    /// assert_eq!(cloakroom.get_locker_spec(12).unwrap().max_space, 20);
+    /// ```
+    pub fn with_zones(zone_specs: Vec<ZoneSpec>) -> Result<Cloakroom, ZoneError> {
+        let mut lockers = Vec::new();
+        let mut zones: Vec<Zone> = Vec::new();
+        for zone_spec in zone_specs {
+            if zone_spec.name.is_empty() {
+                return Err(ZoneError::MissingName);
+            }
+            if zone_spec.lockers.is_empty() {
+                return Err(ZoneError::NoLockers(zone_spec.name));
+            }
+            if zones.iter().any(|zone| zone.name == zone_spec.name) {
+                return Err(ZoneError::RepeatedName(zone_spec.name));
+            }
+
+            let first = lockers.len() + 1;
+            lockers.extend(zone_spec.lockers);
+            zones.push(Zone::new(
+                &zone_spec.name,
+                &zone_spec.location,
+                first,
+                lockers.len(),
+            ));
+        }
+
+        Ok(Cloakroom {
+            zones,
+            ..Cloakroom::with_lockers(lockers)
+        })
+    }
+
+    /// Returns the zones of the cloakroom, in locker number order. A
+    /// cloakroom not made with [`Cloakroom::with_zones`] has none.
+    pub fn get_zones(&self) -> &[Zone] {
+        &self.zones
+    }
+
+    pub fn get_zone(&self, name: &str) -> Option<&Zone> {
+        self.zones.iter().find(|zone| zone.name == name)
+    }
+
+    /// Returns the zone holding a locker, or `None` if there is no such
+    /// locker or the cloakroom has no zones.
+    pub fn get_zone_of(&self, locker_number: LockerNumber) -> Option<&Zone> {
+        self.zones.iter().find(|zone| zone.contains(locker_number))
+    }
+
+    /// Sums up the state of the lockers in a zone, or returns `None` if
+    /// there is no zone called `name`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cloakroom_model::cloakroom::zone::ZoneSpec;
+    /// use cloakroom_model::cloakroom::{Cloakroom, LockerSpec};
+    /// use cloakroom_model::items::CloakroomItems;
+    ///
+    /// let mut cloakroom = Cloakroom::with_zones(vec![
+    ///     ZoneSpec::new("ground", "Ground floor", vec![LockerSpec::new(5); 2]),
+    ///     ZoneSpec::new("first", "", vec![LockerSpec::new(20)]),
+    /// ])
+    /// .unwrap();
+    /// let mut locker = cloakroom.find_free_locker(&CloakroomItems::new()).into_result().unwrap();
+    /// locker.set_num_items("coats", 3).unwrap();
+    /// let _key = cloakroom.close_locker(locker).unwrap();
+    ///
+    /// let report = cloakroom.get_zone_report("ground").unwrap();
+    /// assert_eq!(report.get_num_closed(), 1);
+    /// assert_eq!(report.get_num_free(), 1);
+    /// assert_eq!(
+    ///     report.to_string(),
+    ///     "ground (Ground floor), lockers 1 to 2: 1 free, 1 closed, 0 being changed, \
+    ///      0 abandoned, 0 out of service, space used: 3 of 10"
+    /// );
+    /// ```
+    pub fn get_zone_report(&self, name: &str) -> Option<ZoneReport<'_>> {
+        let zone = self.get_zone(name)?;
+        let mut report = ZoneReport {
+            zone,
+            num_free: 0,
+            num_closed: 0,
+            num_being_changed: 0,
+            num_abandoned: 0,
+            num_out_of_service: 0,
+            used_space: 0,
+            max_space: 0,
+        };
+        for locker_number in zone.get_locker_numbers() {
+            match self.get_locker_state(locker_number) {
+                LockerState::Free => report.num_free += 1,
+                LockerState::Closed(..) => report.num_closed += 1,
+                LockerState::ContentsBeingChanged(_) => report.num_being_changed += 1,
+                LockerState::Abandoned(_) => report.num_abandoned += 1,
+                LockerState::OutOfService { .. } => report.num_out_of_service += 1,
+                LockerState::NonExistent => (),
+            }
+            report.used_space += self.with_state(locker_number, |state| match state {
+                Some(LockerInUseState::Closed { items, .. })
+                | Some(LockerInUseState::ContentsBeingChanged { items })
+                | Some(LockerInUseState::Abandoned { items }) => u64::from(items.get_total_space()),
+                Some(LockerInUseState::OutOfService { .. }) | None => 0,
+            });
+            report.max_space += u64::from(self.lockers[locker_number - 1].max_space);
+        }
+        Some(report)
+    }
+}
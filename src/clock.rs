@@ -0,0 +1,81 @@
+//! Sources of the current time.
+//!
+//! A cloakroom reads the time from its [`Clock`] whenever something happens
+//! to a locker, to timestamp audit events, charge for storage and record
+//! when lockers were used. The [`SystemClock`] is used unless another is
+//! given, and a [`MockClock`] can be used to control time in tests.
+
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+pub trait Clock {
+    /// Returns the current time.
+    fn now(&self) -> SystemTime;
+}
+
+/// Reads the time from the system.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock whose time only changes when it is set or advanced. Clones share
+/// the same time, so a test can keep a clone of the clock given to a
+/// cloakroom and advance it.
+///
+/// # Examples
+///
+/// ```
+/// use cloakroom_model::clock::{Clock, MockClock};
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let clock = MockClock::new(UNIX_EPOCH);
+/// let shared = clock.clone();
+/// shared.advance(Duration::from_secs(60));
+/// assert_eq!(clock.now(), UNIX_EPOCH + Duration::from_secs(60));
+/// ```
+#[derive(Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<SystemTime>>,
+}
+
+impl MockClock {
+    pub fn new(now: SystemTime) -> MockClock {
+        MockClock {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        *self.lock() = now;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.lock() += duration;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SystemTime> {
+        self.now
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        *self.lock()
+    }
+}
+
+impl fmt::Debug for MockClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockClock")
+            .field("now", &self.now())
+            .finish()
+    }
+}
@@ -1,13 +1,25 @@
 use super::console;
 use crate::cloakroom;
+use crate::cloakroom::zone::ZoneSpec;
+use crate::cloakroom::{LockerNumber, LockerSpec};
+use crate::items::{CatalogueError, ItemCatalogue};
+use crate::pricing::{Tariff, TariffError};
 use std::fmt;
+use std::fs;
+use std::io::{self, BufReader, BufWriter};
+use std::ops::RangeInclusive;
+use std::path::Path;
 use std::str::FromStr;
+use std::sync::Arc;
 
 const MIN_VALID_NUM_LOCKERS: usize = 1;
 const MAX_VALID_NUM_LOCKERS: usize = 1000;
 
-const MIN_VALID_MAX_ITEMS: u16 = 5;
-const MAX_VALID_MAX_ITEMS: u16 = 15;
+const MIN_VALID_MAX_SPACE: u32 = 5;
+const MAX_VALID_MAX_SPACE: u32 = 15;
+
+const MIN_VALID_NUM_ZONES: usize = 1;
+const MAX_VALID_NUM_ZONES: usize = 10;
 
 pub fn input_param<T>(min_valid: T, max_valid: T, descr: &str) -> T
 where
@@ -28,25 +40,174 @@ fn input_num_lockers() -> usize {
     )
 }
 
-fn input_locker_capacity() -> u16 {
+fn input_locker_capacity() -> u32 {
     input_param(
-        MIN_VALID_MAX_ITEMS,
-        MAX_VALID_MAX_ITEMS,
-        "number of items each locker can hold",
+        MIN_VALID_MAX_SPACE,
+        MAX_VALID_MAX_SPACE,
+        "amount of space in each locker",
     )
 }
 
-pub fn create_cloakroom() -> cloakroom::Cloakroom {
-    let num_lockers = input_num_lockers();
-    let max_items_per_locker = input_locker_capacity();
+fn input_num_zones() -> usize {
+    input_param(MIN_VALID_NUM_ZONES, MAX_VALID_NUM_ZONES, "number of zones")
+}
+
+/// Asks for the name, location and number of lockers of each zone, giving
+/// every locker `max_space_per_locker`.
+fn input_zones(num_zones: usize, max_space_per_locker: u32) -> Vec<ZoneSpec> {
+    let mut names: Vec<String> = Vec::new();
+    let mut zones = Vec::new();
+    for zone_number in 1..=num_zones {
+        println!("Zone {}:", zone_number);
+        let name = loop {
+            console::print_flush("Enter name: ");
+            let name = console::input_line();
+            if !name.is_empty() && !names.contains(&name) {
+                break name;
+            }
+            console::print_err("each zone needs a different name");
+        };
+        console::print_flush("Enter location: ");
+        let location = console::input_line();
+        let num_lockers = input_num_lockers();
+
+        zones.push(ZoneSpec::new(
+            &name,
+            &location,
+            vec![LockerSpec::new(max_space_per_locker); num_lockers],
+        ));
+        names.push(name);
+    }
+    zones
+}
+
+/// Creates a cloakroom, divided into zones if more than one is asked for.
+/// The first zone should be the one nearest the entrance, as lockers are
+/// given out there first.
+pub fn create_cloakroom(item_catalogue: Arc<ItemCatalogue>) -> cloakroom::Cloakroom {
+    let num_zones = input_num_zones();
+    let cloakroom = if num_zones == MIN_VALID_NUM_ZONES {
+        let num_lockers = input_num_lockers();
+        let max_space_per_locker = input_locker_capacity();
+        cloakroom::Cloakroom::new(num_lockers, max_space_per_locker)
+    } else {
+        let max_space_per_locker = input_locker_capacity();
+        cloakroom::Cloakroom::with_zones(input_zones(num_zones, max_space_per_locker))
+            .expect("zones were checked as they were entered")
+    };
+
+    cloakroom.with_item_catalogue(item_catalogue)
+}
+
+/// Reads the categories of item accepted by the cloakroom from the config
+/// file `path`. The default categories are used if there is no such file or
+/// it cannot be read.
+pub fn load_item_catalogue(path: &Path) -> Arc<ItemCatalogue> {
+    if !path.exists() {
+        return Arc::new(ItemCatalogue::default());
+    }
 
-    cloakroom::Cloakroom::new(num_lockers, max_items_per_locker)
+    let result = fs::File::open(path)
+        .map_err(CatalogueError::from)
+        .and_then(|file| ItemCatalogue::load(BufReader::new(file)));
+    match result {
+        Ok(catalogue) => Arc::new(catalogue),
+
+        Err(err) => {
+            console::print_err(&format!("{}, so using default item categories", err));
+            Arc::new(ItemCatalogue::default())
+        }
+    }
+}
+
+/// Reads the charges made by the cloakroom from the config file `path`.
+/// Nothing is charged if there is no such file or it cannot be read.
+pub fn load_tariff(path: &Path) -> Tariff {
+    if !path.exists() {
+        return Tariff::default();
+    }
+
+    let result = fs::File::open(path)
+        .map_err(TariffError::from)
+        .and_then(|file| Tariff::load(BufReader::new(file)));
+    match result {
+        Ok(tariff) => tariff,
+
+        Err(err) => {
+            console::print_err(&format!("{}, so nothing will be charged", err));
+            Tariff::default()
+        }
+    }
 }
 
+/// Offers to restore the cloakroom saved in `path`, if there is one.
+/// Returns `None` if there is no saved cloakroom, the user declines, or the
+/// file cannot be read.
+pub fn restore_cloakroom(path: &Path) -> Option<(cloakroom::Cloakroom, Vec<cloakroom::Key>)> {
+    if !path.exists() {
+        return None;
+    }
+
+    let prompt = format!("Restore cloakroom saved in {}? (y/n): ", path.display());
+    if !console::input_yes_no_loop(&prompt) {
+        return None;
+    }
+
+    let result = fs::File::open(path)
+        .map_err(cloakroom::persistence::StateFileError::from)
+        .and_then(|file| cloakroom::Cloakroom::load(BufReader::new(file)));
+    match result {
+        Ok(state) => Some(state),
+
+        Err(err) => {
+            console::print_err(&err.to_string());
+            None
+        }
+    }
+}
+
+/// Saves the cloakroom and keys to `path`. The state is written to a
+/// temporary file first so that a failure part way through does not destroy
+/// the previously saved state.
+pub fn save_cloakroom<'a, I>(
+    path: &Path,
+    cloakroom: &cloakroom::Cloakroom,
+    keys: I,
+) -> io::Result<()>
+where
+    I: IntoIterator<Item = &'a cloakroom::Key>,
+{
+    let tmp_path = path.with_extension("tmp");
+    {
+        let file = fs::File::create(&tmp_path)?;
+        cloakroom.save(BufWriter::new(file), keys)?;
+    }
+    fs::rename(&tmp_path, path)
+}
+
+/// Prints the contents of the closed lockers, zone by zone, with a summary
+/// of each zone, if the cloakroom is divided into zones.
 pub fn print_contents_of_closed_lockers(cloakroom: &cloakroom::Cloakroom) {
-    for locker_number in 1..=cloakroom.get_num_lockers() {
+    if cloakroom.get_zones().is_empty() {
+        print_closed_lockers(cloakroom, 1..=cloakroom.get_num_lockers());
+        return;
+    }
+
+    for zone in cloakroom.get_zones() {
+        if let Some(report) = cloakroom.get_zone_report(zone.get_name()) {
+            println!("\n{}", report);
+        }
+        print_closed_lockers(cloakroom, zone.get_locker_numbers());
+    }
+}
+
+fn print_closed_lockers(
+    cloakroom: &cloakroom::Cloakroom,
+    locker_numbers: RangeInclusive<LockerNumber>,
+) {
+    for locker_number in locker_numbers {
         let locker_state = cloakroom.get_locker_state(locker_number);
-        if let cloakroom::LockerState::Closed(items) = locker_state {
+        if let cloakroom::LockerState::Closed(items, _) = locker_state {
             println!("locker number {}: [{}]", locker_number, items);
         }
     }
@@ -31,24 +31,20 @@ pub fn halt() {
     input_line();
 }
 
-pub fn input<T: FromStr>() -> Result<T, ()> {
+pub fn input<T: FromStr>() -> Option<T> {
     let s = input_line();
-    match s.parse() {
-        Ok(data) => Ok(data),
-
-        Err(_) => Err(()),
-    }
+    s.parse().ok()
 }
 
-pub fn input_within_range<T>(min_valid: T, max_valid: T) -> Result<T, ()>
+pub fn input_within_range<T>(min_valid: T, max_valid: T) -> Option<T>
 where
     T: FromStr + PartialOrd + Copy,
 {
     let data = input()?;
     if data < min_valid || data > max_valid {
-        Err(())
+        None
     } else {
-        Ok(data)
+        Some(data)
     }
 }
 
@@ -56,11 +52,11 @@ pub fn input_loop<T: FromStr>(prompt: &str) -> T {
     loop {
         print_flush(prompt);
         match input() {
-            Ok(data) => {
+            Some(data) => {
                 return data;
             }
 
-            Err(_) => {
+            None => {
                 print_err("data entered is invalid/outside of range");
             }
         }
@@ -74,11 +70,11 @@ where
     loop {
         print_flush(prompt);
         match input_within_range(min_valid, max_valid) {
-            Ok(data) => {
+            Some(data) => {
                 return data;
             }
 
-            Err(_) => {
+            None => {
                 println!(
                     "{} number must be between {} and {}.",
                     ERR_PREFIX, min_valid, max_valid
@@ -87,3 +83,23 @@ where
         }
     }
 }
+
+/// Repeatedly prompts for a yes/no answer until `y` or `n` is entered.
+pub fn input_yes_no_loop(prompt: &str) -> bool {
+    loop {
+        print_flush(prompt);
+        match input_line().to_lowercase().as_str() {
+            "y" | "yes" => {
+                return true;
+            }
+
+            "n" | "no" => {
+                return false;
+            }
+
+            _ => {
+                print_err("please enter y or n");
+            }
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use super::console;
 use crate::cloakroom;
+use crate::items::{CloakroomItems, ItemCatalogue, ItemCategory};
+use std::sync::Arc;
 
 pub fn print_locker_info(locker: &cloakroom::Locker) {
     println!("\n-----------------------------------------------------------------------------");
@@ -12,48 +14,46 @@ pub fn print_locker_info(locker: &cloakroom::Locker) {
     println!("{}", locker.get_items());
 
     println!(
-        "Total number of items currently in locker: {}, max items: {}",
+        "Total number of items currently in locker: {}, space used: {} of {}",
         locker.get_total_num_items(),
-        locker.get_max_items()
+        locker.get_used_space(),
+        locker.get_max_space()
     );
+    if let Some(max_weight_grams) = locker.get_max_weight_grams() {
+        println!(
+            "Weight of items: {} g, weight limit: {} g",
+            locker.get_total_weight_grams(),
+            max_weight_grams
+        );
+    }
 
     println!("-----------------------------------------------------------------------------\n");
 }
 
-pub fn input_num_coats(locker: &mut cloakroom::Locker) {
-    let num_coats = input_num_items("coats");
-    if let Err(()) = locker.set_num_coats(num_coats) {
-        print_insufficient_space_error();
+/// Asks for the number of items of `category` to be kept in the locker.
+pub fn input_num_items(locker: &mut cloakroom::Locker, category: &ItemCategory) {
+    let prompt = format!("Enter number of {0}: ", category.get_description());
+    let num_items = console::input_loop(&prompt);
+    if let Err(err) = locker.set_num_items(category.get_name(), num_items) {
+        print_locker_error(&err);
     };
 }
 
-pub fn input_num_backpacks(locker: &mut cloakroom::Locker) {
-    let num_backpacks = input_num_items("backpacks");
-    if let Err(()) = locker.set_num_backpacks(num_backpacks) {
-        print_insufficient_space_error();
-    };
+/// Asks for the number of items of each category that a customer says are
+/// in their locker.
+pub fn input_contents_description(catalogue: &Arc<ItemCatalogue>) -> CloakroomItems {
+    let mut description = CloakroomItems::from_catalogue(catalogue.clone());
+    for category in catalogue.get_categories() {
+        let prompt = format!("Enter number of {0}: ", category.get_description());
+        let num_items = console::input_loop(&prompt);
+        description
+            .set_num_items(category.get_name(), num_items)
+            .expect("category is in the catalogue");
+    }
+    description
 }
 
-pub fn input_num_umbrellas(locker: &mut cloakroom::Locker) {
-    let num_umbrellas = input_num_items("umbrellas");
-    if let Err(()) = locker.set_num_umbrellas(num_umbrellas) {
-        print_insufficient_space_error();
-    };
-}
-
-pub fn input_num_other_items(locker: &mut cloakroom::Locker) {
-    let num_other_items = input_num_items("other items");
-    if let Err(()) = locker.set_num_other_items(num_other_items) {
-        print_insufficient_space_error();
-    };
-}
-
-fn print_insufficient_space_error() {
-    console::print_err("not enough space in locker");
+fn print_locker_error(err: &cloakroom::CloakroomError) {
+    console::print_err(&err.to_string());
     console::halt();
 }
-
-fn input_num_items(item_descr: &str) -> u8 {
-    let prompt = format!("Enter number of {0}: ", item_descr);
-    console::input_loop(&prompt)
-}
@@ -1,13 +1,56 @@
 use std::collections::hash_map;
-
-use super::cloakroom_io::{create_cloakroom, print_contents_of_closed_lockers};
+use std::env;
+use std::path::Path;
+use std::time::Duration;
+
+use super::cloakroom_io::{
+    create_cloakroom, load_item_catalogue, load_tariff, print_contents_of_closed_lockers,
+    restore_cloakroom, save_cloakroom,
+};
 use super::console;
 use super::locker_io;
 use crate::cloakroom;
+use crate::cloakroom::allocation::{FirstFit, PreferZone};
+use crate::cloakroom::audit::JsonLinesAuditSink;
+use crate::cloakroom::lost_key::AdminCredential;
+use crate::cloakroom::queue::{PriorityClass, QueueTicket};
+use crate::items::CloakroomItems;
+use crate::lost_property::{ClaimReference, LostPropertyQuery};
 
 type KeyCollection = hash_map::HashMap<cloakroom::LockerNumber, cloakroom::Key>;
 
-fn input_menu_option(max_option: u8) -> u8 {
+/// Keys whose lockers' contents have been moved to lost property.
+type ClaimCollection = hash_map::HashMap<ClaimReference, cloakroom::Key>;
+
+/// File in which the state of the cloakroom is saved, so that the program
+/// can be restarted without losing the contents of lockers.
+const STATE_FILE: &str = "cloakroom_state.txt";
+
+/// Config file listing the categories of item accepted by a new cloakroom.
+/// A restored cloakroom keeps the categories it was saved with.
+const ITEM_CATEGORIES_FILE: &str = "item_categories.txt";
+
+/// Config file giving the charges made when items are collected. It is read
+/// each time the program starts, as the tariff is not saved with the state.
+const TARIFF_FILE: &str = "tariff.txt";
+
+/// Environment variable holding the passphrase that attendants must enter
+/// to open a locker whose key has been lost. The lost-key procedure cannot
+/// be used if it is not set.
+const ADMIN_PASSPHRASE_VAR: &str = "CLOAKROOM_ADMIN_PASSPHRASE";
+
+/// File to which an audit event is appended for everything that happens to
+/// each locker, as one line of JSON per event.
+const AUDIT_LOG_FILE: &str = "cloakroom_audit.jsonl";
+
+/// How long a locker can stay closed before it is overdue, and its contents
+/// can be moved to lost property.
+const MAX_STORAGE_HOURS: u64 = 24;
+
+/// How long items are kept in lost property before they can be disposed of.
+const LOST_PROPERTY_RETENTION_DAYS: u32 = 90;
+
+fn input_menu_option(max_option: usize) -> usize {
     let prompt = format!("Please enter option between 1 and {}: ", max_option);
 
     console::input_within_range_loop(&prompt, 1, max_option)
@@ -17,45 +60,143 @@ fn input_menu_option(max_option: u8) -> u8 {
 pub struct Model {
     cloakroom: cloakroom::Cloakroom,
     keys: KeyCollection,
+    claims: ClaimCollection,
 }
 
 impl Model {
     pub fn new() -> Model {
-        let cloakroom = create_cloakroom();
+        let mut keys = KeyCollection::new();
+        let mut claims = ClaimCollection::new();
+        let mut cloakroom = match restore_cloakroom(Path::new(STATE_FILE)) {
+            Some((cloakroom, restored_keys)) => {
+                for key in restored_keys {
+                    match cloakroom.find_claim(&key) {
+                        Some(claim) => {
+                            claims.insert(claim.get_claim_reference(), key);
+                        }
+                        None => {
+                            keys.insert(key.get_locker_number(), key);
+                        }
+                    }
+                }
+                cloakroom
+            }
+
+            None => create_cloakroom(load_item_catalogue(Path::new(ITEM_CATEGORIES_FILE))),
+        };
+
+        // Lockers are given out in the first zone, nearest the entrance,
+        // while it has room.
+        if let Some(zone) = cloakroom.get_zones().first() {
+            let locker_numbers = zone.get_locker_numbers();
+            cloakroom =
+                cloakroom.with_allocation_strategy(PreferZone::new(locker_numbers, FirstFit));
+        }
+
+        cloakroom = cloakroom
+            .with_tariff(load_tariff(Path::new(TARIFF_FILE)))
+            .with_max_storage_duration(Duration::from_secs(MAX_STORAGE_HOURS * 60 * 60))
+            .with_lost_property_retention_days(LOST_PROPERTY_RETENTION_DAYS)
+            .with_statistics();
+
+        if let Ok(passphrase) = env::var(ADMIN_PASSPHRASE_VAR) {
+            cloakroom = cloakroom.with_admin_credential(AdminCredential::new(&passphrase));
+        }
+
+        match JsonLinesAuditSink::open(Path::new(AUDIT_LOG_FILE)) {
+            Ok(audit_sink) => cloakroom = cloakroom.with_audit_sink(audit_sink),
+            Err(err) => console::print_err(&format!(
+                "failed to open audit log {}: {}",
+                AUDIT_LOG_FILE, err
+            )),
+        }
+
+        console::print_flush("Enter attendant ID (or press return for none): ");
+        let attendant_id = console::input_line();
+        if !attendant_id.is_empty() {
+            cloakroom.set_attendant_id(Some(&attendant_id));
+        }
 
         Model {
             cloakroom,
-            keys: KeyCollection::new(),
+            keys,
+            claims,
+        }
+    }
+
+    fn save(&mut self) {
+        if let Some(err) = self.cloakroom.take_audit_error() {
+            console::print_err(&format!("failed to write to audit log: {}", err));
+            console::halt();
+        }
+        let keys = self.keys.values().chain(self.claims.values());
+        if let Err(err) = save_cloakroom(Path::new(STATE_FILE), &self.cloakroom, keys) {
+            console::print_err(&format!("failed to save cloakroom state: {}", err));
+            console::halt();
         }
     }
 
-    fn open_locker(&mut self) -> Result<cloakroom::Locker, ()> {
-        if self.keys.len() == 0 {
+    fn open_locker(&mut self) -> Option<cloakroom::Locker> {
+        if self.keys.is_empty() {
             console::print_err("there are no closed lockers from which to collect items");
             console::halt();
-            return Err(());
+            return None;
         };
 
         let locker_number = console::input_loop("Enter locker number printed on key: ");
         match self.keys.remove(&locker_number) {
             Some(key) => match self.cloakroom.open_locker(key) {
-                Ok(locker) => Ok(locker),
+                Ok(locker) => Some(locker),
 
-                Err(err_str) => {
-                    println!("{} {}", console::ERR_PREFIX, err_str);
-                    Err(())
+                Err(err) => {
+                    console::print_err(&err.to_string());
+                    console::halt();
+                    None
                 }
             },
 
             None => {
-                println!(
-                    "{} key for locker number {} not found.",
-                    console::ERR_PREFIX,
+                console::print_err(&format!(
+                    "key for locker number {} not found",
                     locker_number
-                );
-                console::halt();
+                ));
+                if console::input_yes_no_loop("Use lost key procedure? (y/n): ") {
+                    self.open_locker_without_key(locker_number)
+                } else {
+                    None
+                }
+            }
+        }
+    }
 
-                Err(())
+    /// Opens a closed locker whose key has been lost, once an attendant has
+    /// given the admin passphrase and the customer has described the
+    /// contents of the locker.
+    fn open_locker_without_key(
+        &mut self,
+        locker_number: cloakroom::LockerNumber,
+    ) -> Option<cloakroom::Locker> {
+        console::print_flush("Enter admin passphrase: ");
+        let admin_credential = AdminCredential::new(&console::input_line());
+
+        println!("Describe the contents of locker number {}:", locker_number);
+        let description =
+            locker_io::input_contents_description(self.cloakroom.get_item_catalogue());
+        let fee_pence: u32 = console::input_loop("Enter lost key fee in pence (0 for no fee): ");
+        let fee_pence = if fee_pence > 0 { Some(fee_pence) } else { None };
+
+        match self.cloakroom.open_locker_without_key(
+            locker_number,
+            &admin_credential,
+            &description,
+            fee_pence,
+        ) {
+            Ok(locker) => Some(locker),
+
+            Err(err) => {
+                console::print_err(&err.to_string());
+                console::halt();
+                None
             }
         }
     }
@@ -65,7 +206,16 @@ impl Model {
         let locker_contents_str = locker.get_items().to_string();
         let num_items = locker.get_total_num_items();
 
-        let key = self.cloakroom.close_locker(locker);
+        let key = match self.cloakroom.close_locker(locker) {
+            Ok(key) => key,
+
+            Err(err) => {
+                console::print_err(&err.to_string());
+                console::halt();
+                return;
+            }
+        };
+        let ticket = key.to_string();
         self.keys.insert(locker_number, key);
 
         println!("---------------------------------------------------------------------------------------");
@@ -80,6 +230,7 @@ impl Model {
             print!("Locker number {}, which is empty, ", locker_number);
             println!("has been closed and key has been obtained.");
         }
+        println!("Key ticket: {}", ticket);
         println!("---------------------------------------------------------------------------------------");
         console::halt();
     }
@@ -88,50 +239,41 @@ impl Model {
         loop {
             locker_io::print_locker_info(&locker);
 
-            println!("1) Change number of coats");
-            println!("2) Change number of backpacks");
-            println!("3) Change number of umbrellas");
-            println!("4) Change number of other items");
-            println!("5) Close locker\n");
+            let categories = locker.get_items().get_catalogue().clone();
+            let categories = categories.get_categories();
+            for (index, category) in categories.iter().enumerate() {
+                println!(
+                    "{}) Change number of {}",
+                    index + 1,
+                    category.get_description()
+                );
+            }
+            let close_option = categories.len() + 1;
+            println!("{}) Close locker\n", close_option);
 
-            let option = input_menu_option(5);
+            let option = input_menu_option(close_option);
             println!();
-            match option {
-                1 => {
-                    locker_io::input_num_coats(&mut locker);
-                }
-
-                2 => {
-                    locker_io::input_num_backpacks(&mut locker);
-                }
-
-                3 => {
-                    locker_io::input_num_umbrellas(&mut locker);
-                }
-
-                4 => {
-                    locker_io::input_num_other_items(&mut locker);
-                }
-
-                5 => {
-                    self.close_locker(locker);
-                    break;
-                }
-
-                _ => {
-                    // Should not get here.
-                }
+            if option == close_option {
+                self.close_locker(locker);
+                break;
             }
+            locker_io::input_num_items(&mut locker, &categories[option - 1]);
         }
     }
 
     fn deposit_items(&mut self) {
-        let locker = match self.cloakroom.find_free_locker() {
-            cloakroom::FreeLockerResult::FreeLockerFound(locker) => locker,
-
-            cloakroom::FreeLockerResult::NoFreeLockers => {
-                println!("There are no free lockers.");
-                console::halt();
+        println!("Describe the items to be deposited:");
+        let items = locker_io::input_contents_description(self.cloakroom.get_item_catalogue());
+        let mut locker = match self.cloakroom.find_free_locker(&items).into_result() {
+            Ok(locker) => locker,
+
+            Err(err) => {
+                console::print_err(&err.to_string());
+                if console::input_yes_no_loop("Join the queue? (y/n): ") {
+                    self.join_queue(&items);
+                } else {
+                    console::halt();
+                }
                 return;
             }
         };
@@ -139,14 +281,25 @@ impl Model {
             " *** Found free locker number {} ***",
             locker.get_locker_number()
         );
+        locker
+            .set_items(items)
+            .expect("locker was found to be big enough for the items");
         self.change_locker_contents(locker);
     }
 
     fn collect_items(&mut self) {
         let locker = self.open_locker();
-        if let Ok(locker) = locker {
+        if let Some(locker) = locker {
             let locker_number = locker.get_locker_number();
-            let items = self.cloakroom.vacate_locker(locker);
+            let (items, invoice) = match self.cloakroom.checkout_locker(locker) {
+                Ok(checked_out) => checked_out,
+
+                Err(err) => {
+                    console::print_err(&err.to_string());
+                    console::halt();
+                    return;
+                }
+            };
             println!(
                 "-----------------------------------------------------------------------------"
             );
@@ -158,6 +311,10 @@ impl Model {
             println!(
                 "-----------------------------------------------------------------------------"
             );
+            println!("{}", invoice);
+            println!(
+                "-----------------------------------------------------------------------------"
+            );
 
             console::halt();
         };
@@ -165,13 +322,438 @@ impl Model {
 
     fn change_contents(&mut self) {
         let locker = self.open_locker();
-        if let Ok(locker) = locker {
+        if let Some(locker) = locker {
             self.change_locker_contents(locker);
         }
     }
 
+    /// Moves the contents of a closed locker into another locker, e.g.
+    /// because it is broken or too small, and issues a new key.
+    fn transfer_contents(&mut self) {
+        if self.keys.is_empty() {
+            console::print_err("there are no closed lockers whose contents can be moved");
+            console::halt();
+            return;
+        };
+
+        let locker_number = console::input_loop("Enter locker number printed on key: ");
+        let key = match self.keys.get(&locker_number) {
+            Some(key) => key,
+            None => {
+                console::print_err(&format!(
+                    "key for locker number {} not found",
+                    locker_number
+                ));
+                console::halt();
+                return;
+            }
+        };
+        let destination: cloakroom::LockerNumber =
+            console::input_loop("Enter locker number to move to (0 for any free locker): ");
+        let destination = if destination > 0 {
+            Some(destination)
+        } else {
+            None
+        };
+
+        match self.cloakroom.transfer(key, destination) {
+            Ok(new_key) => {
+                let new_locker_number = new_key.get_locker_number();
+                println!(
+                    "Moved contents of locker number {} to locker number {}.",
+                    locker_number, new_locker_number
+                );
+                println!("New key ticket: {}", new_key);
+                self.keys.remove(&locker_number);
+                self.keys.insert(new_locker_number, new_key);
+            }
+
+            Err(err) => console::print_err(&err.to_string()),
+        }
+        console::halt();
+    }
+
+    /// Recovers a locker that was abandoned while its contents were being
+    /// changed, for example because the program stopped, and carries on
+    /// changing them.
+    fn reclaim_locker(&mut self) {
+        let abandoned_lockers = self.cloakroom.get_abandoned_lockers();
+        if abandoned_lockers.is_empty() {
+            console::print_err("there are no abandoned lockers");
+            console::halt();
+            return;
+        }
+        let locker_numbers: Vec<String> = abandoned_lockers
+            .iter()
+            .map(|locker_number| locker_number.to_string())
+            .collect();
+        println!("Abandoned lockers: {}", locker_numbers.join(", "));
+
+        let locker_number = console::input_loop("Enter locker number to reclaim: ");
+        match self.cloakroom.reclaim_locker(locker_number) {
+            Ok(locker) => self.change_locker_contents(locker),
+
+            Err(err) => {
+                console::print_err(&err.to_string());
+                console::halt();
+            }
+        }
+    }
+
+    /// Moves the contents of every overdue locker to lost property.
+    fn move_overdue_items(&mut self) {
+        let overdue_lockers = self.cloakroom.get_overdue_lockers();
+        if overdue_lockers.is_empty() {
+            println!(
+                "No lockers have been closed for more than {} hours.",
+                MAX_STORAGE_HOURS
+            );
+            console::halt();
+            return;
+        }
+
+        for locker_number in overdue_lockers {
+            match self.cloakroom.move_to_lost_property(locker_number) {
+                Ok(claim_reference) => {
+                    println!(
+                        "Moved contents of locker number {} to lost property as {}.",
+                        locker_number, claim_reference
+                    );
+                    if let Some(key) = self.keys.remove(&locker_number) {
+                        self.claims.insert(claim_reference, key);
+                    }
+                }
+
+                Err(err) => console::print_err(&err.to_string()),
+            }
+        }
+        console::halt();
+    }
+
+    /// Hands back items moved to lost property to the customer holding the
+    /// key to the locker they were in.
+    fn redeem_claim(&mut self) {
+        if self.claims.is_empty() {
+            console::print_err("there are no lost-property claims");
+            console::halt();
+            return;
+        }
+        let mut claim_references: Vec<&ClaimReference> = self.claims.keys().collect();
+        claim_references.sort();
+        let claim_references: Vec<String> = claim_references
+            .iter()
+            .map(|claim_reference| claim_reference.to_string())
+            .collect();
+        println!("Claims: {}", claim_references.join(", "));
+
+        let claim_reference: ClaimReference = console::input_loop("Enter claim reference: ");
+        let key = match self.claims.remove(&claim_reference) {
+            Some(key) => key,
+            None => {
+                console::print_err(&format!("claim {} not found", claim_reference));
+                console::halt();
+                return;
+            }
+        };
+        match self.cloakroom.redeem_claim(key) {
+            Ok((items, invoice)) => {
+                println!(
+                    "-----------------------------------------------------------------------------"
+                );
+                println!("Redeemed following items from lost property:");
+                println!("{}", items);
+                println!(
+                    "-----------------------------------------------------------------------------"
+                );
+                println!("{}", invoice);
+                println!(
+                    "-----------------------------------------------------------------------------"
+                );
+            }
+
+            Err(err) => console::print_err(&err.to_string()),
+        }
+        console::halt();
+    }
+
+    /// Puts items found in the cloakroom in lost property.
+    fn hand_in_found_items(&mut self) {
+        println!("Describe the items found:");
+        let items = locker_io::input_contents_description(self.cloakroom.get_item_catalogue());
+        console::print_flush("Enter description (e.g. colour, brand): ");
+        let description = console::input_line();
+        let locker_number: cloakroom::LockerNumber =
+            console::input_loop("Enter number of locker found in or next to (0 for none): ");
+        let locker_number = if locker_number > 0 {
+            Some(locker_number)
+        } else {
+            None
+        };
+
+        match self
+            .cloakroom
+            .hand_in_found_items(&items, &description, locker_number)
+        {
+            Ok(claim_reference) => {
+                println!("Found items are held as {}.", claim_reference);
+            }
+
+            Err(err) => console::print_err(&err.to_string()),
+        }
+        console::halt();
+    }
+
+    /// Lists the items in lost property whose description contains the text
+    /// entered, flagging those that are due for disposal.
+    fn search_lost_property(&self) {
+        console::print_flush("Enter text to search for (or press return for all): ");
+        let text = console::input_line();
+        let lost_property = self.cloakroom.get_lost_property();
+        let bundles = lost_property.search(&LostPropertyQuery::new().with_text(&text));
+        if bundles.is_empty() {
+            println!("No lost property found.");
+        }
+
+        let due_for_disposal = self.cloakroom.get_lost_property_due_for_disposal();
+        for bundle in bundles {
+            if due_for_disposal.contains(&bundle) {
+                println!("{} [due for disposal]", bundle);
+            } else {
+                println!("{}", bundle);
+            }
+        }
+        console::halt();
+    }
+
+    /// Hands back items in lost property to a customer who does not have a
+    /// key for them, once the attendant is satisfied that they own them.
+    fn claim_lost_property(&mut self) {
+        let claim_reference: ClaimReference = console::input_loop("Enter claim reference: ");
+        match self.cloakroom.claim_lost_property(claim_reference) {
+            Ok(bundle) => {
+                self.claims.remove(&claim_reference);
+                println!("Handed back following items from lost property:");
+                println!("{}", bundle.get_items());
+            }
+
+            Err(err) => console::print_err(&err.to_string()),
+        }
+        console::halt();
+    }
+
+    /// Disposes of the items that have been in lost property for longer than
+    /// the retention period.
+    fn dispose_of_lost_property(&mut self) {
+        let claim_references: Vec<ClaimReference> = self
+            .cloakroom
+            .get_lost_property_due_for_disposal()
+            .iter()
+            .map(|bundle| bundle.get_claim_reference())
+            .collect();
+        if claim_references.is_empty() {
+            println!(
+                "No lost property has been held for more than {} days.",
+                LOST_PROPERTY_RETENTION_DAYS
+            );
+            console::halt();
+            return;
+        }
+
+        let claim_references_str: Vec<String> = claim_references
+            .iter()
+            .map(|claim_reference| claim_reference.to_string())
+            .collect();
+        println!("Due for disposal: {}", claim_references_str.join(", "));
+        if !console::input_yes_no_loop("Dispose of these items? (y/n): ") {
+            return;
+        }
+        for claim_reference in claim_references {
+            match self.cloakroom.dispose_of_lost_property(claim_reference) {
+                Ok(_) => {
+                    self.claims.remove(&claim_reference);
+                }
+
+                Err(err) => console::print_err(&err.to_string()),
+            }
+        }
+        console::halt();
+    }
+
+    fn lost_property_menu(&mut self) {
+        loop {
+            println!("\n1) Move overdue items to lost property");
+            println!("2) Hand in found items");
+            println!("3) Search lost property");
+            println!("4) Redeem lost-property claim with key");
+            println!("5) Hand back lost property by claim reference");
+            println!("6) Dispose of lost property held too long");
+            println!("7) Back\n");
+
+            let option = input_menu_option(7);
+            println!();
+            match option {
+                1 => self.move_overdue_items(),
+                2 => self.hand_in_found_items(),
+                3 => self.search_lost_property(),
+                4 => self.redeem_claim(),
+                5 => self.claim_lost_property(),
+                6 => self.dispose_of_lost_property(),
+                _ => break,
+            }
+            self.save();
+        }
+    }
+
+    /// Takes a locker out of service, or returns one to service, once an
+    /// attendant has given the admin passphrase.
+    fn maintain_lockers(&mut self) {
+        let out_of_service: Vec<String> = self
+            .cloakroom
+            .get_out_of_service_lockers()
+            .iter()
+            .map(|locker_number| locker_number.to_string())
+            .collect();
+        if out_of_service.is_empty() {
+            println!("No lockers are out of service.");
+        } else {
+            println!("Out of service: {}", out_of_service.join(", "));
+        }
+
+        println!("\n1) Take a locker out of service");
+        println!("2) Return a locker to service");
+        println!("3) Back\n");
+        let option = input_menu_option(3);
+        println!();
+        if option == 3 {
+            return;
+        }
+
+        let locker_number = console::input_loop("Enter locker number: ");
+        console::print_flush("Enter admin passphrase: ");
+        let admin_credential = AdminCredential::new(&console::input_line());
+        let result = if option == 1 {
+            console::print_flush("Enter reason: ");
+            let reason = console::input_line();
+            self.cloakroom
+                .take_out_of_service(locker_number, &admin_credential, &reason)
+        } else {
+            self.cloakroom
+                .return_to_service(locker_number, &admin_credential)
+        };
+        match result {
+            Ok(()) if option == 1 => {
+                println!("Locker number {} is out of service.", locker_number);
+            }
+            Ok(()) => println!("Locker number {} is back in service.", locker_number),
+            Err(err) => console::print_err(&err.to_string()),
+        }
+        console::halt();
+    }
+
+    /// Adds a customer who is waiting for a locker to the queue.
+    fn join_queue(&mut self, items: &CloakroomItems) {
+        let priority = if console::input_yes_no_loop("Priority customer? (y/n): ") {
+            PriorityClass::Priority
+        } else {
+            PriorityClass::Standard
+        };
+        match self.cloakroom.join_queue(items, priority) {
+            Ok(ticket) => println!("Customer has joined the queue with ticket {}.", ticket),
+            Err(err) => console::print_err(&err.to_string()),
+        }
+        console::halt();
+    }
+
+    fn print_queue(&self) {
+        let served_tickets = self.cloakroom.get_served_tickets();
+        let queue = self.cloakroom.get_queue();
+        if served_tickets.is_empty() && queue.is_empty() {
+            println!("No customers are queueing.");
+            console::halt();
+            return;
+        }
+
+        for ticket in served_tickets {
+            if let (Ok(locker_number), Ok(waiting_time)) = (
+                self.cloakroom.get_served_locker_number(ticket),
+                self.cloakroom.get_waiting_time(ticket),
+            ) {
+                println!(
+                    "{}: locker number {} is ready, after waiting {} minutes",
+                    ticket,
+                    locker_number,
+                    waiting_time.as_secs() / 60
+                );
+            }
+        }
+        for entry in queue {
+            let ticket = entry.get_ticket();
+            if let Ok(waiting_time) = self.cloakroom.get_waiting_time(ticket) {
+                println!(
+                    "{} ({}): waiting for {} minutes",
+                    ticket,
+                    entry.get_priority(),
+                    waiting_time.as_secs() / 60
+                );
+            }
+        }
+        console::halt();
+    }
+
+    /// Hands a customer the locker they were given from the queue, so that
+    /// their items can be put in it.
+    fn serve_queued_customer(&mut self) {
+        let ticket: QueueTicket = console::input_loop("Enter queue ticket: ");
+        let waiting_time = self.cloakroom.get_waiting_time(ticket);
+        match self.cloakroom.take_served_locker(ticket) {
+            Ok(locker) => {
+                if let Ok(waiting_time) = waiting_time {
+                    println!(
+                        "Customer with ticket {} waited {} minutes.",
+                        ticket,
+                        waiting_time.as_secs() / 60
+                    );
+                }
+                self.change_locker_contents(locker);
+            }
+
+            Err(err) => {
+                console::print_err(&err.to_string());
+                console::halt();
+            }
+        }
+    }
+
+    fn leave_queue(&mut self) {
+        let ticket: QueueTicket = console::input_loop("Enter queue ticket: ");
+        match self.cloakroom.leave_queue(ticket) {
+            Ok(()) => println!("Customer with ticket {} has left the queue.", ticket),
+            Err(err) => console::print_err(&err.to_string()),
+        }
+        console::halt();
+    }
+
+    fn queue_menu(&mut self) {
+        loop {
+            println!("\n1) Show queue");
+            println!("2) Serve customer by queue ticket");
+            println!("3) Remove customer from queue");
+            println!("4) Back\n");
+
+            let option = input_menu_option(4);
+            println!();
+            match option {
+                1 => self.print_queue(),
+                2 => self.serve_queued_customer(),
+                3 => self.leave_queue(),
+                _ => break,
+            }
+            self.save();
+        }
+    }
+
     fn print_cloakroom_contents(&self) {
-        if self.keys.len() == 0 {
+        if self.keys.is_empty() {
             println!("There are no closed lockers.");
         } else {
             print_contents_of_closed_lockers(&self.cloakroom);
@@ -179,34 +761,102 @@ impl Model {
         console::halt();
     }
 
+    fn print_locker_history(&self) {
+        let locker_number = console::input_loop("Enter locker number: ");
+        match self.cloakroom.get_locker_history(locker_number) {
+            Ok(history) if history.is_empty() => {
+                println!("No history found for locker number {}.", locker_number);
+            }
+
+            Ok(history) => {
+                for event in history {
+                    println!("{}", event);
+                }
+            }
+
+            Err(err) => {
+                console::print_err(&format!("failed to read audit log: {}", err));
+            }
+        }
+        console::halt();
+    }
+
+    fn print_statistics(&self) {
+        if let Some(report) = self.cloakroom.get_statistics_report() {
+            print!("{}", report);
+        }
+        console::halt();
+    }
+
     pub fn run(&mut self) {
         loop {
             println!("\n1) Deposit items in a locker");
             println!("2) Collect items from a locker");
             println!("3) Change locker contents");
-            println!("4) Print contents of closed lockers");
-            println!("5) Quit\n");
-
-            let option = input_menu_option(5);
+            println!("4) Move locker contents to another locker");
+            println!("5) Print contents of closed lockers");
+            println!("6) Print history of a locker");
+            println!("7) Reclaim abandoned locker");
+            println!("8) Lost property");
+            println!("9) Locker maintenance");
+            println!("10) Customer queue");
+            println!("11) Print usage statistics");
+            println!("12) Quit\n");
+
+            let option = input_menu_option(12);
             println!();
             match option {
                 1 => {
                     self.deposit_items();
+                    self.save();
                 }
 
                 2 => {
                     self.collect_items();
+                    self.save();
                 }
 
                 3 => {
                     self.change_contents();
+                    self.save();
                 }
 
                 4 => {
-                    self.print_cloakroom_contents();
+                    self.transfer_contents();
+                    self.save();
                 }
 
                 5 => {
+                    self.print_cloakroom_contents();
+                }
+
+                6 => {
+                    self.print_locker_history();
+                }
+
+                7 => {
+                    self.reclaim_locker();
+                    self.save();
+                }
+
+                8 => {
+                    self.lost_property_menu();
+                }
+
+                9 => {
+                    self.maintain_lockers();
+                    self.save();
+                }
+
+                10 => {
+                    self.queue_menu();
+                }
+
+                11 => {
+                    self.print_statistics();
+                }
+
+                12 => {
                     break;
                 }
 
@@ -217,3 +867,9 @@ impl Model {
         }
     }
 }
+
+impl Default for Model {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,6 +1,279 @@
+use std::error;
 use std::fmt;
+use std::io::{self, BufRead};
+use std::sync::{Arc, OnceLock};
 
-/// Specifies items to be stored in the cloakroom.
+/// A kind of item that can be stored in the cloakroom, such as coats.
+///
+/// Each item of the category takes up some space in a locker, 1 unit unless
+/// changed, and may have a weight.
+///
+/// # Examples
+///
+/// ```
+/// use cloakroom_model::items::ItemCategory;
+///
+/// let category = ItemCategory::new("suitcases")
+///     .with_space(4)
+///     .with_weight_grams(12_000);
+/// assert_eq!(category.get_space(), 4);
+/// assert_eq!(category.get_weight_grams(), 12_000);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemCategory {
+    name: String,
+    space: u32,
+    weight_grams: u32,
+}
+
+impl ItemCategory {
+    /// Creates a category whose items each take up 1 unit of space and
+    /// weigh nothing. The name is checked when the category is added to a
+    /// catalogue.
+    pub fn new<S: Into<String>>(name: S) -> ItemCategory {
+        ItemCategory {
+            name: name.into(),
+            space: 1,
+            weight_grams: 0,
+        }
+    }
+
+    pub fn with_space(self, space: u32) -> ItemCategory {
+        ItemCategory { space, ..self }
+    }
+
+    pub fn with_weight_grams(self, weight_grams: u32) -> ItemCategory {
+        ItemCategory {
+            weight_grams,
+            ..self
+        }
+    }
+
+    /// Name identifying the category, e.g. `other_items`. Names are made up
+    /// of ASCII letters, digits, underscores and hyphens.
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Description of the category for people to read, which is its name
+    /// with underscores replaced by spaces, e.g. `other items`.
+    pub fn get_description(&self) -> String {
+        self.name.replace('_', " ")
+    }
+
+    /// Space taken up by each item of the category.
+    pub fn get_space(&self) -> u32 {
+        self.space
+    }
+
+    /// Weight of each item of the category.
+    pub fn get_weight_grams(&self) -> u32 {
+        self.weight_grams
+    }
+}
+
+/// Reasons why an item catalogue cannot be created or used.
+#[derive(Debug)]
+pub enum CatalogueError {
+    Io(io::Error),
+    NoCategories,
+    InvalidName(String),
+    RepeatedName(String),
+    /// The catalogue has no category with this name.
+    UnknownCategory(String),
+    /// The catalogue file is not in the expected format. `line_number`
+    /// starts at 1.
+    Parse {
+        line_number: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for CatalogueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CatalogueError::Io(err) => write!(f, "failed to read item categories: {}", err),
+
+            CatalogueError::NoCategories => write!(f, "no item categories given"),
+
+            CatalogueError::InvalidName(name) => {
+                write!(f, "invalid item category name '{}'", name)
+            }
+
+            CatalogueError::RepeatedName(name) => {
+                write!(f, "item category '{}' is repeated", name)
+            }
+
+            CatalogueError::UnknownCategory(name) => {
+                write!(f, "unknown item category '{}'", name)
+            }
+
+            CatalogueError::Parse {
+                line_number,
+                message,
+            } => write!(f, "item categories line {}: {}", line_number, message),
+        }
+    }
+}
+
+impl error::Error for CatalogueError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            CatalogueError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CatalogueError {
+    fn from(err: io::Error) -> CatalogueError {
+        CatalogueError::Io(err)
+    }
+}
+
+/// The categories of item that a cloakroom accepts.
+///
+/// # Examples
+///
+/// ```
+/// use cloakroom_model::items::ItemCatalogue;
+///
+/// let config = "# Items accepted at the cycle track\n\
+///               helmets weight_grams=400\n\
+///               prams space=6\n\
+///               \n\
+///               luggage space=3 weight_grams=15000\n";
+/// let catalogue = ItemCatalogue::load(config.as_bytes()).unwrap();
+///
+/// let names: Vec<&str> = catalogue
+///     .get_categories()
+///     .iter()
+///     .map(|category| category.get_name())
+///     .collect();
+/// assert_eq!(names, vec!["helmets", "prams", "luggage"]);
+/// assert_eq!(catalogue.get_categories()[1].get_space(), 6);
+///
+/// assert!(ItemCatalogue::new(vec!["hats", "hats"]).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemCatalogue {
+    categories: Vec<ItemCategory>,
+}
+
+impl ItemCatalogue {
+    /// Creates a catalogue with a category for each name, in the order
+    /// given. Every item takes up 1 unit of space and weighs nothing.
+    pub fn new<I, S>(names: I) -> Result<ItemCatalogue, CatalogueError>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        ItemCatalogue::from_categories(names.into_iter().map(ItemCategory::new))
+    }
+
+    /// Creates a catalogue holding the given categories, in the order given.
+    pub fn from_categories<I>(categories: I) -> Result<ItemCatalogue, CatalogueError>
+    where
+        I: IntoIterator<Item = ItemCategory>,
+    {
+        let mut catalogue = ItemCatalogue {
+            categories: Vec::new(),
+        };
+        for category in categories {
+            let name = &category.name;
+            let valid = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+            if !valid {
+                return Err(CatalogueError::InvalidName(category.name));
+            }
+            if catalogue.position(name).is_some() {
+                return Err(CatalogueError::RepeatedName(category.name));
+            }
+            catalogue.categories.push(category);
+        }
+
+        if catalogue.categories.is_empty() {
+            return Err(CatalogueError::NoCategories);
+        }
+        Ok(catalogue)
+    }
+
+    /// Reads a catalogue from a config file describing one category on each
+    /// line, in the form `<name> [space=<n>] [weight_grams=<n>]`. Blank
+    /// lines and lines starting with `#` are ignored.
+    pub fn load<R: BufRead>(reader: R) -> Result<ItemCatalogue, CatalogueError> {
+        let mut categories = Vec::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let parse_error = |message: String| CatalogueError::Parse {
+                line_number: index + 1,
+                message,
+            };
+            let mut fields = line.split_whitespace();
+            let mut category = ItemCategory::new(fields.next().unwrap_or_default());
+            for field in fields {
+                let (name, value) = match field.find('=') {
+                    Some(pos) => (&field[..pos], &field[pos + 1..]),
+                    None => {
+                        return Err(parse_error(format!(
+                            "expected name=value but found '{}'",
+                            field
+                        )));
+                    }
+                };
+                let value: u32 = value.parse().map_err(|_| {
+                    parse_error(format!("invalid value '{}' for '{}'", value, name))
+                })?;
+                category = match name {
+                    "space" => category.with_space(value),
+                    "weight_grams" => category.with_weight_grams(value),
+                    other => {
+                        return Err(parse_error(format!("unknown attribute '{}'", other)));
+                    }
+                };
+            }
+            categories.push(category);
+        }
+
+        ItemCatalogue::from_categories(categories)
+    }
+
+    pub fn get_categories(&self) -> &[ItemCategory] {
+        &self.categories
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.categories
+            .iter()
+            .position(|category| category.name == name)
+    }
+}
+
+impl Default for ItemCatalogue {
+    /// Coats, backpacks, umbrellas and other items.
+    fn default() -> Self {
+        ItemCatalogue::new(vec!["coats", "backpacks", "umbrellas", "other_items"])
+            .expect("default item categories are invalid")
+    }
+}
+
+/// Returns the default catalogue, shared so that it is only built once.
+pub(crate) fn default_catalogue() -> Arc<ItemCatalogue> {
+    static DEFAULT_CATALOGUE: OnceLock<Arc<ItemCatalogue>> = OnceLock::new();
+    DEFAULT_CATALOGUE
+        .get_or_init(|| Arc::new(ItemCatalogue::default()))
+        .clone()
+}
+
+/// Specifies items to be stored in the cloakroom: how many items there are
+/// of each category in a catalogue.
 ///
 /// # Examples
 ///
@@ -16,60 +289,129 @@ use std::fmt;
 ///     .to_string();
 /// assert_eq!(items.to_string(), expected_str);
 ///
-/// items.num_coats = 2;
+/// items.set_num_items("coats", 2).unwrap();
 /// assert_eq!(items.get_total_num_items(), 2);
+/// assert_eq!(items.get_num_items("coats"), Some(2));
 ///
 /// let expected_str =
 /// "num coats: 2, num backpacks: 0, num umbrellas: 0, num other items: 0"
 ///     .to_string();
 /// assert_eq!(items.to_string(), expected_str);
 ///
-///
-/// let items = CloakroomItems {
-///     num_coats: 3,
-///     num_backpacks: 1,
-///     num_umbrellas: 1,
-///     num_other_items: 10,
-/// };
-/// assert_eq!(items.get_total_num_items(), 15);
-///
-/// let expected_str =
-/// "num coats: 3, num backpacks: 1, num umbrellas: 1, num other items: 10"
-///     .to_string();
-/// assert_eq!(items.to_string(), expected_str);
+/// // Only categories in the catalogue can be stored.
+/// assert!(items.set_num_items("bicycles", 1).is_err());
+/// assert_eq!(items.get_num_items("bicycles"), None);
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CloakroomItems {
-    pub num_coats: u8,
-    pub num_backpacks: u8,
-    pub num_umbrellas: u8,
-    pub num_other_items: u8,
+    catalogue: Arc<ItemCatalogue>,
+    // counts[i] is the number of items of catalogue.categories[i].
+    counts: Vec<u8>,
 }
 
 impl CloakroomItems {
+    /// Creates an empty set of items using the default catalogue.
     pub fn new() -> CloakroomItems {
-        CloakroomItems {
-            num_coats: 0,
-            num_backpacks: 0,
-            num_umbrellas: 0,
-            num_other_items: 0,
+        CloakroomItems::from_catalogue(default_catalogue())
+    }
+
+    /// Creates an empty set of items of the categories in `catalogue`.
+    pub fn from_catalogue(catalogue: Arc<ItemCatalogue>) -> CloakroomItems {
+        let counts = vec![0; catalogue.categories.len()];
+        CloakroomItems { catalogue, counts }
+    }
+
+    pub fn get_catalogue(&self) -> &Arc<ItemCatalogue> {
+        &self.catalogue
+    }
+
+    /// Returns the number of items of a category, or `None` if the
+    /// catalogue has no such category.
+    pub fn get_num_items(&self, category: &str) -> Option<u8> {
+        self.catalogue
+            .position(category)
+            .map(|index| self.counts[index])
+    }
+
+    pub fn set_num_items(&mut self, category: &str, num_items: u8) -> Result<(), CatalogueError> {
+        match self.catalogue.position(category) {
+            Some(index) => {
+                self.counts[index] = num_items;
+                Ok(())
+            }
+
+            None => Err(CatalogueError::UnknownCategory(category.to_string())),
         }
     }
 
+    /// Iterates over every category in the catalogue, in order, with the
+    /// number of items of that category.
+    pub fn iter(&self) -> impl Iterator<Item = (&ItemCategory, u8)> {
+        self.catalogue
+            .categories
+            .iter()
+            .zip(self.counts.iter().copied())
+    }
+
     pub fn get_total_num_items(&self) -> u16 {
-        self.num_coats as u16
-            + self.num_backpacks as u16
-            + self.num_umbrellas as u16
-            + self.num_other_items as u16
+        self.counts.iter().map(|&count| count as u16).sum()
+    }
+
+    /// Returns the space taken up by all the items, or `u32::MAX` if that
+    /// is too large to represent.
+    pub fn get_total_space(&self) -> u32 {
+        self.weighted_total(ItemCategory::get_space)
+    }
+
+    /// Returns the weight of all the items, or `u32::MAX` grams if that is
+    /// too large to represent.
+    pub fn get_total_weight_grams(&self) -> u32 {
+        self.weighted_total(ItemCategory::get_weight_grams)
+    }
+
+    /// Returns the same items described using another catalogue. Categories
+    /// are matched by name, so this fails if there are items of a category
+    /// that `catalogue` does not have.
+    pub fn convert_to_catalogue(
+        &self,
+        catalogue: &Arc<ItemCatalogue>,
+    ) -> Result<CloakroomItems, CatalogueError> {
+        if Arc::ptr_eq(&self.catalogue, catalogue) {
+            return Ok(self.clone());
+        }
+
+        let mut items = CloakroomItems::from_catalogue(catalogue.clone());
+        for (category, num_items) in self.iter() {
+            if num_items > 0 {
+                items.set_num_items(category.get_name(), num_items)?;
+            }
+        }
+        Ok(items)
+    }
+
+    fn weighted_total(&self, weight: fn(&ItemCategory) -> u32) -> u32 {
+        let total: u64 = self
+            .iter()
+            .map(|(category, num_items)| weight(category) as u64 * num_items as u64)
+            .sum();
+        total.min(u32::MAX as u64) as u32
+    }
+}
+
+impl Default for CloakroomItems {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl fmt::Display for CloakroomItems {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "num coats: {}, ", self.num_coats)?;
-        write!(f, "num backpacks: {}, ", self.num_backpacks)?;
-        write!(f, "num umbrellas: {}, ", self.num_umbrellas)?;
-        write!(f, "num other items: {}", self.num_other_items)?;
+        for (index, (category, num_items)) in self.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "num {}: {}", category.get_description(), num_items)?;
+        }
 
         Ok(())
     }
@@ -77,7 +419,22 @@ impl fmt::Display for CloakroomItems {
 
 #[cfg(test)]
 mod tests {
-    use crate::items::CloakroomItems;
+    use crate::items::{CatalogueError, CloakroomItems, ItemCatalogue, ItemCategory};
+    use std::sync::Arc;
+
+    fn items(
+        num_coats: u8,
+        num_backpacks: u8,
+        num_umbrellas: u8,
+        num_other_items: u8,
+    ) -> CloakroomItems {
+        let mut items = CloakroomItems::new();
+        items.set_num_items("coats", num_coats).unwrap();
+        items.set_num_items("backpacks", num_backpacks).unwrap();
+        items.set_num_items("umbrellas", num_umbrellas).unwrap();
+        items.set_num_items("other_items", num_other_items).unwrap();
+        items
+    }
 
     #[test]
     fn test1() {
@@ -88,8 +445,8 @@ mod tests {
             "num coats: 0, num backpacks: 0, num umbrellas: 0, num other items: 0".to_string();
         assert_eq!(items.to_string(), expected_str);
 
-        items.num_coats = 2;
-        items.num_other_items = 1;
+        items.set_num_items("coats", 2).unwrap();
+        items.set_num_items("other_items", 1).unwrap();
         assert_eq!(items.get_total_num_items(), 3);
 
         let expected_str =
@@ -99,12 +456,7 @@ mod tests {
 
     #[test]
     fn test2() {
-        let items = CloakroomItems {
-            num_coats: 0,
-            num_backpacks: 1,
-            num_umbrellas: 0,
-            num_other_items: 0,
-        };
+        let items = items(0, 1, 0, 0);
         assert_eq!(items.get_total_num_items(), 1);
 
         let expected_str =
@@ -114,12 +466,7 @@ mod tests {
 
     #[test]
     fn test3() {
-        let items = CloakroomItems {
-            num_coats: 2,
-            num_backpacks: 0,
-            num_umbrellas: 1,
-            num_other_items: 2,
-        };
+        let items = items(2, 0, 1, 2);
         assert_eq!(items.get_total_num_items(), 5);
 
         let expected_str =
@@ -129,16 +476,69 @@ mod tests {
 
     #[test]
     fn test4() {
-        let items = CloakroomItems {
-            num_coats: 1,
-            num_backpacks: 2,
-            num_umbrellas: 1,
-            num_other_items: 10,
-        };
+        let items = items(1, 2, 1, 10);
         assert_eq!(items.get_total_num_items(), 14);
 
         let expected_str =
             "num coats: 1, num backpacks: 2, num umbrellas: 1, num other items: 10".to_string();
         assert_eq!(items.to_string(), expected_str);
     }
+
+    #[test]
+    fn test5() {
+        let catalogue = Arc::new(ItemCatalogue::new(vec!["helmets", "coats"]).unwrap());
+        let mut items = CloakroomItems::from_catalogue(catalogue.clone());
+        items.set_num_items("helmets", 2).unwrap();
+        assert_eq!(items.to_string(), "num helmets: 2, num coats: 0");
+        assert!(matches!(
+            items.set_num_items("umbrellas", 1),
+            Err(CatalogueError::UnknownCategory(_))
+        ));
+
+        // Coats can be described by either catalogue, but helmets cannot.
+        let mut coats = CloakroomItems::new();
+        coats.set_num_items("coats", 3).unwrap();
+        let converted = coats.convert_to_catalogue(&catalogue).unwrap();
+        assert_eq!(converted.to_string(), "num helmets: 0, num coats: 3");
+        assert!(items
+            .convert_to_catalogue(&Arc::new(ItemCatalogue::default()))
+            .is_err());
+
+        assert!(matches!(
+            ItemCatalogue::new(Vec::<String>::new()),
+            Err(CatalogueError::NoCategories)
+        ));
+        assert!(matches!(
+            ItemCatalogue::new(vec!["two words"]),
+            Err(CatalogueError::InvalidName(_))
+        ));
+        assert!(matches!(
+            ItemCatalogue::load("helmets\nprams luggage\n".as_bytes()),
+            Err(CatalogueError::Parse { line_number: 2, .. })
+        ));
+        assert!(matches!(
+            ItemCatalogue::load("helmets colour=red\n".as_bytes()),
+            Err(CatalogueError::Parse { line_number: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test6() {
+        let catalogue = ItemCatalogue::from_categories(vec![
+            ItemCategory::new("coats").with_weight_grams(1500),
+            ItemCategory::new("suitcases")
+                .with_space(4)
+                .with_weight_grams(u32::MAX),
+        ])
+        .unwrap();
+        let mut items = CloakroomItems::from_catalogue(Arc::new(catalogue));
+        items.set_num_items("coats", 3).unwrap();
+        assert_eq!(items.get_total_space(), 3);
+        assert_eq!(items.get_total_weight_grams(), 4500);
+
+        items.set_num_items("suitcases", 2).unwrap();
+        assert_eq!(items.get_total_num_items(), 5);
+        assert_eq!(items.get_total_space(), 11);
+        assert_eq!(items.get_total_weight_grams(), u32::MAX);
+    }
 }
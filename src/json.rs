@@ -0,0 +1,255 @@
+//! Helpers for writing JSON by hand, and for reading it back.
+
+use std::fmt::Write;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Returns `s` as a quoted JSON string.
+pub(crate) fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Returns `x` as a JSON number. JSON has no representation for infinite or
+/// NaN values, so they are written as `null`.
+pub(crate) fn number(x: f64) -> String {
+    if x.is_finite() {
+        x.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+/// A parsed JSON value. Numbers keep their text, so that integers too large
+/// for an `f64` to hold exactly are not rounded.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Returns the value of the member `name` of an object.
+    pub(crate) fn get(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members
+                .iter()
+                .find(|(member_name, _)| member_name == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(text) => text.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a complete JSON document.
+pub(crate) fn parse(text: &str) -> Result<Value, String> {
+    let mut chars = text.chars().peekable();
+    let value = parse_value(&mut chars)?;
+    skip_whitespace(&mut chars);
+    match chars.next() {
+        None => Ok(value),
+        Some(c) => Err(format!("unexpected '{}' after value", c)),
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while let Some(' ' | '\t' | '\n' | '\r') = chars.peek() {
+        chars.next();
+    }
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> Result<(), String> {
+    match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        Some(c) => Err(format!("expected '{}' but found '{}'", expected, c)),
+        None => Err(format!("expected '{}' but found end of text", expected)),
+    }
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Result<Value, String> {
+    skip_whitespace(chars);
+    match chars.peek() {
+        Some('{') => parse_object(chars),
+        Some('[') => parse_array(chars),
+        Some('"') => parse_string(chars).map(Value::String),
+        Some('-' | '0'..='9') => parse_number(chars),
+        Some('t' | 'f' | 'n') => {
+            let mut word = String::new();
+            while let Some(c @ 'a'..='z') = chars.peek() {
+                word.push(*c);
+                chars.next();
+            }
+            match word.as_str() {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                "null" => Ok(Value::Null),
+                _ => Err(format!("unexpected '{}'", word)),
+            }
+        }
+        Some(c) => Err(format!("unexpected '{}'", c)),
+        None => Err("unexpected end of text".to_string()),
+    }
+}
+
+fn parse_object(chars: &mut Peekable<Chars>) -> Result<Value, String> {
+    expect(chars, '{')?;
+    let mut members = Vec::new();
+    skip_whitespace(chars);
+    if chars.peek() == Some(&'}') {
+        chars.next();
+        return Ok(Value::Object(members));
+    }
+    loop {
+        skip_whitespace(chars);
+        let name = parse_string(chars)?;
+        skip_whitespace(chars);
+        expect(chars, ':')?;
+        members.push((name, parse_value(chars)?));
+        skip_whitespace(chars);
+        match chars.next() {
+            Some(',') => (),
+            Some('}') => return Ok(Value::Object(members)),
+            _ => return Err("expected ',' or '}' in object".to_string()),
+        }
+    }
+}
+
+fn parse_array(chars: &mut Peekable<Chars>) -> Result<Value, String> {
+    expect(chars, '[')?;
+    let mut elements = Vec::new();
+    skip_whitespace(chars);
+    if chars.peek() == Some(&']') {
+        chars.next();
+        return Ok(Value::Array(elements));
+    }
+    loop {
+        elements.push(parse_value(chars)?);
+        skip_whitespace(chars);
+        match chars.next() {
+            Some(',') => (),
+            Some(']') => return Ok(Value::Array(elements)),
+            _ => return Err("expected ',' or ']' in array".to_string()),
+        }
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    expect(chars, '"')?;
+    let mut s = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(s),
+            Some('\\') => match chars.next() {
+                Some('"') => s.push('"'),
+                Some('\\') => s.push('\\'),
+                Some('/') => s.push('/'),
+                Some('b') => s.push('\u{8}'),
+                Some('f') => s.push('\u{c}'),
+                Some('n') => s.push('\n'),
+                Some('r') => s.push('\r'),
+                Some('t') => s.push('\t'),
+                Some('u') => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    let c = u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| format!("invalid escape '\\u{}'", hex))?;
+                    s.push(c);
+                }
+                _ => return Err("invalid escape in string".to_string()),
+            },
+            Some(c) => s.push(c),
+            None => return Err("unterminated string".to_string()),
+        }
+    }
+}
+
+fn parse_number(chars: &mut Peekable<Chars>) -> Result<Value, String> {
+    let mut text = String::new();
+    while let Some(c @ ('-' | '+' | '.' | 'e' | 'E' | '0'..='9')) = chars.peek() {
+        text.push(*c);
+        chars.next();
+    }
+    if text.parse::<f64>().is_err() {
+        return Err(format!("invalid number '{}'", text));
+    }
+    Ok(Value::Number(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::json::{number, parse, quote, Value};
+
+    #[test]
+    fn test1() {
+        assert_eq!(quote("coats"), "\"coats\"");
+        assert_eq!(quote("a \"b\"\\\n"), "\"a \\\"b\\\"\\\\\\n\"");
+        assert_eq!(quote("\u{1}"), "\"\\u0001\"");
+
+        assert_eq!(number(1.5), "1.5");
+        assert_eq!(number(2.0), "2");
+        assert_eq!(number(f64::NAN), "null");
+    }
+
+    #[test]
+    fn test2() {
+        let text = format!(
+            "{{\"name\": {}, \"n\": 18446744073709551615, \"list\": [1.5, true, null], \"empty\": {{}}}}",
+            quote("a \"b\"\\\n\u{1}")
+        );
+        let value = parse(&text).unwrap();
+        assert_eq!(
+            value.get("name").and_then(Value::as_str),
+            Some("a \"b\"\\\n\u{1}")
+        );
+        assert_eq!(value.get("n").and_then(Value::as_u64), Some(u64::MAX));
+        assert_eq!(
+            value.get("list"),
+            Some(&Value::Array(vec![
+                Value::Number("1.5".to_string()),
+                Value::Bool(true),
+                Value::Null
+            ]))
+        );
+        assert_eq!(value.get("empty"), Some(&Value::Object(Vec::new())));
+        assert_eq!(value.get("missing"), None);
+
+        assert!(parse("{\"a\": 1").is_err());
+        assert!(parse("[1, 2] 3").is_err());
+        assert!(parse("\"\\q\"").is_err());
+        assert!(parse("nul").is_err());
+    }
+}
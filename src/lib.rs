@@ -1,3 +1,10 @@
 pub mod cloakroom;
+pub mod clock;
 pub mod interactive;
 pub mod items;
+mod json;
+pub mod lost_property;
+pub mod pricing;
+mod rng;
+pub mod simulation;
+pub mod statistics;
@@ -0,0 +1,483 @@
+//! Store of items that have been left behind in the cloakroom.
+//!
+//! Items end up in lost property when an attendant takes them out of an
+//! overdue locker, forces open a locker, or hands in items found in the
+//! cloakroom. Each bundle of items is kept under its own [`ClaimReference`],
+//! with when it was taken in, the locker it came from, if any, and a
+//! free-text description to help attendants find it again.
+//!
+//! A bundle leaves the store when it is claimed by its owner, or when it is
+//! disposed of. Bundles can only be disposed of once they have been held for
+//! longer than the store's retention period, if it has one.
+//!
+//! Every cloakroom has a store, which it fills as it moves the contents of
+//! lockers into it. Bundles taken out of lockers can also be redeemed with
+//! the locker's old key through the cloakroom, which charges for the use of
+//! the locker.
+
+use crate::cloakroom::token::Token;
+use crate::cloakroom::LockerNumber;
+use crate::items::CloakroomItems;
+use crate::pricing::Stay;
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+/// Reference under which a bundle is kept in the lost-property store. It is
+/// written as `C` followed by six or more digits, e.g. `C000042`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClaimReference(u32);
+
+impl ClaimReference {
+    pub(crate) fn get_number(&self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for ClaimReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "C{:06}", self.0)
+    }
+}
+
+impl FromStr for ClaimReference {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<ClaimReference, ()> {
+        match s.strip_prefix('C') {
+            Some(digits) if digits.bytes().all(|b| b.is_ascii_digit()) => {
+                digits.parse().map(ClaimReference).map_err(|_| ())
+            }
+            _ => Err(()),
+        }
+    }
+}
+
+/// How a bundle came to be in the lost-property store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IntakeReason {
+    /// Taken out of a locker that was closed for too long.
+    Overdue,
+    /// Taken out of a locker that an attendant forced open.
+    ForcedOpen,
+    /// Found in the cloakroom and handed in.
+    Found,
+}
+
+impl fmt::Display for IntakeReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            IntakeReason::Overdue => "overdue",
+            IntakeReason::ForcedOpen => "forced_open",
+            IntakeReason::Found => "found",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for IntakeReason {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<IntakeReason, ()> {
+        match s {
+            "overdue" => Ok(IntakeReason::Overdue),
+            "forced_open" => Ok(IntakeReason::ForcedOpen),
+            "found" => Ok(IntakeReason::Found),
+            _ => Err(()),
+        }
+    }
+}
+
+/// What is needed to redeem a bundle taken out of a locker with the
+/// locker's old key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Redemption {
+    // Token of the key that redeems the bundle.
+    pub(crate) token: Token,
+    // Stay of the locker up to when its contents were taken out, from which
+    // the invoice given on redemption is worked out.
+    pub(crate) stay: Stay,
+}
+
+/// Items held in the lost-property store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bundle {
+    claim_reference: ClaimReference,
+    items: CloakroomItems,
+    taken_in_at: SystemTime,
+    locker_number: Option<LockerNumber>,
+    reason: IntakeReason,
+    description: String,
+    pub(crate) redemption: Option<Redemption>,
+}
+
+impl Bundle {
+    pub(crate) fn new(
+        claim_reference: ClaimReference,
+        items: CloakroomItems,
+        taken_in_at: SystemTime,
+        locker_number: Option<LockerNumber>,
+        reason: IntakeReason,
+        description: &str,
+        redemption: Option<Redemption>,
+    ) -> Bundle {
+        Bundle {
+            claim_reference,
+            items,
+            taken_in_at,
+            locker_number,
+            reason,
+            description: description.to_string(),
+            redemption,
+        }
+    }
+
+    pub fn get_claim_reference(&self) -> ClaimReference {
+        self.claim_reference
+    }
+
+    pub fn get_items(&self) -> &CloakroomItems {
+        &self.items
+    }
+
+    /// Returns when the bundle was put in the store.
+    pub fn get_taken_in_at(&self) -> SystemTime {
+        self.taken_in_at
+    }
+
+    /// Returns the number of the locker the items were taken out of, or, for
+    /// found items, the locker they were found in or next to, if any.
+    pub fn get_locker_number(&self) -> Option<LockerNumber> {
+        self.locker_number
+    }
+
+    pub fn get_reason(&self) -> IntakeReason {
+        self.reason
+    }
+
+    pub fn get_description(&self) -> &str {
+        &self.description
+    }
+
+    /// Returns whether the bundle can be redeemed with the old key to the
+    /// locker it was taken out of.
+    pub fn is_redeemable_with_key(&self) -> bool {
+        self.redemption.is_some()
+    }
+}
+
+impl fmt::Display for Bundle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}", self.claim_reference, self.reason)?;
+        if let Some(locker_number) = self.locker_number {
+            write!(f, ", locker number {}", locker_number)?;
+        }
+        write!(f, ") ({})", self.items)?;
+        if !self.description.is_empty() {
+            write!(f, ": {}", self.description)?;
+        }
+        Ok(())
+    }
+}
+
+/// Criteria for searching the lost-property store. A bundle matches if it
+/// meets every criterion that has been set, so the default query matches
+/// every bundle.
+///
+/// # Examples
+///
+/// ```
+/// use cloakroom_model::lost_property::{IntakeReason, LostPropertyQuery};
+///
+/// let query = LostPropertyQuery::new()
+///     .with_text("red")
+///     .with_category("umbrellas")
+///     .with_reason(IntakeReason::Found);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LostPropertyQuery {
+    text: Option<String>,
+    locker_number: Option<LockerNumber>,
+    category: Option<String>,
+    reason: Option<IntakeReason>,
+}
+
+impl LostPropertyQuery {
+    pub fn new() -> LostPropertyQuery {
+        LostPropertyQuery::default()
+    }
+
+    /// Matches bundles whose description contains `text`, ignoring case.
+    pub fn with_text(self, text: &str) -> LostPropertyQuery {
+        LostPropertyQuery {
+            text: Some(text.to_lowercase()),
+            ..self
+        }
+    }
+
+    pub fn with_locker_number(self, locker_number: LockerNumber) -> LostPropertyQuery {
+        LostPropertyQuery {
+            locker_number: Some(locker_number),
+            ..self
+        }
+    }
+
+    /// Matches bundles holding at least one item of the named category.
+    pub fn with_category(self, name: &str) -> LostPropertyQuery {
+        LostPropertyQuery {
+            category: Some(name.to_string()),
+            ..self
+        }
+    }
+
+    pub fn with_reason(self, reason: IntakeReason) -> LostPropertyQuery {
+        LostPropertyQuery {
+            reason: Some(reason),
+            ..self
+        }
+    }
+
+    pub fn matches(&self, bundle: &Bundle) -> bool {
+        if let Some(text) = &self.text {
+            if !bundle.description.to_lowercase().contains(text.as_str()) {
+                return false;
+            }
+        }
+        if self.locker_number.is_some() && bundle.locker_number != self.locker_number {
+            return false;
+        }
+        if let Some(category) = &self.category {
+            if bundle.items.get_num_items(category).unwrap_or(0) == 0 {
+                return false;
+            }
+        }
+        match self.reason {
+            Some(reason) => bundle.reason == reason,
+            None => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LostPropertyError {
+    UnknownClaim(ClaimReference),
+    NotDueForDisposal(ClaimReference),
+}
+
+impl fmt::Display for LostPropertyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LostPropertyError::UnknownClaim(claim_reference) => {
+                write!(f, "no lost property is held as {}", claim_reference)
+            }
+
+            LostPropertyError::NotDueForDisposal(claim_reference) => write!(
+                f,
+                "lost property {} has not been held for long enough to be disposed of",
+                claim_reference
+            ),
+        }
+    }
+}
+
+impl error::Error for LostPropertyError {}
+
+/// Bundles of lost property, oldest first, as described in the [module
+/// documentation](self).
+///
+/// # Examples
+///
+/// ```
+/// use cloakroom_model::items::CloakroomItems;
+/// use cloakroom_model::lost_property::{LostPropertyQuery, LostPropertyStore};
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let mut store = LostPropertyStore::new().with_retention_days(30);
+/// let mut items = CloakroomItems::new();
+/// items.set_num_items("umbrellas", 1).unwrap();
+/// let claim_reference = store.hand_in(items, "Red umbrella", None, UNIX_EPOCH);
+///
+/// let found = store.search(&LostPropertyQuery::new().with_text("umbrella"));
+/// assert_eq!(found[0].get_claim_reference(), claim_reference);
+///
+/// let after_31_days = UNIX_EPOCH + Duration::from_secs(31 * 24 * 60 * 60);
+/// assert_eq!(store.get_due_for_disposal(after_31_days).len(), 1);
+/// store.dispose(claim_reference, after_31_days).unwrap();
+/// assert!(store.get_bundles().is_empty());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LostPropertyStore {
+    bundles: Vec<Bundle>,
+    next_claim_number: u32,
+    retention_period: Option<Duration>,
+}
+
+impl LostPropertyStore {
+    pub fn new() -> LostPropertyStore {
+        LostPropertyStore {
+            bundles: Vec::new(),
+            next_claim_number: 1,
+            retention_period: None,
+        }
+    }
+
+    /// Sets how many days bundles are kept before they are due for
+    /// disposal. Nothing is ever due for disposal unless this is called.
+    pub fn with_retention_days(self, retention_days: u32) -> LostPropertyStore {
+        LostPropertyStore {
+            retention_period: Some(Duration::from_secs(
+                u64::from(retention_days) * 24 * 60 * 60,
+            )),
+            ..self
+        }
+    }
+
+    pub fn get_retention_period(&self) -> Option<Duration> {
+        self.retention_period
+    }
+
+    pub fn get_bundles(&self) -> &[Bundle] {
+        &self.bundles
+    }
+
+    pub fn get_bundle(&self, claim_reference: ClaimReference) -> Option<&Bundle> {
+        self.bundles
+            .iter()
+            .find(|bundle| bundle.claim_reference == claim_reference)
+    }
+
+    /// Returns the bundles matching `query`, oldest first.
+    pub fn search(&self, query: &LostPropertyQuery) -> Vec<&Bundle> {
+        self.bundles
+            .iter()
+            .filter(|bundle| query.matches(bundle))
+            .collect()
+    }
+
+    /// Puts items found in the cloakroom in the store at `taken_in_at`, and
+    /// returns the claim reference under which they are kept.
+    pub fn hand_in(
+        &mut self,
+        items: CloakroomItems,
+        description: &str,
+        locker_number: Option<LockerNumber>,
+        taken_in_at: SystemTime,
+    ) -> ClaimReference {
+        let claim_reference = self.get_next_claim_reference();
+        self.bundles.push(Bundle::new(
+            claim_reference,
+            items,
+            taken_in_at,
+            locker_number,
+            IntakeReason::Found,
+            description,
+            None,
+        ));
+        self.next_claim_number += 1;
+        claim_reference
+    }
+
+    /// Hands a bundle back to its owner, removing it from the store. It is
+    /// up to the attendant to check that whoever claims a bundle owns it.
+    pub fn claim(&mut self, claim_reference: ClaimReference) -> Result<Bundle, LostPropertyError> {
+        self.take(claim_reference)
+            .ok_or(LostPropertyError::UnknownClaim(claim_reference))
+    }
+
+    /// Returns the bundles that, at `now`, have been held for longer than the
+    /// retention period, oldest first.
+    pub fn get_due_for_disposal(&self, now: SystemTime) -> Vec<&Bundle> {
+        self.bundles
+            .iter()
+            .filter(|bundle| self.is_due_for_disposal(bundle, now))
+            .collect()
+    }
+
+    /// Removes a bundle that is due for disposal at `now` from the store.
+    pub fn dispose(
+        &mut self,
+        claim_reference: ClaimReference,
+        now: SystemTime,
+    ) -> Result<Bundle, LostPropertyError> {
+        self.check_due_for_disposal(claim_reference, now)?;
+        self.take(claim_reference)
+            .ok_or(LostPropertyError::UnknownClaim(claim_reference))
+    }
+
+    /// Returns the claim reference that the next bundle will be given.
+    pub(crate) fn get_next_claim_reference(&self) -> ClaimReference {
+        ClaimReference(self.next_claim_number)
+    }
+
+    pub(crate) fn set_next_claim_number(&mut self, next_claim_number: u32) {
+        self.next_claim_number = next_claim_number;
+    }
+
+    /// Adds a bundle, under whatever claim reference it has been given, and
+    /// makes sure that claim reference is not issued again. Fails if a
+    /// bundle is already held under it.
+    pub(crate) fn insert(&mut self, bundle: Bundle) -> Result<(), String> {
+        let claim_number = bundle.claim_reference.get_number();
+        if self.get_bundle(bundle.claim_reference).is_some() {
+            return Err(format!(
+                "claim reference {} is in use",
+                bundle.claim_reference
+            ));
+        }
+        self.bundles.push(bundle);
+        self.next_claim_number = self.next_claim_number.max(claim_number + 1);
+        Ok(())
+    }
+
+    pub(crate) fn take(&mut self, claim_reference: ClaimReference) -> Option<Bundle> {
+        let index = self
+            .bundles
+            .iter()
+            .position(|bundle| bundle.claim_reference == claim_reference)?;
+        Some(self.bundles.remove(index))
+    }
+
+    /// Returns the bundle that the key to `locker_number` carrying `token`
+    /// redeems, if any.
+    pub(crate) fn find_redeemable(
+        &self,
+        locker_number: LockerNumber,
+        token: Token,
+    ) -> Option<&Bundle> {
+        self.bundles.iter().find(|bundle| {
+            bundle.locker_number == Some(locker_number)
+                && bundle
+                    .redemption
+                    .as_ref()
+                    .is_some_and(|redemption| redemption.token == token)
+        })
+    }
+
+    pub(crate) fn check_due_for_disposal(
+        &self,
+        claim_reference: ClaimReference,
+        now: SystemTime,
+    ) -> Result<(), LostPropertyError> {
+        match self.get_bundle(claim_reference) {
+            Some(bundle) if self.is_due_for_disposal(bundle, now) => Ok(()),
+            Some(_) => Err(LostPropertyError::NotDueForDisposal(claim_reference)),
+            None => Err(LostPropertyError::UnknownClaim(claim_reference)),
+        }
+    }
+
+    fn is_due_for_disposal(&self, bundle: &Bundle, now: SystemTime) -> bool {
+        let retention_period = match self.retention_period {
+            Some(retention_period) => retention_period,
+            None => return false,
+        };
+        now.duration_since(bundle.taken_in_at)
+            .is_ok_and(|held_for| held_for > retention_period)
+    }
+}
+
+impl Default for LostPropertyStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,538 @@
+//! Charges for storing items in a paid cloakroom, and the invoices given to
+//! customers when they collect their items.
+//!
+//! A [`Tariff`] sets:
+//!
+//! - a deposit fee for each item of a category put in a locker
+//! - a storage charge for each hour, or part of an hour, from when the
+//!   locker was first closed until the items are collected
+//! - an overnight surcharge each time the stay passes the overnight cut-off
+//!   hour
+//! - a penalty each time the locker is opened with the lost-key procedure,
+//!   unless the attendant set a different fee at the time
+//!
+//! Amounts are in pence. Times of day are in UTC.
+
+use crate::cloakroom::LockerNumber;
+use crate::items::{CloakroomItems, ItemCatalogue};
+use std::error;
+use std::fmt;
+use std::io::{self, BufRead};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECS_PER_HOUR: u64 = 60 * 60;
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Reasons why a tariff config file cannot be read.
+#[derive(Debug)]
+pub enum TariffError {
+    Io(io::Error),
+    /// The file is not in the expected format. `line_number` starts at 1.
+    Parse {
+        line_number: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for TariffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TariffError::Io(err) => write!(f, "failed to read tariff: {}", err),
+
+            TariffError::Parse {
+                line_number,
+                message,
+            } => write!(f, "tariff line {}: {}", line_number, message),
+        }
+    }
+}
+
+impl error::Error for TariffError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            TariffError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TariffError {
+    fn from(err: io::Error) -> TariffError {
+        TariffError::Io(err)
+    }
+}
+
+/// The charges made by a cloakroom. The default tariff charges nothing.
+///
+/// # Examples
+///
+/// ```
+/// use cloakroom_model::pricing::Tariff;
+///
+/// let tariff = Tariff::new()
+///     .with_deposit_fee("coats", 150)
+///     .with_storage_charge(50)
+///     .with_overnight_surcharge(500, 3)
+///     .with_lost_key_penalty(1000);
+/// assert_eq!(tariff.get_deposit_fee("coats"), 150);
+/// assert_eq!(tariff.get_deposit_fee("umbrellas"), 0);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tariff {
+    deposit_fees: Vec<(String, u32)>,
+    pence_per_hour: u32,
+    overnight_surcharge: u32,
+    overnight_cutoff_hour: u32,
+    lost_key_penalty: u32,
+}
+
+impl Tariff {
+    pub fn new() -> Tariff {
+        Tariff::default()
+    }
+
+    /// Sets the fee for each item of `category` put in a locker. Categories
+    /// that are not in the cloakroom's catalogue are never charged for.
+    pub fn with_deposit_fee(mut self, category: &str, pence: u32) -> Tariff {
+        match self
+            .deposit_fees
+            .iter_mut()
+            .find(|(name, _)| name == category)
+        {
+            Some((_, fee)) => *fee = pence,
+            None => self.deposit_fees.push((category.to_string(), pence)),
+        }
+        self
+    }
+
+    /// Sets the charge for each hour, or part of an hour, of storage.
+    pub fn with_storage_charge(mut self, pence_per_hour: u32) -> Tariff {
+        self.pence_per_hour = pence_per_hour;
+        self
+    }
+
+    /// Sets the surcharge made each time a stay passes `cutoff_hour`
+    /// o'clock, UTC.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cutoff_hour` is not below 24.
+    pub fn with_overnight_surcharge(mut self, pence: u32, cutoff_hour: u32) -> Tariff {
+        assert!(cutoff_hour < 24, "invalid cut-off hour {}", cutoff_hour);
+        self.overnight_surcharge = pence;
+        self.overnight_cutoff_hour = cutoff_hour;
+        self
+    }
+
+    /// Sets the penalty for opening a locker with the lost-key procedure.
+    pub fn with_lost_key_penalty(mut self, pence: u32) -> Tariff {
+        self.lost_key_penalty = pence;
+        self
+    }
+
+    pub fn get_deposit_fee(&self, category: &str) -> u32 {
+        self.deposit_fees
+            .iter()
+            .find(|(name, _)| name == category)
+            .map_or(0, |(_, fee)| *fee)
+    }
+
+    pub fn get_storage_charge(&self) -> u32 {
+        self.pence_per_hour
+    }
+
+    pub fn get_overnight_surcharge(&self) -> u32 {
+        self.overnight_surcharge
+    }
+
+    pub fn get_overnight_cutoff_hour(&self) -> u32 {
+        self.overnight_cutoff_hour
+    }
+
+    pub fn get_lost_key_penalty(&self) -> u32 {
+        self.lost_key_penalty
+    }
+
+    /// Reads a tariff from a config file giving one charge on each line:
+    ///
+    /// ```text
+    /// deposit_fee category=<name> pence=<n>
+    /// storage pence_per_hour=<n>
+    /// overnight_surcharge pence=<n> cutoff_hour=<n>
+    /// lost_key_penalty pence=<n>
+    /// ```
+    ///
+    /// Charges that are not given are free. Blank lines and lines starting
+    /// with `#` are ignored.
+    pub fn load<R: BufRead>(reader: R) -> Result<Tariff, TariffError> {
+        let mut tariff = Tariff::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let parse_error = |message: String| TariffError::Parse {
+                line_number: index + 1,
+                message,
+            };
+            let mut fields = line.split_whitespace();
+            let charge = fields.next().unwrap_or_default();
+            let mut attributes = Vec::new();
+            for field in fields {
+                match field.find('=') {
+                    Some(pos) => attributes.push((&field[..pos], &field[pos + 1..])),
+                    None => {
+                        return Err(parse_error(format!(
+                            "expected name=value but found '{}'",
+                            field
+                        )));
+                    }
+                }
+            }
+            let get = |name: &str| match attributes.iter().find(|(n, _)| *n == name) {
+                Some((_, value)) => Ok(*value),
+                None => Err(parse_error(format!("missing '{}'", name))),
+            };
+            let get_number = |name: &str| {
+                let value = get(name)?;
+                value
+                    .parse::<u32>()
+                    .map_err(|_| parse_error(format!("invalid value '{}' for '{}'", value, name)))
+            };
+
+            let expected: &[&str] = match charge {
+                "deposit_fee" => {
+                    tariff = tariff.with_deposit_fee(get("category")?, get_number("pence")?);
+                    &["category", "pence"]
+                }
+                "storage" => {
+                    tariff = tariff.with_storage_charge(get_number("pence_per_hour")?);
+                    &["pence_per_hour"]
+                }
+                "overnight_surcharge" => {
+                    let cutoff_hour = get_number("cutoff_hour")?;
+                    if cutoff_hour >= 24 {
+                        return Err(parse_error(format!("invalid cut-off hour {}", cutoff_hour)));
+                    }
+                    tariff = tariff.with_overnight_surcharge(get_number("pence")?, cutoff_hour);
+                    &["pence", "cutoff_hour"]
+                }
+                "lost_key_penalty" => {
+                    tariff = tariff.with_lost_key_penalty(get_number("pence")?);
+                    &["pence"]
+                }
+                other => {
+                    return Err(parse_error(format!("unknown charge '{}'", other)));
+                }
+            };
+            if let Some((name, _)) = attributes.iter().find(|(name, _)| !expected.contains(name)) {
+                return Err(parse_error(format!("unknown attribute '{}'", name)));
+            }
+        }
+
+        Ok(tariff)
+    }
+
+    /// Works out the charges for a stay ending at `collected_at`.
+    pub(crate) fn invoice(
+        &self,
+        locker_number: LockerNumber,
+        stay: &Stay,
+        collected_at: SystemTime,
+    ) -> Invoice {
+        let mut lines = Vec::new();
+
+        for (category, &num_items) in stay.catalogue.get_categories().iter().zip(&stay.deposited) {
+            let fee = self.get_deposit_fee(category.get_name());
+            if num_items > 0 && fee > 0 {
+                lines.push(InvoiceLine::new(
+                    format!(
+                        "{} x {} @ {}",
+                        num_items,
+                        category.get_description(),
+                        format_pence(fee.into())
+                    ),
+                    u64::from(num_items) * u64::from(fee),
+                ));
+            }
+        }
+
+        if let Some(first_closed) = stay.first_closed {
+            let secs = collected_at
+                .duration_since(first_closed)
+                .map_or(0, |duration| duration.as_secs());
+            let hours = secs.div_ceil(SECS_PER_HOUR);
+            if hours > 0 && self.pence_per_hour > 0 {
+                lines.push(InvoiceLine::new(
+                    format!(
+                        "storage, {} hour{} @ {}",
+                        hours,
+                        if hours == 1 { "" } else { "s" },
+                        format_pence(self.pence_per_hour.into())
+                    ),
+                    hours * u64::from(self.pence_per_hour),
+                ));
+            }
+
+            let nights = self.count_cutoffs(first_closed, collected_at);
+            if nights > 0 && self.overnight_surcharge > 0 {
+                lines.push(InvoiceLine::new(
+                    format!(
+                        "overnight surcharge, {} night{} @ {}",
+                        nights,
+                        if nights == 1 { "" } else { "s" },
+                        format_pence(self.overnight_surcharge.into())
+                    ),
+                    nights * u64::from(self.overnight_surcharge),
+                ));
+            }
+        }
+
+        for fee in &stay.lost_key_fees {
+            let fee = fee.unwrap_or(self.lost_key_penalty);
+            if fee > 0 {
+                lines.push(InvoiceLine::new("lost key".to_string(), fee.into()));
+            }
+        }
+
+        Invoice {
+            locker_number,
+            lines,
+        }
+    }
+
+    /// Returns the number of times the overnight cut-off hour falls after
+    /// `start` and no later than `end`.
+    fn count_cutoffs(&self, start: SystemTime, end: SystemTime) -> u64 {
+        let cutoff_secs = i64::from(self.overnight_cutoff_hour) * SECS_PER_HOUR as i64;
+        let days_since_cutoff = |time: SystemTime| {
+            let secs = time
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs() as i64);
+            (secs - cutoff_secs).div_euclid(SECS_PER_DAY)
+        };
+        (days_since_cutoff(end) - days_since_cutoff(start)).max(0) as u64
+    }
+}
+
+/// What has happened to a locker since it was allocated that affects its
+/// invoice, and when it was last used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Stay {
+    catalogue: Arc<ItemCatalogue>,
+    /// When the locker was first closed.
+    pub(crate) first_closed: Option<SystemTime>,
+    /// When anything last happened to the locker.
+    pub(crate) last_accessed: SystemTime,
+    /// Number of items of each category in the catalogue put in the locker.
+    pub(crate) deposited: Vec<u32>,
+    /// Fee set for each use of the lost-key procedure, if one was.
+    pub(crate) lost_key_fees: Vec<Option<u32>>,
+}
+
+impl Stay {
+    pub(crate) fn new(catalogue: Arc<ItemCatalogue>, allocated_at: SystemTime) -> Stay {
+        let num_categories = catalogue.get_categories().len();
+        Stay {
+            catalogue,
+            first_closed: None,
+            last_accessed: allocated_at,
+            deposited: vec![0; num_categories],
+            lost_key_fees: Vec::new(),
+        }
+    }
+
+    /// Counts the items added to the locker by a change in its contents.
+    pub(crate) fn record_contents_change(
+        &mut self,
+        before: &CloakroomItems,
+        after: &CloakroomItems,
+    ) {
+        for (deposited, ((_, before), (_, after))) in self
+            .deposited
+            .iter_mut()
+            .zip(before.iter().zip(after.iter()))
+        {
+            *deposited += u32::from(after.saturating_sub(before));
+        }
+    }
+
+    pub(crate) fn get_catalogue(&self) -> &Arc<ItemCatalogue> {
+        &self.catalogue
+    }
+
+    pub(crate) fn record_closed(&mut self, timestamp: SystemTime) {
+        self.first_closed.get_or_insert(timestamp);
+    }
+
+    pub(crate) fn record_access(&mut self, timestamp: SystemTime) {
+        self.last_accessed = timestamp;
+    }
+
+    pub(crate) fn record_lost_key(&mut self, fee_pence: Option<u32>) {
+        self.lost_key_fees.push(fee_pence);
+    }
+}
+
+/// One charge on an invoice.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvoiceLine {
+    description: String,
+    amount_pence: u64,
+}
+
+impl InvoiceLine {
+    fn new(description: String, amount_pence: u64) -> InvoiceLine {
+        InvoiceLine {
+            description,
+            amount_pence,
+        }
+    }
+
+    pub fn get_description(&self) -> &str {
+        &self.description
+    }
+
+    pub fn get_amount_pence(&self) -> u64 {
+        self.amount_pence
+    }
+}
+
+/// Itemised bill for the stay of a customer's items in a locker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invoice {
+    locker_number: LockerNumber,
+    lines: Vec<InvoiceLine>,
+}
+
+impl Invoice {
+    pub fn get_locker_number(&self) -> LockerNumber {
+        self.locker_number
+    }
+
+    /// Returns the charges, leaving out those that come to nothing.
+    pub fn get_lines(&self) -> &[InvoiceLine] {
+        &self.lines
+    }
+
+    pub fn get_total_pence(&self) -> u64 {
+        self.lines.iter().map(InvoiceLine::get_amount_pence).sum()
+    }
+}
+
+impl fmt::Display for Invoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Invoice for locker number {}", self.locker_number)?;
+        for line in &self.lines {
+            writeln!(
+                f,
+                "  {:<48}{:>10}",
+                line.description,
+                format_pence(line.amount_pence)
+            )?;
+        }
+        write!(
+            f,
+            "  {:<48}{:>10}",
+            "Total",
+            format_pence(self.get_total_pence())
+        )
+    }
+}
+
+/// Formats an amount in pence as pounds, e.g. `12.05`.
+fn format_pence(pence: u64) -> String {
+    format!("{}.{:02}", pence / 100, pence % 100)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::items::{self, CloakroomItems};
+    use crate::pricing::{Stay, Tariff};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test1() {
+        let tariff = Tariff::load(
+            "# Summer prices\n\
+             deposit_fee category=coats pence=100\n\
+             deposit_fee category=umbrellas pence=20\n\
+             storage pence_per_hour=50\n\
+             overnight_surcharge pence=500 cutoff_hour=3\n\
+             lost_key_penalty pence=1000\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            tariff,
+            Tariff::new()
+                .with_deposit_fee("coats", 100)
+                .with_deposit_fee("umbrellas", 20)
+                .with_storage_charge(50)
+                .with_overnight_surcharge(500, 3)
+                .with_lost_key_penalty(1000)
+        );
+
+        let catalogue = items::default_catalogue();
+        let empty = CloakroomItems::from_catalogue(catalogue.clone());
+        let mut coats = empty.clone();
+        coats.set_num_items("coats", 2).unwrap();
+        let mut stay = Stay::new(catalogue, UNIX_EPOCH);
+        stay.record_contents_change(&empty, &coats);
+        stay.record_contents_change(&coats, &empty);
+        stay.record_contents_change(&empty, &coats);
+        stay.record_lost_key(None);
+        stay.record_lost_key(Some(250));
+
+        // Closed at 22:00 and collected at 04:30 two days later.
+        let closed = UNIX_EPOCH + Duration::from_secs(22 * 60 * 60);
+        stay.record_closed(closed);
+        stay.record_closed(closed + Duration::from_secs(60));
+        let collected = closed + Duration::from_secs((24 + 6) * 60 * 60 + 30 * 60);
+
+        let invoice = tariff.invoice(7, &stay, collected);
+        assert_eq!(
+            invoice.get_total_pence(),
+            400 + 31 * 50 + 2 * 500 + 1000 + 250
+        );
+        assert_eq!(
+            invoice.to_string(),
+            "Invoice for locker number 7\n  \
+             4 x coats @ 1.00                                      4.00\n  \
+             storage, 31 hours @ 0.50                             15.50\n  \
+             overnight surcharge, 2 nights @ 5.00                 10.00\n  \
+             lost key                                             10.00\n  \
+             lost key                                              2.50\n  \
+             Total                                                42.00"
+        );
+
+        // Nothing is charged for storage until the locker is closed.
+        let mut stay = Stay::new(items::default_catalogue(), UNIX_EPOCH);
+        assert_eq!(tariff.invoice(1, &stay, collected).get_total_pence(), 0);
+        stay.record_closed(collected);
+        assert_eq!(tariff.invoice(1, &stay, collected).get_total_pence(), 0);
+        let invoice = tariff.invoice(1, &stay, collected + Duration::from_secs(1));
+        assert_eq!(
+            invoice.get_lines()[0].get_description(),
+            "storage, 1 hour @ 0.50"
+        );
+
+        for (text, line_number) in [
+            ("storage\n", 1),
+            ("\nstorage pence_per_hour=x\n", 2),
+            ("overnight_surcharge pence=1 cutoff_hour=24\n", 1),
+            ("lost_key_penalty pence=1 colour=red\n", 1),
+            ("discount pence=1\n", 1),
+        ] {
+            match Tariff::load(text.as_bytes()) {
+                Err(crate::pricing::TariffError::Parse { line_number: n, .. }) => {
+                    assert_eq!(n, line_number, "{}", text)
+                }
+                other => panic!("unexpected result {:?} for {}", other, text),
+            }
+        }
+    }
+}
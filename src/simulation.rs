@@ -0,0 +1,446 @@
+//! Discrete-event simulation of customers using a cloakroom.
+//!
+//! Customers arrive according to an [`ArrivalProcess`], each bringing a
+//! random bundle of items drawn from an [`ItemMix`]. On arrival a customer
+//! asks for a free locker big enough for their items. If there is one they
+//! fill it, close it and keep the key. They then come back after a random
+//! [`StayDuration`] to open the locker and collect their items. Customers
+//! who cannot be given a locker are turned away.
+//!
+//! Simulated time is measured in minutes from the start of the run. The
+//! cloakroom reads the time from a [`MockClock`], which is moved on to the
+//! time of each event before it is handled, so that invoices, audit events
+//! and the cloakroom's own statistics follow simulated time. The random
+//! choices depend only on the seed, so a run can be reproduced exactly.
+//!
+//! # Examples
+//!
+//! ```
+//! use cloakroom_model::cloakroom::Cloakroom;
+//! use cloakroom_model::clock::MockClock;
+//! use cloakroom_model::items::CloakroomItems;
+//! use cloakroom_model::simulation::{self, ArrivalProcess, ItemMix, SimulationConfig, StayDuration};
+//! use std::time::UNIX_EPOCH;
+//!
+//! let mut coat = CloakroomItems::new();
+//! coat.set_num_items("coats", 1).unwrap();
+//!
+//! let config = SimulationConfig {
+//!     seed: 42,
+//!     duration_minutes: 8.0 * 60.0,
+//!     arrivals: ArrivalProcess::Poisson { rate_per_hour: 30.0 },
+//!     stay: StayDuration::Exponential { mean_minutes: 90.0 },
+//!     items: ItemMix::new(vec![(1.0, coat)]),
+//! };
+//!
+//! let clock = MockClock::new(UNIX_EPOCH);
+//! let mut cloakroom = Cloakroom::new(20, 5).with_clock(clock.clone());
+//! let report = simulation::run(&mut cloakroom, &clock, &config).unwrap();
+//!
+//! assert_eq!(report.arrivals, report.served + report.rejected);
+//! assert_eq!(report.served, report.departures);
+//! assert!(report.statistics.peak_occupied_lockers <= 20);
+//! println!("{}", report.statistics);
+//! ```
+
+use crate::cloakroom::{Cloakroom, CloakroomError, Key};
+use crate::clock::{Clock, MockClock};
+use crate::items::{CatalogueError, CloakroomItems, ItemCatalogue};
+use crate::rng::Rng;
+use crate::statistics::{StatisticsCollector, StatisticsReport};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::error;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// How customers arrive at the cloakroom.
+#[derive(Debug, Clone)]
+pub enum ArrivalProcess {
+    /// Customers arrive independently at an average rate, so the time
+    /// between arrivals is exponentially distributed.
+    Poisson { rate_per_hour: f64 },
+}
+
+/// How long customers leave their items in the cloakroom.
+#[derive(Debug, Clone)]
+pub enum StayDuration {
+    Exponential {
+        mean_minutes: f64,
+    },
+    /// Each stay is one of the observed durations, chosen at random. The
+    /// durations must not be negative.
+    Empirical(Vec<f64>),
+}
+
+/// Bundles of items that customers bring, each with a relative weight
+/// giving how often it is brought.
+#[derive(Debug, Clone)]
+pub struct ItemMix {
+    bundles: Vec<(f64, CloakroomItems)>,
+    total_weight: f64,
+}
+
+impl ItemMix {
+    /// Creates an item mix from `(weight, items)` pairs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no bundles, a weight is negative or all the
+    /// weights are 0.
+    pub fn new(bundles: Vec<(f64, CloakroomItems)>) -> ItemMix {
+        assert!(!bundles.is_empty(), "item mix has no bundles");
+        assert!(
+            bundles.iter().all(|(weight, _)| *weight >= 0.0),
+            "item mix has a negative weight"
+        );
+        let total_weight = bundles.iter().map(|(weight, _)| weight).sum();
+        assert!(total_weight > 0.0, "item mix weights are all 0");
+
+        ItemMix {
+            bundles,
+            total_weight,
+        }
+    }
+
+    /// Returns the same mix with each bundle described using `catalogue`.
+    fn convert_to_catalogue(
+        &self,
+        catalogue: &Arc<ItemCatalogue>,
+    ) -> Result<ItemMix, CatalogueError> {
+        let bundles = self
+            .bundles
+            .iter()
+            .map(|(weight, items)| Ok((*weight, items.convert_to_catalogue(catalogue)?)))
+            .collect::<Result<_, CatalogueError>>()?;
+        Ok(ItemMix {
+            bundles,
+            total_weight: self.total_weight,
+        })
+    }
+
+    fn sample(&self, rng: &mut Rng) -> CloakroomItems {
+        let mut target = rng.next_f64() * self.total_weight;
+        for (weight, items) in &self.bundles {
+            if target < *weight {
+                return items.clone();
+            }
+            target -= weight;
+        }
+
+        // Only reached through rounding errors.
+        self.bundles[self.bundles.len() - 1].1.clone()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimulationConfig {
+    pub seed: u64,
+    /// Customers stop arriving after this time, but customers already in
+    /// the cloakroom still come back for their items.
+    pub duration_minutes: f64,
+    pub arrivals: ArrivalProcess,
+    pub stay: StayDuration,
+    pub items: ItemMix,
+}
+
+/// Counts of what happened during a simulation run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimulationReport {
+    pub arrivals: usize,
+    /// Customers who were given a locker.
+    pub served: usize,
+    /// Customers turned away because no free locker was big enough.
+    pub rejected: usize,
+    /// Customers who came back and collected their items.
+    pub departures: usize,
+    /// Time at which the last customer left.
+    pub end_minutes: f64,
+    /// Occupancy and utilisation of the cloakroom from the start of the
+    /// run until `end_minutes`.
+    pub statistics: StatisticsReport,
+}
+
+/// Reasons why a simulation cannot be run to the end.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SimulationError {
+    /// The arrival rate is not a positive, finite number.
+    InvalidArrivalRate(f64),
+    /// A stay duration, or the mean stay, is negative or not a finite
+    /// number.
+    InvalidStayDuration(f64),
+    NoStayDurations,
+    /// The run's duration is not a finite number.
+    InvalidDuration(f64),
+    /// A bundle in the item mix has items of a category that the
+    /// cloakroom's item catalogue does not have.
+    UnknownItemCategory(String),
+    /// The cloakroom refused to do something for a simulated customer, e.g.
+    /// because its lockers were also being used outside the simulation.
+    Cloakroom(CloakroomError),
+}
+
+impl fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimulationError::InvalidArrivalRate(rate_per_hour) => write!(
+                f,
+                "arrival rate must be positive, not {} per hour",
+                rate_per_hour
+            ),
+
+            SimulationError::InvalidStayDuration(minutes) => write!(
+                f,
+                "stay duration must not be negative, not {} minutes",
+                minutes
+            ),
+
+            SimulationError::NoStayDurations => write!(f, "no empirical stay durations given"),
+
+            SimulationError::InvalidDuration(minutes) => {
+                write!(f, "invalid simulation duration of {} minutes", minutes)
+            }
+
+            SimulationError::UnknownItemCategory(name) => {
+                write!(f, "item mix has unknown item category '{}'", name)
+            }
+
+            SimulationError::Cloakroom(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl error::Error for SimulationError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SimulationError::Cloakroom(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<CloakroomError> for SimulationError {
+    fn from(err: CloakroomError) -> SimulationError {
+        SimulationError::Cloakroom(err)
+    }
+}
+
+enum EventKind {
+    Arrival,
+    Departure(Key),
+}
+
+struct Event {
+    minutes: f64,
+    // Breaks ties between events at the same time, so that they are handled
+    // in the order they were scheduled.
+    sequence: u64,
+    kind: EventKind,
+}
+
+impl PartialEq for Event {
+    fn eq(&self, other: &Event) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Event {}
+
+impl PartialOrd for Event {
+    fn partial_cmp(&self, other: &Event) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Event {
+    // Reversed, so that BinaryHeap, which is a max-heap, yields the earliest
+    // event first.
+    fn cmp(&self, other: &Event) -> Ordering {
+        other
+            .minutes
+            .total_cmp(&self.minutes)
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+struct Simulation<'a> {
+    config: &'a SimulationConfig,
+    // The config's item mix, described using the cloakroom's catalogue.
+    items: ItemMix,
+    clock: &'a MockClock,
+    start: SystemTime,
+    rng: Rng,
+    events: BinaryHeap<Event>,
+    num_scheduled: u64,
+    report: SimulationReport,
+    statistics: StatisticsCollector,
+}
+
+impl<'a> Simulation<'a> {
+    fn schedule(&mut self, minutes: f64, kind: EventKind) {
+        self.num_scheduled += 1;
+        self.events.push(Event {
+            minutes,
+            sequence: self.num_scheduled,
+            kind,
+        });
+    }
+
+    /// Moves the cloakroom's clock on to `minutes` after the start of the
+    /// run.
+    fn set_clock(&self, minutes: f64) {
+        self.clock
+            .set(self.start + Duration::from_secs_f64(minutes * 60.0));
+    }
+
+    fn sample_exponential(&mut self, mean: f64) -> f64 {
+        -mean * (1.0 - self.rng.next_f64()).ln()
+    }
+
+    fn sample_interarrival_minutes(&mut self) -> f64 {
+        match self.config.arrivals {
+            ArrivalProcess::Poisson { rate_per_hour } => {
+                self.sample_exponential(60.0 / rate_per_hour)
+            }
+        }
+    }
+
+    fn sample_stay_minutes(&mut self) -> f64 {
+        match &self.config.stay {
+            StayDuration::Exponential { mean_minutes } => self.sample_exponential(*mean_minutes),
+
+            StayDuration::Empirical(durations) => {
+                let index = self.rng.below(durations.len() as u64) as usize;
+                durations[index]
+            }
+        }
+    }
+
+    fn arrive(&mut self, cloakroom: &mut Cloakroom, now: f64) -> Result<(), SimulationError> {
+        self.report.arrivals += 1;
+
+        let next_arrival = now + self.sample_interarrival_minutes();
+        if next_arrival < self.config.duration_minutes {
+            self.schedule(next_arrival, EventKind::Arrival);
+        }
+
+        let items = self.items.sample(&mut self.rng);
+        let mut locker = match cloakroom.find_free_locker(&items).into_result() {
+            Ok(locker) => locker,
+            Err(_) => {
+                self.report.rejected += 1;
+                self.statistics.record_rejected(now);
+                return Ok(());
+            }
+        };
+        self.statistics
+            .record_allocated(now, locker.get_locker_number(), &items);
+        locker.set_items(items)?;
+        let key = cloakroom.close_locker(locker)?;
+        self.report.served += 1;
+
+        let departure = now + self.sample_stay_minutes();
+        self.schedule(departure, EventKind::Departure(key));
+        Ok(())
+    }
+
+    fn depart(
+        &mut self,
+        cloakroom: &mut Cloakroom,
+        key: Key,
+        now: f64,
+    ) -> Result<(), SimulationError> {
+        let locker = cloakroom.open_locker(key)?;
+        self.statistics
+            .record_vacated(now, locker.get_locker_number());
+        cloakroom.vacate_locker(locker)?;
+        self.report.departures += 1;
+        Ok(())
+    }
+}
+
+/// Runs a simulation against `cloakroom`, which must read the time from
+/// `clock`, and returns what happened. Every locker used by a simulated
+/// customer has been vacated by the time it returns, unless an error is
+/// returned.
+///
+/// The run starts at the time `clock` shows when it is called, and the
+/// clock is left at the time the last customer left.
+pub fn run(
+    cloakroom: &mut Cloakroom,
+    clock: &MockClock,
+    config: &SimulationConfig,
+) -> Result<SimulationReport, SimulationError> {
+    check_config(config)?;
+    let items = config
+        .items
+        .convert_to_catalogue(cloakroom.get_item_catalogue())
+        .map_err(|err| match err {
+            CatalogueError::UnknownCategory(name) => SimulationError::UnknownItemCategory(name),
+            err => unreachable!("unexpected error converting item mix: {}", err),
+        })?;
+
+    let mut simulation = Simulation {
+        config,
+        items,
+        clock,
+        start: clock.now(),
+        rng: Rng::new(config.seed),
+        events: BinaryHeap::new(),
+        num_scheduled: 0,
+        report: SimulationReport::default(),
+        statistics: StatisticsCollector::new(
+            cloakroom.get_item_catalogue(),
+            cloakroom.get_num_lockers(),
+            0.0,
+        ),
+    };
+
+    let first_arrival = simulation.sample_interarrival_minutes();
+    if first_arrival < config.duration_minutes {
+        simulation.schedule(first_arrival, EventKind::Arrival);
+    }
+
+    while let Some(event) = simulation.events.pop() {
+        simulation.set_clock(event.minutes);
+        match event.kind {
+            EventKind::Arrival => simulation.arrive(cloakroom, event.minutes)?,
+            EventKind::Departure(key) => simulation.depart(cloakroom, key, event.minutes)?,
+        }
+        simulation.report.end_minutes = event.minutes;
+    }
+
+    simulation.report.statistics = simulation.statistics.report(simulation.report.end_minutes);
+    Ok(simulation.report)
+}
+
+/// Checks that the arrival rate, stay durations and duration of the run
+/// are usable.
+fn check_config(config: &SimulationConfig) -> Result<(), SimulationError> {
+    if !config.duration_minutes.is_finite() {
+        return Err(SimulationError::InvalidDuration(config.duration_minutes));
+    }
+    match config.arrivals {
+        ArrivalProcess::Poisson { rate_per_hour } => {
+            if !(rate_per_hour > 0.0 && rate_per_hour.is_finite()) {
+                return Err(SimulationError::InvalidArrivalRate(rate_per_hour));
+            }
+        }
+    }
+
+    let check_stay = |minutes: f64| {
+        if minutes >= 0.0 && minutes.is_finite() {
+            Ok(())
+        } else {
+            Err(SimulationError::InvalidStayDuration(minutes))
+        }
+    };
+    match &config.stay {
+        StayDuration::Exponential { mean_minutes } => check_stay(*mean_minutes),
+        StayDuration::Empirical(durations) if durations.is_empty() => {
+            Err(SimulationError::NoStayDurations)
+        }
+        StayDuration::Empirical(durations) => durations.iter().try_for_each(|&d| check_stay(d)),
+    }
+}
@@ -0,0 +1,467 @@
+//! Metrics describing how a cloakroom has been used.
+//!
+//! A [`StatisticsCollector`] is told when lockers are allocated and vacated
+//! and when customers are turned away, each at a time measured in minutes.
+//! From these it produces a [`StatisticsReport`], which can be written as
+//! text (using `Display`), JSON or CSV.
+//!
+//! A cloakroom made with
+//! [`Cloakroom::with_statistics`](crate::cloakroom::Cloakroom::with_statistics)
+//! has a collector of its own, which it tells about every change to its
+//! lockers as the change is made.
+//!
+//! # Examples
+//!
+//! ```
+//! use cloakroom_model::items::{CloakroomItems, ItemCatalogue};
+//! use cloakroom_model::statistics::StatisticsCollector;
+//!
+//! let mut items = CloakroomItems::new();
+//! items.set_num_items("coats", 2).unwrap();
+//!
+//! let mut collector = StatisticsCollector::new(&ItemCatalogue::default(), 2, 0.0);
+//! collector.record_allocated(0.0, 1, &items);
+//! collector.record_allocated(30.0, 2, &items);
+//! collector.record_rejected(45.0);
+//! collector.record_vacated(60.0, 1);
+//!
+//! let report = collector.report(120.0);
+//! assert_eq!(report.peak_occupied_lockers, 2);
+//! assert_eq!(report.average_occupied_lockers, 1.25);
+//! assert_eq!(report.rejections, 1);
+//! assert_eq!(report.items_stored[0], ("coats".to_string(), 4));
+//! assert_eq!(report.locker_utilisation, vec![0.5, 0.75]);
+//! ```
+
+use crate::cloakroom::LockerNumber;
+use crate::items::{CloakroomItems, ItemCatalogue};
+use crate::json;
+use std::fmt;
+
+/// Number of equal-width bands that locker utilisation is divided into.
+pub const NUM_UTILISATION_BANDS: usize = 10;
+
+/// Records events as they happen and summarises them.
+#[derive(Debug, Clone)]
+pub struct StatisticsCollector {
+    start_minutes: f64,
+    last_change_minutes: f64,
+
+    occupied_lockers: usize,
+    peak_occupied_lockers: usize,
+    // Integral of the number of occupied lockers over time, up to
+    // last_change_minutes.
+    occupied_locker_minutes: f64,
+
+    allocations: usize,
+    rejections: usize,
+    items_stored: Vec<(String, u64)>,
+
+    occupied_since: Vec<Option<f64>>,
+    occupied_minutes: Vec<f64>,
+}
+
+impl StatisticsCollector {
+    /// Creates a collector for a cloakroom with `num_lockers` lockers, all
+    /// of them free at `start_minutes`, that stores items of the categories
+    /// in `catalogue`.
+    pub fn new(
+        catalogue: &ItemCatalogue,
+        num_lockers: usize,
+        start_minutes: f64,
+    ) -> StatisticsCollector {
+        StatisticsCollector {
+            start_minutes,
+            last_change_minutes: start_minutes,
+            occupied_lockers: 0,
+            peak_occupied_lockers: 0,
+            occupied_locker_minutes: 0.0,
+            allocations: 0,
+            rejections: 0,
+            items_stored: catalogue
+                .get_categories()
+                .iter()
+                .map(|category| (category.get_name().to_string(), 0))
+                .collect(),
+            occupied_since: vec![None; num_lockers],
+            occupied_minutes: vec![0.0; num_lockers],
+        }
+    }
+
+    /// Records that a locker was allocated to a customer storing `items`.
+    /// Nothing is recorded if the locker is already occupied, or the
+    /// cloakroom has no such locker.
+    pub fn record_allocated(
+        &mut self,
+        minutes: f64,
+        locker_number: LockerNumber,
+        items: &CloakroomItems,
+    ) {
+        if self.record_occupied(minutes, locker_number) {
+            self.allocations += 1;
+            for (category, num_items) in items.iter() {
+                self.add_items_stored(category.get_name(), num_items);
+            }
+        }
+    }
+
+    /// Records that the contents of an occupied locker were changed from
+    /// `before` to `after`. Items added are counted as stored.
+    pub fn record_contents_changed(&mut self, before: &CloakroomItems, after: &CloakroomItems) {
+        for (category, num_after) in after.iter() {
+            let num_before = before.get_num_items(category.get_name()).unwrap_or(0);
+            self.add_items_stored(category.get_name(), num_after.saturating_sub(num_before));
+        }
+    }
+
+    /// Records that a locker was vacated. Nothing is recorded if the locker
+    /// is not occupied, or the cloakroom has no such locker.
+    pub fn record_vacated(&mut self, minutes: f64, locker_number: LockerNumber) {
+        let index = match self.index(locker_number) {
+            Some(index) => index,
+            None => return,
+        };
+        let since = match self.occupied_since[index].take() {
+            Some(since) => since,
+            None => return,
+        };
+
+        self.advance_to(minutes);
+        self.occupied_lockers -= 1;
+        self.occupied_minutes[index] += minutes - since;
+    }
+
+    /// Records that the contents of one locker were moved to another, which
+    /// is occupied from then on instead. This is not counted as an
+    /// allocation.
+    pub fn record_transferred(&mut self, minutes: f64, from: LockerNumber, to: LockerNumber) {
+        self.record_vacated(minutes, from);
+        self.record_occupied(minutes, to);
+    }
+
+    /// Records that a customer was turned away because there was no free
+    /// locker for them.
+    pub fn record_rejected(&mut self, minutes: f64) {
+        self.advance_to(minutes);
+        self.rejections += 1;
+    }
+
+    /// Summarises everything recorded between the start and `end_minutes`.
+    /// Lockers still occupied are treated as occupied until `end_minutes`.
+    pub fn report(&self, end_minutes: f64) -> StatisticsReport {
+        let duration_minutes = end_minutes - self.start_minutes;
+        let occupied_locker_minutes = self.occupied_locker_minutes
+            + self.occupied_lockers as f64 * (end_minutes - self.last_change_minutes);
+
+        let locker_utilisation: Vec<f64> = self
+            .occupied_minutes
+            .iter()
+            .zip(self.occupied_since.iter())
+            .map(|(&minutes, since)| {
+                let minutes = minutes + since.map_or(0.0, |since| end_minutes - since);
+                ratio(minutes, duration_minutes)
+            })
+            .collect();
+
+        let mut utilisation_bands = [0; NUM_UTILISATION_BANDS];
+        for &utilisation in &locker_utilisation {
+            let band = (utilisation * NUM_UTILISATION_BANDS as f64) as usize;
+            utilisation_bands[band.min(NUM_UTILISATION_BANDS - 1)] += 1;
+        }
+
+        StatisticsReport {
+            num_lockers: self.occupied_since.len(),
+            duration_minutes,
+            peak_occupied_lockers: self.peak_occupied_lockers,
+            average_occupied_lockers: ratio(occupied_locker_minutes, duration_minutes),
+            allocations: self.allocations,
+            rejections: self.rejections,
+            items_stored: self.items_stored.clone(),
+            locker_utilisation,
+            utilisation_bands,
+        }
+    }
+
+    /// Marks a free locker as occupied from `minutes`, returning whether it
+    /// was free.
+    pub(crate) fn record_occupied(&mut self, minutes: f64, locker_number: LockerNumber) -> bool {
+        let index = match self.index(locker_number) {
+            Some(index) if self.occupied_since[index].is_none() => index,
+            _ => return false,
+        };
+
+        self.advance_to(minutes);
+        self.occupied_lockers += 1;
+        self.peak_occupied_lockers = self.peak_occupied_lockers.max(self.occupied_lockers);
+        self.occupied_since[index] = Some(minutes);
+        true
+    }
+
+    fn add_items_stored(&mut self, category: &str, num_items: u8) {
+        match self
+            .items_stored
+            .iter_mut()
+            .find(|(name, _)| name == category)
+        {
+            Some((_, total)) => *total += num_items as u64,
+            None => self
+                .items_stored
+                .push((category.to_string(), num_items as u64)),
+        }
+    }
+
+    /// Returns the index of a locker in the per-locker records, or `None`
+    /// if there is no such locker.
+    fn index(&self, locker_number: LockerNumber) -> Option<usize> {
+        match locker_number {
+            0 => None,
+            _ if locker_number > self.occupied_since.len() => None,
+            _ => Some(locker_number - 1),
+        }
+    }
+
+    fn advance_to(&mut self, minutes: f64) {
+        self.occupied_locker_minutes +=
+            self.occupied_lockers as f64 * (minutes - self.last_change_minutes);
+        self.last_change_minutes = minutes;
+    }
+}
+
+fn ratio(numerator: f64, denominator: f64) -> f64 {
+    if denominator > 0.0 {
+        numerator / denominator
+    } else {
+        0.0
+    }
+}
+
+/// Summary of how a cloakroom was used over a period.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatisticsReport {
+    pub num_lockers: usize,
+    pub duration_minutes: f64,
+    pub peak_occupied_lockers: usize,
+    /// Number of occupied lockers averaged over time.
+    pub average_occupied_lockers: f64,
+    pub allocations: usize,
+    /// Customers turned away because no free locker was big enough.
+    pub rejections: usize,
+    /// Total number of items of each category stored, by category name, in
+    /// catalogue order.
+    pub items_stored: Vec<(String, u64)>,
+    /// Fraction of the time each locker was occupied. The first value is
+    /// for locker number 1, and so on.
+    pub locker_utilisation: Vec<f64>,
+    /// Number of lockers whose utilisation fell in each band. Band `i`
+    /// covers utilisation from `i / 10` up to, but not including,
+    /// `(i + 1) / 10`, except that the last band includes 1.
+    pub utilisation_bands: [usize; NUM_UTILISATION_BANDS],
+}
+
+impl StatisticsReport {
+    /// Fraction of customers who were turned away.
+    pub fn rejection_rate(&self) -> f64 {
+        ratio(
+            self.rejections as f64,
+            (self.allocations + self.rejections) as f64,
+        )
+    }
+
+    /// Name of a utilisation band, such as `"10-20%"`.
+    pub fn utilisation_band_name(band: usize) -> String {
+        let width = 100 / NUM_UTILISATION_BANDS;
+        format!("{}-{}%", band * width, (band + 1) * width)
+    }
+
+    pub fn to_json(&self) -> String {
+        let items_stored: Vec<String> = self
+            .items_stored
+            .iter()
+            .map(|(category, count)| format!("{}: {}", json::quote(category), count))
+            .collect();
+        let bands: Vec<String> = self
+            .utilisation_bands
+            .iter()
+            .enumerate()
+            .map(|(band, count)| {
+                format!(
+                    "{}: {}",
+                    json::quote(&StatisticsReport::utilisation_band_name(band)),
+                    count
+                )
+            })
+            .collect();
+        let utilisation: Vec<String> = self
+            .locker_utilisation
+            .iter()
+            .map(|&utilisation| json::number(utilisation))
+            .collect();
+
+        format!(
+            "{{\"num_lockers\": {}, \"duration_minutes\": {}, \
+             \"peak_occupied_lockers\": {}, \"average_occupied_lockers\": {}, \
+             \"allocations\": {}, \"rejections\": {}, \"rejection_rate\": {}, \
+             \"items_stored\": {{{}}}, \"utilisation_bands\": {{{}}}, \
+             \"locker_utilisation\": [{}]}}",
+            self.num_lockers,
+            json::number(self.duration_minutes),
+            self.peak_occupied_lockers,
+            json::number(self.average_occupied_lockers),
+            self.allocations,
+            self.rejections,
+            json::number(self.rejection_rate()),
+            items_stored.join(", "),
+            bands.join(", "),
+            utilisation.join(", ")
+        )
+    }
+
+    /// Writes the report as CSV with a `metric,value` header and one row per
+    /// value. Per-category and per-band values are named
+    /// `items_stored.<category>` and `utilisation_band.<band>`, and
+    /// per-locker values `locker_utilisation.<locker number>`.
+    pub fn to_csv(&self) -> String {
+        let mut rows = vec![
+            "metric,value".to_string(),
+            format!("num_lockers,{}", self.num_lockers),
+            format!("duration_minutes,{}", self.duration_minutes),
+            format!("peak_occupied_lockers,{}", self.peak_occupied_lockers),
+            format!("average_occupied_lockers,{}", self.average_occupied_lockers),
+            format!("allocations,{}", self.allocations),
+            format!("rejections,{}", self.rejections),
+            format!("rejection_rate,{}", self.rejection_rate()),
+        ];
+        for (category, count) in &self.items_stored {
+            rows.push(format!("items_stored.{},{}", category, count));
+        }
+        for (band, count) in self.utilisation_bands.iter().enumerate() {
+            rows.push(format!(
+                "utilisation_band.{},{}",
+                StatisticsReport::utilisation_band_name(band),
+                count
+            ));
+        }
+        for (index, utilisation) in self.locker_utilisation.iter().enumerate() {
+            rows.push(format!("locker_utilisation.{},{}", index + 1, utilisation));
+        }
+
+        let mut csv = rows.join("\n");
+        csv.push('\n');
+        csv
+    }
+}
+
+impl fmt::Display for StatisticsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Lockers: {}, period: {:.1} minutes",
+            self.num_lockers, self.duration_minutes
+        )?;
+        writeln!(
+            f,
+            "Occupied lockers: peak {}, average {:.2}",
+            self.peak_occupied_lockers, self.average_occupied_lockers
+        )?;
+        writeln!(
+            f,
+            "Lockers allocated: {}, customers turned away: {} ({:.1}%)",
+            self.allocations,
+            self.rejections,
+            self.rejection_rate() * 100.0
+        )?;
+
+        let items_stored: Vec<String> = self
+            .items_stored
+            .iter()
+            .map(|(category, count)| format!("{}: {}", category, count))
+            .collect();
+        writeln!(f, "Items stored: {}", items_stored.join(", "))?;
+
+        writeln!(f, "Locker utilisation:")?;
+        for (band, count) in self.utilisation_bands.iter().enumerate() {
+            writeln!(
+                f,
+                "  {:>8}: {} lockers",
+                StatisticsReport::utilisation_band_name(band),
+                count
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::items::{CloakroomItems, ItemCatalogue};
+    use crate::statistics::StatisticsCollector;
+
+    #[test]
+    fn test1() {
+        let mut items = CloakroomItems::new();
+        items.set_num_items("umbrellas", 1).unwrap();
+
+        let mut collector = StatisticsCollector::new(&ItemCatalogue::default(), 3, 10.0);
+        collector.record_allocated(10.0, 1, &items);
+        collector.record_vacated(20.0, 1);
+        collector.record_allocated(20.0, 2, &items);
+        collector.record_rejected(25.0);
+
+        let report = collector.report(30.0);
+        assert_eq!(report.duration_minutes, 20.0);
+        assert_eq!(report.peak_occupied_lockers, 1);
+        assert_eq!(report.average_occupied_lockers, 1.0);
+        assert_eq!(report.allocations, 2);
+        assert_eq!(report.rejection_rate(), 1.0 / 3.0);
+        assert_eq!(report.locker_utilisation, vec![0.5, 0.5, 0.0]);
+        assert_eq!(report.utilisation_bands, [1, 0, 0, 0, 0, 2, 0, 0, 0, 0]);
+
+        let csv = report.to_csv();
+        assert!(csv.starts_with("metric,value\nnum_lockers,3\nduration_minutes,20\n"));
+        assert!(csv.contains("\nitems_stored.umbrellas,2\n"));
+        assert!(csv.contains("\nutilisation_band.50-60%,2\n"));
+        assert!(csv.ends_with("\nlocker_utilisation.3,0\n"));
+
+        let json = report.to_json();
+        assert!(json.starts_with("{\"num_lockers\": 3, \"duration_minutes\": 20, "));
+        assert!(json.contains(
+            "\"items_stored\": {\"coats\": 0, \"backpacks\": 0, \"umbrellas\": 2, \"other_items\": 0}"
+        ));
+        assert!(json.ends_with("\"locker_utilisation\": [0.5, 0.5, 0]}"));
+
+        let text = report.to_string();
+        assert!(text.contains("Occupied lockers: peak 1, average 1.00\n"));
+        assert!(text.contains("customers turned away: 1 (33.3%)\n"));
+        assert!(text.contains("    0-10%: 1 lockers\n"));
+    }
+
+    #[test]
+    fn test2() {
+        let mut before = CloakroomItems::new();
+        before.set_num_items("coats", 2).unwrap();
+        let mut after = CloakroomItems::new();
+        after.set_num_items("coats", 1).unwrap();
+        after.set_num_items("umbrellas", 1).unwrap();
+
+        // Lockers that do not exist are ignored.
+        let mut collector = StatisticsCollector::new(&ItemCatalogue::default(), 2, 0.0);
+        collector.record_allocated(0.0, 0, &before);
+        collector.record_allocated(0.0, 3, &before);
+        collector.record_vacated(5.0, 0);
+        collector.record_vacated(5.0, 3);
+        assert_eq!(collector.report(10.0).allocations, 0);
+
+        // Only items added are counted as stored, and a transfer is not an
+        // allocation.
+        collector.record_allocated(0.0, 1, &before);
+        collector.record_contents_changed(&before, &after);
+        collector.record_transferred(5.0, 1, 2);
+        let report = collector.report(10.0);
+        assert_eq!(report.allocations, 1);
+        assert_eq!(report.peak_occupied_lockers, 1);
+        assert_eq!(report.locker_utilisation, vec![0.5, 0.5]);
+        assert_eq!(report.items_stored[0], ("coats".to_string(), 2));
+        assert_eq!(report.items_stored[2], ("umbrellas".to_string(), 1));
+    }
+}
@@ -0,0 +1,148 @@
+extern crate cloakroom_model;
+
+use cloakroom_model::cloakroom;
+use cloakroom_model::cloakroom::allocation;
+use cloakroom_model::cloakroom::LockerSpec;
+use cloakroom_model::items::CloakroomItems;
+
+fn items_with_coats(num_coats: u8) -> CloakroomItems {
+    let mut items = CloakroomItems::new();
+    items.set_num_items("coats", num_coats).unwrap();
+    items
+}
+
+fn find_free_locker(
+    cloakroom: &mut cloakroom::Cloakroom,
+    items: &CloakroomItems,
+) -> cloakroom::Locker {
+    match cloakroom.find_free_locker(items).into_result() {
+        Ok(locker) => locker,
+        Err(err) => panic!("{}", err),
+    }
+}
+
+fn mixed_lockers() -> Vec<LockerSpec> {
+    vec![
+        LockerSpec::new(10),
+        LockerSpec::new(5),
+        LockerSpec::new(20),
+        LockerSpec::new(5),
+    ]
+}
+
+#[test]
+fn test_first_fit() {
+    let mut cloakroom = cloakroom::Cloakroom::with_lockers(mixed_lockers())
+        .with_allocation_strategy(allocation::FirstFit);
+
+    let locker = find_free_locker(&mut cloakroom, &items_with_coats(3));
+    assert_eq!(locker.get_locker_number(), 1);
+
+    let locker = find_free_locker(&mut cloakroom, &items_with_coats(3));
+    assert_eq!(locker.get_locker_number(), 2);
+
+    let locker = find_free_locker(&mut cloakroom, &items_with_coats(12));
+    assert_eq!(locker.get_locker_number(), 3);
+
+    assert!(matches!(
+        cloakroom.find_free_locker(&items_with_coats(6)),
+        cloakroom::FreeLockerResult::NoFreeLockers
+    ));
+}
+
+#[test]
+fn test_best_fit() {
+    let mut cloakroom = cloakroom::Cloakroom::with_lockers(mixed_lockers())
+        .with_allocation_strategy(allocation::BestFit);
+
+    let locker = find_free_locker(&mut cloakroom, &items_with_coats(3));
+    assert_eq!(locker.get_locker_number(), 2);
+
+    let locker = find_free_locker(&mut cloakroom, &items_with_coats(6));
+    assert_eq!(locker.get_locker_number(), 1);
+
+    let locker = find_free_locker(&mut cloakroom, &items_with_coats(2));
+    assert_eq!(locker.get_locker_number(), 4);
+
+    let locker = find_free_locker(&mut cloakroom, &items_with_coats(2));
+    assert_eq!(locker.get_locker_number(), 3);
+}
+
+#[test]
+fn test_round_robin() {
+    let mut cloakroom =
+        cloakroom::Cloakroom::new(3, 5).with_allocation_strategy(allocation::RoundRobin::new());
+    let no_items = CloakroomItems::new();
+
+    let mut locker_numbers = Vec::new();
+    for _ in 0..5 {
+        let locker = find_free_locker(&mut cloakroom, &no_items);
+        locker_numbers.push(locker.get_locker_number());
+        cloakroom.vacate_locker(locker).unwrap();
+    }
+    assert_eq!(locker_numbers, vec![1, 2, 3, 1, 2]);
+
+    // Lockers that are in use are skipped.
+    let _locker3 = find_free_locker(&mut cloakroom, &no_items);
+    let locker = find_free_locker(&mut cloakroom, &no_items);
+    assert_eq!(locker.get_locker_number(), 1);
+}
+
+#[test]
+fn test_least_recently_used() {
+    let mut cloakroom =
+        cloakroom::Cloakroom::new(3, 5).with_allocation_strategy(allocation::LeastRecentlyUsed);
+    let no_items = CloakroomItems::new();
+
+    let locker1 = find_free_locker(&mut cloakroom, &no_items);
+    let locker2 = find_free_locker(&mut cloakroom, &no_items);
+    let locker3 = find_free_locker(&mut cloakroom, &no_items);
+    assert_eq!(locker3.get_locker_number(), 3);
+
+    cloakroom.vacate_locker(locker2).unwrap();
+    cloakroom.vacate_locker(locker3).unwrap();
+    cloakroom.vacate_locker(locker1).unwrap();
+
+    let locker = find_free_locker(&mut cloakroom, &no_items);
+    assert_eq!(locker.get_locker_number(), 2);
+    let locker = find_free_locker(&mut cloakroom, &no_items);
+    assert_eq!(locker.get_locker_number(), 3);
+    let locker = find_free_locker(&mut cloakroom, &no_items);
+    assert_eq!(locker.get_locker_number(), 1);
+}
+
+#[test]
+fn test_seeded_random() {
+    fn allocate_all(seed: u64) -> Vec<cloakroom::LockerNumber> {
+        let mut cloakroom = cloakroom::Cloakroom::new(20, 5)
+            .with_allocation_strategy(allocation::SeededRandom::new(seed));
+        let mut locker_numbers = Vec::new();
+        for _ in 0..20 {
+            let locker = find_free_locker(&mut cloakroom, &CloakroomItems::new());
+            locker_numbers.push(locker.get_locker_number());
+        }
+        assert!(matches!(
+            cloakroom.find_free_locker(&CloakroomItems::new()),
+            cloakroom::FreeLockerResult::NoFreeLockers
+        ));
+        locker_numbers
+    }
+
+    // The same seed gives the same choices.
+    let locker_numbers = allocate_all(1);
+    assert_eq!(locker_numbers, allocate_all(1));
+    assert_ne!(locker_numbers, (1..=20).collect::<Vec<_>>());
+
+    // Every locker is used exactly once.
+    let mut sorted = locker_numbers;
+    sorted.sort();
+    assert_eq!(sorted, (1..=20).collect::<Vec<_>>());
+
+    // Only lockers that are big enough are chosen.
+    let mut cloakroom = cloakroom::Cloakroom::with_lockers(mixed_lockers())
+        .with_allocation_strategy(allocation::SeededRandom::new(5));
+    for _ in 0..2 {
+        let locker = find_free_locker(&mut cloakroom, &items_with_coats(8));
+        assert!(locker.get_max_space() >= 8);
+    }
+}
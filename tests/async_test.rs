@@ -0,0 +1,299 @@
+#![cfg(feature = "async")]
+
+extern crate cloakroom_model;
+
+use cloakroom_model::cloakroom::queue::PriorityClass;
+use cloakroom_model::cloakroom::reservation::ReservationTarget;
+use cloakroom_model::cloakroom::shared::SharedCloakroom;
+use cloakroom_model::cloakroom::{Cloakroom, CloakroomError, FreeLockerResult, Key, LockerSpec};
+use cloakroom_model::clock::MockClock;
+use cloakroom_model::items::CloakroomItems;
+use std::future::Future;
+use std::pin::pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Wakes a thread blocked in `block_on`.
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Runs a future to completion on the current thread.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut context = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+/// Counts the times it is woken.
+struct CountingWaker(AtomicUsize);
+
+impl Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn items(category: &str, num_items: u8) -> CloakroomItems {
+    let mut items = CloakroomItems::new();
+    items.set_num_items(category, num_items).unwrap();
+    items
+}
+
+#[test]
+fn test1() {
+    let cloakroom = SharedCloakroom::new(Cloakroom::new(2, 5));
+    let coats = items("coats", 2);
+
+    let key = block_on(async {
+        let mut locker = cloakroom
+            .find_free_locker_async(&coats)
+            .await
+            .into_result()
+            .unwrap();
+        locker.set_items(coats.clone()).unwrap();
+        cloakroom.close_locker_async(locker).await.unwrap()
+    });
+    let stale: Key = key.to_string().parse().unwrap();
+
+    let collected = block_on(async {
+        let locker = cloakroom.open_locker_async(key).await.unwrap();
+        cloakroom.vacate_locker_async(locker).await.unwrap()
+    });
+    assert_eq!(collected, coats);
+    assert_eq!(
+        block_on(cloakroom.open_locker_async(stale)).err(),
+        Some(CloakroomError::UnknownLocker(1))
+    );
+
+    // Waiting only gives up when no locker could ever hold the items.
+    assert!(matches!(
+        block_on(cloakroom.wait_for_free_locker(&items("coats", 6), PriorityClass::Standard)),
+        FreeLockerResult::NoFreeLockers
+    ));
+    assert!(matches!(
+        block_on(cloakroom.wait_for_free_locker(&coats, PriorityClass::Standard)),
+        FreeLockerResult::FreeLockerFound(_)
+    ));
+}
+
+#[test]
+fn test2() {
+    // A task waiting for a big locker is only woken when it is served, so
+    // not when a locker too small for its items is vacated.
+    let cloakroom = SharedCloakroom::new(Cloakroom::with_lockers(vec![
+        LockerSpec::new(1),
+        LockerSpec::new(5),
+    ]));
+    let small = cloakroom
+        .find_free_locker(&CloakroomItems::new())
+        .into_result()
+        .unwrap();
+    let big = cloakroom
+        .find_free_locker(&CloakroomItems::new())
+        .into_result()
+        .unwrap();
+    assert_eq!(big.get_locker_number(), 2);
+
+    let waker = Arc::new(CountingWaker(AtomicUsize::new(0)));
+    let context_waker = Waker::from(waker.clone());
+    let mut context = Context::from_waker(&context_waker);
+    let mut waiting =
+        Box::pin(cloakroom.wait_for_free_locker(&items("coats", 3), PriorityClass::Standard));
+    assert!(waiting.as_mut().poll(&mut context).is_pending());
+    assert!(waiting.as_mut().poll(&mut context).is_pending());
+    assert_eq!(cloakroom.lock().get_queue().len(), 1);
+
+    cloakroom.vacate_locker(small).unwrap();
+    assert_eq!(waker.0.load(Ordering::SeqCst), 0);
+    assert!(waiting.as_mut().poll(&mut context).is_pending());
+
+    cloakroom.vacate_locker(big).unwrap();
+    assert_eq!(waker.0.load(Ordering::SeqCst), 1);
+    match waiting.as_mut().poll(&mut context) {
+        Poll::Ready(FreeLockerResult::FreeLockerFound(locker)) => {
+            assert_eq!(locker.get_locker_number(), 2)
+        }
+        _ => panic!("the big locker was not handed out"),
+    }
+
+    // Nothing is left waiting.
+    assert!(cloakroom.lock().get_queue().is_empty());
+    cloakroom
+        .vacate_locker(
+            cloakroom
+                .find_free_locker(&CloakroomItems::new())
+                .into_result()
+                .unwrap(),
+        )
+        .unwrap();
+    assert_eq!(waker.0.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test3() {
+    // Desks on other threads each wait for the only locker, and are given
+    // it in turn as each one vacates it.
+    const NUM_DESKS: usize = 8;
+
+    let cloakroom = SharedCloakroom::new(Cloakroom::new(1, 5));
+    let num_served = Arc::new(AtomicUsize::new(0));
+    let desks: Vec<_> = (0..NUM_DESKS)
+        .map(|_| {
+            let cloakroom = cloakroom.clone();
+            let num_served = num_served.clone();
+            thread::spawn(move || {
+                block_on(async {
+                    let locker = cloakroom
+                        .wait_for_free_locker(&CloakroomItems::new(), PriorityClass::Standard)
+                        .await
+                        .into_result()
+                        .unwrap();
+                    num_served.fetch_add(1, Ordering::SeqCst);
+                    thread::yield_now();
+                    cloakroom.vacate_locker_async(locker).await.unwrap();
+                })
+            })
+        })
+        .collect();
+    for desk in desks {
+        desk.join().unwrap();
+    }
+
+    assert_eq!(num_served.load(Ordering::SeqCst), NUM_DESKS);
+    assert!(cloakroom
+        .find_free_locker(&CloakroomItems::new())
+        .into_result()
+        .is_ok());
+}
+
+#[test]
+fn test4() {
+    // Waiting tasks are served in queue order, after customers with
+    // priority, and never given a locker held back for a reservation.
+    let clock = MockClock::new(UNIX_EPOCH);
+    let cloakroom = SharedCloakroom::new(Cloakroom::new(2, 5).with_clock(clock));
+    let no_items = CloakroomItems::new();
+    let first = cloakroom.find_free_locker(&no_items).into_result().unwrap();
+    let second = cloakroom.find_free_locker(&no_items).into_result().unwrap();
+
+    let waker = Arc::new(CountingWaker(AtomicUsize::new(0)));
+    let context_waker = Waker::from(waker.clone());
+    let mut context = Context::from_waker(&context_waker);
+    let mut standard = Box::pin(cloakroom.wait_for_free_locker(&no_items, PriorityClass::Standard));
+    assert!(standard.as_mut().poll(&mut context).is_pending());
+    let mut left = Box::pin(cloakroom.wait_for_free_locker(&no_items, PriorityClass::Standard));
+    assert!(left.as_mut().poll(&mut context).is_pending());
+    let ticket = cloakroom
+        .lock()
+        .join_queue(&no_items, PriorityClass::Priority)
+        .unwrap();
+    assert_eq!(cloakroom.lock().get_queue().len(), 3);
+
+    // Dropping a future that is waiting leaves the queue.
+    drop(left);
+    assert_eq!(cloakroom.lock().get_queue().len(), 2);
+
+    cloakroom.vacate_locker(first).unwrap();
+    assert_eq!(cloakroom.lock().get_served_tickets(), vec![ticket]);
+    assert_eq!(waker.0.load(Ordering::SeqCst), 0);
+    assert!(standard.as_mut().poll(&mut context).is_pending());
+
+    let reference = cloakroom
+        .lock()
+        .reserve_locker(
+            "Ada",
+            ReservationTarget::Locker(2),
+            UNIX_EPOCH,
+            UNIX_EPOCH + Duration::from_secs(3600),
+        )
+        .unwrap();
+    cloakroom.vacate_locker(second).unwrap();
+    assert_eq!(waker.0.load(Ordering::SeqCst), 0);
+    assert!(standard.as_mut().poll(&mut context).is_pending());
+
+    // Locker 2 is given to the task once the reservation is cancelled.
+    cloakroom.lock().cancel_reservation(reference).unwrap();
+    assert_eq!(waker.0.load(Ordering::SeqCst), 1);
+    match standard.as_mut().poll(&mut context) {
+        Poll::Ready(FreeLockerResult::FreeLockerFound(locker)) => {
+            assert_eq!(locker.get_locker_number(), 2)
+        }
+        _ => panic!("locker 2 was not handed out"),
+    }
+}
+
+#[test]
+fn test5() {
+    // Locking the cloakroom waits for another desk to unlock it without
+    // blocking the thread.
+    let cloakroom = SharedCloakroom::new(Cloakroom::new(1, 5));
+    let waker = Arc::new(CountingWaker(AtomicUsize::new(0)));
+    let context_waker = Waker::from(waker.clone());
+    let mut context = Context::from_waker(&context_waker);
+
+    let no_items = CloakroomItems::new();
+    let guard = cloakroom.lock();
+    let mut finding = Box::pin(cloakroom.find_free_locker_async(&no_items));
+    assert!(finding.as_mut().poll(&mut context).is_pending());
+    assert!(finding.as_mut().poll(&mut context).is_pending());
+    drop(guard);
+    assert_eq!(waker.0.load(Ordering::SeqCst), 1);
+    match finding.as_mut().poll(&mut context) {
+        Poll::Ready(FreeLockerResult::FreeLockerFound(locker)) => {
+            assert_eq!(locker.get_locker_number(), 1)
+        }
+        _ => panic!("no locker was found"),
+    }
+}
+
+#[test]
+fn test6() {
+    // A locker can be opened while another is being inspected, but locking
+    // the whole cloakroom waits until the inspection is over.
+    let cloakroom = SharedCloakroom::new(Cloakroom::new(2, 5));
+    let waker = Arc::new(CountingWaker(AtomicUsize::new(0)));
+    let context_waker = Waker::from(waker.clone());
+    let mut context = Context::from_waker(&context_waker);
+
+    let no_items = CloakroomItems::new();
+    let mut keys: Vec<Key> = (0..2)
+        .map(|_| {
+            let locker = cloakroom.find_free_locker(&no_items).into_result().unwrap();
+            cloakroom.close_locker(locker).unwrap()
+        })
+        .collect();
+    let key = keys.pop().unwrap();
+
+    let mut locking = Box::pin(cloakroom.lock_async());
+    let locker = cloakroom.inspect_locker(1, |_| {
+        let mut opening = Box::pin(cloakroom.open_locker_async(key));
+        let locker = match opening.as_mut().poll(&mut context) {
+            Poll::Ready(locker) => locker.unwrap(),
+            Poll::Pending => panic!("locker 2 was not opened"),
+        };
+        assert!(locking.as_mut().poll(&mut context).is_pending());
+        locker
+    });
+    assert!(waker.0.load(Ordering::SeqCst) >= 1);
+    match locking.as_mut().poll(&mut context) {
+        Poll::Ready(guard) => assert_eq!(guard.get_num_lockers(), 2),
+        Poll::Pending => panic!("the cloakroom was not locked"),
+    }
+    drop(locking);
+    assert_eq!(locker.get_locker_number(), 2);
+    cloakroom.vacate_locker(locker).unwrap();
+}
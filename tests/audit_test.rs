@@ -0,0 +1,131 @@
+extern crate cloakroom_model;
+
+use cloakroom_model::cloakroom::audit::{
+    AuditAction, AuditEvent, InMemoryAuditSink, JsonLinesAuditSink,
+};
+use cloakroom_model::cloakroom::Cloakroom;
+use cloakroom_model::items::CloakroomItems;
+use std::env;
+use std::fs;
+use std::process;
+
+/// Deposits two coats in locker 1, adds an umbrella, collects everything,
+/// and abandons locker 2 while holding a backpack.
+fn run_cloakroom(mut cloakroom: Cloakroom) -> Cloakroom {
+    cloakroom.set_attendant_id(Some("ann"));
+    let mut locker = cloakroom
+        .find_free_locker(&CloakroomItems::new())
+        .into_result()
+        .unwrap();
+    locker.set_num_items("coats", 2).unwrap();
+    let key = cloakroom.close_locker(locker).unwrap();
+
+    cloakroom.set_attendant_id(None);
+    let mut locker = cloakroom.open_locker(key).unwrap();
+    locker.set_num_items("umbrellas", 1).unwrap();
+    let key = cloakroom.close_locker(locker).unwrap();
+
+    let mut locker = cloakroom
+        .find_free_locker(&CloakroomItems::new())
+        .into_result()
+        .unwrap();
+    assert_eq!(locker.get_locker_number(), 2);
+    locker.set_num_items("backpacks", 1).unwrap();
+    drop(locker);
+    let locker = cloakroom.reclaim_locker(2).unwrap();
+    let _ = cloakroom.vacate_locker(locker).unwrap();
+
+    let locker = cloakroom.open_locker(key).unwrap();
+    let _ = cloakroom.vacate_locker(locker).unwrap();
+
+    cloakroom
+}
+
+fn items(coats: u8, umbrellas: u8) -> CloakroomItems {
+    let mut items = CloakroomItems::new();
+    items.set_num_items("coats", coats).unwrap();
+    items.set_num_items("umbrellas", umbrellas).unwrap();
+    items
+}
+
+fn check_history(cloakroom: &Cloakroom) {
+    let history = cloakroom.get_locker_history(1).unwrap();
+    let actions: Vec<&AuditAction> = history.iter().map(AuditEvent::get_action).collect();
+    assert_eq!(
+        actions,
+        vec![
+            &AuditAction::Allocated,
+            &AuditAction::ContentsChanged {
+                before: items(0, 0),
+                after: items(2, 0)
+            },
+            &AuditAction::Closed { items: items(2, 0) },
+            &AuditAction::Opened,
+            &AuditAction::ContentsChanged {
+                before: items(2, 0),
+                after: items(2, 1)
+            },
+            &AuditAction::Closed { items: items(2, 1) },
+            &AuditAction::Opened,
+            &AuditAction::Vacated { items: items(2, 1) },
+        ]
+    );
+    assert_eq!(history[2].get_attendant_id(), Some("ann"));
+    assert_eq!(history[3].get_attendant_id(), None);
+    assert!(history
+        .windows(2)
+        .all(|pair| pair[0].get_timestamp() <= pair[1].get_timestamp()));
+
+    let history = cloakroom.get_locker_history(2).unwrap();
+    let actions: Vec<String> = history
+        .iter()
+        .map(|event| event.get_action().to_string())
+        .collect();
+    assert_eq!(
+        actions,
+        vec![
+            "allocated",
+            "contents changed from (num coats: 0, num backpacks: 0, num umbrellas: 0, num other items: 0) \
+             to (num coats: 0, num backpacks: 1, num umbrellas: 0, num other items: 0)",
+            "abandoned holding (num coats: 0, num backpacks: 1, num umbrellas: 0, num other items: 0)",
+            "reclaimed",
+            "vacated, removing (num coats: 0, num backpacks: 1, num umbrellas: 0, num other items: 0)",
+        ]
+    );
+}
+
+#[test]
+fn test1() {
+    let cloakroom = run_cloakroom(Cloakroom::new(3, 5).with_audit_sink(InMemoryAuditSink::new()));
+    check_history(&cloakroom);
+    assert!(cloakroom.get_locker_history(3).unwrap().is_empty());
+
+    // Without a sink nothing is recorded.
+    let cloakroom = run_cloakroom(Cloakroom::new(3, 5));
+    assert!(cloakroom.get_locker_history(1).unwrap().is_empty());
+}
+
+#[test]
+fn test2() {
+    let path = env::temp_dir().join(format!("cloakroom_audit_test_{}.jsonl", process::id()));
+    let _ = fs::remove_file(&path);
+
+    let sink = JsonLinesAuditSink::open(&path).unwrap();
+    let mut cloakroom = run_cloakroom(Cloakroom::new(3, 5).with_audit_sink(sink));
+    assert!(cloakroom.take_audit_error().is_none());
+    check_history(&cloakroom);
+
+    // The log is appended to, one line per event, when it is reopened.
+    let sink = JsonLinesAuditSink::open(&path).unwrap();
+    let mut cloakroom = Cloakroom::new(3, 5).with_audit_sink(sink);
+    let _locker = cloakroom.find_free_locker(&CloakroomItems::new());
+    assert_eq!(cloakroom.get_locker_history(1).unwrap().len(), 9);
+    let log = fs::read_to_string(&path).unwrap();
+    assert_eq!(log.lines().count(), 14);
+    assert!(log.lines().all(|line| line.starts_with('{')));
+
+    fs::write(&path, "{\"timestamp_ms\":0}\n").unwrap();
+    assert!(cloakroom.get_locker_history(1).is_err());
+
+    let _ = fs::remove_file(&path);
+}
@@ -1,16 +1,18 @@
 extern crate cloakroom_model;
 
 use cloakroom_model::cloakroom;
-use cloakroom_model::items::CloakroomItems;
+use cloakroom_model::cloakroom::lost_key::AdminCredential;
+use cloakroom_model::clock::MockClock;
+use cloakroom_model::items::{CloakroomItems, ItemCatalogue, ItemCategory};
+use cloakroom_model::pricing::Tariff;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
 
 fn find_free_locker(cloakroom: &mut cloakroom::Cloakroom) -> Result<cloakroom::Locker, ()> {
-    match cloakroom.find_free_locker() {
+    match cloakroom.find_free_locker(&CloakroomItems::new()) {
         cloakroom::FreeLockerResult::FreeLockerFound(locker) => Ok(locker),
 
-        cloakroom::FreeLockerResult::NoFreeLockers => {
-            assert!(false);
-            Err(())
-        }
+        cloakroom::FreeLockerResult::NoFreeLockers => panic!("no free lockers"),
     }
 }
 
@@ -21,23 +23,15 @@ fn open_locker(
     match cloakroom.open_locker(key) {
         Ok(locker) => Ok(locker),
 
-        Err(err_str) => {
-            assert!(false, "{}", err_str);
-            Err(())
-        }
+        Err(err) => panic!("{}", err),
     }
 }
 
 fn test1_items(items: &CloakroomItems) {
-    assert!(matches!(
-        items,
-        CloakroomItems {
-            num_coats: 1,
-            num_backpacks: 1,
-            num_umbrellas: 0,
-            num_other_items: 6
-        }
-    ));
+    assert_eq!(items.get_num_items("coats"), Some(1));
+    assert_eq!(items.get_num_items("backpacks"), Some(1));
+    assert_eq!(items.get_num_items("umbrellas"), Some(0));
+    assert_eq!(items.get_num_items("other_items"), Some(6));
 
     assert_eq!(items.get_total_num_items(), 8);
 
@@ -74,28 +68,26 @@ fn test1() -> Result<(), ()> {
     let mut locker = find_free_locker(&mut cloakroom)?;
     assert!(matches!(
         cloakroom.get_locker_state(locker.get_locker_number()),
-        cloakroom::LockerState::ContentsBeingChanged
+        cloakroom::LockerState::ContentsBeingChanged(_)
     ));
 
-    assert!(locker.set_num_backpacks(1).is_ok());
-    assert!(locker.set_num_coats(1).is_ok());
-    assert!(locker.set_num_other_items(6).is_ok());
+    assert!(locker.set_num_items("backpacks", 1).is_ok());
+    assert!(locker.set_num_items("coats", 1).is_ok());
+    assert!(locker.set_num_items("other_items", 6).is_ok());
 
     // Cannot add any more items because locker is full.
-    assert!(locker.set_num_umbrellas(1).is_err());
+    assert!(locker.set_num_items("umbrellas", 1).is_err());
 
     test1_items(locker.get_items());
 
     let locker_number = locker.get_locker_number();
-    let key = cloakroom.close_locker(locker);
+    let key = cloakroom.close_locker(locker).unwrap();
     match cloakroom.get_locker_state(locker_number) {
-        cloakroom::LockerState::Closed(items) => {
+        cloakroom::LockerState::Closed(items, _) => {
             test1_items(items);
         }
 
-        _ => {
-            assert!(false);
-        }
+        _ => panic!("locker is not closed"),
     }
 
     let locker = open_locker(&mut cloakroom, key)?;
@@ -103,10 +95,10 @@ fn test1() -> Result<(), ()> {
 
     assert!(matches!(
         cloakroom.get_locker_state(locker_number),
-        cloakroom::LockerState::ContentsBeingChanged
+        cloakroom::LockerState::ContentsBeingChanged(_)
     ));
 
-    let items = cloakroom.vacate_locker(locker);
+    let items = cloakroom.vacate_locker(locker).unwrap();
     test1_items(&items);
 
     assert!(matches!(
@@ -126,22 +118,22 @@ fn test2() -> Result<(), ()> {
 
     // Deposit some items.
     let mut locker = find_free_locker(&mut cloakroom)?;
-    assert!(locker.set_num_coats(3).is_ok());
-    let key = cloakroom.close_locker(locker);
+    assert!(locker.set_num_items("coats", 3).is_ok());
+    let key = cloakroom.close_locker(locker).unwrap();
 
     // Change locker contents.
     let mut locker = open_locker(&mut cloakroom, key)?;
 
-    assert!(locker.set_num_other_items(11).is_err());
+    assert!(locker.set_num_items("other_items", 11).is_err());
 
-    assert!(locker.set_num_coats(2).is_ok());
-    assert!(locker.set_num_other_items(7).is_ok());
+    assert!(locker.set_num_items("coats", 2).is_ok());
+    assert!(locker.set_num_items("other_items", 7).is_ok());
 
-    let key = cloakroom.close_locker(locker);
+    let key = cloakroom.close_locker(locker).unwrap();
 
     // Collect items.
     let locker = open_locker(&mut cloakroom, key)?;
-    let items = cloakroom.vacate_locker(locker);
+    let items = cloakroom.vacate_locker(locker).unwrap();
 
     assert_eq!(items.get_total_num_items(), 9);
 
@@ -160,19 +152,19 @@ fn test3() -> Result<(), ()> {
     let mut cloakroom = cloakroom::Cloakroom::new(num_lockers, max_items_per_locker);
 
     let mut customer1_locker = find_free_locker(&mut cloakroom)?;
-    assert!(customer1_locker.set_num_umbrellas(1).is_ok());
-    let customer1_key = cloakroom.close_locker(customer1_locker);
+    assert!(customer1_locker.set_num_items("umbrellas", 1).is_ok());
+    let customer1_key = cloakroom.close_locker(customer1_locker).unwrap();
 
     let mut _customer2_locker = find_free_locker(&mut cloakroom)?;
 
     // Cloakroom should now be full.
     assert!(matches!(
-        cloakroom.find_free_locker(),
+        cloakroom.find_free_locker(&CloakroomItems::new()),
         cloakroom::FreeLockerResult::NoFreeLockers
     ));
 
     let customer1_locker = open_locker(&mut cloakroom, customer1_key)?;
-    let items = cloakroom.vacate_locker(customer1_locker);
+    let items = cloakroom.vacate_locker(customer1_locker).unwrap();
 
     assert_eq!(items.get_total_num_items(), 1);
 
@@ -197,18 +189,18 @@ fn test4() -> Result<(), ()> {
 
     // The only locker is now in use.
     assert!(matches!(
-        cloakroom.find_free_locker(),
+        cloakroom.find_free_locker(&CloakroomItems::new()),
         cloakroom::FreeLockerResult::NoFreeLockers
     ));
 
-    assert!(customer1_locker.set_num_umbrellas(2).is_ok());
-    assert!(customer1_locker.set_num_other_items(4).is_ok());
-    assert!(customer1_locker.set_num_coats(3).is_ok());
-    assert!(customer1_locker.set_num_backpacks(1).is_ok());
+    assert!(customer1_locker.set_num_items("umbrellas", 2).is_ok());
+    assert!(customer1_locker.set_num_items("other_items", 4).is_ok());
+    assert!(customer1_locker.set_num_items("coats", 3).is_ok());
+    assert!(customer1_locker.set_num_items("backpacks", 1).is_ok());
 
     // Customer has change of heart and decides to vacate locker.
     let locker_number = customer1_locker.get_locker_number();
-    let items = cloakroom.vacate_locker(customer1_locker);
+    let items = cloakroom.vacate_locker(customer1_locker).unwrap();
     assert!(matches!(
         cloakroom.get_locker_state(locker_number),
         cloakroom::LockerState::Free
@@ -225,3 +217,373 @@ fn test4() -> Result<(), ()> {
 
     Ok(())
 }
+
+#[test]
+fn test5() -> Result<(), ()> {
+    //  Create cloakroom.
+    let num_lockers = 1;
+    let max_items_per_locker = 5;
+    let mut cloakroom = cloakroom::Cloakroom::new(num_lockers, max_items_per_locker);
+
+    let mut locker = find_free_locker(&mut cloakroom)?;
+    assert!(locker.set_num_items("coats", 2).is_ok());
+    assert_eq!(
+        locker.set_num_items("backpacks", 4),
+        Err(cloakroom::CloakroomError::InsufficientSpace {
+            requested: 4,
+            available: 3
+        })
+    );
+
+    assert_eq!(
+        locker.set_num_items("helmets", 1),
+        Err(cloakroom::CloakroomError::UnknownItemCategory(
+            "helmets".to_string()
+        ))
+    );
+
+    // Replacing the coats frees up their space.
+    assert!(locker.set_num_items("coats", 5).is_ok());
+
+    assert!(matches!(
+        cloakroom
+            .find_free_locker(&CloakroomItems::new())
+            .into_result(),
+        Err(cloakroom::CloakroomError::NoFreeLockers)
+    ));
+
+    let locker_number = locker.get_locker_number();
+    let key = cloakroom.close_locker(locker).unwrap();
+    let locker = open_locker(&mut cloakroom, key)?;
+    let _ = cloakroom.vacate_locker(locker).unwrap();
+
+    assert_eq!(
+        cloakroom::CloakroomError::UnknownLocker(locker_number).to_string(),
+        "no record found for locker number 1"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test6() -> Result<(), ()> {
+    let mut cloakroom = cloakroom::Cloakroom::new(3, 5);
+
+    let mut locker = find_free_locker(&mut cloakroom)?;
+    assert!(locker.set_num_items("coats", 1).is_ok());
+    let locker_number = locker.get_locker_number();
+    let key = cloakroom.close_locker(locker).unwrap();
+    let stale_ticket = key.to_string();
+
+    // Reopening and reclosing the locker issues a new key.
+    let locker = open_locker(&mut cloakroom, key)?;
+    let key = cloakroom.close_locker(locker).unwrap();
+    assert_ne!(key.to_string(), stale_ticket);
+
+    let stale_key: cloakroom::Key = stale_ticket.parse().map_err(|_| ())?;
+    assert!(matches!(
+        cloakroom.open_locker(stale_key),
+        Err(cloakroom::CloakroomError::InvalidKey(n)) if n == locker_number
+    ));
+
+    // A guessed key is rejected too.
+    let forged_key: cloakroom::Key = format!("{}-{}", locker_number, "0".repeat(32))
+        .parse()
+        .map_err(|_| ())?;
+    assert!(matches!(
+        cloakroom.open_locker(forged_key),
+        Err(cloakroom::CloakroomError::InvalidKey(_))
+    ));
+
+    // The locker is still closed, and the current key opens it.
+    assert!(matches!(
+        cloakroom.get_locker_state(locker_number),
+        cloakroom::LockerState::Closed(_, _)
+    ));
+    let _locker = open_locker(&mut cloakroom, key)?;
+
+    assert!("1".parse::<cloakroom::Key>().is_err());
+    assert!("x-00".parse::<cloakroom::Key>().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test7() -> Result<(), ()> {
+    let catalogue = ItemCatalogue::from_categories(vec![
+        ItemCategory::new("umbrellas").with_weight_grams(500),
+        ItemCategory::new("suitcases")
+            .with_space(4)
+            .with_weight_grams(15_000),
+    ])
+    .map_err(|_| ())?;
+    let mut cloakroom = cloakroom::Cloakroom::with_lockers(vec![
+        cloakroom::LockerSpec::new(10).with_max_weight_grams(10_000),
+        cloakroom::LockerSpec::new(10).with_max_weight_grams(40_000),
+    ])
+    .with_item_catalogue(Arc::new(catalogue));
+
+    // Suitcases take up more space than umbrellas.
+    let mut locker = find_free_locker(&mut cloakroom)?;
+    assert!(locker.set_num_items("umbrellas", 6).is_ok());
+    assert_eq!(locker.get_used_space(), 6);
+    assert_eq!(
+        locker.set_num_items("suitcases", 2),
+        Err(cloakroom::CloakroomError::InsufficientSpace {
+            requested: 8,
+            available: 4
+        })
+    );
+
+    // A single suitcase fits, but is too heavy for the first locker.
+    assert_eq!(
+        locker.set_num_items("suitcases", 1),
+        Err(cloakroom::CloakroomError::InsufficientWeight {
+            requested_grams: 15_000,
+            available_grams: 7_000
+        })
+    );
+    assert_eq!(
+        locker.get_items().to_string(),
+        "num umbrellas: 6, num suitcases: 0"
+    );
+    assert_eq!(
+        cloakroom::CloakroomError::InsufficientWeight {
+            requested_grams: 15_000,
+            available_grams: 7_000
+        }
+        .to_string(),
+        "locker weight limit would be exceeded (15000 g requested, 7000 g available)"
+    );
+    let _ = cloakroom.vacate_locker(locker).unwrap();
+
+    // Lockers are only allocated if they can take the weight.
+    let mut items = CloakroomItems::from_catalogue(cloakroom.get_item_catalogue().clone());
+    items.set_num_items("suitcases", 1).map_err(|_| ())?;
+    let mut locker = cloakroom
+        .find_free_locker(&items)
+        .into_result()
+        .map_err(|_| ())?;
+    assert_eq!(locker.get_locker_number(), 2);
+    assert!(locker.set_items(items).is_ok());
+    assert_eq!(locker.get_total_weight_grams(), 15_000);
+
+    Ok(())
+}
+
+#[test]
+fn test8() -> Result<(), ()> {
+    let mut cloakroom = cloakroom::Cloakroom::new(3, 5);
+
+    let mut locker = find_free_locker(&mut cloakroom)?;
+    assert!(locker.set_num_items("coats", 2).is_ok());
+    let key = cloakroom.close_locker(locker).unwrap();
+    let mut locker = open_locker(&mut cloakroom, key)?;
+    assert!(locker.set_num_items("umbrellas", 1).is_ok());
+
+    // Dropping the locker abandons it, along with its contents.
+    drop(locker);
+    assert!(matches!(
+        cloakroom.get_locker_state(1),
+        cloakroom::LockerState::Abandoned(_)
+    ));
+    assert_eq!(cloakroom.get_abandoned_lockers(), vec![1]);
+
+    // Abandoned lockers are neither allocated nor opened.
+    let locker = find_free_locker(&mut cloakroom)?;
+    assert_eq!(locker.get_locker_number(), 2);
+    let key = cloakroom.close_locker(locker).unwrap();
+    assert_eq!(
+        cloakroom.reclaim_locker(2).err(),
+        Some(cloakroom::CloakroomError::LockerNotAbandoned(2))
+    );
+    assert_eq!(
+        cloakroom.reclaim_locker(3).err(),
+        Some(cloakroom::CloakroomError::UnknownLocker(3))
+    );
+    assert_eq!(
+        cloakroom::CloakroomError::LockerNotAbandoned(2).to_string(),
+        "locker number 2 has not been abandoned"
+    );
+
+    // Reclaiming the locker recovers its contents.
+    let locker = cloakroom.reclaim_locker(1).map_err(|_| ())?;
+    assert_eq!(
+        locker.get_items().to_string(),
+        "num coats: 2, num backpacks: 0, num umbrellas: 1, num other items: 0"
+    );
+    assert!(matches!(
+        cloakroom.get_locker_state(1),
+        cloakroom::LockerState::ContentsBeingChanged(_)
+    ));
+    let _ = cloakroom.vacate_locker(locker).unwrap();
+    assert!(cloakroom.get_abandoned_lockers().is_empty());
+    assert!(cloakroom.open_locker(key).is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn test9() -> Result<(), ()> {
+    let credential = AdminCredential::new("letmein");
+    let mut cloakroom = cloakroom::Cloakroom::new(2, 5);
+
+    let mut locker = find_free_locker(&mut cloakroom)?;
+    assert!(locker.set_num_items("backpacks", 2).is_ok());
+    let lost_key = cloakroom.close_locker(locker).unwrap();
+    let mut description = CloakroomItems::new();
+    description.set_num_items("backpacks", 2).map_err(|_| ())?;
+
+    // The procedure cannot be used until the cloakroom has a credential.
+    assert_eq!(
+        cloakroom
+            .open_locker_without_key(1, &credential, &description, None)
+            .err(),
+        Some(cloakroom::CloakroomError::InvalidAdminCredential)
+    );
+    let mut cloakroom = cloakroom.with_admin_credential(credential.clone());
+    assert_eq!(
+        cloakroom
+            .open_locker_without_key(1, &AdminCredential::new("letmeout"), &description, None)
+            .err(),
+        Some(cloakroom::CloakroomError::InvalidAdminCredential)
+    );
+    assert_eq!(
+        cloakroom
+            .open_locker_without_key(2, &credential, &description, None)
+            .err(),
+        Some(cloakroom::CloakroomError::UnknownLocker(2))
+    );
+    assert_eq!(
+        cloakroom::CloakroomError::ContentsDoNotMatch(1).to_string(),
+        "description does not match the contents of locker number 1"
+    );
+    assert!(cloakroom.get_lost_key_log().is_empty());
+
+    let locker = cloakroom
+        .open_locker_without_key(1, &credential, &description, Some(1000))
+        .map_err(|_| ())?;
+    assert_eq!(
+        cloakroom
+            .open_locker_without_key(1, &credential, &description, None)
+            .err(),
+        Some(cloakroom::CloakroomError::LockerNotClosed(1))
+    );
+    let items = cloakroom.vacate_locker(locker).unwrap();
+    assert_eq!(items.get_num_items("backpacks"), Some(2));
+
+    let log = cloakroom.get_lost_key_log();
+    assert_eq!(log.len(), 1);
+    assert_eq!(log[0].get_locker_number(), 1);
+    assert_eq!(log[0].get_items(), &items);
+    assert_eq!(log[0].get_fee_pence(), Some(1000));
+
+    // The lost key stays invalid once the locker is used again.
+    let locker = find_free_locker(&mut cloakroom)?;
+    let _key = cloakroom.close_locker(locker).unwrap();
+    assert_eq!(
+        cloakroom.open_locker(lost_key).err(),
+        Some(cloakroom::CloakroomError::InvalidKey(1))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test10() -> Result<(), ()> {
+    // Midday on 1 January 2024, UTC.
+    let start = UNIX_EPOCH + Duration::from_secs(1_704_110_400);
+    let minutes = |n: u64| Duration::from_secs(n * 60);
+    let clock = MockClock::new(start);
+    let tariff = Tariff::new()
+        .with_storage_charge(100)
+        .with_overnight_surcharge(500, 3);
+    let mut cloakroom = cloakroom::Cloakroom::new(2, 5)
+        .with_clock(clock.clone())
+        .with_tariff(tariff);
+
+    let times = |cloakroom: &cloakroom::Cloakroom| match cloakroom.get_locker_state(1) {
+        cloakroom::LockerState::Closed(_, times)
+        | cloakroom::LockerState::ContentsBeingChanged(times)
+        | cloakroom::LockerState::Abandoned(times) => times,
+        _ => panic!("locker is not in use"),
+    };
+
+    let mut locker = find_free_locker(&mut cloakroom)?;
+    assert_eq!(times(&cloakroom).get_deposited_at(), None);
+    assert_eq!(times(&cloakroom).get_last_accessed_at(), start);
+
+    clock.advance(minutes(5));
+    assert!(locker.set_num_items("coats", 1).is_ok());
+    let key = cloakroom.close_locker(locker).unwrap();
+    let deposited_at = start + minutes(5);
+    assert_eq!(times(&cloakroom).get_deposited_at(), Some(deposited_at));
+    assert_eq!(times(&cloakroom).get_last_accessed_at(), deposited_at);
+
+    // Reopening the locker does not change when it was deposited in.
+    clock.advance(minutes(60));
+    let locker = open_locker(&mut cloakroom, key)?;
+    clock.advance(minutes(1));
+    let key = cloakroom.close_locker(locker).unwrap();
+    assert_eq!(times(&cloakroom).get_deposited_at(), Some(deposited_at));
+    assert_eq!(
+        times(&cloakroom).get_last_accessed_at(),
+        deposited_at + minutes(61)
+    );
+
+    // Collected the next morning at 09:05, 21 hours after being deposited.
+    clock.set(deposited_at + minutes(21 * 60));
+    let locker = open_locker(&mut cloakroom, key)?;
+    let (_, invoice) = cloakroom.checkout_locker(locker).unwrap();
+    assert_eq!(
+        invoice.to_string(),
+        "Invoice for locker number 1\n  \
+         storage, 21 hours @ 1.00                             21.00\n  \
+         overnight surcharge, 1 night @ 5.00                   5.00\n  \
+         Total                                                26.00"
+    );
+
+    // Nothing is charged for storage in a locker that was never closed.
+    let mut locker = find_free_locker(&mut cloakroom)?;
+    clock.advance(minutes(1));
+    assert!(locker.set_num_items("umbrellas", 1).is_ok());
+    let (_, invoice) = cloakroom.checkout_locker(locker).unwrap();
+    assert_eq!(invoice.get_total_pence(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test11() -> Result<(), ()> {
+    // A locker handed out by another cloakroom is refused, and left
+    // abandoned in the cloakroom that handed it out.
+    let mut cloakroom1 = cloakroom::Cloakroom::new(2, 5);
+    let mut cloakroom2 = cloakroom::Cloakroom::new(2, 5);
+    let locker = find_free_locker(&mut cloakroom1)?;
+    assert_eq!(
+        cloakroom2.close_locker(locker).err(),
+        Some(cloakroom::CloakroomError::LockerNotHandedOut(1))
+    );
+    assert!(matches!(
+        cloakroom2.get_locker_state(1),
+        cloakroom::LockerState::Free
+    ));
+    assert_eq!(cloakroom1.get_abandoned_lockers(), vec![1]);
+
+    // So is one handed out before the cloakroom was saved and restored.
+    let locker = find_free_locker(&mut cloakroom2)?;
+    let mut state = Vec::new();
+    cloakroom2.save(&mut state, &[]).unwrap();
+    let (mut restored, _) = cloakroom::Cloakroom::load(state.as_slice()).unwrap();
+    assert_eq!(
+        restored.vacate_locker(locker).err(),
+        Some(cloakroom::CloakroomError::LockerNotHandedOut(1))
+    );
+    let locker = find_free_locker(&mut cloakroom1)?;
+    assert_eq!(
+        restored.checkout_locker(locker).err(),
+        Some(cloakroom::CloakroomError::LockerNotHandedOut(2))
+    );
+
+    Ok(())
+}
@@ -0,0 +1,177 @@
+extern crate cloakroom_model;
+
+use cloakroom_model::cloakroom::audit::{AuditAction, InMemoryAuditSink};
+use cloakroom_model::cloakroom::{Cloakroom, CloakroomError, Key, LockerState};
+use cloakroom_model::clock::{Clock, MockClock};
+use cloakroom_model::items::CloakroomItems;
+use cloakroom_model::lost_property::{ClaimReference, IntakeReason};
+use cloakroom_model::pricing::Tariff;
+use std::time::{Duration, UNIX_EPOCH};
+
+fn hours(n: u64) -> Duration {
+    Duration::from_secs(n * 60 * 60)
+}
+
+fn deposit(cloakroom: &mut Cloakroom, num_coats: u8) -> Key {
+    let mut locker = cloakroom
+        .find_free_locker(&CloakroomItems::new())
+        .into_result()
+        .unwrap();
+    locker.set_num_items("coats", num_coats).unwrap();
+    cloakroom.close_locker(locker).unwrap()
+}
+
+#[test]
+fn test1() {
+    let clock = MockClock::new(UNIX_EPOCH + hours(24 * 365));
+    let mut cloakroom = Cloakroom::new(3, 5)
+        .with_clock(clock.clone())
+        .with_tariff(Tariff::new().with_storage_charge(10))
+        .with_audit_sink(InMemoryAuditSink::new());
+
+    // Nothing is overdue without a maximum storage duration.
+    let key1 = deposit(&mut cloakroom, 1);
+    clock.advance(hours(100));
+    assert!(cloakroom.get_overdue_lockers().is_empty());
+
+    let mut cloakroom = cloakroom.with_max_storage_duration(hours(24));
+    let key2 = deposit(&mut cloakroom, 2);
+    let locker = cloakroom
+        .find_free_locker(&CloakroomItems::new())
+        .into_result()
+        .unwrap();
+    clock.advance(hours(24));
+    assert_eq!(cloakroom.get_overdue_lockers(), vec![1]);
+    assert_eq!(
+        cloakroom.move_to_lost_property(2),
+        Err(CloakroomError::LockerNotOverdue(2))
+    );
+    assert_eq!(
+        cloakroom.move_to_lost_property(3),
+        Err(CloakroomError::LockerNotClosed(3))
+    );
+    cloakroom.vacate_locker(locker).unwrap();
+
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(cloakroom.get_overdue_lockers(), vec![1, 2]);
+    let claim1 = cloakroom.move_to_lost_property(1).unwrap();
+    let claim2 = cloakroom.move_to_lost_property(2).unwrap();
+    assert_eq!(claim1.to_string(), "C000001");
+    assert_eq!(claim2, "C000002".parse::<ClaimReference>().unwrap());
+    assert!(cloakroom.get_overdue_lockers().is_empty());
+    assert!(matches!(cloakroom.get_locker_state(1), LockerState::Free));
+
+    let bundles = cloakroom.get_lost_property().get_bundles();
+    assert_eq!(bundles.len(), 2);
+    assert_eq!(bundles[1].get_claim_reference(), claim2);
+    assert_eq!(bundles[1].get_locker_number(), Some(2));
+    assert_eq!(bundles[1].get_reason(), IntakeReason::Overdue);
+    assert_eq!(bundles[1].get_items().get_num_items("coats"), Some(2));
+    assert_eq!(bundles[1].get_taken_in_at(), clock.now());
+
+    // The locker can be used again, and its new key does not redeem the
+    // claim, nor the old key open it.
+    let key3 = deposit(&mut cloakroom, 3);
+    assert_eq!(key3.get_locker_number(), 1);
+    assert!(cloakroom.find_claim(&key3).is_none());
+    let ticket1 = key1.to_string();
+    assert_eq!(
+        cloakroom.open_locker(key1).err(),
+        Some(CloakroomError::InvalidKey(1))
+    );
+
+    // The old key redeems the claim, with storage charged until the items
+    // were moved.
+    clock.advance(hours(48));
+    let key1: Key = ticket1.parse().unwrap();
+    let (items, invoice) = cloakroom.redeem_claim(key1).unwrap();
+    assert_eq!(items.get_num_items("coats"), Some(1));
+    assert_eq!(invoice.get_total_pence(), 125 * 10);
+    let key1: Key = ticket1.parse().unwrap();
+    assert_eq!(
+        cloakroom.redeem_claim(key1).err(),
+        Some(CloakroomError::NoClaimForKey(1))
+    );
+    assert_eq!(cloakroom.get_lost_property().get_bundles().len(), 1);
+    assert!(cloakroom.find_claim(&key2).is_some());
+
+    let history = cloakroom.get_locker_history(1).unwrap();
+    let actions: Vec<String> = history
+        .iter()
+        .map(|event| event.get_action().to_string())
+        .collect();
+    assert_eq!(
+        actions[3..6],
+        [
+            "overdue, moved to lost property as C000001 holding \
+             (num coats: 1, num backpacks: 0, num umbrellas: 0, num other items: 0)",
+            "allocated",
+            "contents changed from (num coats: 0, num backpacks: 0, num umbrellas: 0, num other items: 0) \
+             to (num coats: 3, num backpacks: 0, num umbrellas: 0, num other items: 0)",
+        ]
+    );
+    assert_eq!(
+        history.last().unwrap().get_action(),
+        &AuditAction::ClaimRedeemed {
+            claim_reference: claim1
+        }
+    );
+
+    // Replaying the events rebuilds the lost-property store.
+    let events = cloakroom.get_audit_events().unwrap();
+    let replayed = Cloakroom::new(3, 5).replay(&events).unwrap();
+    let claims: Vec<ClaimReference> = replayed
+        .get_lost_property()
+        .get_bundles()
+        .iter()
+        .map(|bundle| bundle.get_claim_reference())
+        .collect();
+    assert_eq!(claims, vec![claim2]);
+}
+
+#[test]
+fn test2() {
+    let clock = MockClock::new(UNIX_EPOCH + hours(24 * 365));
+    let mut cloakroom = Cloakroom::new(2, 5)
+        .with_clock(clock.clone())
+        .with_max_storage_duration(hours(1));
+    let key1 = deposit(&mut cloakroom, 1);
+    clock.advance(hours(2));
+    let claim_reference = cloakroom.move_to_lost_property(1).unwrap();
+    let key2 = deposit(&mut cloakroom, 4);
+    assert_eq!(key2.get_locker_number(), 1);
+
+    // The store is saved, and both keys for locker 1 are restored.
+    let mut state = Vec::new();
+    cloakroom.save(&mut state, [&key1, &key2]).unwrap();
+    let state = String::from_utf8(state).unwrap();
+    assert!(state.contains("cloakroom num_lockers=2 next_claim_number=2\n"));
+    assert!(state.contains("\nlost_property claim_reference=C000001 reason=overdue items=coats:1,"));
+    let (mut restored, keys) = Cloakroom::load(state.as_bytes()).unwrap();
+    assert_eq!(restored.get_lost_property(), cloakroom.get_lost_property());
+    let mut keys = keys.into_iter();
+    let key1 = keys.next().unwrap();
+    let key2 = keys.next().unwrap();
+    assert_eq!(
+        restored
+            .find_claim(&key1)
+            .map(|claim| claim.get_claim_reference()),
+        Some(claim_reference)
+    );
+    assert!(restored.redeem_claim(key1).is_ok());
+    assert!(restored.open_locker(key2).is_ok());
+
+    // Claim references are not issued again after a restore.
+    let (mut restored, _) = Cloakroom::load(state.as_bytes()).unwrap();
+    restored = restored
+        .with_clock(clock.clone())
+        .with_max_storage_duration(hours(1));
+    clock.advance(hours(2));
+    assert_eq!(
+        restored.move_to_lost_property(1).unwrap().to_string(),
+        "C000002"
+    );
+
+    let state = state.replace("next_claim_number=2", "next_claim_number=1");
+    assert!(Cloakroom::load(state.as_bytes()).is_err());
+}
@@ -0,0 +1,242 @@
+extern crate cloakroom_model;
+
+use cloakroom_model::cloakroom::audit::InMemoryAuditSink;
+use cloakroom_model::cloakroom::lost_key::AdminCredential;
+use cloakroom_model::cloakroom::{Cloakroom, CloakroomError, LockerState};
+use cloakroom_model::clock::MockClock;
+use cloakroom_model::items::CloakroomItems;
+use cloakroom_model::lost_property::{
+    ClaimReference, IntakeReason, LostPropertyError, LostPropertyQuery, LostPropertyStore,
+};
+use std::time::{Duration, UNIX_EPOCH};
+
+fn days(n: u64) -> Duration {
+    Duration::from_secs(n * 24 * 60 * 60)
+}
+
+fn items(category: &str, num_items: u8) -> CloakroomItems {
+    let mut items = CloakroomItems::new();
+    items.set_num_items(category, num_items).unwrap();
+    items
+}
+
+#[test]
+fn test1() {
+    let mut store = LostPropertyStore::new();
+    let umbrella = store.hand_in(items("umbrellas", 1), "Red umbrella", Some(3), UNIX_EPOCH);
+    let coat = store.hand_in(
+        items("coats", 2),
+        "Two grey coats",
+        None,
+        UNIX_EPOCH + days(1),
+    );
+    assert_eq!(umbrella.to_string(), "C000001");
+    assert_eq!(coat.to_string(), "C000002");
+
+    let claim_references = |query: LostPropertyQuery| -> Vec<ClaimReference> {
+        store
+            .search(&query)
+            .iter()
+            .map(|bundle| bundle.get_claim_reference())
+            .collect()
+    };
+    assert_eq!(
+        claim_references(LostPropertyQuery::new()),
+        vec![umbrella, coat]
+    );
+    assert_eq!(
+        claim_references(LostPropertyQuery::new().with_text("GREY")),
+        vec![coat]
+    );
+    assert_eq!(
+        claim_references(LostPropertyQuery::new().with_locker_number(3)),
+        vec![umbrella]
+    );
+    assert_eq!(
+        claim_references(LostPropertyQuery::new().with_category("coats")),
+        vec![coat]
+    );
+    assert!(claim_references(
+        LostPropertyQuery::new()
+            .with_text("red")
+            .with_reason(IntakeReason::Overdue)
+    )
+    .is_empty());
+
+    let bundle = store.get_bundle(umbrella).unwrap();
+    assert_eq!(bundle.get_reason(), IntakeReason::Found);
+    assert!(!bundle.is_redeemable_with_key());
+    assert_eq!(
+        bundle.to_string(),
+        "C000001 (found, locker number 3) (num coats: 0, num backpacks: 0, num umbrellas: 1, \
+         num other items: 0): Red umbrella"
+    );
+
+    // Without a retention period, nothing is ever due for disposal.
+    assert!(store
+        .get_due_for_disposal(UNIX_EPOCH + days(1000))
+        .is_empty());
+    assert_eq!(
+        store.dispose(umbrella, UNIX_EPOCH + days(1000)),
+        Err(LostPropertyError::NotDueForDisposal(umbrella))
+    );
+
+    let mut store = store.with_retention_days(30);
+    assert!(store.get_due_for_disposal(UNIX_EPOCH + days(30)).is_empty());
+    let due = store.get_due_for_disposal(UNIX_EPOCH + days(30) + Duration::from_secs(1));
+    assert_eq!(due.len(), 1);
+    assert_eq!(due[0].get_claim_reference(), umbrella);
+    assert_eq!(
+        store.dispose(coat, UNIX_EPOCH + days(31)),
+        Err(LostPropertyError::NotDueForDisposal(coat))
+    );
+    assert!(store.dispose(umbrella, UNIX_EPOCH + days(31)).is_ok());
+
+    let bundle = store.claim(coat).unwrap();
+    assert_eq!(bundle.get_items().get_num_items("coats"), Some(2));
+    assert_eq!(
+        store.claim(coat),
+        Err(LostPropertyError::UnknownClaim(coat))
+    );
+    assert!(store.get_bundles().is_empty());
+}
+
+#[test]
+fn test2() {
+    let clock = MockClock::new(UNIX_EPOCH + days(365));
+    let credential = AdminCredential::new("open sesame");
+    let mut cloakroom = Cloakroom::new(2, 5)
+        .with_clock(clock.clone())
+        .with_admin_credential(credential.clone())
+        .with_lost_property_retention_days(30)
+        .with_audit_sink(InMemoryAuditSink::new());
+
+    let mut locker = cloakroom
+        .find_free_locker(&CloakroomItems::new())
+        .into_result()
+        .unwrap();
+    locker.set_num_items("backpacks", 1).unwrap();
+    let key = cloakroom.close_locker(locker).unwrap();
+
+    // Any closed locker can be forced open, but only with the credential.
+    assert_eq!(
+        cloakroom.force_open_to_lost_property(1, &AdminCredential::new("guess"), ""),
+        Err(CloakroomError::InvalidAdminCredential)
+    );
+    assert_eq!(
+        cloakroom.force_open_to_lost_property(2, &credential, ""),
+        Err(CloakroomError::UnknownLocker(2))
+    );
+    let forced = cloakroom
+        .force_open_to_lost_property(1, &credential, "Black backpack, 50% full")
+        .unwrap();
+    assert!(matches!(cloakroom.get_locker_state(1), LockerState::Free));
+    let bundle = cloakroom.find_claim(&key).unwrap();
+    assert_eq!(bundle.get_claim_reference(), forced);
+    assert_eq!(bundle.get_reason(), IntakeReason::ForcedOpen);
+    assert_eq!(bundle.get_description(), "Black backpack, 50% full");
+
+    assert_eq!(
+        cloakroom.hand_in_found_items(&items("coats", 1), "Scarf", Some(3)),
+        Err(CloakroomError::UnknownLocker(3))
+    );
+    let found = cloakroom
+        .hand_in_found_items(&items("other_items", 1), "Green  scarf\twith tassels", None)
+        .unwrap();
+
+    // Bundles and descriptions survive a save and restore.
+    let mut state = Vec::new();
+    cloakroom.save(&mut state, [&key]).unwrap();
+    let state = String::from_utf8(state).unwrap();
+    assert!(state.contains("description=Black%20backpack,%2050%25%20full"));
+    let (restored, keys) = Cloakroom::load(state.as_bytes()).unwrap();
+    assert_eq!(
+        restored.get_lost_property().get_bundles(),
+        cloakroom.get_lost_property().get_bundles()
+    );
+    assert!(restored.find_claim(&keys[0]).is_some());
+
+    // Only bundles held for longer than the retention period can be
+    // disposed of.
+    clock.advance(days(10));
+    let later = cloakroom
+        .hand_in_found_items(&items("umbrellas", 1), "", Some(2))
+        .unwrap();
+    clock.advance(days(25));
+    let due: Vec<ClaimReference> = cloakroom
+        .get_lost_property_due_for_disposal()
+        .iter()
+        .map(|bundle| bundle.get_claim_reference())
+        .collect();
+    assert_eq!(due, vec![forced, found]);
+    assert_eq!(
+        cloakroom.dispose_of_lost_property(later),
+        Err(LostPropertyError::NotDueForDisposal(later))
+    );
+    cloakroom.dispose_of_lost_property(forced).unwrap();
+    assert!(cloakroom.find_claim(&key).is_none());
+    let bundle = cloakroom.claim_lost_property(found).unwrap();
+    assert_eq!(bundle.get_description(), "Green  scarf\twith tassels");
+    assert_eq!(
+        cloakroom.claim_lost_property(found).err(),
+        Some(LostPropertyError::UnknownClaim(found))
+    );
+
+    let claims: Vec<ClaimReference> = cloakroom
+        .get_lost_property()
+        .get_bundles()
+        .iter()
+        .map(|bundle| bundle.get_claim_reference())
+        .collect();
+    assert_eq!(claims, vec![later]);
+
+    // Only bundles taken out of lockers are audited, so replaying the events
+    // leaves none in the store.
+    let history = cloakroom.get_locker_history(1).unwrap();
+    assert_eq!(
+        history.last().unwrap().get_action().to_string(),
+        "lost property C000001 disposed of"
+    );
+    let events = cloakroom.get_audit_events().unwrap();
+    let replayed = Cloakroom::new(2, 5).replay(&events).unwrap();
+    assert!(replayed.get_lost_property().get_bundles().is_empty());
+}
+
+#[test]
+fn test3() {
+    // Bundles taken out of overdue lockers can be claimed with the locker's
+    // key after a restore.
+    let state = "cloakroom-state version=1\n\
+                 cloakroom num_lockers=2 next_claim_number=4\n\
+                 category name=coats space=1 weight_grams=0\n\
+                 lockers first=1 last=2 max_space=5\n\
+                 key locker_number=2 token=0123456789abcdef0123456789abcdef\n\
+                 lost_property claim_reference=C000003 reason=overdue items=coats:2 \
+                 taken_in_at_ms=5000 locker_number=2 token=0123456789abcdef0123456789abcdef \
+                 deposited=coats:2 accessed_at_ms=1000 closed_at_ms=1000\n";
+    let (cloakroom, keys) = Cloakroom::load(state.as_bytes()).unwrap();
+    let bundle = cloakroom.find_claim(&keys[0]).unwrap();
+    assert_eq!(bundle.get_claim_reference().to_string(), "C000003");
+    assert_eq!(bundle.get_reason(), IntakeReason::Overdue);
+    assert_eq!(bundle.get_locker_number(), Some(2));
+    assert_eq!(bundle.get_description(), "");
+    assert_eq!(
+        bundle.get_taken_in_at(),
+        UNIX_EPOCH + Duration::from_millis(5000)
+    );
+
+    let mut saved = Vec::new();
+    cloakroom.save(&mut saved, &keys).unwrap();
+    let saved = String::from_utf8(saved).unwrap();
+    assert!(saved.contains(
+        "\nlost_property claim_reference=C000003 reason=overdue items=coats:2 \
+         taken_in_at_ms=5000 locker_number=2 token="
+    ));
+
+    // Bundles with a token must say which locker they came from, and
+    // descriptions must be validly encoded.
+    let state = saved.replace(" locker_number=2 token=", " token=");
+    assert!(Cloakroom::load(state.as_bytes()).is_err());
+    let state = saved.replace(" token=", " description=a%2 token=");
+    assert!(Cloakroom::load(state.as_bytes()).is_err());
+}
@@ -0,0 +1,104 @@
+extern crate cloakroom_model;
+
+use cloakroom_model::cloakroom::audit::InMemoryAuditSink;
+use cloakroom_model::cloakroom::lost_key::AdminCredential;
+use cloakroom_model::cloakroom::{Cloakroom, CloakroomError, LockerState};
+use cloakroom_model::items::CloakroomItems;
+
+#[test]
+fn test1() {
+    let credential = AdminCredential::new("open sesame");
+    let mut cloakroom = Cloakroom::new(3, 5)
+        .with_admin_credential(credential.clone())
+        .with_audit_sink(InMemoryAuditSink::new());
+
+    let mut locker = cloakroom
+        .find_free_locker(&CloakroomItems::new())
+        .into_result()
+        .unwrap();
+    locker.set_num_items("coats", 1).unwrap();
+    let key = cloakroom.close_locker(locker).unwrap();
+
+    assert_eq!(
+        cloakroom.take_out_of_service(2, &AdminCredential::new("guess"), "broken"),
+        Err(CloakroomError::InvalidAdminCredential)
+    );
+    assert_eq!(
+        cloakroom.take_out_of_service(4, &credential, "broken"),
+        Err(CloakroomError::UnknownLocker(4))
+    );
+    assert_eq!(
+        cloakroom.take_out_of_service(1, &credential, "broken"),
+        Err(CloakroomError::LockerNotFree(1))
+    );
+    cloakroom
+        .take_out_of_service(2, &credential, "door sticks")
+        .unwrap();
+    assert_eq!(
+        cloakroom.take_out_of_service(2, &credential, "broken"),
+        Err(CloakroomError::LockerOutOfService(2))
+    );
+    assert_eq!(
+        cloakroom.return_to_service(3, &credential),
+        Err(CloakroomError::LockerNotOutOfService(3))
+    );
+
+    // Free lockers are found around the out-of-service one.
+    let locker = cloakroom
+        .find_free_locker(&CloakroomItems::new())
+        .into_result()
+        .unwrap();
+    assert_eq!(locker.get_locker_number(), 3);
+    assert!(cloakroom
+        .find_free_locker(&CloakroomItems::new())
+        .into_result()
+        .is_err());
+    cloakroom.vacate_locker(locker).unwrap();
+
+    // A closed locker can be taken out of service once its contents have
+    // been moved.
+    cloakroom
+        .force_open_to_lost_property(1, &credential, "")
+        .unwrap();
+    cloakroom.take_out_of_service(1, &credential, "").unwrap();
+    assert_eq!(cloakroom.get_out_of_service_lockers(), vec![1, 2]);
+    assert!(cloakroom.find_claim(&key).is_some());
+
+    // Out-of-service lockers survive a save and restore, and a replay.
+    let mut state = Vec::new();
+    cloakroom.save(&mut state, [&key]).unwrap();
+    let state = String::from_utf8(state).unwrap();
+    assert!(state.contains("\nlocker number=1 state=out_of_service\n"));
+    assert!(state.contains("\nlocker number=2 state=out_of_service reason=door%20sticks\n"));
+    let (mut restored, _) = Cloakroom::load(state.as_bytes()).unwrap();
+    assert!(matches!(
+        restored.get_locker_state(2),
+        LockerState::OutOfService {
+            reason: "door sticks"
+        }
+    ));
+    assert_eq!(
+        restored
+            .find_free_locker(&CloakroomItems::new())
+            .into_result()
+            .unwrap()
+            .get_locker_number(),
+        3
+    );
+
+    let events = cloakroom.get_audit_events().unwrap();
+    let replayed = Cloakroom::new(3, 5).replay(&events).unwrap();
+    assert_eq!(replayed.get_out_of_service_lockers(), vec![1, 2]);
+
+    cloakroom.return_to_service(2, &credential).unwrap();
+    assert!(matches!(cloakroom.get_locker_state(2), LockerState::Free));
+    let history = cloakroom.get_locker_history(2).unwrap();
+    let actions: Vec<String> = history
+        .iter()
+        .map(|event| event.get_action().to_string())
+        .collect();
+    assert_eq!(
+        actions,
+        ["taken out of service: door sticks", "returned to service"]
+    );
+}
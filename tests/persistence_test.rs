@@ -0,0 +1,473 @@
+extern crate cloakroom_model;
+
+use cloakroom_model::cloakroom;
+use cloakroom_model::cloakroom::lost_key::AdminCredential;
+use cloakroom_model::cloakroom::persistence::StateFileError;
+use cloakroom_model::items::{CloakroomItems, ItemCatalogue, ItemCategory};
+use cloakroom_model::pricing::Tariff;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn find_free_locker(cloakroom: &mut cloakroom::Cloakroom) -> cloakroom::Locker {
+    match cloakroom
+        .find_free_locker(&CloakroomItems::new())
+        .into_result()
+    {
+        Ok(locker) => locker,
+        Err(err) => panic!("{}", err),
+    }
+}
+
+fn save_to_string(cloakroom: &cloakroom::Cloakroom, keys: &[cloakroom::Key]) -> String {
+    let mut buf = Vec::new();
+    cloakroom.save(&mut buf, keys).expect("save failed");
+    String::from_utf8(buf).expect("state is not UTF-8")
+}
+
+/// Replaces the secret tokens, and the times at which lockers were used,
+/// which differ on every run, with `T`.
+fn mask_tokens_and_times(state: &str) -> String {
+    state
+        .split('\n')
+        .map(|line| {
+            line.split(' ')
+                .map(|field| {
+                    if field.starts_with("token=") {
+                        "token=T"
+                    } else if field.starts_with("accessed_at_ms=") {
+                        "accessed_at_ms=T"
+                    } else if field.starts_with("closed_at_ms=") {
+                        "closed_at_ms=T"
+                    } else {
+                        field
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn load_from_str(
+    state: &str,
+) -> Result<(cloakroom::Cloakroom, Vec<cloakroom::Key>), StateFileError> {
+    cloakroom::Cloakroom::load(state.as_bytes())
+}
+
+#[test]
+fn test1() {
+    let mut cloakroom = cloakroom::Cloakroom::new(5, 10);
+
+    let mut locker = find_free_locker(&mut cloakroom);
+    assert!(locker.set_num_items("coats", 2).is_ok());
+    assert!(locker.set_num_items("other_items", 3).is_ok());
+    let key1 = cloakroom.close_locker(locker).unwrap();
+
+    let _being_changed = find_free_locker(&mut cloakroom);
+
+    let mut locker = find_free_locker(&mut cloakroom);
+    assert!(locker.set_num_items("umbrellas", 1).is_ok());
+    let key3 = cloakroom.close_locker(locker).unwrap();
+
+    let state = save_to_string(&cloakroom, &[key1, key3]);
+    assert_eq!(
+        mask_tokens_and_times(&state),
+        "cloakroom-state version=1\n\
+         cloakroom num_lockers=5\n\
+         category name=coats space=1 weight_grams=0\n\
+         category name=backpacks space=1 weight_grams=0\n\
+         category name=umbrellas space=1 weight_grams=0\n\
+         category name=other_items space=1 weight_grams=0\n\
+         lockers first=1 last=5 max_space=10\n\
+         locker number=1 state=closed items=coats:2,backpacks:0,umbrellas:0,other_items:3 token=T \
+         deposited=coats:2,backpacks:0,umbrellas:0,other_items:3 accessed_at_ms=T closed_at_ms=T\n\
+         locker number=2 state=being_changed items=coats:0,backpacks:0,umbrellas:0,other_items:0 \
+         deposited=coats:0,backpacks:0,umbrellas:0,other_items:0 accessed_at_ms=T\n\
+         locker number=3 state=closed items=coats:0,backpacks:0,umbrellas:1,other_items:0 token=T \
+         deposited=coats:0,backpacks:0,umbrellas:1,other_items:0 accessed_at_ms=T closed_at_ms=T\n\
+         key locker_number=1 token=T\n\
+         key locker_number=3 token=T\n"
+    );
+
+    let (mut restored, keys) = load_from_str(&state).expect("load failed");
+    assert_eq!(restored.get_num_lockers(), 5);
+    // The locker being changed can only be reclaimed after a restore.
+    assert!(matches!(
+        restored.get_locker_state(2),
+        cloakroom::LockerState::Abandoned(_)
+    ));
+    assert!(matches!(
+        restored.get_locker_state(4),
+        cloakroom::LockerState::Free
+    ));
+
+    // The restored keys open the restored lockers.
+    assert_eq!(keys.len(), 2);
+    for key in keys {
+        let locker = restored.open_locker(key).expect("open failed");
+        let items = restored.vacate_locker(locker).unwrap();
+        match items.get_total_num_items() {
+            5 => assert_eq!(
+                items.to_string(),
+                "num coats: 2, num backpacks: 0, num umbrellas: 0, num other items: 3"
+            ),
+            1 => assert_eq!(
+                items.to_string(),
+                "num coats: 0, num backpacks: 0, num umbrellas: 1, num other items: 0"
+            ),
+            n => panic!("unexpected number of items {}", n),
+        }
+    }
+}
+
+#[test]
+fn test2() {
+    // Comments and blank lines are ignored.
+    let state = "# saved by attendant\n\
+                 cloakroom-state version=1\n\
+                 \n\
+                 cloakroom num_lockers=2\n\
+                 category name=coats space=1 weight_grams=0\n\
+                 lockers first=1 last=2 max_space=5\n";
+    let (cloakroom, keys) = load_from_str(state).expect("load failed");
+    assert_eq!(cloakroom.get_num_lockers(), 2);
+    assert!(keys.is_empty());
+
+    assert!(matches!(
+        load_from_str("cloakroom-state version=2\n"),
+        Err(StateFileError::UnsupportedVersion(2))
+    ));
+
+    assert!(matches!(
+        load_from_str("cloakroom num_lockers=2\n"),
+        Err(StateFileError::Parse { line_number: 1, .. })
+    ));
+
+    // The number of lockers is checked before room is made for them.
+    for num_lockers in &["1000001", "18446744073709551615"] {
+        let state = format!(
+            "cloakroom-state version=1\n\
+             cloakroom num_lockers={}\n\
+             category name=coats space=1 weight_grams=0\n\
+             lockers first=1 last=1 max_space=5\n",
+            num_lockers
+        );
+        assert!(matches!(
+            load_from_str(&state),
+            Err(StateFileError::Parse { line_number: 2, .. })
+        ));
+    }
+
+    let header = "cloakroom-state version=1\n\
+                  cloakroom num_lockers=2\n\
+                  category name=coats space=1 weight_grams=0\n\
+                  lockers first=1 last=2 max_space=5\n";
+
+    // Locker number out of range.
+    let state = format!(
+        "{}locker number=3 state=being_changed items=coats:0 deposited=coats:0 \
+         accessed_at_ms=0\n",
+        header
+    );
+    assert!(matches!(
+        load_from_str(&state),
+        Err(StateFileError::Parse { line_number: 5, .. })
+    ));
+
+    // Too many items for the locker.
+    let state = format!(
+        "{}locker number=1 state=closed items=coats:6 \
+         token=0123456789abcdef0123456789abcdef deposited=coats:6 accessed_at_ms=0\n",
+        header
+    );
+    assert!(matches!(
+        load_from_str(&state),
+        Err(StateFileError::Parse { line_number: 5, .. })
+    ));
+
+    // Key for a locker that is not closed.
+    let state = format!(
+        "{}locker number=1 state=being_changed items=coats:0 deposited=coats:0 \
+         accessed_at_ms=0\n\
+         key locker_number=1 token=0123456789abcdef0123456789abcdef\n",
+        header
+    );
+    assert!(matches!(
+        load_from_str(&state),
+        Err(StateFileError::Parse { line_number: 6, .. })
+    ));
+}
+
+#[test]
+fn test3() {
+    let spec_small = cloakroom::LockerSpec::new(5).with_size_class(cloakroom::SizeClass::Small);
+    let spec_large = cloakroom::LockerSpec::new(20).with_size_class(cloakroom::SizeClass::Large);
+    let mut cloakroom = cloakroom::Cloakroom::with_lockers(vec![
+        spec_small,
+        spec_small,
+        cloakroom::LockerSpec::new(8),
+        spec_large,
+    ]);
+
+    let mut items = CloakroomItems::new();
+    items.set_num_items("backpacks", 12).unwrap();
+    let mut locker = match cloakroom.find_free_locker(&items).into_result() {
+        Ok(locker) => locker,
+        Err(err) => panic!("{}", err),
+    };
+    assert_eq!(locker.get_locker_number(), 4);
+    assert!(locker.set_num_items("backpacks", 12).is_ok());
+    let key = cloakroom.close_locker(locker).unwrap();
+
+    let state = save_to_string(&cloakroom, &[key]);
+    assert_eq!(
+        mask_tokens_and_times(&state),
+        "cloakroom-state version=1\n\
+         cloakroom num_lockers=4\n\
+         category name=coats space=1 weight_grams=0\n\
+         category name=backpacks space=1 weight_grams=0\n\
+         category name=umbrellas space=1 weight_grams=0\n\
+         category name=other_items space=1 weight_grams=0\n\
+         lockers first=1 last=2 max_space=5 size_class=small\n\
+         lockers first=3 last=3 max_space=8\n\
+         lockers first=4 last=4 max_space=20 size_class=large\n\
+         locker number=4 state=closed items=coats:0,backpacks:12,umbrellas:0,other_items:0 token=T \
+         deposited=coats:0,backpacks:12,umbrellas:0,other_items:0 accessed_at_ms=T closed_at_ms=T\n\
+         key locker_number=4 token=T\n"
+    );
+
+    let (restored, keys) = load_from_str(&state).expect("load failed");
+    assert_eq!(keys.len(), 1);
+    for locker_number in 1..=4 {
+        assert_eq!(
+            restored.get_locker_spec(locker_number),
+            cloakroom.get_locker_spec(locker_number)
+        );
+    }
+
+    // Every locker needs a capacity.
+    let state = "cloakroom-state version=1\n\
+                 cloakroom num_lockers=3\n\
+                 category name=coats space=1 weight_grams=0\n\
+                 lockers first=1 last=2 max_space=5\n";
+    assert!(matches!(
+        load_from_str(state),
+        Err(StateFileError::Parse { line_number: 2, .. })
+    ));
+}
+
+#[test]
+fn test4() {
+    let mut cloakroom = cloakroom::Cloakroom::new(2, 5);
+    let locker = find_free_locker(&mut cloakroom);
+    let key = cloakroom.close_locker(locker).unwrap();
+    let ticket = key.to_string();
+
+    // Tokens survive a save and restore, so tickets printed before the
+    // restart still work.
+    let state = save_to_string(&cloakroom, &[key]);
+    let (mut restored, _) = load_from_str(&state).expect("load failed");
+    let key: cloakroom::Key = ticket.parse().expect("invalid ticket");
+    assert!(restored.open_locker(key).is_ok());
+
+    // A key whose token does not match its locker is rejected.
+    let token_pos = state.rfind("token=").expect("no key token") + "token=".len();
+    let mut state = state;
+    let replacement = if &state[token_pos..token_pos + 1] == "0" {
+        "1"
+    } else {
+        "0"
+    };
+    state.replace_range(token_pos..token_pos + 1, replacement);
+    assert!(matches!(
+        load_from_str(&state),
+        Err(StateFileError::Parse { line_number: 9, .. })
+    ));
+}
+
+#[test]
+fn test5() {
+    let catalogue = ItemCatalogue::from_categories(vec![
+        ItemCategory::new("helmets").with_weight_grams(400),
+        ItemCategory::new("prams").with_space(4),
+    ])
+    .unwrap();
+    let mut cloakroom = cloakroom::Cloakroom::with_lockers(vec![
+        cloakroom::LockerSpec::new(5).with_max_weight_grams(2000),
+        cloakroom::LockerSpec::new(5),
+    ])
+    .with_item_catalogue(Arc::new(catalogue.clone()));
+    let mut locker = find_free_locker(&mut cloakroom);
+    assert!(locker.set_num_items("prams", 1).is_ok());
+    let key = cloakroom.close_locker(locker).unwrap();
+
+    // The catalogue is saved with the cloakroom.
+    let state = save_to_string(&cloakroom, &[key]);
+    assert_eq!(
+        mask_tokens_and_times(&state),
+        "cloakroom-state version=1\n\
+         cloakroom num_lockers=2\n\
+         category name=helmets space=1 weight_grams=400\n\
+         category name=prams space=4 weight_grams=0\n\
+         lockers first=1 last=1 max_space=5 max_weight_grams=2000\n\
+         lockers first=2 last=2 max_space=5\n\
+         locker number=1 state=closed items=helmets:0,prams:1 token=T \
+         deposited=helmets:0,prams:1 accessed_at_ms=T closed_at_ms=T\n\
+         key locker_number=1 token=T\n"
+    );
+    let (restored, _) = load_from_str(&state).expect("load failed");
+    assert_eq!(**restored.get_item_catalogue(), catalogue);
+    assert_eq!(restored.get_locker_spec(1), cloakroom.get_locker_spec(1));
+    match restored.get_locker_state(1) {
+        cloakroom::LockerState::Closed(items, _) => {
+            assert_eq!(items.to_string(), "num helmets: 0, num prams: 1")
+        }
+        _ => panic!("locker is not closed"),
+    }
+
+    // Items must be of a category in the catalogue, and fit in the locker.
+    for items in &["items=coats:1", "items=helmets:6", "items=prams:2"] {
+        let state = state.replace("items=helmets:0,prams:1", items);
+        assert!(matches!(
+            load_from_str(&state),
+            Err(StateFileError::Parse { line_number: 7, .. })
+        ));
+    }
+
+    // The catalogue must have at least one category.
+    let state = "cloakroom-state version=1\n\
+                 cloakroom num_lockers=1\n\
+                 lockers first=1 last=1 max_space=5\n";
+    assert!(matches!(
+        load_from_str(state),
+        Err(StateFileError::Parse { line_number: 2, .. })
+    ));
+}
+
+#[test]
+fn test6() {
+    let credential = AdminCredential::new("secret");
+    let mut cloakroom = cloakroom::Cloakroom::new(2, 5).with_admin_credential(credential.clone());
+    for fee_pence in [Some(300), None] {
+        let mut locker = find_free_locker(&mut cloakroom);
+        assert!(locker.set_num_items("coats", 1).is_ok());
+        let locker_number = locker.get_locker_number();
+        let _lost_key = cloakroom.close_locker(locker).unwrap();
+
+        let mut description = CloakroomItems::new();
+        description.set_num_items("coats", 1).unwrap();
+        let locker = cloakroom
+            .open_locker_without_key(locker_number, &credential, &description, fee_pence)
+            .expect("lost-key procedure failed");
+        cloakroom.vacate_locker(locker).unwrap();
+    }
+
+    // The lost-key log is saved, but the credential is not.
+    let state = save_to_string(&cloakroom, &[]);
+    assert_eq!(
+        state,
+        "cloakroom-state version=1\n\
+         cloakroom num_lockers=2\n\
+         category name=coats space=1 weight_grams=0\n\
+         category name=backpacks space=1 weight_grams=0\n\
+         category name=umbrellas space=1 weight_grams=0\n\
+         category name=other_items space=1 weight_grams=0\n\
+         lockers first=1 last=2 max_space=5\n\
+         lost_key locker_number=1 items=coats:1,backpacks:0,umbrellas:0,other_items:0 fee_pence=300\n\
+         lost_key locker_number=1 items=coats:1,backpacks:0,umbrellas:0,other_items:0\n"
+    );
+    let (restored, _) = load_from_str(&state).expect("load failed");
+    assert_eq!(restored.get_lost_key_log(), cloakroom.get_lost_key_log());
+
+    let state = state.replace(
+        "lost_key locker_number=1 items=coats:1,",
+        "lost_key locker_number=3 items=coats:1,",
+    );
+    assert!(matches!(
+        load_from_str(&state),
+        Err(StateFileError::Parse { line_number: 8, .. })
+    ));
+}
+
+#[test]
+fn test7() {
+    let tariff = Tariff::new()
+        .with_deposit_fee("coats", 100)
+        .with_storage_charge(50)
+        .with_lost_key_penalty(1000);
+    let credential = AdminCredential::new("secret");
+    let mut cloakroom = cloakroom::Cloakroom::new(2, 5)
+        .with_tariff(tariff.clone())
+        .with_admin_credential(credential.clone());
+    let mut locker = find_free_locker(&mut cloakroom);
+    assert!(locker.set_num_items("coats", 2).is_ok());
+    let _lost_key = cloakroom.close_locker(locker).unwrap();
+    let mut description = CloakroomItems::new();
+    description.set_num_items("coats", 2).unwrap();
+    let mut locker = cloakroom
+        .open_locker_without_key(1, &credential, &description, None)
+        .expect("lost-key procedure failed");
+    assert!(locker.set_num_items("coats", 1).is_ok());
+    let key = cloakroom.close_locker(locker).unwrap();
+
+    // What the invoice is worked out from survives a save and restore.
+    let state = save_to_string(&cloakroom, &[key]);
+    assert!(mask_tokens_and_times(&state).contains(
+        "locker number=1 state=closed items=coats:1,backpacks:0,umbrellas:0,other_items:0 token=T \
+         deposited=coats:2,backpacks:0,umbrellas:0,other_items:0 accessed_at_ms=T closed_at_ms=T lost_key_fees=-\n"
+    ));
+
+    // Storage is charged from when the locker was first closed.
+    let ninety_minutes_ago = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis()
+        - 90 * 60 * 1000;
+    let closed_at_pos = state.find("closed_at_ms=").unwrap();
+    let closed_at_end = closed_at_pos + state[closed_at_pos..].find(' ').unwrap();
+    let mut state = state;
+    state.replace_range(
+        closed_at_pos..closed_at_end,
+        &format!("closed_at_ms={}", ninety_minutes_ago),
+    );
+    let (restored, mut keys) = load_from_str(&state).expect("load failed");
+    let mut restored = restored.with_tariff(tariff.clone());
+    let locker = restored.open_locker(keys.remove(0)).expect("open failed");
+    let (items, invoice) = restored.checkout_locker(locker).unwrap();
+    assert_eq!(items.get_num_items("coats"), Some(1));
+    let descriptions: Vec<&str> = invoice
+        .get_lines()
+        .iter()
+        .map(|line| line.get_description())
+        .collect();
+    assert_eq!(
+        descriptions,
+        vec!["2 x coats @ 1.00", "storage, 2 hours @ 0.50", "lost key"]
+    );
+    assert_eq!(invoice.get_total_pence(), 200 + 100 + 1000);
+
+    // Every locker in use must give its stay.
+    let state = "cloakroom-state version=1\n\
+                 cloakroom num_lockers=1\n\
+                 category name=coats space=1 weight_grams=0\n\
+                 lockers first=1 last=1 max_space=5\n\
+                 locker number=1 state=closed items=coats:3 token=00000000000000000000000000000001\n\
+                 key locker_number=1 token=00000000000000000000000000000001\n";
+    assert!(matches!(
+        load_from_str(state),
+        Err(StateFileError::Parse { line_number: 5, .. })
+    ));
+
+    // Closed lockers must say when they were first closed.
+    let state = state.replace(
+        "token=00000000000000000000000000000001\nkey",
+        "token=00000000000000000000000000000001 deposited=coats:3 accessed_at_ms=0\nkey",
+    );
+    assert!(matches!(
+        load_from_str(&state),
+        Err(StateFileError::Parse { line_number: 5, .. })
+    ));
+    let state = state.replace("accessed_at_ms=0", "accessed_at_ms=0 closed_at_ms=0");
+    assert!(load_from_str(&state).is_ok());
+}
@@ -0,0 +1,215 @@
+extern crate cloakroom_model;
+
+use cloakroom_model::cloakroom::queue::{PriorityClass, QueueError, QueueTicket};
+use cloakroom_model::cloakroom::{Cloakroom, CloakroomError, LockerSpec, LockerState};
+use cloakroom_model::clock::MockClock;
+use cloakroom_model::items::CloakroomItems;
+use std::time::{Duration, UNIX_EPOCH};
+
+fn coats(num_items: u8) -> CloakroomItems {
+    let mut items = CloakroomItems::new();
+    items.set_num_items("coats", num_items).unwrap();
+    items
+}
+
+fn minutes(n: u64) -> Duration {
+    Duration::from_secs(n * 60)
+}
+
+fn queued_tickets(cloakroom: &Cloakroom) -> Vec<QueueTicket> {
+    cloakroom
+        .get_queue()
+        .iter()
+        .map(|entry| entry.get_ticket())
+        .collect()
+}
+
+#[test]
+fn test1() {
+    let clock = MockClock::new(UNIX_EPOCH);
+    let mut cloakroom = Cloakroom::with_lockers(vec![LockerSpec::new(2), LockerSpec::new(5)])
+        .with_clock(clock.clone());
+    let small = cloakroom.find_free_locker(&coats(0)).into_result().unwrap();
+    let big = cloakroom.find_free_locker(&coats(0)).into_result().unwrap();
+
+    let needs_big = cloakroom
+        .join_queue(&coats(4), PriorityClass::Standard)
+        .unwrap();
+    let standard = cloakroom
+        .join_queue(&coats(1), PriorityClass::Standard)
+        .unwrap();
+    let priority = cloakroom
+        .join_queue(&coats(1), PriorityClass::Priority)
+        .unwrap();
+    assert_eq!(needs_big.to_string(), "Q000001");
+    assert_eq!("Q000003".parse(), Ok(priority));
+    assert_eq!(
+        cloakroom.join_queue(&coats(6), PriorityClass::Priority),
+        Err(CloakroomError::NoLockerBigEnough)
+    );
+    assert_eq!(
+        queued_tickets(&cloakroom),
+        vec![priority, needs_big, standard]
+    );
+    assert_eq!(
+        cloakroom.take_served_locker(needs_big).err(),
+        Some(QueueError::NotServed(needs_big))
+    );
+    let unknown: QueueTicket = "Q000099".parse().unwrap();
+    assert_eq!(
+        cloakroom.get_waiting_time(unknown),
+        Err(QueueError::UnknownTicket(unknown))
+    );
+
+    // The small locker goes to the first customer it can hold, in order of
+    // priority.
+    clock.advance(minutes(5));
+    cloakroom.vacate_locker(small).unwrap();
+    assert_eq!(cloakroom.get_served_tickets(), vec![priority]);
+    assert_eq!(cloakroom.get_served_locker_number(priority), Ok(1));
+    assert!(matches!(
+        cloakroom.get_locker_state(1),
+        LockerState::ContentsBeingChanged(_)
+    ));
+    clock.advance(minutes(1));
+    assert_eq!(cloakroom.get_waiting_time(priority), Ok(minutes(5)));
+    assert_eq!(cloakroom.get_waiting_time(standard), Ok(minutes(6)));
+
+    cloakroom.leave_queue(standard).unwrap();
+    assert_eq!(
+        cloakroom.leave_queue(standard),
+        Err(QueueError::UnknownTicket(standard))
+    );
+    cloakroom.vacate_locker(big).unwrap();
+    assert_eq!(cloakroom.get_served_tickets(), vec![priority, needs_big]);
+    assert!(cloakroom.get_queue().is_empty());
+
+    // A customer who leaves after being served gives up their locker.
+    cloakroom.leave_queue(priority).unwrap();
+    assert!(matches!(cloakroom.get_locker_state(1), LockerState::Free));
+
+    let mut locker = cloakroom.take_served_locker(needs_big).unwrap();
+    assert_eq!(locker.get_locker_number(), 2);
+    locker.set_items(coats(4)).unwrap();
+    let _key = cloakroom.close_locker(locker).unwrap();
+    assert_eq!(
+        cloakroom.get_waiting_time(needs_big),
+        Err(QueueError::UnknownTicket(needs_big))
+    );
+
+    // Customers are served at once if a locker is free.
+    let served_at_once = cloakroom
+        .join_queue(&coats(2), PriorityClass::Standard)
+        .unwrap();
+    assert_eq!(cloakroom.get_served_tickets(), vec![served_at_once]);
+    assert_eq!(cloakroom.get_waiting_time(served_at_once), Ok(minutes(0)));
+}
+
+#[test]
+fn test2() {
+    let clock = MockClock::new(UNIX_EPOCH);
+    let mut cloakroom = Cloakroom::new(2, 5).with_clock(clock.clone());
+    let locker = cloakroom.find_free_locker(&coats(0)).into_result().unwrap();
+    let _key = cloakroom.close_locker(locker).unwrap();
+    let first = cloakroom
+        .join_queue(&coats(3), PriorityClass::Standard)
+        .unwrap();
+    clock.advance(minutes(2));
+    let second = cloakroom
+        .join_queue(&coats(1), PriorityClass::Priority)
+        .unwrap();
+    assert_eq!(cloakroom.get_served_tickets(), vec![first]);
+
+    // Customers waiting, and those served, survive a save and restore.
+    let mut state = Vec::new();
+    cloakroom.save(&mut state, &[]).unwrap();
+    let state = String::from_utf8(state).unwrap();
+    assert!(state.contains(" next_ticket_number=3"));
+    assert!(state.contains(
+        "\nqueue ticket=Q000001 priority=standard \
+         items=coats:3,backpacks:0,umbrellas:0,other_items:0 joined_at_ms=0 \
+         locker_number=2 served_at_ms=0\n\
+         queue ticket=Q000002 priority=priority \
+         items=coats:1,backpacks:0,umbrellas:0,other_items:0 joined_at_ms=120000\n"
+    ));
+
+    let (mut restored, _) = Cloakroom::load(state.as_bytes()).unwrap();
+    restored = restored.with_clock(clock.clone());
+    assert_eq!(queued_tickets(&restored), vec![second]);
+    assert_eq!(restored.get_waiting_time(first), Ok(minutes(0)));
+    assert!(restored.get_abandoned_lockers().is_empty());
+    let locker = restored.take_served_locker(first).unwrap();
+    assert_eq!(locker.get_locker_number(), 2);
+    restored.vacate_locker(locker).unwrap();
+    assert_eq!(restored.get_served_tickets(), vec![second]);
+    assert_eq!(
+        restored.join_queue(&coats(1), PriorityClass::Standard),
+        Ok("Q000003".parse().unwrap())
+    );
+
+    // A served customer's locker must be one whose contents are being
+    // changed, and tickets must have been issued.
+    let invalid = state.replace(
+        "locker_number=2 served_at_ms",
+        "locker_number=1 served_at_ms",
+    );
+    assert!(Cloakroom::load(invalid.as_bytes()).is_err());
+    let invalid = state.replace(" next_ticket_number=3", " next_ticket_number=2");
+    assert!(Cloakroom::load(invalid.as_bytes()).is_err());
+    let invalid = state.replace("priority=priority", "priority=urgent");
+    assert!(Cloakroom::load(invalid.as_bytes()).is_err());
+}
+
+#[test]
+fn test3() {
+    let clock = MockClock::new(UNIX_EPOCH);
+    let mut cloakroom = Cloakroom::new(2, 5)
+        .with_clock(clock.clone())
+        .with_queue_pickup_timeout(minutes(10));
+    assert_eq!(cloakroom.get_queue_pickup_timeout(), Some(minutes(10)));
+    let locker = cloakroom.find_free_locker(&coats(0)).into_result().unwrap();
+    let _key = cloakroom.close_locker(locker).unwrap();
+
+    let first = cloakroom
+        .join_queue(&coats(1), PriorityClass::Standard)
+        .unwrap();
+    let second = cloakroom
+        .join_queue(&coats(1), PriorityClass::Standard)
+        .unwrap();
+    let third = cloakroom
+        .join_queue(&coats(1), PriorityClass::Standard)
+        .unwrap();
+    assert_eq!(cloakroom.get_served_tickets(), vec![first]);
+
+    // The first customer does not come for their locker in time, so it is
+    // passed to the second.
+    clock.advance(minutes(9));
+    assert!(cloakroom.find_free_locker(&coats(0)).into_result().is_err());
+    assert_eq!(cloakroom.get_served_tickets(), vec![first]);
+    clock.advance(minutes(1));
+    assert_eq!(
+        cloakroom.take_served_locker(first).err(),
+        Some(QueueError::UnknownTicket(first))
+    );
+    assert_eq!(cloakroom.get_served_tickets(), vec![second]);
+    assert_eq!(cloakroom.get_served_locker_number(second), Ok(2));
+    assert_eq!(cloakroom.get_waiting_time(second), Ok(minutes(10)));
+    assert_eq!(queued_tickets(&cloakroom), vec![third]);
+
+    // The time served survives a save and restore, so the second customer
+    // loses their locker too once restored with the same timeout.
+    let mut state = Vec::new();
+    cloakroom.save(&mut state, &[]).unwrap();
+    let (restored, _) = Cloakroom::load(state.as_slice()).unwrap();
+    let mut restored = restored
+        .with_clock(clock.clone())
+        .with_queue_pickup_timeout(minutes(10));
+    clock.advance(minutes(10));
+    assert_eq!(
+        restored.leave_queue(second),
+        Err(QueueError::UnknownTicket(second))
+    );
+    let locker = restored.take_served_locker(third).unwrap();
+    assert_eq!(locker.get_locker_number(), 2);
+    assert!(restored.get_served_tickets().is_empty());
+}
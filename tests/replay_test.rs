@@ -0,0 +1,177 @@
+extern crate cloakroom_model;
+
+use cloakroom_model::cloakroom::audit::{self, AuditAction, AuditEvent, InMemoryAuditSink};
+use cloakroom_model::cloakroom::replay::ReplayError;
+use cloakroom_model::cloakroom::{Cloakroom, Key, Locker, LockerState};
+use cloakroom_model::items::CloakroomItems;
+use std::time::{Duration, UNIX_EPOCH};
+
+fn describe_lockers(cloakroom: &Cloakroom) -> Vec<String> {
+    (1..=cloakroom.get_num_lockers())
+        .map(
+            |locker_number| match cloakroom.get_locker_state(locker_number) {
+                LockerState::Free => "free".to_string(),
+                LockerState::Closed(items, _) => format!("closed ({})", items),
+                LockerState::ContentsBeingChanged(_) => "being changed".to_string(),
+                LockerState::Abandoned(_) => "abandoned".to_string(),
+                LockerState::OutOfService { reason } => format!("out of service ({})", reason),
+                LockerState::NonExistent => "non-existent".to_string(),
+            },
+        )
+        .collect()
+}
+
+#[test]
+fn test1() {
+    let mut cloakroom = Cloakroom::new(6, 5).with_audit_sink(InMemoryAuditSink::new());
+    let mut keys: Vec<Key> = Vec::new();
+    let mut open_lockers: Vec<Locker> = Vec::new();
+    let mut snapshots = Vec::new();
+
+    // Carries out a pseudo-random mix of operations, noting the state of
+    // the lockers after each one.
+    let mut seed: u64 = 12345;
+    for _ in 0..400 {
+        seed = seed
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        let choice = (seed >> 33) % 7;
+        let num_items = ((seed >> 40) % 4) as u8;
+        match choice {
+            0 | 1 => {
+                if let Ok(locker) = cloakroom
+                    .find_free_locker(&CloakroomItems::new())
+                    .into_result()
+                {
+                    open_lockers.push(locker);
+                }
+            }
+            2 if !keys.is_empty() => {
+                let key = keys.remove((seed as usize >> 8) % keys.len());
+                open_lockers.push(cloakroom.open_locker(key).unwrap());
+            }
+            3 if !open_lockers.is_empty() => {
+                let mut locker = open_lockers.remove(0);
+                let _ = locker.set_num_items("coats", num_items);
+                keys.push(cloakroom.close_locker(locker).unwrap());
+            }
+            4 if !open_lockers.is_empty() => {
+                let mut locker = open_lockers.pop().unwrap();
+                let _ = locker.set_num_items("umbrellas", num_items);
+                cloakroom.vacate_locker(locker).unwrap();
+            }
+            5 if !open_lockers.is_empty() => {
+                let mut locker = open_lockers.pop().unwrap();
+                let _ = locker.set_num_items("backpacks", num_items);
+                drop(locker);
+                // Abandoned lockers are recorded at the next operation.
+                assert!(cloakroom.reclaim_locker(0).is_err());
+            }
+            6 => {
+                if let Some(&locker_number) = cloakroom.get_abandoned_lockers().first() {
+                    open_lockers.push(cloakroom.reclaim_locker(locker_number).unwrap());
+                }
+            }
+            _ => continue,
+        }
+        let num_events = cloakroom.get_audit_events().unwrap().len();
+        snapshots.push((num_events, describe_lockers(&cloakroom)));
+    }
+
+    // Replaying the events up to each point gives the state at that point.
+    let events = cloakroom.get_audit_events().unwrap();
+    assert!(events.len() > 200);
+    for action in &["opened", "abandoned", "reclaimed"] {
+        assert!(events
+            .iter()
+            .any(|event| event.get_action().to_string().starts_with(action)));
+    }
+    for (num_events, lockers) in &snapshots {
+        let replayed = Cloakroom::new(6, 5).replay(&events[..*num_events]).unwrap();
+        assert_eq!(&describe_lockers(&replayed), lockers);
+    }
+
+    // So does replaying the events read back from JSON lines.
+    let log: String = events.iter().map(|event| event.to_json() + "\n").collect();
+    let catalogue = cloakroom.get_item_catalogue();
+    let read = audit::read_events(log.as_bytes(), catalogue).unwrap();
+    assert_eq!(read.len(), events.len());
+    let replayed = Cloakroom::new(6, 5).replay(&read).unwrap();
+    assert_eq!(
+        describe_lockers(&replayed),
+        snapshots.last().unwrap().1.clone()
+    );
+}
+
+#[test]
+fn test2() {
+    let mut coats = CloakroomItems::new();
+    coats.set_num_items("coats", 2).unwrap();
+    let empty = CloakroomItems::new();
+    let at = |minutes: u64| UNIX_EPOCH + Duration::from_secs(1_700_000_000 + minutes * 60);
+    let event =
+        |minutes, locker_number, action| AuditEvent::new(at(minutes), locker_number, None, action);
+    let events = vec![
+        event(0, 12, AuditAction::Allocated),
+        event(
+            1,
+            12,
+            AuditAction::ContentsChanged {
+                before: empty.clone(),
+                after: coats.clone(),
+            },
+        ),
+        event(
+            1,
+            12,
+            AuditAction::Closed {
+                items: coats.clone(),
+            },
+        ),
+        event(90, 12, AuditAction::Opened),
+        event(
+            91,
+            12,
+            AuditAction::Vacated {
+                items: coats.clone(),
+            },
+        ),
+    ];
+
+    // What was in locker 12 an hour after it was closed?
+    let cloakroom = Cloakroom::new(20, 5).replay_until(&events, at(61)).unwrap();
+    match cloakroom.get_locker_state(12) {
+        LockerState::Closed(items, _) => assert_eq!(items, &coats),
+        _ => panic!("locker is not closed"),
+    }
+    let cloakroom = Cloakroom::new(20, 5).replay_until(&events, at(90)).unwrap();
+    assert!(matches!(
+        cloakroom.get_locker_state(12),
+        LockerState::ContentsBeingChanged(_)
+    ));
+    let cloakroom = Cloakroom::new(20, 5).replay(&events).unwrap();
+    assert!(matches!(cloakroom.get_locker_state(12), LockerState::Free));
+
+    // Events that do not fit the state of the locker are rejected.
+    let err = Cloakroom::new(20, 5).replay(&events[1..]).err();
+    assert_eq!(
+        err,
+        Some(ReplayError {
+            event_index: 0,
+            message: "'contents_changed' event for locker number 12 when it is free".to_string()
+        })
+    );
+    assert!(Cloakroom::new(10, 5).replay(&events).is_err());
+    assert!(Cloakroom::new(20, 1).replay(&events).is_err());
+
+    let mut events = events;
+    events[2] = event(1, 12, AuditAction::Closed { items: empty });
+    let err = Cloakroom::new(20, 5).replay(&events).err().unwrap();
+    assert_eq!(err.event_index, 2);
+    assert_eq!(
+        err.to_string(),
+        "cannot replay event 2: event gives contents \
+         (num coats: 0, num backpacks: 0, num umbrellas: 0, num other items: 0) \
+         but locker holds (num coats: 2, num backpacks: 0, num umbrellas: 0, num other items: 0)"
+    );
+}
@@ -0,0 +1,268 @@
+extern crate cloakroom_model;
+
+use cloakroom_model::cloakroom::lost_key::AdminCredential;
+use cloakroom_model::cloakroom::queue::PriorityClass;
+use cloakroom_model::cloakroom::reservation::{
+    ReservationError, ReservationReference, ReservationTarget,
+};
+use cloakroom_model::cloakroom::{Cloakroom, CloakroomError, LockerSpec, LockerState, SizeClass};
+use cloakroom_model::clock::MockClock;
+use cloakroom_model::items::CloakroomItems;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn hours(n: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(n * 3600)
+}
+
+fn held_lockers(cloakroom: &Cloakroom) -> Vec<Option<usize>> {
+    cloakroom
+        .get_reservations()
+        .iter()
+        .map(|reservation| reservation.get_held_locker_number())
+        .collect()
+}
+
+#[test]
+fn test1() {
+    let clock = MockClock::new(UNIX_EPOCH);
+    let mut cloakroom = Cloakroom::with_lockers(vec![
+        LockerSpec::new(5).with_size_class(SizeClass::Small),
+        LockerSpec::new(20).with_size_class(SizeClass::Large),
+        LockerSpec::new(20).with_size_class(SizeClass::Large),
+        LockerSpec::new(5),
+    ])
+    .with_clock(clock.clone());
+    let large = ReservationTarget::SizeClass(SizeClass::Large);
+    let ada = cloakroom
+        .reserve_locker("Ada", ReservationTarget::Locker(2), hours(1), hours(3))
+        .unwrap();
+    let bo = cloakroom
+        .reserve_locker("Bo", large, hours(0), hours(2))
+        .unwrap();
+    assert_eq!(ada.to_string(), "R000001");
+    assert_eq!("R000002".parse(), Ok(bo));
+    assert_eq!(held_lockers(&cloakroom), vec![Some(2), None]);
+
+    assert_eq!(
+        cloakroom.reserve_locker("Cy", large, hours(1), hours(2)),
+        Err(ReservationError::AlreadyReserved)
+    );
+    assert_eq!(
+        cloakroom.reserve_locker("Cy", ReservationTarget::Locker(2), hours(2), hours(4)),
+        Err(ReservationError::AlreadyReserved)
+    );
+    assert_eq!(
+        cloakroom.reserve_locker("Cy", ReservationTarget::Locker(9), hours(0), hours(1)),
+        Err(ReservationError::UnknownLocker(9))
+    );
+    assert_eq!(
+        cloakroom.reserve_locker(
+            "Cy",
+            ReservationTarget::SizeClass(SizeClass::Medium),
+            hours(0),
+            hours(1)
+        ),
+        Err(ReservationError::NoLockersOfSizeClass(SizeClass::Medium))
+    );
+    assert_eq!(
+        cloakroom.reserve_locker("", large, hours(3), hours(4)),
+        Err(ReservationError::MissingHolder)
+    );
+    assert_eq!(
+        cloakroom.reserve_locker("Cy", large, hours(4), hours(3)),
+        Err(ReservationError::InvalidWindow)
+    );
+
+    // Everyone else is given the other lockers.
+    let no_items = CloakroomItems::new();
+    let mut lockers = Vec::new();
+    while let Ok(locker) = cloakroom.find_free_locker(&no_items).into_result() {
+        lockers.push(locker);
+    }
+    let locker_numbers: Vec<usize> = lockers
+        .iter()
+        .map(|locker| locker.get_locker_number())
+        .collect();
+    assert_eq!(locker_numbers, vec![1, 3, 4]);
+    assert!(matches!(cloakroom.get_locker_state(2), LockerState::Free));
+    assert_eq!(
+        cloakroom.claim_reservation(bo, "Ada").err(),
+        Some(ReservationError::WrongHolder(bo))
+    );
+    assert_eq!(
+        cloakroom.claim_reservation(ada, "Ada").err(),
+        Some(ReservationError::NotStarted(ada))
+    );
+
+    // Ada's reservation takes locker 2, and Bo's waits for the next large
+    // locker to be vacated.
+    clock.advance(Duration::from_secs(3600));
+    assert_eq!(
+        cloakroom.claim_reservation(bo, "Bo").err(),
+        Some(ReservationError::NoLockerHeld(bo))
+    );
+    assert_eq!(held_lockers(&cloakroom), vec![None, Some(2)]);
+    cloakroom.vacate_locker(lockers.remove(1)).unwrap();
+    let locker = cloakroom.claim_reservation(bo, "Bo").unwrap();
+    assert_eq!(locker.get_locker_number(), 3);
+    assert_eq!(
+        cloakroom.claim_reservation(bo, "Bo").err(),
+        Some(ReservationError::UnknownReservation(bo))
+    );
+    lockers.push(locker);
+
+    // Ada's reservation expires unclaimed.
+    clock.advance(Duration::from_secs(2 * 3600));
+    let locker = cloakroom.find_free_locker(&no_items).into_result().unwrap();
+    assert_eq!(locker.get_locker_number(), 2);
+    assert_eq!(
+        cloakroom.claim_reservation(ada, "Ada").err(),
+        Some(ReservationError::UnknownReservation(ada))
+    );
+    assert!(cloakroom.get_reservations().is_empty());
+    lockers.push(locker);
+
+    // A locker held for a reservation is not given to the queue until the
+    // reservation is cancelled.
+    let cy = cloakroom
+        .reserve_locker(
+            "Cy",
+            ReservationTarget::SizeClass(SizeClass::Small),
+            hours(3),
+            hours(4),
+        )
+        .unwrap();
+    let ticket = cloakroom
+        .join_queue(&no_items, PriorityClass::Standard)
+        .unwrap();
+    cloakroom.vacate_locker(lockers.remove(0)).unwrap();
+    assert!(cloakroom.get_served_tickets().is_empty());
+    assert_eq!(held_lockers(&cloakroom), vec![Some(1)]);
+    cloakroom.cancel_reservation(cy).unwrap();
+    assert_eq!(cloakroom.get_served_locker_number(ticket), Ok(1));
+    assert_eq!(
+        cloakroom.cancel_reservation(cy),
+        Err(ReservationError::UnknownReservation(cy))
+    );
+}
+
+#[test]
+fn test2() {
+    let clock = MockClock::new(UNIX_EPOCH);
+    let credential = AdminCredential::new("open sesame");
+    let mut cloakroom = Cloakroom::with_lockers(vec![
+        LockerSpec::new(20).with_size_class(SizeClass::Large),
+        LockerSpec::new(20).with_size_class(SizeClass::Large),
+        LockerSpec::new(5),
+    ])
+    .with_clock(clock.clone())
+    .with_admin_credential(credential.clone());
+    let ada = cloakroom
+        .reserve_locker(
+            "Ada Lovelace",
+            ReservationTarget::SizeClass(SizeClass::Large),
+            hours(0),
+            hours(1),
+        )
+        .unwrap();
+
+    let mut locker = cloakroom
+        .find_free_locker(&CloakroomItems::new())
+        .into_result()
+        .unwrap();
+    assert_eq!(locker.get_locker_number(), 2);
+    locker.set_num_items("coats", 2).unwrap();
+    let key = cloakroom.close_locker(locker).unwrap();
+    assert_eq!(
+        cloakroom.transfer(&key, Some(1)).err(),
+        Some(CloakroomError::LockerReserved(1))
+    );
+
+    // Taking the locker held back out of service leaves the reservation
+    // waiting for another.
+    cloakroom
+        .take_out_of_service(1, &credential, "broken lock")
+        .unwrap();
+    assert_eq!(held_lockers(&cloakroom), vec![None]);
+    let locker = cloakroom.open_locker(key).unwrap();
+    cloakroom.vacate_locker(locker).unwrap();
+    assert_eq!(held_lockers(&cloakroom), vec![Some(2)]);
+
+    // Reservations survive a save and restore.
+    let mut state = Vec::new();
+    cloakroom.save(&mut state, &[]).unwrap();
+    let state = String::from_utf8(state).unwrap();
+    assert!(state.contains(" next_reservation_number=2"));
+    assert!(state.contains(
+        "\nreservation reference=R000001 holder=Ada%20Lovelace size_class=large \
+         starts_at_ms=0 ends_at_ms=3600000 held_locker_number=2\n"
+    ));
+
+    let (mut restored, _) = Cloakroom::load(state.as_bytes()).unwrap();
+    restored = restored.with_clock(clock.clone());
+    let locker = restored
+        .find_free_locker(&CloakroomItems::new())
+        .into_result()
+        .unwrap();
+    assert_eq!(locker.get_locker_number(), 3);
+    assert!(restored
+        .find_free_locker(&CloakroomItems::new())
+        .into_result()
+        .is_err());
+    let locker = restored.claim_reservation(ada, "Ada Lovelace").unwrap();
+    assert_eq!(locker.get_locker_number(), 2);
+    assert_eq!(
+        restored.reserve_locker("Bo", ReservationTarget::Locker(3), hours(0), hours(1)),
+        Ok("R000002".parse::<ReservationReference>().unwrap())
+    );
+
+    // The locker held back must be free and suit the reservation, and the
+    // reference must have been given.
+    let invalid = state.replace("held_locker_number=2", "held_locker_number=3");
+    assert!(Cloakroom::load(invalid.as_bytes()).is_err());
+    let invalid = state.replace("held_locker_number=2", "held_locker_number=1");
+    assert!(Cloakroom::load(invalid.as_bytes()).is_err());
+    let invalid = state.replace(" next_reservation_number=2", "");
+    assert!(Cloakroom::load(invalid.as_bytes()).is_err());
+}
+
+#[test]
+fn test3() {
+    let clock = MockClock::new(UNIX_EPOCH);
+    let mut cloakroom = Cloakroom::with_lockers(vec![
+        LockerSpec::new(20).with_size_class(SizeClass::Large),
+        LockerSpec::new(20).with_size_class(SizeClass::Large),
+        LockerSpec::new(5),
+    ])
+    .with_clock(clock);
+    let large = ReservationTarget::SizeClass(SizeClass::Large);
+    cloakroom
+        .reserve_locker("Ada", large, hours(1), hours(2))
+        .unwrap();
+    cloakroom
+        .reserve_locker("Bo", large, hours(3), hours(4))
+        .unwrap();
+
+    // Ada's and Bo's reservations never overlap, so a reservation spanning
+    // both needs only the second large locker.
+    cloakroom
+        .reserve_locker("Cy", large, hours(1), hours(4))
+        .unwrap();
+
+    // Both large lockers are reserved while Ada's or Bo's reservation is on,
+    // but not in between.
+    assert_eq!(
+        cloakroom.reserve_locker("Di", large, hours(0), hours(4)),
+        Err(ReservationError::AlreadyReserved)
+    );
+    assert_eq!(
+        cloakroom.reserve_locker("Di", ReservationTarget::Locker(1), hours(3), hours(5)),
+        Err(ReservationError::AlreadyReserved)
+    );
+    cloakroom
+        .reserve_locker("Di", large, hours(2), hours(3))
+        .unwrap();
+    cloakroom
+        .reserve_locker("Ed", large, hours(0), hours(1))
+        .unwrap();
+}
@@ -0,0 +1,492 @@
+extern crate cloakroom_model;
+
+use cloakroom_model::cloakroom::allocation::RoundRobin;
+use cloakroom_model::cloakroom::audit::{AuditAction, InMemoryAuditSink};
+use cloakroom_model::cloakroom::lost_key::AdminCredential;
+use cloakroom_model::cloakroom::queue::PriorityClass;
+use cloakroom_model::cloakroom::reservation::{ReservationError, ReservationTarget};
+use cloakroom_model::cloakroom::shared::SharedCloakroom;
+use cloakroom_model::cloakroom::{
+    Cloakroom, CloakroomError, Key, LockerSpec, LockerState, SizeClass,
+};
+use cloakroom_model::clock::MockClock;
+use cloakroom_model::items::CloakroomItems;
+use cloakroom_model::pricing::Tariff;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+fn assert_shareable<T: Clone + Send + Sync>() {}
+
+fn describe_locker(cloakroom: &SharedCloakroom, locker_number: usize) -> String {
+    cloakroom.inspect_locker(locker_number, |state| match state {
+        LockerState::Free => "free".to_string(),
+        LockerState::Closed(items, _) => format!("closed ({})", items),
+        LockerState::ContentsBeingChanged(_) => "being changed".to_string(),
+        LockerState::Abandoned(_) => "abandoned".to_string(),
+        LockerState::OutOfService { reason } => format!("out of service ({})", reason),
+        LockerState::NonExistent => "non-existent".to_string(),
+    })
+}
+
+#[test]
+fn test1() {
+    assert_shareable::<SharedCloakroom>();
+
+    let clock = MockClock::new(UNIX_EPOCH);
+    let mut cloakroom = SharedCloakroom::new(
+        Cloakroom::with_lockers(vec![LockerSpec::new(1), LockerSpec::new(5)])
+            .with_clock(clock.clone())
+            .with_tariff(Tariff::new().with_deposit_fee("coats", 150))
+            .with_audit_sink(InMemoryAuditSink::new()),
+    );
+    cloakroom.set_attendant_id(Some("ann"));
+    let mut desk2 = cloakroom.clone();
+    desk2.set_attendant_id(Some("bo"));
+
+    let mut coats = CloakroomItems::new();
+    coats.set_num_items("coats", 2).unwrap();
+    let mut locker = cloakroom.find_free_locker(&coats).into_result().unwrap();
+    assert_eq!(locker.get_locker_number(), 2);
+    locker.set_items(coats.clone()).unwrap();
+    let key = cloakroom.close_locker(locker).unwrap();
+    assert!(desk2.find_free_locker(&coats).into_result().is_err());
+
+    // Either desk can open the locker, but only with the latest key.
+    let stale: Key = key.to_string().parse().unwrap();
+    let locker = desk2.open_locker(key).unwrap();
+    assert_eq!(locker.get_items().get_num_items("coats"), Some(2));
+    let key = desk2.close_locker(locker).unwrap();
+    assert_eq!(
+        cloakroom.open_locker(stale).err(),
+        Some(CloakroomError::InvalidKey(2))
+    );
+    assert_eq!(
+        cloakroom
+            .open_locker("3-0123456789abcdef0123456789abcdef".parse().unwrap())
+            .err(),
+        Some(CloakroomError::UnknownLocker(3))
+    );
+
+    clock.advance(Duration::from_secs(60));
+    let locker = cloakroom.open_locker(key).unwrap();
+    let (items, invoice) = cloakroom.checkout_locker(locker).unwrap();
+    assert_eq!(items, coats);
+    assert_eq!(invoice.get_total_pence(), 300);
+    assert_eq!(describe_locker(&cloakroom, 2), "free");
+    assert_eq!(describe_locker(&cloakroom, 3), "non-existent");
+
+    // A locker dropped by one desk can be reclaimed by another.
+    let mut locker = desk2
+        .find_free_locker(&CloakroomItems::new())
+        .into_result()
+        .unwrap();
+    locker.set_num_items("umbrellas", 1).unwrap();
+    drop(locker);
+    assert_eq!(describe_locker(&cloakroom, 1), "abandoned");
+    let locker = cloakroom.reclaim_locker(1).unwrap();
+    assert_eq!(locker.get_items().get_num_items("umbrellas"), Some(1));
+    let _key = cloakroom.close_locker(locker).unwrap();
+
+    let history = cloakroom.get_locker_history(2).unwrap();
+    assert_eq!(history[0].get_action(), &AuditAction::Allocated);
+    assert_eq!(history[0].get_attendant_id(), Some("ann"));
+    assert_eq!(history[3].get_action(), &AuditAction::Opened);
+    assert_eq!(history[3].get_attendant_id(), Some("bo"));
+    assert!(cloakroom.take_audit_error().is_none());
+
+    // The events can be replayed into a cloakroom.
+    let events = cloakroom.get_audit_events().unwrap();
+    let replayed = Cloakroom::with_lockers(vec![LockerSpec::new(1), LockerSpec::new(5)])
+        .replay(&events)
+        .unwrap();
+    assert!(matches!(
+        replayed.get_locker_state(1),
+        LockerState::Closed(..)
+    ));
+    assert!(matches!(replayed.get_locker_state(2), LockerState::Free));
+}
+
+#[test]
+fn test2() {
+    // The cloakroom's allocation strategy, queue and reservations are used
+    // by every desk.
+    let clock = MockClock::new(UNIX_EPOCH);
+    let cloakroom = SharedCloakroom::new(
+        Cloakroom::with_lockers(vec![
+            LockerSpec::new(5),
+            LockerSpec::new(5),
+            LockerSpec::new(20).with_size_class(SizeClass::Large),
+        ])
+        .with_clock(clock.clone())
+        .with_allocation_strategy(RoundRobin::new()),
+    );
+    let desk2 = cloakroom.clone();
+    let no_items = CloakroomItems::new();
+
+    let reference = cloakroom
+        .lock()
+        .reserve_locker(
+            "Ada",
+            ReservationTarget::SizeClass(SizeClass::Large),
+            UNIX_EPOCH,
+            UNIX_EPOCH + Duration::from_secs(3600),
+        )
+        .unwrap();
+    let first = cloakroom.find_free_locker(&no_items).into_result().unwrap();
+    assert_eq!(first.get_locker_number(), 1);
+    cloakroom.vacate_locker(first).unwrap();
+    let second = desk2.find_free_locker(&no_items).into_result().unwrap();
+    assert_eq!(second.get_locker_number(), 2);
+    let third = desk2.find_free_locker(&no_items).into_result().unwrap();
+    assert_eq!(third.get_locker_number(), 1);
+    assert!(cloakroom.find_free_locker(&no_items).into_result().is_err());
+
+    // A locker vacated by one desk goes to the customer queueing at
+    // another.
+    let ticket = desk2
+        .lock()
+        .join_queue(&no_items, PriorityClass::Standard)
+        .unwrap();
+    cloakroom.vacate_locker(second).unwrap();
+    let locker = desk2.lock().take_served_locker(ticket).unwrap();
+    assert_eq!(locker.get_locker_number(), 2);
+
+    let claimed = cloakroom
+        .lock()
+        .claim_reservation(reference, "Ada")
+        .unwrap();
+    assert_eq!(claimed.get_locker_number(), 3);
+    for locker in [locker, third, claimed] {
+        desk2.vacate_locker(locker).unwrap();
+    }
+
+    // A locker handed out by another cloakroom is refused.
+    let other = SharedCloakroom::new(Cloakroom::new(1, 5));
+    let foreign = other.find_free_locker(&no_items).into_result().unwrap();
+    assert_eq!(
+        cloakroom.close_locker(foreign).err(),
+        Some(CloakroomError::LockerNotHandedOut(1))
+    );
+}
+
+#[test]
+fn test3() {
+    // Many desks repeatedly take lockers, use them and give them back. Each
+    // locker records whether a desk holds it, so that a locker handed to a
+    // second desk is noticed at once.
+    const NUM_LOCKERS: usize = 8;
+    const NUM_DESKS: usize = 16;
+    const NUM_ROUNDS: usize = 500;
+
+    let cloakroom = SharedCloakroom::new(
+        Cloakroom::new(NUM_LOCKERS, 5).with_audit_sink(InMemoryAuditSink::new()),
+    );
+    let held: Arc<Vec<AtomicBool>> =
+        Arc::new((0..NUM_LOCKERS).map(|_| AtomicBool::new(false)).collect());
+    let num_allocated = Arc::new(AtomicUsize::new(0));
+
+    let desks: Vec<_> = (0..NUM_DESKS)
+        .map(|desk| {
+            let cloakroom = cloakroom.clone();
+            let held = held.clone();
+            let num_allocated = num_allocated.clone();
+            thread::spawn(move || {
+                for round in 0..NUM_ROUNDS {
+                    let mut locker = match cloakroom
+                        .find_free_locker(&CloakroomItems::new())
+                        .into_result()
+                    {
+                        Ok(locker) => locker,
+                        Err(_) => continue,
+                    };
+                    num_allocated.fetch_add(1, Ordering::SeqCst);
+                    let index = locker.get_locker_number() - 1;
+                    assert!(
+                        !held[index].swap(true, Ordering::SeqCst),
+                        "locker number {} allocated twice",
+                        index + 1
+                    );
+
+                    locker
+                        .set_num_items("coats", ((desk + round) % 5) as u8)
+                        .unwrap();
+                    let key = cloakroom.close_locker(locker).unwrap();
+                    let locker = cloakroom.open_locker(key).unwrap();
+                    assert_eq!(locker.get_total_num_items(), ((desk + round) % 5) as u16);
+
+                    // The flag is cleared while the locker is still held, so
+                    // another desk cannot have been given it yet.
+                    held[index].store(false, Ordering::SeqCst);
+                    cloakroom.vacate_locker(locker).unwrap();
+                }
+            })
+        })
+        .collect();
+    for desk in desks {
+        desk.join().unwrap();
+    }
+
+    for locker_number in 1..=NUM_LOCKERS {
+        assert_eq!(describe_locker(&cloakroom, locker_number), "free");
+    }
+    let events = cloakroom.get_audit_events().unwrap();
+    let num_allocated_events = events
+        .iter()
+        .filter(|event| event.get_action() == &AuditAction::Allocated)
+        .count();
+    assert_eq!(num_allocated_events, num_allocated.load(Ordering::SeqCst));
+    assert!(num_allocated_events > NUM_ROUNDS);
+
+    // Each locker's events are in order, so they can be replayed.
+    let replayed = Cloakroom::new(NUM_LOCKERS, 5).replay(&events).unwrap();
+    for locker_number in 1..=NUM_LOCKERS {
+        assert!(matches!(
+            replayed.get_locker_state(locker_number),
+            LockerState::Free
+        ));
+    }
+}
+
+#[test]
+fn test4() {
+    // More desks than lockers all ask for lockers at the same moment, and
+    // keep every locker they are given. Every locker is given out exactly
+    // once.
+    const NUM_LOCKERS: usize = 50;
+    const NUM_DESKS: usize = 8;
+
+    let cloakroom = SharedCloakroom::new(Cloakroom::new(NUM_LOCKERS, 5));
+    let start = Arc::new(Barrier::new(NUM_DESKS));
+    let desks: Vec<_> = (0..NUM_DESKS)
+        .map(|_| {
+            let cloakroom = cloakroom.clone();
+            let start = start.clone();
+            thread::spawn(move || {
+                start.wait();
+                let mut keys = Vec::new();
+                while let Ok(locker) = cloakroom
+                    .find_free_locker(&CloakroomItems::new())
+                    .into_result()
+                {
+                    keys.push(cloakroom.close_locker(locker).unwrap());
+                }
+                keys
+            })
+        })
+        .collect();
+
+    let mut locker_numbers: Vec<usize> = desks
+        .into_iter()
+        .flat_map(|desk| desk.join().unwrap())
+        .map(|key| key.get_locker_number())
+        .collect();
+    locker_numbers.sort_unstable();
+    assert_eq!(locker_numbers, (1..=NUM_LOCKERS).collect::<Vec<usize>>());
+}
+
+#[test]
+fn test5() {
+    // Desks allocate lockers while an attendant keeps taking free lockers
+    // out of service and returning them, and VIPs keep reserving large
+    // lockers and claiming them. No locker is ever handed out twice, or
+    // while it is out of service.
+    const NUM_SMALL: usize = 6;
+    const NUM_LARGE: usize = 3;
+    const NUM_LOCKERS: usize = NUM_SMALL + NUM_LARGE;
+    const NUM_DESKS: usize = 8;
+    const NUM_ROUNDS: usize = 300;
+
+    let credential = AdminCredential::new("open sesame");
+    let mut specs = vec![LockerSpec::new(5).with_size_class(SizeClass::Small); NUM_SMALL];
+    specs.extend(vec![
+        LockerSpec::new(20).with_size_class(SizeClass::Large);
+        NUM_LARGE
+    ]);
+    let cloakroom = SharedCloakroom::new(
+        Cloakroom::with_lockers(specs)
+            .with_clock(MockClock::new(UNIX_EPOCH))
+            .with_admin_credential(credential.clone())
+            .with_audit_sink(InMemoryAuditSink::new()),
+    );
+    let held: Arc<Vec<AtomicBool>> =
+        Arc::new((0..NUM_LOCKERS).map(|_| AtomicBool::new(false)).collect());
+    let out_of_service: Arc<Vec<AtomicBool>> =
+        Arc::new((0..NUM_LOCKERS).map(|_| AtomicBool::new(false)).collect());
+    let desks_done = Arc::new(AtomicUsize::new(0));
+
+    let take = {
+        let held = held.clone();
+        let out_of_service = out_of_service.clone();
+        move |locker_number: usize| {
+            let index = locker_number - 1;
+            assert!(
+                !held[index].swap(true, Ordering::SeqCst),
+                "locker number {} handed out twice",
+                locker_number
+            );
+            assert!(
+                !out_of_service[index].load(Ordering::SeqCst),
+                "locker number {} handed out while out of service",
+                locker_number
+            );
+        }
+    };
+    let give_back = {
+        let held = held.clone();
+        move |locker_number: usize| held[locker_number - 1].store(false, Ordering::SeqCst)
+    };
+
+    let mut threads: Vec<_> = (0..NUM_DESKS)
+        .map(|desk| {
+            let cloakroom = cloakroom.clone();
+            let take = take.clone();
+            let give_back = give_back.clone();
+            let desks_done = desks_done.clone();
+            thread::spawn(move || {
+                for round in 0..NUM_ROUNDS {
+                    let mut items = CloakroomItems::new();
+                    items
+                        .set_num_items("coats", ((desk * 7 + round) % 9) as u8)
+                        .unwrap();
+                    let mut locker = match cloakroom.find_free_locker(&items).into_result() {
+                        Ok(locker) => locker,
+                        Err(_) => continue,
+                    };
+                    let locker_number = locker.get_locker_number();
+                    take(locker_number);
+                    locker.set_items(items).unwrap();
+                    let key = cloakroom.close_locker(locker).unwrap();
+                    let locker = cloakroom.open_locker(key).unwrap();
+                    give_back(locker_number);
+                    cloakroom.vacate_locker(locker).unwrap();
+                }
+                desks_done.fetch_add(1, Ordering::SeqCst);
+            })
+        })
+        .collect();
+
+    {
+        let cloakroom = cloakroom.clone();
+        let out_of_service = out_of_service.clone();
+        let desks_done = desks_done.clone();
+        threads.push(thread::spawn(move || {
+            let mut locker_number = 1;
+            while desks_done.load(Ordering::SeqCst) < NUM_DESKS {
+                let mut guard = cloakroom.lock();
+                if guard
+                    .take_out_of_service(locker_number, &credential, "inspection")
+                    .is_ok()
+                {
+                    out_of_service[locker_number - 1].store(true, Ordering::SeqCst);
+                    drop(guard);
+                    thread::yield_now();
+                    let mut guard = cloakroom.lock();
+                    out_of_service[locker_number - 1].store(false, Ordering::SeqCst);
+                    guard.return_to_service(locker_number, &credential).unwrap();
+                }
+                locker_number = locker_number % NUM_LOCKERS + 1;
+            }
+        }));
+    }
+
+    let num_claimed = Arc::new(AtomicUsize::new(0));
+    {
+        let cloakroom = cloakroom.clone();
+        let num_claimed = num_claimed.clone();
+        let desks_done = desks_done.clone();
+        threads.push(thread::spawn(move || {
+            let large = ReservationTarget::SizeClass(SizeClass::Large);
+            let until = UNIX_EPOCH + Duration::from_secs(3600);
+            while desks_done.load(Ordering::SeqCst) < NUM_DESKS {
+                let reference = cloakroom
+                    .lock()
+                    .reserve_locker("VIP", large, UNIX_EPOCH, until)
+                    .unwrap();
+                loop {
+                    // The guard must be dropped before the locker is vacated.
+                    let claimed = cloakroom.lock().claim_reservation(reference, "VIP");
+                    match claimed {
+                        Ok(locker) => {
+                            let locker_number = locker.get_locker_number();
+                            assert!(locker_number > NUM_SMALL);
+                            take(locker_number);
+                            give_back(locker_number);
+                            cloakroom.vacate_locker(locker).unwrap();
+                            num_claimed.fetch_add(1, Ordering::SeqCst);
+                            break;
+                        }
+                        Err(ReservationError::NoLockerHeld(_)) => thread::yield_now(),
+                        Err(err) => panic!("cannot claim reservation: {}", err),
+                    }
+                }
+            }
+        }));
+    }
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert!(num_claimed.load(Ordering::SeqCst) > 0);
+    for locker_number in 1..=NUM_LOCKERS {
+        assert_eq!(describe_locker(&cloakroom, locker_number), "free");
+    }
+    assert!(cloakroom.lock().get_reservations().is_empty());
+
+    // Every event was applied in order, so they can be replayed.
+    let events = cloakroom.get_audit_events().unwrap();
+    assert!(events
+        .iter()
+        .any(|event| matches!(event.get_action(), AuditAction::TakenOutOfService { .. })));
+    let mut specs = vec![LockerSpec::new(5).with_size_class(SizeClass::Small); NUM_SMALL];
+    specs.extend(vec![
+        LockerSpec::new(20).with_size_class(SizeClass::Large);
+        NUM_LARGE
+    ]);
+    let replayed = Cloakroom::with_lockers(specs).replay(&events).unwrap();
+    for locker_number in 1..=NUM_LOCKERS {
+        assert!(matches!(
+            replayed.get_locker_state(locker_number),
+            LockerState::Free
+        ));
+    }
+}
+
+#[test]
+fn test6() {
+    // Each locker has a lock of its own, so desks can open and close other
+    // lockers while one is being inspected, which would never finish if
+    // the whole cloakroom were locked.
+    let cloakroom = SharedCloakroom::new(Cloakroom::new(3, 5));
+    let mut keys: Vec<Key> = (0..2)
+        .map(|_| {
+            let found = cloakroom.find_free_locker(&CloakroomItems::new());
+            let mut locker = found.into_result().unwrap();
+            locker.set_num_items("coats", 1).unwrap();
+            cloakroom.close_locker(locker).unwrap()
+        })
+        .collect();
+    let key = keys.pop().unwrap();
+    assert_eq!(key.get_locker_number(), 2);
+
+    let key = cloakroom.inspect_locker(1, |state| {
+        assert!(matches!(state, LockerState::Closed(..)));
+        let desk = cloakroom.clone();
+        let key = thread::spawn(move || {
+            let mut locker = desk.open_locker(key).unwrap();
+            locker.set_num_items("coats", 2).unwrap();
+            assert_eq!(describe_locker(&desk, 3), "free");
+            desk.close_locker(locker).unwrap()
+        })
+        .join()
+        .unwrap();
+        assert!(matches!(state, LockerState::Closed(..)));
+        key
+    });
+    assert!(cloakroom.inspect_locker(2, |state| matches!(
+        state,
+        LockerState::Closed(items, _) if items.get_num_items("coats") == Some(2)
+    )));
+    assert!(cloakroom.open_locker(key).is_ok());
+}
@@ -0,0 +1,156 @@
+extern crate cloakroom_model;
+
+use cloakroom_model::cloakroom;
+use cloakroom_model::clock::{Clock, MockClock};
+use cloakroom_model::items::{CloakroomItems, ItemCatalogue};
+use cloakroom_model::simulation::{
+    self, ArrivalProcess, ItemMix, SimulationConfig, SimulationError, StayDuration,
+};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+fn item_mix() -> ItemMix {
+    let mut coat = CloakroomItems::new();
+    coat.set_num_items("coats", 1).unwrap();
+
+    let mut luggage = CloakroomItems::new();
+    luggage.set_num_items("backpacks", 2).unwrap();
+    luggage.set_num_items("other_items", 6).unwrap();
+
+    ItemMix::new(vec![(3.0, coat), (1.0, luggage)])
+}
+
+fn run(
+    num_lockers: usize,
+    max_space: u32,
+    config: &SimulationConfig,
+) -> (cloakroom::Cloakroom, simulation::SimulationReport) {
+    let clock = MockClock::new(UNIX_EPOCH);
+    let mut cloakroom = cloakroom::Cloakroom::new(num_lockers, max_space).with_clock(clock.clone());
+    let report = simulation::run(&mut cloakroom, &clock, config).unwrap();
+    (cloakroom, report)
+}
+
+fn config(seed: u64) -> SimulationConfig {
+    SimulationConfig {
+        seed,
+        duration_minutes: 6.0 * 60.0,
+        arrivals: ArrivalProcess::Poisson {
+            rate_per_hour: 60.0,
+        },
+        stay: StayDuration::Exponential {
+            mean_minutes: 120.0,
+        },
+        items: item_mix(),
+    }
+}
+
+#[test]
+fn test1() {
+    let (cloakroom1, report1) = run(50, 10, &config(7));
+
+    // The same seed gives the same run.
+    let (_, report2) = run(50, 10, &config(7));
+    assert_eq!(report1, report2);
+
+    // About 360 customers are expected, and 50 lockers are not enough for
+    // them all.
+    assert!(report1.arrivals > 250 && report1.arrivals < 450);
+    assert!(report1.rejected > 0);
+    assert_eq!(report1.arrivals, report1.served + report1.rejected);
+    assert_eq!(report1.served, report1.departures);
+    assert!(report1.end_minutes >= 6.0 * 60.0 * 0.9);
+
+    // Every locker is free again afterwards.
+    for locker_number in 1..=50 {
+        assert!(matches!(
+            cloakroom1.get_locker_state(locker_number),
+            cloakroom::LockerState::Free
+        ));
+    }
+
+    let (_, report3) = run(50, 10, &config(8));
+    assert_ne!(report1, report3);
+}
+
+#[test]
+fn test2() {
+    // Lockers too small for the luggage bundle turn those customers away.
+    let (_, report) = run(1000, 5, &config(3));
+    assert!(report.rejected > 0);
+    assert!(report.rejected < report.served);
+
+    // With short, fixed stays and plenty of lockers nobody is turned away.
+    let mut config = config(3);
+    config.stay = StayDuration::Empirical(vec![1.0, 2.0, 5.0]);
+    let (_, report) = run(20, 10, &config);
+    assert_eq!(report.rejected, 0);
+    assert!(report.end_minutes < config.duration_minutes + 5.0);
+}
+
+#[test]
+fn test3() {
+    // The cloakroom's clock follows simulated time, so its own statistics
+    // agree with the simulation's.
+    let clock = MockClock::new(UNIX_EPOCH);
+    let mut cloakroom = cloakroom::Cloakroom::new(30, 10)
+        .with_clock(clock.clone())
+        .with_statistics();
+    let report = simulation::run(&mut cloakroom, &clock, &config(5)).unwrap();
+    assert_eq!(
+        clock.now(),
+        UNIX_EPOCH + Duration::from_secs_f64(report.end_minutes * 60.0)
+    );
+
+    let statistics = cloakroom.get_statistics_report().unwrap();
+    assert_eq!(statistics.allocations, report.served);
+    assert_eq!(statistics.rejections, report.rejected);
+    assert_eq!(
+        statistics.peak_occupied_lockers,
+        report.statistics.peak_occupied_lockers
+    );
+    assert!((statistics.duration_minutes - report.end_minutes).abs() < 0.001);
+}
+
+#[test]
+fn test4() {
+    let clock = MockClock::new(UNIX_EPOCH);
+    let mut cloakroom = cloakroom::Cloakroom::new(10, 10).with_clock(clock.clone());
+
+    let mut config = config(1);
+    config.stay = StayDuration::Empirical(vec![5.0, -1.0]);
+    assert_eq!(
+        simulation::run(&mut cloakroom, &clock, &config),
+        Err(SimulationError::InvalidStayDuration(-1.0))
+    );
+    config.stay = StayDuration::Empirical(vec![5.0, f64::NAN]);
+    assert!(matches!(
+        simulation::run(&mut cloakroom, &clock, &config),
+        Err(SimulationError::InvalidStayDuration(minutes)) if minutes.is_nan()
+    ));
+    config.stay = StayDuration::Empirical(Vec::new());
+    assert_eq!(
+        simulation::run(&mut cloakroom, &clock, &config),
+        Err(SimulationError::NoStayDurations)
+    );
+
+    let mut config = self::config(1);
+    config.arrivals = ArrivalProcess::Poisson { rate_per_hour: 0.0 };
+    assert_eq!(
+        simulation::run(&mut cloakroom, &clock, &config),
+        Err(SimulationError::InvalidArrivalRate(0.0))
+    );
+
+    // A bundle of items the cloakroom does not accept is reported before
+    // any customer arrives.
+    let catalogue = Arc::new(ItemCatalogue::new(vec!["coats", "prams"]).unwrap());
+    let mut prams = CloakroomItems::from_catalogue(catalogue);
+    prams.set_num_items("prams", 1).unwrap();
+    let mut config = self::config(1);
+    config.items = ItemMix::new(vec![(1.0, prams)]);
+    assert_eq!(
+        simulation::run(&mut cloakroom, &clock, &config),
+        Err(SimulationError::UnknownItemCategory("prams".to_string()))
+    );
+    assert_eq!(clock.now(), UNIX_EPOCH);
+}
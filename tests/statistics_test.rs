@@ -0,0 +1,115 @@
+extern crate cloakroom_model;
+
+use cloakroom_model::cloakroom::Cloakroom;
+use cloakroom_model::clock::MockClock;
+use cloakroom_model::items::CloakroomItems;
+use cloakroom_model::simulation::{self, ArrivalProcess, ItemMix, SimulationConfig, StayDuration};
+use std::time::{Duration, UNIX_EPOCH};
+
+#[test]
+fn test1() {
+    let mut coat = CloakroomItems::new();
+    coat.set_num_items("coats", 1).unwrap();
+    let mut luggage = CloakroomItems::new();
+    luggage.set_num_items("backpacks", 1).unwrap();
+    luggage.set_num_items("umbrellas", 1).unwrap();
+
+    let config = SimulationConfig {
+        seed: 11,
+        duration_minutes: 4.0 * 60.0,
+        arrivals: ArrivalProcess::Poisson {
+            rate_per_hour: 40.0,
+        },
+        stay: StayDuration::Exponential { mean_minutes: 60.0 },
+        items: ItemMix::new(vec![(1.0, coat), (1.0, luggage)]),
+    };
+
+    let clock = MockClock::new(UNIX_EPOCH);
+    let mut cloakroom = Cloakroom::new(25, 5).with_clock(clock.clone());
+    let report = simulation::run(&mut cloakroom, &clock, &config).unwrap();
+    let statistics = &report.statistics;
+
+    assert_eq!(statistics.num_lockers, 25);
+    assert_eq!(statistics.duration_minutes, report.end_minutes);
+    assert_eq!(statistics.allocations, report.served);
+    assert_eq!(statistics.rejections, report.rejected);
+    assert!(statistics.peak_occupied_lockers <= 25);
+    assert!(statistics.average_occupied_lockers > 0.0);
+    assert!(statistics.average_occupied_lockers <= statistics.peak_occupied_lockers as f64);
+
+    // Every served customer brought either a coat, or a backpack and an
+    // umbrella.
+    let count = |category: &str| {
+        statistics
+            .items_stored
+            .iter()
+            .find(|(name, _)| name == category)
+            .unwrap()
+            .1
+    };
+    assert_eq!(count("coats") + count("backpacks"), report.served as u64);
+    assert_eq!(count("backpacks"), count("umbrellas"));
+    assert_eq!(count("other_items"), 0);
+
+    assert_eq!(statistics.locker_utilisation.len(), 25);
+    assert!(statistics
+        .locker_utilisation
+        .iter()
+        .all(|&utilisation| (0.0..=1.0).contains(&utilisation)));
+    assert_eq!(statistics.utilisation_bands.iter().sum::<usize>(), 25);
+
+    // Average occupancy is the mean of the per-locker utilisation.
+    let total_utilisation: f64 = statistics.locker_utilisation.iter().sum();
+    assert!((total_utilisation - statistics.average_occupied_lockers).abs() < 1e-9);
+
+    let csv = statistics.to_csv();
+    assert_eq!(csv.lines().count(), 1 + 7 + 4 + 10 + 25);
+    assert!(csv.contains(&format!("\nrejections,{}\n", report.rejected)));
+
+    let json = statistics.to_json();
+    assert!(json.contains(&format!("\"allocations\": {}, ", report.served)));
+}
+
+#[test]
+fn test2() {
+    let clock = MockClock::new(UNIX_EPOCH);
+    let mut cloakroom = Cloakroom::new(3, 5)
+        .with_clock(clock.clone())
+        .with_statistics();
+    let no_items = CloakroomItems::new();
+    let mut locker = cloakroom.find_free_locker(&no_items).into_result().unwrap();
+    locker.set_num_items("coats", 2).unwrap();
+    let key = cloakroom.close_locker(locker).unwrap();
+    let _other = cloakroom.find_free_locker(&no_items).into_result().unwrap();
+
+    // Moving the coats to another locker is not another allocation, and
+    // taking one out and putting it back before closing the locker does not
+    // store it twice.
+    clock.advance(Duration::from_secs(15 * 60));
+    let key = cloakroom.transfer(&key, Some(3)).unwrap();
+    let mut locker = cloakroom.open_locker(key).unwrap();
+    locker.set_num_items("coats", 1).unwrap();
+    locker.set_num_items("coats", 2).unwrap();
+    locker.set_num_items("umbrellas", 1).unwrap();
+    let mut too_many_coats = CloakroomItems::new();
+    too_many_coats.set_num_items("coats", 6).unwrap();
+    assert!(cloakroom
+        .find_free_locker(&too_many_coats)
+        .into_result()
+        .is_err());
+
+    clock.advance(Duration::from_secs(15 * 60));
+    cloakroom.vacate_locker(locker).unwrap();
+    clock.advance(Duration::from_secs(30 * 60));
+
+    let statistics = cloakroom.get_statistics_report().unwrap();
+    assert_eq!(statistics.duration_minutes, 60.0);
+    assert_eq!((statistics.allocations, statistics.rejections), (2, 1));
+    assert_eq!(statistics.peak_occupied_lockers, 2);
+    assert_eq!(statistics.locker_utilisation, vec![0.25, 1.0, 0.25]);
+    assert_eq!(statistics.average_occupied_lockers, 1.5);
+    assert_eq!(statistics.items_stored[0], ("coats".to_string(), 2));
+    assert_eq!(statistics.items_stored[2], ("umbrellas".to_string(), 1));
+
+    assert!(Cloakroom::new(3, 5).get_statistics_report().is_none());
+}
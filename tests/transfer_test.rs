@@ -0,0 +1,142 @@
+extern crate cloakroom_model;
+
+use cloakroom_model::cloakroom::audit::InMemoryAuditSink;
+use cloakroom_model::cloakroom::lost_key::AdminCredential;
+use cloakroom_model::cloakroom::{Cloakroom, CloakroomError, Key, LockerSpec, LockerState};
+use cloakroom_model::clock::MockClock;
+use cloakroom_model::items::CloakroomItems;
+use cloakroom_model::pricing::Tariff;
+use std::time::{Duration, UNIX_EPOCH};
+
+fn deposit(cloakroom: &mut Cloakroom, num_coats: u8) -> Key {
+    let mut locker = cloakroom
+        .find_free_locker(&CloakroomItems::new())
+        .into_result()
+        .unwrap();
+    locker.set_num_items("coats", num_coats).unwrap();
+    cloakroom.close_locker(locker).unwrap()
+}
+
+fn describe_lockers(cloakroom: &Cloakroom) -> Vec<String> {
+    (1..=cloakroom.get_num_lockers())
+        .map(
+            |locker_number| match cloakroom.get_locker_state(locker_number) {
+                LockerState::Closed(items, _) => format!("closed ({})", items),
+                LockerState::Free => "free".to_string(),
+                _ => "other".to_string(),
+            },
+        )
+        .collect()
+}
+
+#[test]
+fn test1() {
+    let clock = MockClock::new(UNIX_EPOCH);
+    let credential = AdminCredential::new("open sesame");
+    let mut cloakroom = Cloakroom::with_lockers(vec![
+        LockerSpec::new(3),
+        LockerSpec::new(3),
+        LockerSpec::new(1),
+        LockerSpec::new(5),
+    ])
+    .with_clock(clock.clone())
+    .with_tariff(Tariff::new().with_storage_charge(100))
+    .with_admin_credential(credential.clone())
+    .with_audit_sink(InMemoryAuditSink::new());
+
+    let key1 = deposit(&mut cloakroom, 3);
+    let key2 = deposit(&mut cloakroom, 1);
+    cloakroom
+        .take_out_of_service(4, &credential, "broken")
+        .unwrap();
+
+    // Nothing changes when the transfer cannot be made.
+    assert_eq!(
+        cloakroom.transfer(&key1, Some(2)).err(),
+        Some(CloakroomError::LockerNotFree(2))
+    );
+    assert_eq!(
+        cloakroom.transfer(&key1, Some(3)).err(),
+        Some(CloakroomError::InsufficientSpace {
+            requested: 3,
+            available: 1
+        })
+    );
+    assert_eq!(
+        cloakroom.transfer(&key1, Some(4)).err(),
+        Some(CloakroomError::LockerOutOfService(4))
+    );
+    assert_eq!(
+        cloakroom.transfer(&key1, Some(5)).err(),
+        Some(CloakroomError::UnknownLocker(5))
+    );
+    assert_eq!(
+        cloakroom.transfer(&key1, None).err(),
+        Some(CloakroomError::NoFreeLockers)
+    );
+    assert!(matches!(
+        cloakroom.get_locker_state(1),
+        LockerState::Closed(..)
+    ));
+
+    // A key from before the locker was last closed cannot be used.
+    let stale: Key = key2.to_string().parse().unwrap();
+    let locker = cloakroom.open_locker(key2).unwrap();
+    let key2 = cloakroom.close_locker(locker).unwrap();
+    assert_eq!(
+        cloakroom.transfer(&stale, Some(3)).err(),
+        Some(CloakroomError::InvalidKey(2))
+    );
+    let key3 = cloakroom.transfer(&key2, Some(3)).unwrap();
+    assert_eq!(key3.get_locker_number(), 3);
+
+    // The allocation strategy chooses the new locker when none is given.
+    clock.advance(Duration::from_secs(2 * 60 * 60));
+    let key2 = cloakroom.transfer(&key1, None).unwrap();
+    assert_eq!(key2.get_locker_number(), 2);
+    assert!(matches!(cloakroom.get_locker_state(1), LockerState::Free));
+    match cloakroom.get_locker_state(2) {
+        LockerState::Closed(items, times) => {
+            assert_eq!(items.get_num_items("coats"), Some(3));
+            assert_eq!(times.get_deposited_at(), Some(UNIX_EPOCH));
+        }
+        _ => panic!("locker number 2 is not closed"),
+    }
+    assert_eq!(
+        cloakroom.open_locker(key1).err(),
+        Some(CloakroomError::UnknownLocker(1))
+    );
+    assert_eq!(
+        cloakroom
+            .transfer(&key3, Some(1))
+            .unwrap()
+            .get_locker_number(),
+        1
+    );
+
+    // Storage is charged from when the items were first deposited.
+    clock.advance(Duration::from_secs(60 * 60));
+    let locker = cloakroom.open_locker(key2).unwrap();
+    let (_, invoice) = cloakroom.checkout_locker(locker).unwrap();
+    assert_eq!(invoice.get_locker_number(), 2);
+    assert_eq!(invoice.get_total_pence(), 300);
+
+    let history = cloakroom.get_locker_history(1).unwrap();
+    assert_eq!(
+        history[history.len() - 2].get_action().to_string(),
+        "transferred out to locker number 2 holding \
+         (num coats: 3, num backpacks: 0, num umbrellas: 0, num other items: 0)"
+    );
+
+    // Replaying the events moves the contents in the same way.
+    let events = cloakroom.get_audit_events().unwrap();
+    let replayed = Cloakroom::with_lockers(vec![
+        LockerSpec::new(3),
+        LockerSpec::new(3),
+        LockerSpec::new(1),
+        LockerSpec::new(5),
+    ])
+    .replay(&events)
+    .unwrap();
+    assert_eq!(describe_lockers(&replayed), describe_lockers(&cloakroom));
+}
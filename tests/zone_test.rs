@@ -0,0 +1,151 @@
+extern crate cloakroom_model;
+
+use cloakroom_model::cloakroom::allocation::{BestFit, PreferZone};
+use cloakroom_model::cloakroom::lost_key::AdminCredential;
+use cloakroom_model::cloakroom::zone::{ZoneError, ZoneSpec};
+use cloakroom_model::cloakroom::{Cloakroom, LockerSpec};
+use cloakroom_model::items::CloakroomItems;
+
+fn coats(num_items: u8) -> CloakroomItems {
+    let mut items = CloakroomItems::new();
+    items.set_num_items("coats", num_items).unwrap();
+    items
+}
+
+fn venue() -> Cloakroom {
+    Cloakroom::with_zones(vec![
+        ZoneSpec::new(
+            "basement",
+            "Lower ground floor",
+            vec![LockerSpec::new(20), LockerSpec::new(5)],
+        ),
+        ZoneSpec::new(
+            "entrance",
+            "Ground floor, by the main doors",
+            vec![LockerSpec::new(10), LockerSpec::new(5)],
+        ),
+        ZoneSpec::new("balcony", "", vec![LockerSpec::new(5)]),
+    ])
+    .unwrap()
+}
+
+#[test]
+fn test1() {
+    let cloakroom = venue();
+    assert_eq!(cloakroom.get_num_lockers(), 5);
+    let names: Vec<&str> = cloakroom
+        .get_zones()
+        .iter()
+        .map(|zone| zone.get_name())
+        .collect();
+    assert_eq!(names, vec!["basement", "entrance", "balcony"]);
+    let entrance = cloakroom.get_zone("entrance").unwrap();
+    assert_eq!(entrance.get_locker_numbers(), 3..=4);
+    assert_eq!(entrance.get_num_lockers(), 2);
+    assert_eq!(
+        entrance.to_string(),
+        "entrance (Ground floor, by the main doors), lockers 3 to 4"
+    );
+    assert_eq!(
+        cloakroom.get_zone_of(5).unwrap().to_string(),
+        "balcony, lockers 5 to 5"
+    );
+    assert!(cloakroom.get_zone_of(6).is_none());
+    assert!(cloakroom.get_zone("attic").is_none());
+    assert!(Cloakroom::new(3, 5).get_zones().is_empty());
+
+    // Customers are given the smallest locker at the entrance that will
+    // do, and only sent elsewhere once none there will.
+    let locker_numbers = entrance.get_locker_numbers();
+    let credential = AdminCredential::new("open sesame");
+    let mut cloakroom = cloakroom
+        .with_allocation_strategy(PreferZone::new(locker_numbers, BestFit))
+        .with_admin_credential(credential.clone());
+    let mut keys = Vec::new();
+    for &(num_coats, expected) in &[(2, 4), (2, 3), (2, 2), (12, 1)] {
+        let mut locker = cloakroom
+            .find_free_locker(&coats(num_coats))
+            .into_result()
+            .unwrap();
+        assert_eq!(locker.get_locker_number(), expected);
+        locker.set_items(coats(num_coats)).unwrap();
+        keys.push(cloakroom.close_locker(locker).unwrap());
+    }
+
+    let locker = cloakroom.open_locker(keys.remove(0)).unwrap();
+    drop(locker);
+    cloakroom
+        .take_out_of_service(5, &credential, "broken lock")
+        .unwrap();
+    let report = cloakroom.get_zone_report("entrance").unwrap();
+    assert_eq!(report.get_zone().get_name(), "entrance");
+    assert_eq!(
+        (
+            report.get_num_free(),
+            report.get_num_closed(),
+            report.get_num_abandoned()
+        ),
+        (0, 1, 1)
+    );
+    assert_eq!((report.get_used_space(), report.get_max_space()), (4, 15));
+    assert_eq!(
+        cloakroom.get_zone_report("balcony").unwrap().to_string(),
+        "balcony, lockers 5 to 5: 0 free, 0 closed, 0 being changed, 0 abandoned, \
+         1 out of service, space used: 0 of 5"
+    );
+    let report = cloakroom.get_zone_report("basement").unwrap();
+    assert_eq!((report.get_num_closed(), report.get_used_space()), (2, 14));
+    assert!(cloakroom.get_zone_report("attic").is_none());
+}
+
+#[test]
+fn test2() {
+    let entrance = || ZoneSpec::new("entrance", "", vec![LockerSpec::new(5)]);
+    assert_eq!(
+        Cloakroom::with_zones(vec![entrance(), entrance()]).err(),
+        Some(ZoneError::RepeatedName("entrance".to_string()))
+    );
+    assert_eq!(
+        Cloakroom::with_zones(vec![
+            entrance(),
+            ZoneSpec::new("", "", vec![LockerSpec::new(5)])
+        ])
+        .err(),
+        Some(ZoneError::MissingName)
+    );
+    assert_eq!(
+        Cloakroom::with_zones(vec![ZoneSpec::new("balcony", "Upstairs", Vec::new())]).err(),
+        Some(ZoneError::NoLockers("balcony".to_string()))
+    );
+}
+
+#[test]
+fn test3() {
+    // Zones survive a save and restore.
+    let mut state = Vec::new();
+    venue().save(&mut state, &[]).unwrap();
+    let state = String::from_utf8(state).unwrap();
+    assert!(state.contains(
+        "\nzone name=basement first=1 last=2 location=Lower%20ground%20floor\n\
+         zone name=entrance first=3 last=4 \
+         location=Ground%20floor,%20by%20the%20main%20doors\n\
+         zone name=balcony first=5 last=5\n"
+    ));
+
+    let (restored, _) = Cloakroom::load(state.as_bytes()).unwrap();
+    assert_eq!(restored.get_zones(), venue().get_zones());
+    assert_eq!(
+        restored.get_zone("entrance").unwrap().get_location(),
+        "Ground floor, by the main doors"
+    );
+
+    // Zones must cover every locker, in order, once.
+    let invalid = state.replace("zone name=balcony first=5 last=5\n", "");
+    assert!(Cloakroom::load(invalid.as_bytes()).is_err());
+    let invalid = state.replace("first=3 last=4", "first=2 last=4");
+    assert!(Cloakroom::load(invalid.as_bytes()).is_err());
+    let invalid = state.replace("first=5 last=5", "first=5 last=6");
+    assert!(Cloakroom::load(invalid.as_bytes()).is_err());
+    let invalid = state.replace("name=balcony", "name=entrance");
+    assert!(Cloakroom::load(invalid.as_bytes()).is_err());
+}